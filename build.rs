@@ -2,6 +2,9 @@ use std::{fs::File, io, path::Path};
 
 use vergen::{vergen, Config};
 
+const MAVLINK_DEFINITIONS_URL: &str =
+    "https://raw.githubusercontent.com/mavlink/mavlink/master/message_definitions/v1.0";
+
 fn main() {
     // Generate the 'cargo:' key output
     vergen(Config::default()).expect("Something is wrong!");
@@ -16,11 +19,29 @@ fn main() {
     ] {
         download_file(remote_file, &artifacts_dir);
     }
+
+    // Used to provide message and enum metadata
+    let definitions_dir = Path::new(&std::env::var("OUT_DIR").unwrap()).join("message_definitions");
+    std::fs::create_dir_all(&definitions_dir).expect("failed to create a dir");
+
+    for definition in [
+        "ardupilotmega.xml",
+        "common.xml",
+        "standard.xml",
+        "minimal.xml",
+        "uAvionix.xml",
+        "icarous.xml",
+    ] {
+        download_file(
+            &format!("{MAVLINK_DEFINITIONS_URL}/{definition}"),
+            &definitions_dir,
+        );
+    }
 }
 
 fn download_file(remote_file: &str, dir: &Path) {
     let mut resp = reqwest::blocking::get(remote_file)
-        .unwrap_or_else(|_| panic!("Failed to download vue file: {}", remote_file));
+        .unwrap_or_else(|_| panic!("Failed to download file: {}", remote_file));
 
     let filename = remote_file.split('/').last().unwrap();
     let file_path = dir.join(filename);
@@ -0,0 +1,144 @@
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Euler {
+    roll: f64,
+    pitch: f64,
+    yaw: f64,
+}
+
+// Hamilton convention, as q1 to q4 of ATTITUDE_QUATERNION
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Euler {
+    fn to_quaternion(self) -> Quaternion {
+        let (sr, cr) = (self.roll / 2.0).sin_cos();
+        let (sp, cp) = (self.pitch / 2.0).sin_cos();
+        let (sy, cy) = (self.yaw / 2.0).sin_cos();
+        Quaternion {
+            w: cr * cp * cy + sr * sp * sy,
+            x: sr * cp * cy - cr * sp * sy,
+            y: cr * sp * cy + sr * cp * sy,
+            z: cr * cp * sy - sr * sp * cy,
+        }
+    }
+}
+
+impl Quaternion {
+    fn to_euler(self) -> Euler {
+        let Quaternion { w, x, y, z } = self;
+        Euler {
+            roll: (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y)),
+            pitch: (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin(),
+            yaw: (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z)),
+        }
+    }
+
+    // Rotation from the body frame to the local NED frame
+    fn to_rotation_matrix(self) -> [[f64; 3]; 3] {
+        let Quaternion { w, x, y, z } = self;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+}
+
+fn euler(attitude: &Value) -> Option<Euler> {
+    Some(Euler {
+        roll: attitude["roll"].as_f64()?,
+        pitch: attitude["pitch"].as_f64()?,
+        yaw: attitude["yaw"].as_f64()?,
+    })
+}
+
+fn quaternion(attitude_quaternion: &Value) -> Option<Quaternion> {
+    Some(Quaternion {
+        w: attitude_quaternion["q1"].as_f64()?,
+        x: attitude_quaternion["q2"].as_f64()?,
+        y: attitude_quaternion["q3"].as_f64()?,
+        z: attitude_quaternion["q4"].as_f64()?,
+    })
+}
+
+/// Euler angles, quaternion and rotation matrix, from ATTITUDE_QUATERNION or ATTITUDE
+pub fn describe(attitude: Option<&Value>, attitude_quaternion: Option<&Value>) -> Option<Value> {
+    // The quaternion has no singularities, so it is preferred when both are available
+    let (source, quaternion, euler) = match (
+        attitude_quaternion.and_then(quaternion),
+        attitude.and_then(euler),
+    ) {
+        (Some(quaternion), _) => ("ATTITUDE_QUATERNION", quaternion, quaternion.to_euler()),
+        (None, Some(euler)) => ("ATTITUDE", euler.to_quaternion(), euler),
+        (None, None) => return None,
+    };
+
+    Some(json!({
+        "source": source,
+        "euler": {
+            "roll": euler.roll,
+            "pitch": euler.pitch,
+            "yaw": euler.yaw,
+        },
+        "euler_degrees": {
+            "roll": euler.roll.to_degrees(),
+            "pitch": euler.pitch.to_degrees(),
+            "yaw": euler.yaw.to_degrees(),
+        },
+        "quaternion": {
+            "w": quaternion.w,
+            "x": quaternion.x,
+            "y": quaternion.y,
+            "z": quaternion.z,
+        },
+        "rotation_matrix": quaternion.to_rotation_matrix(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let euler = Euler {
+            roll: 0.1,
+            pitch: -0.2,
+            yaw: 1.5,
+        };
+        let converted = euler.to_quaternion().to_euler();
+        assert!((converted.roll - euler.roll).abs() < 1e-9);
+        assert!((converted.pitch - euler.pitch).abs() < 1e-9);
+        assert!((converted.yaw - euler.yaw).abs() < 1e-9);
+
+        // 90 degrees of yaw turns the body x axis to east
+        let description = describe(
+            Some(&json!({"roll": 0.0, "pitch": 0.0, "yaw": std::f64::consts::FRAC_PI_2})),
+            None,
+        )
+        .unwrap();
+        assert_eq!(description["source"], "ATTITUDE");
+        let east = description["rotation_matrix"][1][0].as_f64().unwrap();
+        assert!((east - 1.0).abs() < 1e-9);
+        assert!(describe(None, None).is_none());
+    }
+}
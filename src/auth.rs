@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use actix_web::{dev::ServiceRequest, http::header, web};
+use lazy_static::lazy_static;
+
+use super::cli;
+
+lazy_static! {
+    static ref API_TOKEN: Option<String> = cli::api_token();
+}
+
+// Check if the request carries the configured API token, any request is valid if there is none
+pub fn is_authorized(request: &ServiceRequest) -> bool {
+    let token = match API_TOKEN.as_ref() {
+        Some(token) => token,
+        None => return true,
+    };
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    has_token(token, authorization, request.query_string())
+}
+
+fn has_token(token: &str, authorization: Option<&str>, query_string: &str) -> bool {
+    if let Some(bearer) = authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        return constant_time_eq(bearer.trim(), token);
+    }
+
+    // Browsers are not able to set headers in websocket connections, so allow it as query
+    web::Query::<HashMap<String, String>>::from_query(query_string)
+        .ok()
+        .and_then(|query| query.get("token").cloned())
+        .map_or(false, |query_token| constant_time_eq(&query_token, token))
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |result, (a, b)| result | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_sources() {
+        assert!(has_token("secret", Some("Bearer secret"), ""));
+        assert!(has_token("secret", Some("Bearer  secret "), ""));
+        assert!(has_token("secret", None, "token=secret"));
+        assert!(has_token("secret", None, "system_id=1&token=secret"));
+        // Other authorization schemes fall back to the query
+        assert!(has_token("secret", Some("Basic c2VjcmV0"), "token=secret"));
+
+        assert!(!has_token("secret", None, ""));
+        assert!(!has_token("secret", Some("Bearer wrong"), ""));
+        assert!(!has_token("secret", Some("secret"), ""));
+        assert!(!has_token("secret", None, "token=wrong"));
+        // A wrong header is not rescued by the query
+        assert!(!has_token("secret", Some("Bearer wrong"), "token=secret"));
+
+        assert!(constant_time_eq("secret", "secret"));
+        assert!(!constant_time_eq("secret", "secrets"));
+        assert!(!constant_time_eq("secret", "secreT"));
+        assert!(!constant_time_eq("", "secret"));
+    }
+}
@@ -0,0 +1,111 @@
+use serde_json::{json, Value};
+
+use super::definitions;
+
+fn bytes(value: &Value) -> Vec<u8> {
+    value
+        .as_array()
+        .map(|bytes| {
+            bytes
+                .iter()
+                .filter_map(|byte| byte.as_u64())
+                .map(|byte| byte as u8)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// ArduPilot sends the git hash as text and PX4 as binary
+fn custom_version(value: &Value) -> String {
+    let bytes = bytes(value);
+    if bytes.iter().all(|byte| byte.is_ascii_alphanumeric()) {
+        return String::from_utf8_lossy(&bytes).to_string();
+    }
+    hex(&bytes)
+}
+
+// Versions are encoded as major, minor, patch and FIRMWARE_VERSION_TYPE bytes
+fn version(value: &Value) -> Value {
+    let version = match value.as_u64() {
+        Some(version) => version,
+        None => return Value::Null,
+    };
+
+    let version_type = definitions::enumeration("FIRMWARE_VERSION_TYPE")
+        .and_then(|enumeration| enumeration.name_of(version & 0xff))
+        .map(|name| {
+            name.trim_start_matches("FIRMWARE_VERSION_TYPE_")
+                .to_lowercase()
+        });
+    json!({
+        "version": format!("{}.{}.{}", (version >> 24) & 0xff, (version >> 16) & 0xff, (version >> 8) & 0xff),
+        "type": version_type,
+    })
+}
+
+/// Names of the MAV_PROTOCOL_CAPABILITY flags in the bitmask
+pub fn capabilities(bits: u64) -> Vec<String> {
+    definitions::enumeration("MAV_PROTOCOL_CAPABILITY")
+        .map(|enumeration| {
+            enumeration
+                .entries
+                .iter()
+                .filter(|entry| entry.value != 0 && bits & entry.value == entry.value)
+                .map(|entry| entry.name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Readable description of an AUTOPILOT_VERSION message
+pub fn describe(autopilot_version: &Value) -> Value {
+    let bits = autopilot_version["capabilities"]["bits"]
+        .as_u64()
+        .or_else(|| autopilot_version["capabilities"].as_u64())
+        .unwrap_or(0);
+
+    json!({
+        "capabilities": capabilities(bits),
+        "capabilities_bitmask": bits,
+        "flight_sw_version": version(&autopilot_version["flight_sw_version"]),
+        "middleware_sw_version": version(&autopilot_version["middleware_sw_version"]),
+        "os_sw_version": version(&autopilot_version["os_sw_version"]),
+        "board_version": autopilot_version["board_version"],
+        "flight_custom_version": custom_version(&autopilot_version["flight_custom_version"]),
+        "vendor_id": autopilot_version["vendor_id"],
+        "product_id": autopilot_version["product_id"],
+        "uid": autopilot_version["uid"],
+        "uid2": hex(&bytes(&autopilot_version["uid2"])),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autopilot_version() {
+        let description = describe(&json!({
+            "capabilities": {"bits": 0b1100},
+            "flight_sw_version": 0x040100ff_u32,
+            "flight_custom_version": [54, 55, 97, 98, 99, 100, 101, 102],
+            "uid2": [1, 171],
+        }));
+
+        assert_eq!(
+            description["capabilities"],
+            json!([
+                "MAV_PROTOCOL_CAPABILITY_MISSION_INT",
+                "MAV_PROTOCOL_CAPABILITY_COMMAND_INT"
+            ])
+        );
+        assert_eq!(description["flight_sw_version"]["version"], "4.1.0");
+        assert_eq!(description["flight_sw_version"]["type"], "official");
+        assert_eq!(description["flight_custom_version"], "67abcdef");
+        assert_eq!(description["uid2"], "01ab");
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+
+use super::output;
+
+// Cells not used have UINT16_MAX, and UINT16_MAX - 1 when the voltage continues in the next cell
+const CELL_UNUSED: u64 = u16::MAX as u64;
+
+lazy_static! {
+    // Last BATTERY_STATUS of each battery by system, component and battery id, the message store
+    // only keeps the last message of each component
+    static ref INSTANCES: Mutex<BTreeMap<(u8, u8, u8), Value>> = Mutex::new(BTreeMap::new());
+}
+
+/// Keep the BATTERY_STATUS of each battery
+pub fn update(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    if let mavlink::ardupilotmega::MavMessage::common(
+        mavlink::common::MavMessage::BATTERY_STATUS(data),
+    ) = message
+    {
+        INSTANCES.lock().unwrap().insert(
+            (header.system_id, header.component_id, data.id),
+            output::to_value(message),
+        );
+    }
+}
+
+// Last BATTERY_STATUS of each battery of the vehicle, with the component that reported it
+fn instances(system_id: u8) -> Vec<(u8, Value)> {
+    INSTANCES
+        .lock()
+        .unwrap()
+        .range((system_id, 0, 0)..=(system_id, u8::MAX, u8::MAX))
+        .map(|((_, component_id, _), battery_status)| (*component_id, battery_status.clone()))
+        .collect()
+}
+
+fn known(value: &Value, scale: f64) -> Value {
+    match value.as_f64() {
+        Some(value) if value >= 0.0 => json!(value * scale),
+        _ => Value::Null,
+    }
+}
+
+// Voltage of the battery in volts, the sum of the cells
+fn voltage(battery_status: &Value) -> Option<f64> {
+    let cells: Vec<u64> = ["voltages", "voltages_ext"]
+        .iter()
+        .filter_map(|field| battery_status[field].as_array())
+        .flatten()
+        .filter_map(Value::as_u64)
+        .filter(|cell| *cell != CELL_UNUSED && *cell != 0)
+        .collect();
+
+    if cells.is_empty() {
+        return None;
+    }
+    Some(cells.iter().sum::<u64>() as f64 / 1e3)
+}
+
+fn cell_voltages(battery_status: &Value) -> Vec<f64> {
+    battery_status["voltages"]
+        .as_array()
+        .map(|cells| {
+            cells
+                .iter()
+                .filter_map(Value::as_u64)
+                .filter(|cell| *cell < CELL_UNUSED - 1 && *cell != 0)
+                .map(|cell| cell as f64 / 1e3)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Battery entry from BATTERY_STATUS, with the consumed energy estimated from the charge if not reported
+pub fn battery(component_id: u8, battery_status: &Value) -> Value {
+    let voltage = voltage(battery_status);
+    let current_consumed = battery_status["current_consumed"]
+        .as_f64()
+        .filter(|consumed| *consumed >= 0.0);
+    let energy_consumed = battery_status["energy_consumed"]
+        .as_f64()
+        .filter(|energy| *energy >= 0.0)
+        // hJ to Wh
+        .map(|energy| energy * 100.0 / 3600.0)
+        .or_else(|| Some(current_consumed? * voltage? / 1e3));
+    let temperature = battery_status["temperature"]
+        .as_i64()
+        .filter(|temperature| *temperature != i16::MAX as i64)
+        .map(|temperature| temperature as f64 / 100.0);
+
+    json!({
+        "id": battery_status["id"],
+        "component_id": component_id,
+        "function": battery_status["battery_function"]["type"],
+        "type": battery_status["mavtype"]["type"],
+        "voltage": voltage,
+        "cell_voltages": cell_voltages(battery_status),
+        "current": known(&battery_status["current_battery"], 0.01),
+        "remaining": known(&battery_status["battery_remaining"], 1.0),
+        "current_consumed": current_consumed,
+        "energy_consumed": energy_consumed,
+        "temperature": temperature,
+        "time_remaining": battery_status["time_remaining"].as_i64().filter(|time| *time > 0),
+        "charge_state": battery_status["charge_state"]["type"],
+    })
+}
+
+/// Batteries of all components of a vehicle, and the main battery from SYS_STATUS
+pub fn summary(system_id: u8, components: &Value, component_id: u8) -> Value {
+    summarize(&instances(system_id), components, component_id)
+}
+
+fn summarize(instances: &[(u8, Value)], components: &Value, component_id: u8) -> Value {
+    let mut batteries: Vec<Value> = instances
+        .iter()
+        .map(|(id, battery_status)| battery(*id, battery_status))
+        .collect();
+    batteries.sort_by_key(|battery| (battery["component_id"].as_u64(), battery["id"].as_u64()));
+
+    let sys_status = &components[component_id.to_string()]["messages"]["SYS_STATUS"]["message"];
+    let system = if sys_status.is_object() {
+        json!({
+            "voltage": sys_status["voltage_battery"].as_f64().map(|voltage| voltage / 1e3),
+            "current": known(&sys_status["current_battery"], 0.01),
+            "remaining": known(&sys_status["battery_remaining"], 1.0),
+        })
+    } else {
+        Value::Null
+    };
+
+    json!({
+        "batteries": batteries,
+        "system": system,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery_status() {
+        let mut voltages = vec![4200; 4];
+        voltages.extend(vec![u16::MAX; 6]);
+        let battery = battery(
+            1,
+            &json!({
+                "id": 1,
+                "voltages": voltages,
+                "current_battery": 1500,
+                "battery_remaining": 75,
+                "current_consumed": 1000,
+                "energy_consumed": -1,
+                "temperature": i16::MAX,
+            }),
+        );
+
+        assert_eq!(battery["voltage"], 16.8);
+        assert_eq!(battery["cell_voltages"].as_array().unwrap().len(), 4);
+        assert_eq!(battery["current"], 15.0);
+        assert_eq!(battery["energy_consumed"], 16.8);
+        assert_eq!(battery["temperature"], Value::Null);
+    }
+
+    #[test]
+    fn batteries_by_id() {
+        let header = mavlink::MavHeader {
+            system_id: 42,
+            component_id: 1,
+            sequence: 0,
+        };
+        for id in [1, 0, 1] {
+            update(
+                &header,
+                &mavlink::ardupilotmega::MavMessage::common(
+                    mavlink::common::MavMessage::BATTERY_STATUS(
+                        mavlink::common::BATTERY_STATUS_DATA {
+                            id,
+                            ..Default::default()
+                        },
+                    ),
+                ),
+            );
+        }
+
+        let components = json!({"1": {"messages": {"SYS_STATUS": {"message": {
+            "voltage_battery": 12600,
+            "current_battery": -1,
+            "battery_remaining": 50,
+        }}}}});
+        let summary = summary(42, &components, 1);
+        let ids: Vec<u64> = summary["batteries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|battery| battery["id"].as_u64())
+            .collect();
+        assert_eq!(ids, [0, 1]);
+        assert_eq!(summary["system"]["voltage"], 12.6);
+        assert!(summary["system"]["current"].is_null());
+        assert!(summarize(&[], &json!({}), 1)["system"].is_null());
+    }
+}
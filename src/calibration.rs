@@ -0,0 +1,43 @@
+use regex::Regex;
+
+/// Messages reporting the calibration progress, PX4 reports it with STATUSTEXT prefixed by [cal]
+pub const PROGRESS_MESSAGES: &str = "^(COMMAND_ACK|STATUSTEXT|MAG_CAL_PROGRESS|MAG_CAL_REPORT)$";
+
+pub const SENSORS: &[&str] = &["gyro", "accelerometer", "level", "compass", "barometer"];
+
+pub fn progress_filter() -> Regex {
+    Regex::new(PROGRESS_MESSAGES).unwrap()
+}
+
+/// MAV_CMD_PREFLIGHT_CALIBRATION parameters to calibrate the sensor
+pub fn params(sensor: &str) -> Option<[f32; 7]> {
+    let mut params = [0.0; 7];
+    match sensor {
+        "gyro" => params[0] = 1.0,
+        "compass" => params[1] = 1.0,
+        "barometer" => params[2] = 1.0,
+        // Full calibration, with the vehicle on each side when requested
+        "accelerometer" => params[4] = 1.0,
+        // Board level, with the vehicle leveled
+        "level" => params[4] = 2.0,
+        _ => return None,
+    }
+    Some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_params() {
+        assert_eq!(params("gyro"), Some([1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+        assert_eq!(params("level").unwrap()[4], 2.0);
+        assert!(SENSORS.iter().all(|sensor| params(sensor).is_some()));
+        assert_eq!(params("radio"), None);
+
+        let filter = progress_filter();
+        assert!(filter.is_match("MAG_CAL_PROGRESS"));
+        assert!(!filter.is_match("HEARTBEAT"));
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::MavMessage as CommonMessage;
+use mavlink::error::MessageReadError;
+
+use super::cli;
+use super::fake;
+use super::mavlink_vehicle;
+use super::network;
+use super::serial_detection;
+
+// Exit status of each failed check, zero when everything is fine
+const EXIT_CONNECTION: i32 = 2;
+const EXIT_SERVER: i32 = 3;
+const EXIT_HEARTBEAT: i32 = 4;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Check that the connection string is valid, without connecting
+pub fn validate_connection_string(connection_string: &str) -> Result<(), String> {
+    if connection_string == "auto" {
+        return Ok(());
+    }
+
+    let (kind, address) = connection_string.split_once(':').ok_or_else(|| {
+        format!("Invalid connection string: {connection_string}, expected TYPE:ADDRESS")
+    })?;
+    match kind {
+        "tcpin" | "tcpout" | "udpin" | "udpout" | "udpbcast" | "udpmcast" => {
+            network::socket_address(address)
+                .map(drop)
+                .map_err(|error| error.to_string())
+        }
+        "serial" => match address.rsplit_once(':') {
+            Some((port, baudrate)) if !port.is_empty() && baudrate.parse::<u32>().is_ok() => Ok(()),
+            _ => Err(format!(
+                "Invalid serial connection: {address}, expected PORT:BAUDRATE"
+            )),
+        },
+        "file" => Ok(()),
+        "fake" => fake::connect(address)
+            .map(drop)
+            .map_err(|error| error.to_string()),
+        _ => Err(format!("Unknown connection type: {kind}")),
+    }
+}
+
+enum Server {
+    // The address is free, the server is able to start
+    Available,
+    // Another instance is serving the address, like when checking a running container
+    Running(Option<SocketAddr>),
+}
+
+#[cfg(unix)]
+fn check_unix_server(path: &str) -> Result<Server, String> {
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Ok(Server::Running(None));
+    }
+    // Sockets left by previous runs are replaced by the server
+    Ok(Server::Available)
+}
+
+#[cfg(not(unix))]
+fn check_unix_server(_path: &str) -> Result<Server, String> {
+    Err("Unix domain sockets are not supported in this platform.".to_string())
+}
+
+fn check_server(address: &str) -> Result<Server, String> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        return check_unix_server(path);
+    }
+
+    match network::tcp_listener(address) {
+        Ok(_) => Ok(Server::Available),
+        Err(error) if error.kind() == ErrorKind::AddrInUse => {
+            let address = local_address(network::socket_address(address).unwrap());
+            TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+                .map(|_| Server::Running(Some(address)))
+                .map_err(|_| format!("{address} is used by another application"))
+        }
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+// Servers bound to all interfaces are reached by the loopback address
+fn local_address(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, address.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, address.port()).into(),
+        _ => address,
+    }
+}
+
+// Wait for a HEARTBEAT in a new connection with the vehicle
+fn heartbeat_from_vehicle(timeout: Duration) -> Result<(), String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let version = match cli::mavlink_version() {
+            1 => mavlink::MavlinkVersion::V1,
+            _ => mavlink::MavlinkVersion::V2,
+        };
+        let connection_string = serial_detection::resolve(&cli::mavlink_connection_string());
+        let connection = match mavlink_vehicle::connect::<MavMessage>(&connection_string, version) {
+            Ok(connection) => connection,
+            Err(error) => {
+                let _ = sender.send(Err(format!("Failed to connect: {error}")));
+                return;
+            }
+        };
+        loop {
+            let result = match connection.recv() {
+                Ok((_, MavMessage::common(CommonMessage::HEARTBEAT(_)))) => Ok(()),
+                Err(MessageReadError::Io(error))
+                    if !matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) =>
+                {
+                    Err(format!("Connection failed: {error}"))
+                }
+                // Other messages and invalid frames
+                _ => continue,
+            };
+            let _ = sender.send(result);
+            return;
+        }
+    });
+
+    receiver
+        .recv_timeout(timeout)
+        .unwrap_or_else(|_| Err(format!("No HEARTBEAT in {}ms", timeout.as_millis())))
+}
+
+fn status_of(address: SocketAddr) -> Result<serde_json::Value, String> {
+    let mut stream =
+        TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(|error| error.to_string())?;
+    stream
+        .set_read_timeout(Some(CONNECT_TIMEOUT))
+        .map_err(|error| error.to_string())?;
+
+    let authorization = cli::api_token()
+        .map(|token| format!("Authorization: Bearer {token}\r\n"))
+        .unwrap_or_default();
+    let request = format!(
+        "GET {}/status HTTP/1.0\r\nHost: {address}\r\n{authorization}Connection: close\r\n\r\n",
+        cli::url_prefix()
+    );
+    let mut response = String::new();
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|_| stream.read_to_string(&mut response))
+        .map_err(|error| error.to_string())?;
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| "Invalid response of the running server".to_string())?;
+    if !head.starts_with("HTTP/1.1 200") && !head.starts_with("HTTP/1.0 200") {
+        return Err(format!(
+            "Running server answered: {}",
+            head.lines().next().unwrap_or_default()
+        ));
+    }
+    serde_json::from_str(body).map_err(|error| error.to_string())
+}
+
+// Wait for an online vehicle in the running server, its HEARTBEATs are already received
+fn heartbeat_from_server(address: Option<SocketAddr>, timeout: Duration) -> Result<(), String> {
+    let address = match address {
+        Some(address) if cli::tls_files().is_none() => address,
+        _ => {
+            return Err(
+                "HEARTBEATs of a running server are only checked over plain HTTP".to_string(),
+            )
+        }
+    };
+
+    let start = Instant::now();
+    loop {
+        let status = status_of(address)?;
+        let is_online = status["vehicles"].as_array().map_or(false, |vehicles| {
+            vehicles.iter().any(|vehicle| vehicle["online"] == true)
+        });
+        if is_online {
+            return Ok(());
+        }
+        if start.elapsed() > timeout {
+            return Err(format!("No HEARTBEAT in {}ms", timeout.as_millis()));
+        }
+        std::thread::sleep(STATUS_INTERVAL);
+    }
+}
+
+/// Check the configuration and the server addresses, and optionally wait for a HEARTBEAT,
+/// returning the exit status
+pub fn run() -> i32 {
+    let connection_string = cli::mavlink_connection_string();
+    if let Err(error) = validate_connection_string(&connection_string) {
+        eprintln!("Connection: {error}");
+        return EXIT_CONNECTION;
+    }
+    println!("Connection: {connection_string}");
+
+    let mut running = None;
+    for address in cli::server_addresses() {
+        match check_server(&address) {
+            Ok(Server::Available) => println!("Server: {address} is available"),
+            Ok(Server::Running(local)) => {
+                println!("Server: {address} is served by a running instance");
+                // TCP addresses are preferred to check the HEARTBEATs
+                running = Some(running.flatten().or(local));
+            }
+            Err(error) => {
+                eprintln!("Server: {address}: {error}");
+                return EXIT_SERVER;
+            }
+        }
+    }
+
+    if let Some(timeout) = cli::check_heartbeat() {
+        let result = match running {
+            Some(address) => heartbeat_from_server(address, timeout),
+            None => heartbeat_from_vehicle(timeout),
+        };
+        if let Err(error) = result {
+            eprintln!("Heartbeat: {error}");
+            return EXIT_HEARTBEAT;
+        }
+        println!("Heartbeat: received");
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_strings() {
+        for valid in [
+            "auto",
+            "udpin:0.0.0.0:14550",
+            "tcpout:127.0.0.1:5760",
+            "udpmcast:239.255.145.50:14550",
+            "serial:/dev/ttyACM0:115200",
+            "serial:COM3:57600",
+            "fake:50",
+        ] {
+            assert_eq!(validate_connection_string(valid), Ok(()), "{valid}");
+        }
+        for invalid in [
+            "udpin:0.0.0.0",
+            "serial:/dev/ttyACM0",
+            "fake:fast",
+            "ftp:0.0.0.0:21",
+            "14550",
+        ] {
+            assert!(validate_connection_string(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(
+            local_address("0.0.0.0:8088".parse().unwrap()),
+            "127.0.0.1:8088".parse().unwrap()
+        );
+    }
+}
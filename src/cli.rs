@@ -1,9 +1,14 @@
+use derivative::Derivative;
 use lazy_static::lazy_static;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 struct Manager<'a> {
     clap_matches: clap::ArgMatches<'a>,
+    configuration: RwLock<toml::value::Table>,
+    #[derivative(Debug = "ignore")]
+    reload_callbacks: Mutex<Vec<Box<dyn Fn() + Send + Sync>>>,
 }
 
 lazy_static! {
@@ -12,10 +17,137 @@ lazy_static! {
 
 impl Manager<'_> {
     fn new() -> Self {
+        let clap_matches = get_clap_matches();
+        let configuration = match clap_matches.value_of("config") {
+            Some(path) => load_configuration(path).unwrap_or_else(|error| panic!("{error}")),
+            None => Default::default(),
+        };
+
         Self {
-            clap_matches: get_clap_matches(),
+            clap_matches,
+            configuration: RwLock::new(configuration),
+            reload_callbacks: Default::default(),
+        }
+    }
+}
+
+fn load_configuration(path: &str) -> Result<toml::value::Table, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|error| format!("Failed to read configuration file {path}: {error}"))?;
+    toml::from_str(&content)
+        .map_err(|error| format!("Failed to parse configuration file {path}: {error}"))
+}
+
+// Configuration file keys are the same as the long command line options
+fn configuration_value(name: &str) -> Option<toml::Value> {
+    MANAGER
+        .as_ref()
+        .configuration
+        .read()
+        .unwrap()
+        .get(&name.replace('_', "-"))
+        .cloned()
+}
+
+fn toml_to_string(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+// Command line arguments have priority over the configuration file, that has priority over default values
+fn value_of(name: &str) -> Option<String> {
+    let matches = &MANAGER.as_ref().clap_matches;
+    if matches.occurrences_of(name) == 0 {
+        if let Some(value) = configuration_value(name) {
+            return Some(toml_to_string(&value));
+        }
+    }
+
+    matches.value_of(name).map(String::from)
+}
+
+fn values_of(name: &str) -> Vec<String> {
+    let matches = &MANAGER.as_ref().clap_matches;
+    if matches.occurrences_of(name) == 0 {
+        match configuration_value(name) {
+            Some(toml::Value::Array(values)) => return values.iter().map(toml_to_string).collect(),
+            Some(value) => return vec![toml_to_string(&value)],
+            None => {}
         }
     }
+
+    matches
+        .values_of(name)
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn is_present(name: &str) -> bool {
+    MANAGER.as_ref().clap_matches.is_present(name)
+        || configuration_value(name).and_then(|value| value.as_bool()) == Some(true)
+}
+
+// Simulator of --sitl, the configuration file also accepts a boolean for ArduPilot
+fn sitl() -> Option<&'static str> {
+    match value_of("sitl").as_deref() {
+        Some("px4") => Some("px4"),
+        Some("ardupilot") | Some("true") => Some("ardupilot"),
+        Some(_) => None,
+        None if is_present("sitl") => Some("ardupilot"),
+        None => None,
+    }
+}
+
+// Defaults of each simulator, the vehicle is system 1 and expects a ground station component
+fn sitl_default(sitl: &str, name: &str) -> Option<&'static str> {
+    match (sitl, name) {
+        ("ardupilot", "connect") => Some("udpin:0.0.0.0:14550"),
+        ("px4", "connect") => Some("udpin:0.0.0.0:14540"),
+        (_, "component_id") => Some("190"),
+        // Without a ground station requesting them, ArduPilot SITL only streams HEARTBEATs
+        ("ardupilot", "stream_rate") => Some("ALL:10"),
+        _ => None,
+    }
+}
+
+// Options set by the command line or configuration file have priority over --sitl
+fn sitl_value_of(name: &str) -> Option<String> {
+    let is_set = MANAGER.as_ref().clap_matches.occurrences_of(name) > 0
+        || configuration_value(name).is_some();
+    match sitl().and_then(|sitl| sitl_default(sitl, name)) {
+        Some(value) if !is_set => Some(value.to_string()),
+        _ => value_of(name),
+    }
+}
+
+// Register a callback to apply configuration changes that do not require a restart
+pub fn on_reload<F: Fn() + Send + Sync + 'static>(callback: F) {
+    MANAGER
+        .as_ref()
+        .reload_callbacks
+        .lock()
+        .unwrap()
+        .push(Box::new(callback));
+}
+
+// Read the configuration file again, the previous configuration is kept if the file is invalid
+pub fn reload() -> Result<(), String> {
+    let path = MANAGER
+        .as_ref()
+        .clap_matches
+        .value_of("config")
+        .ok_or_else(|| "No configuration file in use.".to_string())?;
+
+    let configuration = load_configuration(path)?;
+    *MANAGER.as_ref().configuration.write().unwrap() = configuration;
+
+    for callback in MANAGER.as_ref().reload_callbacks.lock().unwrap().iter() {
+        callback();
+    }
+
+    Ok(())
 }
 
 pub fn init() {
@@ -23,40 +155,292 @@ pub fn init() {
 }
 
 pub fn is_verbose() -> bool {
-    return MANAGER.as_ref().clap_matches.is_present("verbose");
+    return verbosity() > 0;
 }
 
-pub fn mavlink_connection_string() -> &'static str {
-    return MANAGER.as_ref().clap_matches.value_of("connect").unwrap();
+// Number of verbose flags, the configuration file accepts a boolean or the level
+pub fn verbosity() -> u64 {
+    let occurrences = MANAGER.as_ref().clap_matches.occurrences_of("verbose");
+    if occurrences > 0 {
+        return occurrences;
+    }
+
+    match configuration_value("verbose") {
+        Some(toml::Value::Integer(level)) => level.max(0) as u64,
+        Some(toml::Value::Boolean(true)) => 1,
+        _ => 0,
+    }
 }
 
-pub fn server_address() -> &'static str {
-    return MANAGER.as_ref().clap_matches.value_of("server").unwrap();
+pub fn is_log_json() -> bool {
+    return is_present("log_json");
 }
 
-pub fn mavlink_version() -> u8 {
-    return MANAGER
-        .as_ref()
-        .clap_matches
-        .value_of("mavlink")
+pub fn is_enum_values() -> bool {
+    return is_present("enum_values");
+}
+
+pub fn non_finite_policy() -> crate::output::NonFinitePolicy {
+    return value_of("non_finite").unwrap().parse().unwrap_or_default();
+}
+
+pub fn is_si_units() -> bool {
+    return value_of("units").unwrap() == "si";
+}
+
+pub fn key_case() -> crate::output::KeyCase {
+    return value_of("key_case").unwrap().parse().unwrap_or_default();
+}
+
+pub fn is_access_log() -> bool {
+    return is_present("access_log");
+}
+
+pub fn mavlink_connection_string() -> String {
+    return sitl_value_of("connect").unwrap();
+}
+
+pub fn multicast_interface_and_ttl() -> (std::net::Ipv4Addr, u32) {
+    let interface = value_of("multicast_interface")
+        .unwrap()
+        .parse()
+        .expect("Multicast interface should be an IPv4 address.");
+    let ttl = value_of("multicast_ttl")
+        .unwrap()
+        .parse()
+        .expect("Multicast TTL should be a number.");
+    (interface, ttl)
+}
+
+pub fn is_list_ports() -> bool {
+    return is_present("list_ports");
+}
+
+pub fn is_check() -> bool {
+    return is_present("check");
+}
+
+pub fn check_heartbeat() -> Option<std::time::Duration> {
+    return duration_ms_of("check_heartbeat");
+}
+
+pub fn mavlink_outputs() -> Vec<String> {
+    return values_of("out");
+}
+
+pub fn server_addresses() -> Vec<String> {
+    return values_of("server");
+}
+
+// Base path of all routes, with a leading slash and without the trailing one, empty by default
+pub fn url_prefix() -> String {
+    let prefix = value_of("url_prefix").unwrap_or_default();
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        return String::new();
+    }
+    format!("/{prefix}")
+}
+
+pub fn allowed_ips() -> Vec<String> {
+    return values_of("allow_ip");
+}
+
+pub fn cors_origins() -> Vec<String> {
+    return values_of("cors_origin");
+}
+
+pub fn api_token() -> Option<String> {
+    if let Some(token) = value_of("api_token") {
+        return Some(token);
+    }
+
+    value_of("api_token_file").map(|path| {
+        std::fs::read_to_string(path)
+            .expect("Failed to read API token file.")
+            .trim()
+            .to_string()
+    })
+}
+
+pub fn tls_files() -> Option<(String, String)> {
+    Some((value_of("tls_cert")?, value_of("tls_key")?))
+}
+
+pub fn tls_client_ca() -> Option<String> {
+    return value_of("tls_client_ca");
+}
+
+pub fn rate_limit() -> Option<(f64, f64)> {
+    let rate = value_of("rate_limit")?
+        .parse::<f64>()
+        .expect("Rate limit should be a number of requests per second.");
+
+    let burst = value_of("rate_limit_burst")
+        .unwrap()
+        .parse::<f64>()
+        .expect("Rate limit burst should be a number of requests.");
+
+    Some((rate, burst.max(1.0)))
+}
+
+pub fn allowed_messages() -> Vec<String> {
+    return values_of("allow_messages");
+}
+
+pub fn blocked_messages() -> Vec<String> {
+    return values_of("block_messages");
+}
+
+pub fn max_store_rates() -> Vec<String> {
+    return values_of("max_store_rate");
+}
+
+// Optional duration in milliseconds, where zero disables it
+fn duration_ms_of(name: &str) -> Option<std::time::Duration> {
+    let milliseconds = value_of(name)?
+        .parse::<u64>()
+        .unwrap_or_else(|_| panic!("{name} should be a number of milliseconds."));
+    if milliseconds == 0 {
+        return None;
+    }
+    Some(std::time::Duration::from_millis(milliseconds))
+}
+
+pub fn stale_after() -> Option<std::time::Duration> {
+    return duration_ms_of("stale_after");
+}
+
+pub fn message_ttl() -> Option<std::time::Duration> {
+    return duration_ms_of("message_ttl");
+}
+
+pub fn mqtt_url() -> Option<String> {
+    return value_of("mqtt");
+}
+
+pub fn mqtt_qos() -> u8 {
+    return value_of("mqtt_qos").unwrap().parse::<u8>().unwrap();
+}
+
+pub fn json_outputs() -> Vec<String> {
+    return values_of("json_out");
+}
+
+pub fn influx_url() -> Option<String> {
+    return value_of("influx");
+}
+
+pub fn influx_token() -> Option<String> {
+    return value_of("influx_token");
+}
+
+pub fn influx_fields() -> Vec<String> {
+    return values_of("influx_field");
+}
+
+pub fn redis_url() -> Option<String> {
+    return value_of("redis");
+}
+
+pub fn redis_prefix() -> String {
+    return value_of("redis_prefix").unwrap();
+}
+
+pub fn is_mdns() -> bool {
+    return is_present("mdns");
+}
+
+pub fn history_size() -> usize {
+    return value_of("history")
+        .unwrap()
+        .parse::<usize>()
+        .expect("History should be a number of messages.");
+}
+
+pub fn reencoded_frames_size() -> usize {
+    return value_of("reencoded_frames")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Re-encoded frames should be a number of frames.");
+}
+
+// No stream is requested by default, the stream rates of the autopilot are kept
+pub fn stream_rates() -> Vec<String> {
+    // It also keeps the stream rates of the autopilot with --sitl
+    if is_no_stream_request() {
+        return vec![];
+    }
+    let stream_rates = values_of("stream_rate");
+    match sitl().and_then(|sitl| sitl_default(sitl, "stream_rate")) {
+        Some(stream_rate) if stream_rates.is_empty() => vec![stream_rate.to_string()],
+        _ => stream_rates,
+    }
+}
+
+pub fn is_no_stream_request() -> bool {
+    return is_present("no_stream_request");
+}
+
+pub fn heartbeat_timeout() -> std::time::Duration {
+    let milliseconds = value_of("heartbeat_timeout")
+        .unwrap()
+        .parse::<u64>()
+        .expect("Heartbeat timeout should be a number of milliseconds.");
+    std::time::Duration::from_millis(milliseconds)
+}
+
+// The configuration file also accepts a table of names by system id, E.g: [vehicle-name] 1 = "boat"
+pub fn vehicle_names() -> Vec<String> {
+    if MANAGER.as_ref().clap_matches.occurrences_of("vehicle_name") == 0 {
+        if let Some(toml::Value::Table(names)) = configuration_value("vehicle_name") {
+            return names
+                .iter()
+                .map(|(system_id, name)| format!("{system_id}:{}", toml_to_string(name)))
+                .collect();
+        }
+    }
+    return values_of("vehicle_name");
+}
+
+pub fn is_vehicle_events() -> bool {
+    return is_present("vehicle_events");
+}
+
+pub fn timesync_interval() -> Option<std::time::Duration> {
+    return duration_ms_of("timesync_interval");
+}
+
+pub fn is_utc_time() -> bool {
+    return is_present("utc_time");
+}
+
+pub fn command_retries() -> u8 {
+    return value_of("command_retries")
         .unwrap()
         .parse::<u8>()
-        .unwrap();
+        .expect("Command retries should be a number between 0-255.");
+}
+
+pub fn is_strict_messages() -> bool {
+    return is_present("strict_messages");
+}
+
+pub fn is_motor_test_allowed() -> bool {
+    return is_present("allow_motor_test");
+}
+
+pub fn mavlink_version() -> u8 {
+    return value_of("mavlink").unwrap().parse::<u8>().unwrap();
 }
 
 pub fn mavlink_system_and_component_id() -> (u8, u8) {
-    let system_id = MANAGER
-        .as_ref()
-        .clap_matches
-        .value_of("system_id")
+    let system_id = value_of("system_id")
         .unwrap()
         .parse::<u8>()
         .expect("System ID should be a value between 1-255.");
 
-    let component_id = MANAGER
-        .as_ref()
-        .clap_matches
-        .value_of("component_id")
+    let component_id = sitl_value_of("component_id")
         .unwrap()
         .parse::<u8>()
         .expect("Component ID should be a value between 1-255.");
@@ -76,24 +460,351 @@ fn get_clap_matches<'a>() -> clap::ArgMatches<'a> {
         .version(version.as_str())
         .about("MAVLink to REST API!")
         .author(env!("CARGO_PKG_AUTHORS"))
+        .arg(
+            clap::Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Sets a TOML configuration file, keys are the long options, command line arguments have priority over it")
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::with_name("connect")
                 .short("c")
                 .long("connect")
                 .value_name("TYPE:<IP/SERIAL>:<PORT/BAUDRATE>")
-                .help("Sets the mavlink connection string")
+                .help("Sets the mavlink connection string, 'auto' connects to the first serial port with an autopilot, 'fake:<RATE>' generates messages at RATE Hz")
                 .takes_value(true)
                 .default_value("udpin:0.0.0.0:14550"),
         )
+        .arg(
+            clap::Arg::with_name("sitl")
+                .long("sitl")
+                .value_name("AUTOPILOT")
+                .help("Uses the defaults of the ArduPilot or PX4 simulator: the connection, component ID and data streams")
+                .takes_value(true)
+                .min_values(0)
+                .possible_values(&["ardupilot", "px4"]),
+        )
+        .arg(
+            clap::Arg::with_name("multicast_interface")
+                .long("multicast-interface")
+                .value_name("IP")
+                .help("Sets the IPv4 address of the interface used by udpmcast:GROUP:PORT connections")
+                .takes_value(true)
+                .default_value("0.0.0.0"),
+        )
+        .arg(
+            clap::Arg::with_name("multicast_ttl")
+                .long("multicast-ttl")
+                .value_name("TTL")
+                .help("Sets the time to live of messages sent by udpmcast:GROUP:PORT connections")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            clap::Arg::with_name("list_ports")
+                .long("list-ports")
+                .help("Lists the available serial ports and exits")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("check")
+                .long("check")
+                .help("Checks the connection string and the server addresses and exits, with a non-zero status on failures")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("check_heartbeat")
+                .long("check-heartbeat")
+                .value_name("MILLISECONDS")
+                .help("With --check, also waits for a HEARTBEAT from the vehicle during this time")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("out")
+                .long("out")
+                .value_name("tcpin:IP:PORT")
+                .help("Serves the MAVLink stream to other clients, like ground stations, can be used multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
         .arg(
             clap::Arg::with_name("server")
                 .short("s")
                 .long("server")
                 .value_name("IP:PORT")
-                .help("Sets the IP and port that the rest server will be provided")
+                .help("Sets the IP and port that the rest server will be provided, or unix:PATH for a unix domain socket, can be used multiple times")
                 .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
                 .default_value("0.0.0.0:8088"),
         )
+        .arg(
+            clap::Arg::with_name("url_prefix")
+                .long("url-prefix")
+                .value_name("PATH")
+                .help("Serves all routes under a base path, for reverse proxies, E.g: /mavlink2rest")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("cors_origin")
+                .long("cors-origin")
+                .value_name("ORIGIN")
+                .help("Sets an origin allowed by CORS, can be used multiple times, '*' allows any origin [default: *]")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("allow_ip")
+                .long("allow-ip")
+                .value_name("CIDR")
+                .help("Only accepts requests from clients in the network, can be used multiple times, E.g: 192.168.2.0/24")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("tls_cert")
+                .long("tls-cert")
+                .value_name("FILE")
+                .help("Sets the PEM certificate chain used to serve the REST API over HTTPS")
+                .takes_value(true)
+                .requires("tls_key"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_key")
+                .long("tls-key")
+                .value_name("FILE")
+                .help("Sets the PEM private key used to serve the REST API over HTTPS")
+                .takes_value(true)
+                .requires("tls_cert"),
+        )
+        .arg(
+            clap::Arg::with_name("tls_client_ca")
+                .long("tls-client-ca")
+                .value_name("FILE")
+                .help("Requires client certificates signed by the PEM certificate authorities (mutual TLS)")
+                .takes_value(true)
+                .requires("tls_cert"),
+        )
+        .arg(
+            clap::Arg::with_name("api_token")
+                .long("api-token")
+                .value_name("TOKEN")
+                .help("Requires 'Authorization: Bearer <TOKEN>' header (or 'token' query parameter) for all routes")
+                .takes_value(true)
+                .conflicts_with("api_token_file"),
+        )
+        .arg(
+            clap::Arg::with_name("api_token_file")
+                .long("api-token-file")
+                .value_name("FILE")
+                .help("Same as --api-token, but reads the token from a file")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("rate_limit")
+                .long("rate-limit")
+                .value_name("REQUESTS_PER_SECOND")
+                .help("Limits the number of requests per second accepted from each client IP")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("rate_limit_burst")
+                .long("rate-limit-burst")
+                .value_name("REQUESTS")
+                .help("Sets the number of requests a client can do in a burst before being limited")
+                .takes_value(true)
+                .default_value("10"),
+        )
+        .arg(
+            clap::Arg::with_name("allow_messages")
+                .long("allow-messages")
+                .value_name("NAMES")
+                .help("Only process messages matching the comma-separated names or regexes, E.g: 'HEARTBEAT,ATTITUDE,GPS_.*'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("block_messages")
+                .long("block-messages")
+                .value_name("NAMES")
+                .help("Drop messages matching the comma-separated names or regexes, E.g: 'RAW_IMU,SCALED_IMU.*'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("max_store_rate")
+                .long("max-store-rate")
+                .value_name("NAME:FREQUENCY")
+                .help("Limits how often a message type is stored, by name for NAMED_VALUE and DEBUG messages, can be used multiple times, E.g: 'ATTITUDE:10'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("stale_after")
+                .long("stale-after")
+                .value_name("MILLISECONDS")
+                .help("Marks messages as stale when not updated during this time, 0 disables it")
+                .takes_value(true)
+                .default_value("5000"),
+        )
+        .arg(
+            clap::Arg::with_name("message_ttl")
+                .long("message-ttl")
+                .value_name("MILLISECONDS")
+                .help("Removes messages not updated during this time, 0 disables it")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("history")
+                .long("history")
+                .value_name("SIZE")
+                .help("Keeps the last SIZE messages of each message type per component, used for tracks and exports, 0 disables it")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("reencoded_frames")
+                .long("reencoded-frames")
+                .value_name("SIZE")
+                .help("Keeps the last SIZE frames of each message type re-encoded from the parsed messages for debugging, 0 disables it")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("mqtt")
+                .long("mqtt")
+                .value_name("mqtt://BROKER:PORT/PREFIX")
+                .help("Publishes each received message as JSON to the MQTT topic PREFIX/<SYSTEM_ID>/<COMPONENT_ID>/<MESSAGE_NAME>")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("mqtt_qos")
+                .long("mqtt-qos")
+                .value_name("QOS")
+                .help("Sets the quality of service used to publish MQTT messages")
+                .takes_value(true)
+                .possible_values(&["0", "1", "2"])
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("redis")
+                .long("redis")
+                .value_name("redis://HOST:PORT")
+                .help("Publishes each received message to Redis and keeps the latest value under a key mirroring the REST path")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("redis_prefix")
+                .long("redis-prefix")
+                .value_name("PREFIX")
+                .help("Sets the prefix of Redis keys and channels, E.g: PREFIX/vehicles/1/components/1/messages/ATTITUDE")
+                .takes_value(true)
+                .default_value("mavlink"),
+        )
+        .arg(
+            clap::Arg::with_name("json_out")
+                .long("json-out")
+                .value_name("udp:IP:PORT")
+                .help("Sends each received message as a JSON datagram to the address, can be used multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("influx")
+                .long("influx")
+                .value_name("URL")
+                .help("Writes the received messages as InfluxDB line protocol, E.g: http://localhost:8086/api/v2/write?org=ORG&bucket=BUCKET or udp:localhost:8089")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("influx_token")
+                .long("influx-token")
+                .value_name("TOKEN")
+                .help("Sets the token of the InfluxDB v2 write API")
+                .takes_value(true)
+                .requires("influx"),
+        )
+        .arg(
+            clap::Arg::with_name("influx_field")
+                .long("influx-field")
+                .value_name("MESSAGE.FIELD")
+                .help("Selects the fields written to InfluxDB, can be used multiple times, all by default, E.g: 'ATTITUDE.roll' or 'GPS_RAW_INT.*'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("influx"),
+        )
+        .arg(
+            clap::Arg::with_name("mdns")
+                .long("mdns")
+                .help("Advertises the REST API on the local network as _mavlink2rest._tcp.local via mDNS")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("heartbeat_timeout")
+                .long("heartbeat-timeout")
+                .value_name("MILLISECONDS")
+                .help("Considers a vehicle offline when no HEARTBEAT is received during this time")
+                .takes_value(true)
+                .default_value("3000"),
+        )
+        .arg(
+            clap::Arg::with_name("vehicle_name")
+                .long("vehicle-name")
+                .value_name("ID:NAME")
+                .help("Names a vehicle by system id, the name can be used in REST paths, can be used multiple times, E.g: '1:boat'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("vehicle_events")
+                .long("vehicle-events")
+                .help("Sends events to websocket clients when a vehicle goes online or offline")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("utc_time")
+                .long("utc-time")
+                .help("Adds the UTC time of messages with time_boot_ms or time_usec fields to their status")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("strict_messages")
+                .long("strict-messages")
+                .help("Rejects POSTed messages with missing fields instead of filling them with default values")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("allow_motor_test")
+                .long("allow-motor-test")
+                .help("Allows spinning the motors with /helper/motor_test, for bench tests without propellers")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("command_retries")
+                .long("command-retries")
+                .value_name("COUNT")
+                .help("Sets how many times commands are sent again when no COMMAND_ACK arrives, can be changed by each request with retries")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("timesync_interval")
+                .long("timesync-interval")
+                .value_name("MILLISECONDS")
+                .help("Sets the interval of TIMESYNC requests used to estimate the vehicles clock offset, 0 disables it")
+                .takes_value(true)
+                .default_value("1000"),
+        )
         .arg(
             clap::Arg::with_name("mavlink")
                 .long("mavlink")
@@ -103,6 +814,21 @@ fn get_clap_matches<'a>() -> clap::ArgMatches<'a> {
                 .possible_values(&["1", "2"])
                 .default_value("2"),
         )
+        .arg(
+            clap::Arg::with_name("stream_rate")
+                .long("stream-rate")
+                .value_name("STREAM:RATE")
+                .help("Requests a MAV_DATA_STREAM from the vehicle at the rate in Hz, can be used multiple times, E.g: 'POSITION:10'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            clap::Arg::with_name("no_stream_request")
+                .long("no-stream-request")
+                .help("Do not request data streams from the vehicle, not even the ones of --sitl")
+                .takes_value(false),
+        )
         .arg(
             clap::Arg::with_name("system_id")
                 .long("system-id")
@@ -123,7 +849,53 @@ fn get_clap_matches<'a>() -> clap::ArgMatches<'a> {
             clap::Arg::with_name("verbose")
                 .short("v")
                 .long("verbose")
-                .help("Be verbose")
+                .help("Be verbose, can be used multiple times to increase the level (-v info, -vv debug, -vvv trace)")
+                .takes_value(false)
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::with_name("enum_values")
+                .long("enum-values")
+                .help("Add the numeric value to enums in the output, E.g: {\"type\": \"MAV_TYPE_QUADROTOR\", \"value\": 2}")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("non_finite")
+                .long("non-finite")
+                .value_name("POLICY")
+                .help("Sets how NaN and infinity float values are represented in the output")
+                .takes_value(true)
+                .possible_values(&["null", "string", "omit"])
+                .default_value("null"),
+        )
+        .arg(
+            clap::Arg::with_name("units")
+                .long("units")
+                .value_name("UNITS")
+                .help("Sets the units of the output, si converts scaled integer fields to natural units, E.g: degE7 to degrees and cm/s to m/s")
+                .takes_value(true)
+                .possible_values(&["raw", "si"])
+                .default_value("raw"),
+        )
+        .arg(
+            clap::Arg::with_name("key_case")
+                .long("key-case")
+                .value_name("CASE")
+                .help("Sets the casing of message and field names in the output, E.g: GLOBAL_POSITION_INT.relative_alt is globalPositionInt.relativeAlt in camel and global_position_int.relative_alt in lower")
+                .takes_value(true)
+                .possible_values(&["mavlink", "camel", "lower"])
+                .default_value("mavlink"),
+        )
+        .arg(
+            clap::Arg::with_name("access_log")
+                .long("access-log")
+                .help("Log all HTTP requests with client IP, method, path, status and latency, without the API token of the query")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("log_json")
+                .long("log-json")
+                .help("Output logs as JSON lines")
                 .takes_value(false),
         );
 
@@ -137,8 +909,56 @@ mod tests {
     #[test]
     fn default_arguments() {
         assert!(!is_verbose());
+        assert_eq!(verbosity(), 0);
+        assert!(!is_log_json());
+        assert!(!is_access_log());
+        assert!(!is_enum_values());
+        assert!(!is_mdns());
+        assert!(!is_list_ports());
+        assert!(!is_check());
+        assert!(check_heartbeat().is_none());
+        assert!(!is_no_stream_request());
+        assert!(!is_vehicle_events());
+        assert!(!is_utc_time());
+        assert!(!is_motor_test_allowed());
+        assert!(!is_strict_messages());
+        assert_eq!(heartbeat_timeout(), std::time::Duration::from_secs(3));
+        assert!(vehicle_names().is_empty());
+        assert!(stream_rates().is_empty());
+        assert_eq!(non_finite_policy(), crate::output::NonFinitePolicy::Null);
+        assert!(!is_si_units());
+        assert_eq!(key_case(), crate::output::KeyCase::Mavlink);
         assert_eq!(mavlink_connection_string(), "udpin:0.0.0.0:14550");
-        assert_eq!(server_address(), "0.0.0.0:8088");
+        assert!(sitl().is_none());
+        assert_eq!(sitl_default("px4", "connect"), Some("udpin:0.0.0.0:14540"));
+        assert_eq!(sitl_default("px4", "stream_rate"), None);
+        assert_eq!(sitl_default("ardupilot", "stream_rate"), Some("ALL:10"));
+        assert_eq!(server_addresses(), vec!["0.0.0.0:8088"]);
+        assert_eq!(url_prefix(), "");
+        assert!(mavlink_outputs().is_empty());
         assert_eq!(mavlink_version(), 2);
+        assert_eq!(
+            multicast_interface_and_ttl(),
+            (std::net::Ipv4Addr::UNSPECIFIED, 1)
+        );
+        assert!(cors_origins().is_empty());
+        assert!(allowed_ips().is_empty());
+        assert!(api_token().is_none());
+        assert!(tls_files().is_none());
+        assert!(tls_client_ca().is_none());
+        assert!(rate_limit().is_none());
+        assert_eq!(stale_after(), Some(std::time::Duration::from_secs(5)));
+        assert!(message_ttl().is_none());
+        assert_eq!(timesync_interval(), Some(std::time::Duration::from_secs(1)));
+        assert_eq!(history_size(), 0);
+        assert_eq!(command_retries(), 0);
+        assert_eq!(reencoded_frames_size(), 0);
+        assert!(mqtt_url().is_none());
+        assert_eq!(mqtt_qos(), 0);
+        assert!(redis_url().is_none());
+        assert!(json_outputs().is_empty());
+        assert!(influx_url().is_none());
+        assert!(influx_fields().is_empty());
+        assert_eq!(redis_prefix(), "mavlink");
     }
 }
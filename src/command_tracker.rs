@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{self, MavCmd, MavResult};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::output::{self, OutputOptions};
+
+// Commands without COMMAND_ACK during this time are not waiting anymore
+const ACK_TIMEOUT_S: i64 = 30;
+// Number of commands kept, to be checked by their correlation id
+const MAX_COMMANDS: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    Pending,
+    /// COMMAND_ACK with MAV_RESULT_IN_PROGRESS, the final result is still expected
+    InProgress,
+    Acknowledged,
+    Timeout,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TrackedCommand {
+    /// Correlation id, returned when the command is sent
+    pub id: u64,
+    pub command: String,
+    pub target_system: u8,
+    pub target_component: u8,
+    pub state: State,
+    pub sent: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Last COMMAND_ACK of the command
+    pub ack: Option<Value>,
+}
+
+lazy_static! {
+    static ref COMMANDS: Mutex<VecDeque<TrackedCommand>> = Mutex::new(VecDeque::new());
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Command and target of a COMMAND_LONG or COMMAND_INT
+pub fn command_of(message: &MavMessage) -> Option<(MavCmd, u8, u8)> {
+    match message {
+        MavMessage::common(common::MavMessage::COMMAND_LONG(data)) => {
+            Some((data.command, data.target_system, data.target_component))
+        }
+        MavMessage::common(common::MavMessage::COMMAND_INT(data)) => {
+            Some((data.command, data.target_system, data.target_component))
+        }
+        _ => None,
+    }
+}
+
+fn is_waiting(command: &TrackedCommand) -> bool {
+    matches!(command.state, State::Pending | State::InProgress)
+}
+
+fn expire(commands: &mut VecDeque<TrackedCommand>, now: DateTime<Utc>) {
+    for command in commands.iter_mut() {
+        if is_waiting(command) && now - command.updated > Duration::seconds(ACK_TIMEOUT_S) {
+            command.state = State::Timeout;
+            command.updated = now;
+        }
+    }
+}
+
+/// Track the message if it is a COMMAND_LONG or COMMAND_INT, returning its correlation id
+pub fn track(message: &MavMessage) -> Option<u64> {
+    let (command, target_system, target_component) = command_of(message)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let now = Utc::now();
+
+    let mut commands = COMMANDS.lock().unwrap();
+    commands.push_back(TrackedCommand {
+        id,
+        command: format!("{command:?}"),
+        target_system,
+        target_component,
+        state: State::Pending,
+        sent: now,
+        updated: now,
+        ack: None,
+    });
+    while commands.len() > MAX_COMMANDS {
+        commands.pop_front();
+    }
+    Some(id)
+}
+
+/// Match COMMAND_ACKs with the oldest command waiting for it from the same target
+pub fn on_message(header: &mavlink::MavHeader, message: &MavMessage) {
+    let ack = match message {
+        MavMessage::common(common::MavMessage::COMMAND_ACK(ack)) => ack,
+        _ => return,
+    };
+    let command = format!("{:?}", ack.command);
+    let now = Utc::now();
+
+    let mut commands = COMMANDS.lock().unwrap();
+    expire(&mut commands, now);
+    // Broadcast commands are acknowledged by each component, the first one is used
+    let tracked = commands.iter_mut().find(|tracked| {
+        is_waiting(tracked)
+            && tracked.command == command
+            && (tracked.target_system == header.system_id || tracked.target_system == 0)
+            && (tracked.target_component == header.component_id || tracked.target_component == 0)
+    });
+    if let Some(tracked) = tracked {
+        let mut value = output::to_value(ack);
+        output::apply(&mut value, &OutputOptions::global());
+        tracked.state = match ack.result {
+            MavResult::MAV_RESULT_IN_PROGRESS => State::InProgress,
+            _ => State::Acknowledged,
+        };
+        tracked.updated = now;
+        tracked.ack = Some(value);
+    }
+}
+
+/// Commands waiting for the final COMMAND_ACK
+pub fn pending() -> Vec<TrackedCommand> {
+    let mut commands = COMMANDS.lock().unwrap();
+    expire(&mut commands, Utc::now());
+    commands
+        .iter()
+        .filter(|command| is_waiting(command))
+        .cloned()
+        .collect()
+}
+
+/// Command of the correlation id, while it is kept
+pub fn command(id: u64) -> Option<TrackedCommand> {
+    let mut commands = COMMANDS.lock().unwrap();
+    expire(&mut commands, Utc::now());
+    commands.iter().find(|command| command.id == id).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack(command: MavCmd, result: MavResult) -> MavMessage {
+        MavMessage::common(common::MavMessage::COMMAND_ACK(common::COMMAND_ACK_DATA {
+            command,
+            result,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn acks_correlation() {
+        let header = mavlink::MavHeader {
+            system_id: 42,
+            component_id: 1,
+            sequence: 0,
+        };
+        let arm = crate::commands::command_long(
+            42,
+            1,
+            MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+            [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        let first = track(&arm).unwrap();
+        let second = track(&arm).unwrap();
+        assert_ne!(first, second);
+
+        on_message(
+            &header,
+            &ack(
+                MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+                MavResult::MAV_RESULT_IN_PROGRESS,
+            ),
+        );
+        assert_eq!(command(first).unwrap().state, State::InProgress);
+        assert_eq!(command(second).unwrap().state, State::Pending);
+
+        on_message(
+            &header,
+            &ack(
+                MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+                MavResult::MAV_RESULT_ACCEPTED,
+            ),
+        );
+        assert_eq!(command(first).unwrap().state, State::Acknowledged);
+        assert!(pending().iter().any(|command| command.id == second));
+    }
+}
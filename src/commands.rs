@@ -0,0 +1,248 @@
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{MavCmd, MavResult};
+
+use super::command_tracker;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::waiter;
+
+#[derive(Debug)]
+pub enum CommandError {
+    Send(std::io::Error),
+    /// No COMMAND_ACK after the number of attempts
+    Timeout(u16),
+    /// Only COMMAND_ACKs with MAV_RESULT_IN_PROGRESS were received, without the final result
+    InProgress,
+    /// Only COMMAND_LONG and COMMAND_INT are acknowledged
+    NotCommand,
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Send(error) => write!(f, "Failed to send command: {error}"),
+            CommandError::Timeout(attempts) => {
+                write!(f, "No COMMAND_ACK received after {attempts} attempts")
+            }
+            CommandError::InProgress => {
+                write!(f, "Command in progress, no final COMMAND_ACK received")
+            }
+            CommandError::NotCommand => write!(f, "Not a COMMAND_LONG or COMMAND_INT message"),
+        }
+    }
+}
+
+pub fn command_long(
+    target_system: u8,
+    target_component: u8,
+    command: MavCmd,
+    params: [f32; 7],
+) -> MavMessage {
+    MavMessage::common(mavlink::common::MavMessage::COMMAND_LONG(
+        mavlink::common::COMMAND_LONG_DATA {
+            param1: params[0],
+            param2: params[1],
+            param3: params[2],
+            param4: params[3],
+            param5: params[4],
+            param6: params[5],
+            param7: params[6],
+            command,
+            target_system,
+            target_component,
+            confirmation: 0,
+        },
+    ))
+}
+
+/// MAV_CMD_REQUEST_MESSAGE for the given message id
+pub fn request_message(target_system: u8, target_component: u8, message_id: u32) -> MavMessage {
+    command_long(
+        target_system,
+        target_component,
+        MavCmd::MAV_CMD_REQUEST_MESSAGE,
+        [message_id as f32, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    )
+}
+
+/// COMMAND_INT with the position in x (latitude), y (longitude) and z (altitude)
+pub fn command_int(
+    target_system: u8,
+    target_component: u8,
+    frame: mavlink::common::MavFrame,
+    command: MavCmd,
+    params: [f32; 4],
+    (x, y, z): (i32, i32, f32),
+) -> MavMessage {
+    MavMessage::common(mavlink::common::MavMessage::COMMAND_INT(
+        mavlink::common::COMMAND_INT_DATA {
+            param1: params[0],
+            param2: params[1],
+            param3: params[2],
+            param4: params[3],
+            x,
+            y,
+            z,
+            command,
+            target_system,
+            target_component,
+            frame,
+            current: 0,
+            autocontinue: 0,
+        },
+    ))
+}
+
+/// Send a COMMAND_LONG and block until the target acknowledges it, see [send_message] for the retries
+pub fn send_command(
+    vehicle: &MAVLinkVehicleArcMutex,
+    target_system: u8,
+    target_component: u8,
+    command: MavCmd,
+    params: [f32; 7],
+    timeout: std::time::Duration,
+    retries: u8,
+) -> Result<mavlink::common::COMMAND_ACK_DATA, CommandError> {
+    let message = command_long(target_system, target_component, command, params);
+    send_message(vehicle, None, &message, timeout, retries)
+}
+
+// Each retransmission of a COMMAND_LONG increments its confirmation, so the autopilot knows it is a repetition
+fn with_confirmation(message: &MavMessage, confirmation: u8) -> MavMessage {
+    let mut message = message.clone();
+    if let MavMessage::common(mavlink::common::MavMessage::COMMAND_LONG(data)) = &mut message {
+        data.confirmation = confirmation;
+    }
+    message
+}
+
+// COMMAND_ACKs of the command from the target, any system or component acknowledges broadcasts
+fn ack_subscription(
+    target_system: u8,
+    target_component: u8,
+    command: MavCmd,
+) -> waiter::Subscription {
+    waiter::subscribe_all(move |header, message| match message {
+        MavMessage::common(mavlink::common::MavMessage::COMMAND_ACK(ack)) => {
+            (target_system == 0 || header.system_id == target_system)
+                && (target_component == 0 || header.component_id == target_component)
+                && ack.command == command
+        }
+        _ => false,
+    })
+}
+
+// Wait for the final COMMAND_ACK, each MAV_RESULT_IN_PROGRESS restarts the timeout
+fn final_ack(
+    subscription: &waiter::Subscription,
+    timeout: std::time::Duration,
+) -> Result<Option<mavlink::common::COMMAND_ACK_DATA>, CommandError> {
+    let mut in_progress = false;
+    while let Some((_, message)) = subscription.next(timeout) {
+        if let MavMessage::common(mavlink::common::MavMessage::COMMAND_ACK(ack)) = message {
+            if ack.result != MavResult::MAV_RESULT_IN_PROGRESS {
+                return Ok(Some(ack));
+            }
+            in_progress = true;
+        }
+    }
+    if in_progress {
+        return Err(CommandError::InProgress);
+    }
+    Ok(None)
+}
+
+/// Send a COMMAND_LONG or COMMAND_INT message and block until the target acknowledges it,
+/// sending it again up to `retries` times when no COMMAND_ACK arrives within `timeout`.
+/// The header of this service is used when `header` is None
+pub fn send_message(
+    vehicle: &MAVLinkVehicleArcMutex,
+    header: Option<&mavlink::MavHeader>,
+    message: &MavMessage,
+    timeout: std::time::Duration,
+    retries: u8,
+) -> Result<mavlink::common::COMMAND_ACK_DATA, CommandError> {
+    let (command, target_system, target_component) =
+        command_tracker::command_of(message).ok_or(CommandError::NotCommand)?;
+    command_tracker::track(message);
+    // A single subscription for all attempts, so a late COMMAND_ACK of a previous attempt still counts
+    let subscription = ack_subscription(target_system, target_component, command);
+
+    for attempt in 0..=retries {
+        let message = with_confirmation(message, attempt);
+        let vehicle = vehicle.lock().unwrap();
+        match header {
+            Some(header) => vehicle.send(header, &message),
+            None => vehicle.send_default(&message),
+        }
+        .map_err(CommandError::Send)?;
+        drop(vehicle);
+
+        // Commands in progress are not sent again
+        if let Some(ack) = final_ack(&subscription, timeout)? {
+            return Ok(ack);
+        }
+    }
+    Err(CommandError::Timeout(u16::from(retries) + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmation_counter() {
+        let long = command_long(1, 1, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM, [1.0; 7]);
+        match with_confirmation(&long, 2) {
+            MavMessage::common(mavlink::common::MavMessage::COMMAND_LONG(data)) => {
+                assert_eq!(data.confirmation, 2);
+                assert_eq!(data.param1, 1.0);
+            }
+            _ => panic!("COMMAND_LONG expected"),
+        }
+
+        let int = command_int(
+            1,
+            1,
+            mavlink::common::MavFrame::MAV_FRAME_GLOBAL,
+            MavCmd::MAV_CMD_DO_REPOSITION,
+            [0.0; 4],
+            (0, 0, 0.0),
+        );
+        assert!(matches!(
+            with_confirmation(&int, 1),
+            MavMessage::common(mavlink::common::MavMessage::COMMAND_INT(_))
+        ));
+    }
+
+    #[test]
+    fn waits_for_final_ack() {
+        let header = mavlink::MavHeader {
+            system_id: 213,
+            component_id: 1,
+            sequence: 0,
+        };
+        let ack = |result| {
+            MavMessage::common(mavlink::common::MavMessage::COMMAND_ACK(
+                mavlink::common::COMMAND_ACK_DATA {
+                    command: MavCmd::MAV_CMD_PREFLIGHT_CALIBRATION,
+                    result,
+                    ..Default::default()
+                },
+            ))
+        };
+        let timeout = std::time::Duration::from_millis(20);
+
+        let subscription = ack_subscription(213, 1, MavCmd::MAV_CMD_PREFLIGHT_CALIBRATION);
+        waiter::notify(&header, &ack(MavResult::MAV_RESULT_IN_PROGRESS));
+        waiter::notify(&header, &ack(MavResult::MAV_RESULT_ACCEPTED));
+        let final_result = final_ack(&subscription, timeout).unwrap().unwrap();
+        assert_eq!(final_result.result, MavResult::MAV_RESULT_ACCEPTED);
+        assert!(final_ack(&subscription, timeout).unwrap().is_none());
+
+        waiter::notify(&header, &ack(MavResult::MAV_RESULT_IN_PROGRESS));
+        assert!(matches!(
+            final_ack(&subscription, timeout),
+            Err(CommandError::InProgress)
+        ));
+    }
+}
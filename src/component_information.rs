@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use super::definitions;
+use super::ftp::{self, FtpError};
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+
+#[derive(Debug)]
+pub enum MetadataError {
+    Ftp(FtpError),
+    Unavailable(String),
+}
+
+impl std::fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataError::Ftp(error) => write!(f, "{error}"),
+            MetadataError::Unavailable(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct FtpLocation {
+    // Component of the FTP server, the component that sent the information by default
+    component_id: Option<u8>,
+    path: String,
+}
+
+// Metadata locations are mftp://[;comp=<id>]<path> on the vehicle, or http(s) URLs
+fn ftp_location(uri: &str) -> Option<FtpLocation> {
+    let location = uri.strip_prefix("mftp://")?;
+    let (component_id, path) = match location
+        .strip_prefix("[;comp=")
+        .and_then(|location| location.split_once(']'))
+    {
+        Some((component_id, path)) => (component_id.parse().ok(), path),
+        None => (None, location),
+    };
+    Some(FtpLocation {
+        component_id,
+        path: path.to_string(),
+    })
+}
+
+fn text(value: &Value) -> String {
+    let bytes: Vec<u8> = value
+        .as_array()
+        .map(|bytes| {
+            bytes
+                .iter()
+                .filter_map(|byte| byte.as_u64())
+                .take_while(|byte| *byte != 0)
+                .map(|byte| byte as u8)
+                .collect()
+        })
+        .unwrap_or_default();
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+// Name of a COMP_METADATA_TYPE, E.g: parameter for COMP_METADATA_TYPE_PARAMETER
+fn metadata_type_name(metadata_type: u64) -> String {
+    definitions::enumeration("COMP_METADATA_TYPE")
+        .and_then(|enumeration| enumeration.name_of(metadata_type))
+        .map(|name| {
+            name.trim_start_matches("COMP_METADATA_TYPE_")
+                .to_lowercase()
+        })
+        .unwrap_or_else(|| metadata_type.to_string())
+}
+
+fn download(
+    vehicle: &MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    uri: &str,
+    timeout: Duration,
+) -> Result<Value, MetadataError> {
+    let location = ftp_location(uri).ok_or_else(|| {
+        MetadataError::Unavailable(format!("Metadata is not available with MAVLink FTP: {uri}"))
+    })?;
+    if location.path.ends_with(".xz") {
+        return Err(MetadataError::Unavailable(format!(
+            "Compressed metadata is not supported: {uri}"
+        )));
+    }
+
+    let component_id = location.component_id.unwrap_or(component_id);
+    let content = ftp::Client::new(vehicle, system_id, component_id, timeout)
+        .read_file(&location.path)
+        .map_err(MetadataError::Ftp)?;
+    serde_json::from_slice(&content)
+        .map_err(|error| MetadataError::Unavailable(format!("Invalid metadata {uri}: {error}")))
+}
+
+// Downloaded metadata, or its uri and the reason it is not available
+fn metadata(
+    vehicle: &MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    uri: &str,
+    timeout: Duration,
+) -> Value {
+    match download(vehicle, system_id, component_id, uri, timeout) {
+        Ok(metadata) => metadata,
+        Err(error) => json!({
+            "uri": uri,
+            "error": error.to_string(),
+        }),
+    }
+}
+
+/// General metadata of a COMPONENT_INFORMATION message and the metadata files it references
+pub fn fetch(
+    vehicle: &MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    component_information: &Value,
+    timeout: Duration,
+) -> Result<Value, MetadataError> {
+    let uri = text(&component_information["general_metadata_uri"]);
+    let general = download(vehicle, system_id, component_id, &uri, timeout)?;
+
+    let mut metadata_types = Map::new();
+    if let Some(entries) = general["metadataTypes"].as_array() {
+        for entry in entries {
+            let (metadata_type, uri) = match (entry["type"].as_u64(), entry["uri"].as_str()) {
+                (Some(metadata_type), Some(uri)) => (metadata_type, uri),
+                _ => continue,
+            };
+            metadata_types.insert(
+                metadata_type_name(metadata_type),
+                metadata(vehicle, system_id, component_id, uri, timeout),
+            );
+        }
+    }
+
+    Ok(json!({
+        "general_metadata_uri": uri,
+        "general": general,
+        "metadata": metadata_types,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            ftp_location("mftp://[;comp=100]/camera/metadata.json"),
+            Some(FtpLocation {
+                component_id: Some(100),
+                path: "/camera/metadata.json".into(),
+            })
+        );
+        assert_eq!(
+            ftp_location("mftp:///etc/component_general.json"),
+            Some(FtpLocation {
+                component_id: None,
+                path: "/etc/component_general.json".into(),
+            })
+        );
+        assert_eq!(ftp_location("https://px4.io/component_general.json"), None);
+        assert_eq!(text(&json!([109, 102, 0, 116])), "mf");
+    }
+}
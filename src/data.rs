@@ -1,36 +1,182 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
 
+use actix_web::web::Bytes;
+use arc_swap::ArcSwap;
+use futures::channel::oneshot;
 use lazy_static::lazy_static;
+use log::*;
 use mavlink::{self, Message};
 use serde::{Deserialize, Serialize};
 
+use super::cli;
+use super::metrics;
+use super::output;
+use super::timesync;
+use super::vehicles;
+
+lazy_static! {
+    // Reference for monotonic timestamps
+    static ref START: std::time::Instant = std::time::Instant::now();
+    // Long-polling requests waiting for the next update of message entries
+    static ref UPDATE_WAITERS: Mutex<UpdateWaiters> = Default::default();
+}
+
+#[derive(Default)]
+struct UpdateWaiters {
+    // Waiters of a single message entry, only woken up by its updates
+    entries: HashMap<(u8, u8, String), Vec<oneshot::Sender<()>>>,
+    // Waiters of any system, component or message
+    patterns: Vec<UpdatePattern>,
+}
+
+struct UpdatePattern {
+    system_id: Option<u8>,
+    component_id: Option<u8>,
+    name: Option<String>,
+    sender: oneshot::Sender<()>,
+}
+
+impl UpdatePattern {
+    fn matches(&self, (system_id, component_id, name): &(u8, u8, String)) -> bool {
+        self.system_id.map_or(true, |id| id == *system_id)
+            && self.component_id.map_or(true, |id| id == *component_id)
+            && self.name.as_ref().map_or(true, |entry| entry == name)
+    }
+}
+
+impl UpdateWaiters {
+    fn notify(&mut self, key: &(u8, u8, String)) {
+        for sender in self.entries.remove(key).unwrap_or_default() {
+            let _ = sender.send(());
+        }
+        if self.patterns.is_empty() {
+            return;
+        }
+        for pattern in std::mem::take(&mut self.patterns) {
+            if pattern.matches(key) {
+                let _ = pattern.sender.send(());
+            } else if !pattern.sender.is_canceled() {
+                self.patterns.push(pattern);
+            }
+        }
+    }
+}
+
+fn monotonic_time_us() -> u64 {
+    START.elapsed().as_micros() as u64
+}
+
+// Weight of the newest interval in the moving average
+const FREQUENCY_SMOOTHING: f64 = 0.1;
+// Intervals bigger than this factor of the average interval (and one second) are considered gaps
+const FREQUENCY_GAP_FACTOR: f64 = 5.0;
+
+// Exponential moving average of the interval between messages
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FrequencyEstimator {
+    /// Frequency estimated from the average interval
+    frequency: f32,
+    /// Interval statistics since the last gap
+    interval_min_ms: f32,
+    interval_max_ms: f32,
+    jitter_ms: f32,
+    #[serde(skip)]
+    mean_interval: f64,
+    #[serde(skip)]
+    interval_variance: f64,
+}
+
+impl FrequencyEstimator {
+    /// Add the interval in seconds since the previous message
+    pub fn update(&mut self, interval: f64) {
+        let is_gap = interval > (FREQUENCY_GAP_FACTOR * self.mean_interval).max(1.0);
+        if self.mean_interval <= 0.0 || is_gap {
+            self.mean_interval = interval;
+            self.interval_variance = 0.0;
+            self.interval_min_ms = (interval * 1e3) as f32;
+            self.interval_max_ms = (interval * 1e3) as f32;
+        } else {
+            let delta = interval - self.mean_interval;
+            self.mean_interval += FREQUENCY_SMOOTHING * delta;
+            self.interval_variance = (1.0 - FREQUENCY_SMOOTHING)
+                * (self.interval_variance + FREQUENCY_SMOOTHING * delta * delta);
+            self.interval_min_ms = self.interval_min_ms.min((interval * 1e3) as f32);
+            self.interval_max_ms = self.interval_max_ms.max((interval * 1e3) as f32);
+        }
+
+        self.jitter_ms = (self.interval_variance.sqrt() * 1e3) as f32;
+        self.frequency = if self.mean_interval > 0.0 {
+            (1.0 / self.mean_interval) as f32
+        } else {
+            0.0
+        };
+    }
+}
+
 //TODO: break all this types to a new file
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct Temporal {
-    first_update: chrono::DateTime<chrono::Local>,
-    last_update: chrono::DateTime<chrono::Local>,
+    /// ISO 8601 UTC timestamps
+    first_update: chrono::DateTime<chrono::Utc>,
+    last_update: chrono::DateTime<chrono::Utc>,
+    /// Monotonic time of the last update in microseconds since the service started
+    #[serde(default)]
+    time_last_message_us: u64,
     counter: i64,
-    frequency: f32,
+    #[serde(flatten)]
+    frequency: FrequencyEstimator,
+    /// Time since the last update, filled when the data is requested
+    #[serde(default)]
+    age_ms: i64,
+    /// If the message was not updated after the configured threshold
+    #[serde(default)]
+    stale: bool,
+    /// Vehicle time of the message converted to UTC, with --utc-time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utc_time: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Default for Temporal {
     fn default() -> Self {
         Self {
-            first_update: chrono::Local::now(),
-            last_update: chrono::Local::now(),
-            counter: 1,
-            frequency: 0.0,
+            first_update: chrono::Utc::now(),
+            last_update: chrono::Utc::now(),
+            time_last_message_us: monotonic_time_us(),
+            counter: 0,
+            frequency: FrequencyEstimator::default(),
+            age_ms: 0,
+            stale: false,
+            utc_time: None,
         }
     }
 }
 
 impl Temporal {
     fn update(&mut self) {
-        self.last_update = chrono::Local::now();
+        self.update_at(chrono::Utc::now(), monotonic_time_us());
+    }
+
+    // The wall clock can jump with NTP or manual changes, so intervals use the monotonic time
+    fn update_at(&mut self, now: chrono::DateTime<chrono::Utc>, now_us: u64) {
+        if self.counter > 0 {
+            let interval_us = now_us.saturating_sub(self.time_last_message_us);
+            self.frequency.update(interval_us as f64 / 1e6);
+        }
+
+        self.last_update = now;
+        self.time_last_message_us = now_us;
         self.counter = self.counter.wrapping_add(1);
-        self.frequency =
-            (self.counter as f32) / ((self.last_update - self.first_update).num_seconds() as f32);
+    }
+
+    fn age(&self, now_us: u64) -> std::time::Duration {
+        std::time::Duration::from_micros(now_us.saturating_sub(self.time_last_message_us))
+    }
+
+    fn refresh(&mut self, now_us: u64, stale_after: Option<std::time::Duration>) {
+        let age = self.age(now_us);
+        self.age_ms = age.as_millis() as i64;
+        self.stale = stale_after.map_or(false, |stale_after| age > stale_after);
     }
 }
 
@@ -52,115 +198,687 @@ pub struct MAVLinkMessage<T> {
     pub message: T,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+// Serialized message, done once per message update and shared by the snapshots that have it,
+// it depends on the output configuration so it's cleared when the configuration is reloaded
+#[derive(Debug, Default)]
+struct MessageCache {
+    value: OnceLock<serde_json::Value>,
+    json: OnceLock<Bytes>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
 struct MAVLinkMessageStatus {
     message: mavlink::ardupilotmega::MavMessage,
     status: Status,
+    #[serde(skip)]
+    cache: Arc<MessageCache>,
 }
 
 impl MAVLinkMessageStatus {
     fn update(&mut self, message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
         self.message = message.message.clone();
+        self.cache = Arc::default();
         self.status.update();
+        if cli::is_utc_time() {
+            self.status.time.utc_time =
+                timesync::message_utc_time(message.header.system_id, &message.message);
+        }
+    }
+
+    fn message_value(&self) -> &serde_json::Value {
+        self.cache
+            .value
+            .get_or_init(|| output::to_value(&self.message))
+    }
+
+    fn message_json(&self) -> Bytes {
+        let mut is_hit = true;
+        let json = self.cache.json.get_or_init(|| {
+            is_hit = false;
+            let start = std::time::Instant::now();
+            let json = Bytes::from(serde_json::to_vec_pretty(self.message_value()).unwrap());
+            metrics::record_serialization(start.elapsed());
+            json
+        });
+        metrics::record_serialization_cache(is_hit);
+        json.clone()
+    }
+}
+
+impl Serialize for MAVLinkMessageStatus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("MAVLinkMessageStatus", 2)?;
+        state.serialize_field("message", self.message_value())?;
+        state.serialize_field("status", &self.status)?;
+        state.end()
+    }
+}
+
+// Messages that carry independent signals are stored by their name or index inside the message name
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum MAVLinkMessageEntry {
+    Single(Arc<MAVLinkMessageStatus>),
+    Keyed(BTreeMap<String, Arc<MAVLinkMessageStatus>>),
+}
+
+impl MAVLinkMessageEntry {
+    fn statuses(&self) -> Box<dyn Iterator<Item = &Arc<MAVLinkMessageStatus>> + '_> {
+        match self {
+            MAVLinkMessageEntry::Single(status) => Box::new(std::iter::once(status)),
+            MAVLinkMessageEntry::Keyed(statuses) => Box::new(statuses.values()),
+        }
+    }
+
+    fn statuses_mut(&mut self) -> Box<dyn Iterator<Item = &mut Arc<MAVLinkMessageStatus>> + '_> {
+        match self {
+            MAVLinkMessageEntry::Single(status) => Box::new(std::iter::once(status)),
+            MAVLinkMessageEntry::Keyed(statuses) => Box::new(statuses.values_mut()),
+        }
+    }
+
+    fn clear_caches(&mut self) {
+        for status in self.statuses_mut() {
+            Arc::make_mut(status).cache = Arc::default();
+        }
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&MAVLinkMessageStatus) -> bool) -> bool {
+        match self {
+            MAVLinkMessageEntry::Single(status) => keep(status),
+            MAVLinkMessageEntry::Keyed(statuses) => {
+                statuses.retain(|_, status| keep(status));
+                !statuses.is_empty()
+            }
+        }
+    }
+
+    // Only the refreshed statuses are copied, the ones of other snapshots are shared
+    fn refresh(&mut self, now_us: u64, stale_after: Option<std::time::Duration>) {
+        for status in self.statuses_mut() {
+            Arc::make_mut(status)
+                .status
+                .time
+                .refresh(now_us, stale_after);
+        }
+    }
+}
+
+fn name_of(name: &[u8]) -> String {
+    String::from_utf8_lossy(name)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// Key of the messages stored by signal
+pub fn message_key(message: &mavlink::ardupilotmega::MavMessage) -> Option<String> {
+    use mavlink::ardupilotmega::MavMessage;
+    use mavlink::common::MavMessage as CommonMessage;
+
+    match message {
+        MavMessage::common(CommonMessage::NAMED_VALUE_FLOAT(data)) => Some(name_of(&data.name)),
+        MavMessage::common(CommonMessage::NAMED_VALUE_INT(data)) => Some(name_of(&data.name)),
+        MavMessage::common(CommonMessage::DEBUG_VECT(data)) => Some(name_of(&data.name)),
+        MavMessage::common(CommonMessage::DEBUG(data)) => Some(data.ind.to_string()),
+        _ => None,
     }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct MAVLinkVehicleComponentData {
     id: u8,
-    messages: HashMap<String, MAVLinkMessageStatus>,
+    messages: HashMap<String, MAVLinkMessageEntry>,
 }
 
 impl MAVLinkVehicleComponentData {
     fn update(&mut self, message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
         let message_name = message.message.message_name().to_string();
-        self.messages
-            .entry(message_name)
-            .or_insert(MAVLinkMessageStatus {
+        let new_status = || {
+            Arc::new(MAVLinkMessageStatus {
                 message: message.message.clone(),
                 status: Status::default(),
+                cache: Arc::default(),
             })
-            .update(message);
+        };
+
+        let key = match message_key(&message.message) {
+            Some(key) => key,
+            None => {
+                let entry = self
+                    .messages
+                    .entry(message_name)
+                    .or_insert_with(|| MAVLinkMessageEntry::Single(new_status()));
+                if let MAVLinkMessageEntry::Single(status) = entry {
+                    Arc::make_mut(status).update(message);
+                }
+                return;
+            }
+        };
+
+        let entry = self
+            .messages
+            .entry(message_name)
+            .or_insert_with(|| MAVLinkMessageEntry::Keyed(BTreeMap::new()));
+        if let MAVLinkMessageEntry::Keyed(statuses) = entry {
+            Arc::make_mut(statuses.entry(key).or_insert_with(new_status)).update(message);
+        }
+    }
+
+    fn refresh(&mut self, now_us: u64, stale_after: Option<std::time::Duration>) {
+        for entry in self.messages.values_mut() {
+            entry.refresh(now_us, stale_after);
+        }
     }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct MAVLinkVehicleData {
     id: u8,
-    components: HashMap<u8, MAVLinkVehicleComponentData>,
+    /// Name of the vehicle, from --vehicle-name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    components: HashMap<u8, Arc<MAVLinkVehicleComponentData>>,
 }
 
 impl MAVLinkVehicleData {
     fn update(&mut self, message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
         let component_id = message.header.component_id;
-        self.components
-            .entry(component_id)
-            .or_insert(MAVLinkVehicleComponentData {
+        let component = self.components.entry(component_id).or_insert_with(|| {
+            Arc::new(MAVLinkVehicleComponentData {
                 id: component_id,
                 messages: HashMap::new(),
             })
-            .update(message);
+        });
+        Arc::make_mut(component).update(message);
+    }
+
+    fn component(&self, component_id: &str) -> Option<&MAVLinkVehicleComponentData> {
+        let component_id = component_id.parse::<u8>().ok()?;
+        self.components.get(&component_id).map(Arc::as_ref)
+    }
+
+    fn refresh(&mut self, now_us: u64, stale_after: Option<std::time::Duration>) {
+        for component in self.components.values_mut() {
+            Arc::make_mut(component).refresh(now_us, stale_after);
+        }
     }
 }
 
+// Snapshot of the received messages, each level is shared between snapshots until it changes,
+// so updates only copy the path to the updated message and readers never copy unrelated messages
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct MAVLinkVehiclesData {
-    vehicles: HashMap<u8, MAVLinkVehicleData>,
+    vehicles: HashMap<u8, Arc<MAVLinkVehicleData>>,
+    #[serde(skip)]
+    stale_after: Option<std::time::Duration>,
 }
 
 impl MAVLinkVehiclesData {
-    //TODO: Move message to reference
-    fn update(&mut self, message: MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    fn update(&mut self, message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
         let vehicle_id = message.header.system_id;
-        self.vehicles
-            .entry(vehicle_id)
-            .or_insert(MAVLinkVehicleData {
+        let vehicle = self.vehicles.entry(vehicle_id).or_insert_with(|| {
+            Arc::new(MAVLinkVehicleData {
                 id: vehicle_id,
+                name: None,
                 components: HashMap::new(),
             })
-            .update(&message);
+        });
+        Arc::make_mut(vehicle).update(message);
+    }
+
+    fn vehicle(&self, system_id: &str) -> Option<&MAVLinkVehicleData> {
+        let system_id = system_id.parse::<u8>().ok()?;
+        self.vehicles.get(&system_id).map(Arc::as_ref)
+    }
+
+    fn is_expired(&self, ttl: std::time::Duration) -> bool {
+        let now_us = monotonic_time_us();
+        let mut statuses = self
+            .vehicles
+            .values()
+            .flat_map(|vehicle| vehicle.components.values())
+            .flat_map(|component| component.messages.values())
+            .flat_map(MAVLinkMessageEntry::statuses);
+        statuses.any(|message| message.status.time.age(now_us) > ttl)
     }
 
-    pub fn pointer(&self, path: &str) -> String {
-        if path.is_empty() {
-            return serde_json::to_string_pretty(self).unwrap();
+    // Remove messages without updates for longer than ttl, and vehicles or components left empty
+    fn prune(&mut self, ttl: std::time::Duration) {
+        let now_us = monotonic_time_us();
+        for vehicle in self.vehicles.values_mut() {
+            let vehicle = Arc::make_mut(vehicle);
+            for component in vehicle.components.values_mut() {
+                Arc::make_mut(component).messages.retain(|_, entry| {
+                    entry.retain(|message| message.status.time.age(now_us) <= ttl)
+                });
+            }
+            vehicle
+                .components
+                .retain(|_, component| !component.messages.is_empty());
         }
+        self.vehicles
+            .retain(|_, vehicle| !vehicle.components.is_empty());
+    }
 
-        let path = format!("/{path}");
+    // Serialize the messages again with the current output configuration
+    fn clear_caches(&mut self) {
+        for vehicle in self.vehicles.values_mut() {
+            for component in Arc::make_mut(vehicle).components.values_mut() {
+                for entry in Arc::make_mut(component).messages.values_mut() {
+                    entry.clear_caches();
+                }
+            }
+        }
+    }
+
+    /// Keep only the given message types, and the vehicles and components that have them
+    pub fn retain_types(&mut self, types: &[&str]) {
+        for vehicle in self.vehicles.values_mut() {
+            let vehicle = Arc::make_mut(vehicle);
+            for component in vehicle.components.values_mut() {
+                Arc::make_mut(component)
+                    .messages
+                    .retain(|name, _| types.contains(&name.as_str()));
+            }
+            vehicle
+                .components
+                .retain(|_, component| !component.messages.is_empty());
+        }
+        self.vehicles
+            .retain(|_, vehicle| !vehicle.components.is_empty());
+    }
 
-        dbg!(&path);
+    fn refresh(&mut self, now_us: u64, stale_after: Option<std::time::Duration>) {
+        for vehicle in self.vehicles.values_mut() {
+            Arc::make_mut(vehicle).refresh(now_us, stale_after);
+        }
+    }
 
-        if path == "/vehicles" {
-            return serde_json::to_string_pretty(&self.vehicles).unwrap();
+    /// JSON of the message of a vehicles/ID/components/ID/messages/NAME[/KEY]/message path,
+    /// the same bytes are served until the message is updated
+    pub fn message_json(&self, path: &str) -> Option<Bytes> {
+        let segments: Vec<&str> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let (system_id, component_id, name, key) = match segments.as_slice() {
+            ["vehicles", system_id, "components", component_id, "messages", name, "message"] => {
+                (system_id, component_id, name, None)
+            }
+            ["vehicles", system_id, "components", component_id, "messages", name, key, "message"] => {
+                (system_id, component_id, name, Some(key))
+            }
+            _ => return None,
         };
+        let entry = self
+            .vehicle(system_id)?
+            .component(component_id)?
+            .messages
+            .get(*name)?;
+        let status = match (entry, key) {
+            (MAVLinkMessageEntry::Single(status), None) => status,
+            (MAVLinkMessageEntry::Keyed(statuses), Some(key)) => statuses.get(*key)?,
+            _ => return None,
+        };
+        Some(status.message_json())
+    }
 
-        let value = serde_json::to_value(self).unwrap();
-        return match value.pointer(&path) {
-            Some(content) => serde_json::to_string_pretty(content).unwrap(),
-            None => "None".into(),
+    /// Value of the path, only the vehicle, component or message of the path is copied and serialized
+    pub fn pointer(&self, path: &str) -> Option<serde_json::Value> {
+        trace!("Pointer path: {path}");
+
+        let segments: Vec<&str> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let now_us = monotonic_time_us();
+        let (mut value, rest) = match segments.as_slice() {
+            ["vehicles", system_id, "components", component_id, "messages", name, rest @ ..] => {
+                let mut entry = self
+                    .vehicle(system_id)?
+                    .component(component_id)?
+                    .messages
+                    .get(*name)?
+                    .clone();
+                entry.refresh(now_us, self.stale_after);
+                (output::to_value(&entry), rest)
+            }
+            ["vehicles", system_id, "components", component_id, rest @ ..] => {
+                let mut component = self.vehicle(system_id)?.component(component_id)?.clone();
+                component.refresh(now_us, self.stale_after);
+                (output::to_value(&component), rest)
+            }
+            ["vehicles", system_id, rest @ ..] => {
+                let mut vehicle = self.vehicle(system_id)?.clone();
+                vehicle.refresh(now_us, self.stale_after);
+                (output::to_value(&vehicle), rest)
+            }
+            // The whole tree is only copied when requested
+            rest => {
+                let mut data = self.clone();
+                data.refresh(now_us, self.stale_after);
+                (output::to_value(&data), rest)
+            }
         };
+
+        if rest.is_empty() {
+            return Some(value);
+        }
+        value
+            .pointer_mut(&format!("/{}", rest.join("/")))
+            .map(serde_json::Value::take)
     }
 }
 
-#[derive(Debug)]
-struct Data {
-    messages: Arc<Mutex<MAVLinkVehiclesData>>,
+lazy_static! {
+    static ref DATA: ArcSwap<MAVLinkVehiclesData> =
+        ArcSwap::from_pointee(MAVLinkVehiclesData::default());
 }
 
-lazy_static! {
-    static ref DATA: Data = Data {
-        messages: Arc::new(Mutex::new(MAVLinkVehiclesData::default())),
-    };
+pub fn init() {
+    lazy_static::initialize(&START);
+    cli::on_reload(|| {
+        DATA.rcu(|messages| {
+            let mut messages = MAVLinkVehiclesData::clone(messages);
+            messages.clear_caches();
+            messages
+        });
+    });
 }
 
 pub fn update((header, message): (mavlink::MavHeader, mavlink::ardupilotmega::MavMessage)) {
-    DATA.messages
-        .lock()
-        .unwrap()
-        .update(MAVLinkMessage { header, message });
+    let key = (
+        header.system_id,
+        header.component_id,
+        message.message_name().to_string(),
+    );
+    let message = MAVLinkMessage { header, message };
+    DATA.rcu(|messages| {
+        let mut messages = MAVLinkVehiclesData::clone(messages);
+        messages.update(&message);
+        messages
+    });
+
+    UPDATE_WAITERS.lock().unwrap().notify(&key);
+}
+
+/// Wait until a matching message entry is updated, returns false on timeout
+pub async fn wait_update(
+    system_id: Option<u8>,
+    component_id: Option<u8>,
+    name: Option<String>,
+    timeout: std::time::Duration,
+) -> bool {
+    let (sender, receiver) = oneshot::channel();
+    {
+        // Waiters of timed out requests are dropped here or when the entry is updated
+        let mut waiters = UPDATE_WAITERS.lock().unwrap();
+        match (system_id, component_id, name) {
+            (Some(system_id), Some(component_id), Some(name)) => {
+                let senders = waiters
+                    .entries
+                    .entry((system_id, component_id, name))
+                    .or_default();
+                senders.retain(|sender| !sender.is_canceled());
+                senders.push(sender);
+            }
+            (system_id, component_id, name) => {
+                waiters
+                    .patterns
+                    .retain(|pattern| !pattern.sender.is_canceled());
+                waiters.patterns.push(UpdatePattern {
+                    system_id,
+                    component_id,
+                    name,
+                    sender,
+                });
+            }
+        }
+    }
+    matches!(
+        actix_web::rt::time::timeout(timeout, receiver).await,
+        Ok(Ok(()))
+    )
 }
 
+/// Snapshot of the messages, it is not changed by updates
 pub fn messages() -> MAVLinkVehiclesData {
-    let messages = DATA.messages.lock().unwrap();
-    messages.clone()
+    if let Some(ttl) = cli::message_ttl() {
+        if DATA.load().is_expired(ttl) {
+            DATA.rcu(|messages| {
+                let mut messages = MAVLinkVehiclesData::clone(messages);
+                messages.prune(ttl);
+                messages
+            });
+        }
+    }
+
+    let mut messages_snapshot = MAVLinkVehiclesData::clone(&DATA.load());
+    messages_snapshot.stale_after = cli::stale_after();
+    for (system_id, vehicle) in messages_snapshot.vehicles.iter_mut() {
+        if let Some(name) = vehicles::name(*system_id) {
+            Arc::make_mut(vehicle).name = Some(name);
+        }
+    }
+    messages_snapshot
+}
+
+/// Components of the vehicle with the message type, sorted by id
+pub fn message_components(system_id: u8, name: &str) -> Vec<u8> {
+    let mut ids: Vec<u8> = DATA
+        .load()
+        .vehicles
+        .get(&system_id)
+        .map(|vehicle| {
+            vehicle
+                .components
+                .values()
+                .filter(|component| component.messages.contains_key(name))
+                .map(|component| component.id)
+                .collect()
+        })
+        .unwrap_or_default();
+    ids.sort_unstable();
+    ids
+}
+
+pub fn vehicle_ids() -> Vec<u8> {
+    let mut ids: Vec<u8> = DATA.load().vehicles.keys().cloned().collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_values_by_name() {
+        let mut data = MAVLinkVehiclesData::default();
+        for (name, value) in [(*b"rpm\0\0\0\0\0\0\0", 1.0), (*b"depth\0\0\0\0\0", 2.0)] {
+            data.update(&MAVLinkMessage {
+                header: mavlink::MavHeader {
+                    system_id: 1,
+                    component_id: 1,
+                    sequence: 0,
+                },
+                message: mavlink::ardupilotmega::MavMessage::common(
+                    mavlink::common::MavMessage::NAMED_VALUE_FLOAT(
+                        mavlink::common::NAMED_VALUE_FLOAT_DATA {
+                            time_boot_ms: 0,
+                            value,
+                            name,
+                        },
+                    ),
+                ),
+            });
+        }
+
+        let path = "vehicles/1/components/1/messages/NAMED_VALUE_FLOAT";
+        let value = |name: &str| data.pointer(&format!("{path}/{name}/message/value"));
+        assert_eq!(value("rpm"), Some(serde_json::json!(1.0)));
+        assert_eq!(value("depth"), Some(serde_json::json!(2.0)));
+    }
+
+    #[test]
+    fn frequency_estimation() {
+        let mut estimator = FrequencyEstimator::default();
+        for _ in 0..100 {
+            estimator.update(0.1);
+        }
+        assert!((estimator.frequency - 10.0).abs() < 1e-3);
+        assert!(estimator.jitter_ms < 1e-3);
+
+        // A burst should not spike the frequency
+        estimator.update(0.001);
+        assert!(estimator.frequency < 12.0);
+        assert!((estimator.interval_min_ms - 1.0).abs() < 1e-3);
+
+        // After a gap the statistics start again
+        estimator.update(10.0);
+        assert!((estimator.frequency - 0.1).abs() < 1e-3);
+        assert!((estimator.interval_max_ms - 10_000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn frequency_with_wall_clock_jumps() {
+        let start = chrono::Utc::now();
+        let mut time = Temporal::default();
+        time.update_at(start, 0);
+        // NTP steps backwards and forwards, the messages still arrive at 10Hz
+        time.update_at(start - chrono::Duration::hours(1), 100_000);
+        time.update_at(start + chrono::Duration::days(1), 200_000);
+        time.update_at(start + chrono::Duration::days(1), 300_000);
+        assert!((time.frequency.frequency - 10.0).abs() < 1e-3);
+        assert!((time.frequency.interval_max_ms - 100.0).abs() < 1e-3);
+
+        time.refresh(400_000, Some(std::time::Duration::from_millis(50)));
+        assert_eq!(time.age_ms, 100);
+        assert!(time.stale);
+    }
+
+    #[test]
+    fn snapshots_share_unchanged_messages() {
+        let message = |component_id, value| MAVLinkMessage {
+            header: mavlink::MavHeader {
+                system_id: 1,
+                component_id,
+                sequence: 0,
+            },
+            message: mavlink::ardupilotmega::MavMessage::common(
+                mavlink::common::MavMessage::NAMED_VALUE_INT(
+                    mavlink::common::NAMED_VALUE_INT_DATA {
+                        time_boot_ms: 0,
+                        value,
+                        name: *b"count\0\0\0\0\0",
+                    },
+                ),
+            ),
+        };
+        let mut data = MAVLinkVehiclesData::default();
+        data.update(&message(1, 1));
+        data.update(&message(2, 1));
+
+        let snapshot = data.clone();
+        data.update(&message(1, 2));
+
+        let path = |component_id| {
+            format!(
+                "vehicles/1/components/{component_id}/messages/NAMED_VALUE_INT/count/message/value"
+            )
+        };
+        assert_eq!(snapshot.pointer(&path(1)), Some(serde_json::json!(1)));
+        assert_eq!(data.pointer(&path(1)), Some(serde_json::json!(2)));
+        assert!(Arc::ptr_eq(
+            &snapshot.vehicles[&1].components[&2],
+            &data.vehicles[&1].components[&2]
+        ));
+        assert!(data.pointer("vehicles/1/components/3").is_none());
+        assert!(data.pointer("").unwrap()["vehicles"]["1"]["components"]["2"].is_object());
+    }
+
+    #[test]
+    fn message_json_cached_until_update() {
+        let heartbeat = |custom_mode| MAVLinkMessage {
+            header: mavlink::MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            },
+            message: mavlink::ardupilotmega::MavMessage::common(
+                mavlink::common::MavMessage::HEARTBEAT(mavlink::common::HEARTBEAT_DATA {
+                    custom_mode,
+                    ..Default::default()
+                }),
+            ),
+        };
+        let path = "vehicles/1/components/1/messages/HEARTBEAT/message";
+        let mut data = MAVLinkVehiclesData::default();
+        data.update(&heartbeat(1));
+
+        let first = data.message_json(path).unwrap();
+        let snapshot = data.clone();
+        assert_eq!(
+            snapshot.message_json(path).unwrap().as_ptr(),
+            first.as_ptr()
+        );
+        let value: serde_json::Value = serde_json::from_slice(&first).unwrap();
+        assert_eq!(value, data.pointer(path).unwrap());
+
+        data.update(&heartbeat(2));
+        let second = data.message_json(path).unwrap();
+        assert_ne!(second.as_ptr(), first.as_ptr());
+        assert_eq!(
+            data.pointer(&format!("{path}/custom_mode")),
+            Some(serde_json::json!(2))
+        );
+        assert!(data
+            .message_json("vehicles/1/components/1/messages/HEARTBEAT")
+            .is_none());
+
+        // A reload serializes it again, the old snapshot keeps its own
+        data.clear_caches();
+        assert_ne!(data.message_json(path).unwrap().as_ptr(), second.as_ptr());
+        assert_eq!(
+            snapshot.message_json(path).unwrap().as_ptr(),
+            first.as_ptr()
+        );
+    }
+
+    #[test]
+    fn wait_update_until_timeout() {
+        let key = (250, 1, "HEARTBEAT".to_string());
+        let wait = |name: Option<&str>, timeout_ms| {
+            wait_update(
+                Some(key.0),
+                Some(key.1),
+                name.map(String::from),
+                std::time::Duration::from_millis(timeout_ms),
+            )
+        };
+        let notify = || async { UPDATE_WAITERS.lock().unwrap().notify(&key) };
+        actix_web::rt::System::new("test").block_on(async {
+            assert!(!wait(Some("HEARTBEAT"), 10).await);
+            let (updated, _) = futures::join!(wait(Some("HEARTBEAT"), 1000), notify());
+            assert!(updated);
+            let (updated, _) = futures::join!(wait(None, 1000), notify());
+            assert!(updated);
+            // Updates of other messages do not wake up the request
+            let (updated, _) = futures::join!(wait(Some("ATTITUDE"), 10), notify());
+            assert!(!updated);
+        });
+        // Nothing is left waiting once the requests are done
+        let waiters = UPDATE_WAITERS.lock().unwrap();
+        assert!(waiters
+            .entries
+            .values()
+            .flatten()
+            .all(|sender| sender.is_canceled()));
+        assert!(waiters
+            .patterns
+            .iter()
+            .all(|pattern| pattern.sender.is_canceled()));
+    }
 }
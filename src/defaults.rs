@@ -0,0 +1,126 @@
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::Message;
+use serde_json::{json, Map, Value};
+
+use super::data::MAVLinkMessage;
+use super::definitions;
+
+/// Message of the type with default values, in the format of POST /mavlink
+pub fn default_message(name: &str) -> Option<Value> {
+    let id = MavMessage::message_id_from_name(name).ok()?;
+    let message = MavMessage::default_message_from_id(id).ok()?;
+    let header = mavlink::MavHeader::default();
+    match message {
+        MavMessage::common(message) => serde_json::to_value(MAVLinkMessage { header, message }),
+        message => serde_json::to_value(MAVLinkMessage { header, message }),
+    }
+    .ok()
+}
+
+/// Default message with the options of its enum and bitmask fields, E.g: MAV_CMD for command
+pub fn template(name: &str) -> Option<Value> {
+    let mut template = default_message(name)?;
+    let mut enums = Map::new();
+    let fields = definitions::message(name)
+        .map(|definition| definition.fields.as_slice())
+        .unwrap_or_default();
+    for field in fields {
+        let enumeration = match field
+            .enum_name
+            .as_deref()
+            .and_then(definitions::enumeration)
+        {
+            Some(enumeration) => enumeration,
+            None => continue,
+        };
+        let options: Vec<&str> = enumeration
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        enums.insert(
+            field.name.clone(),
+            json!({
+                "enum": enumeration.name,
+                "bitmask": enumeration.bitmask,
+                "options": options,
+            }),
+        );
+    }
+    template["enums"] = Value::Object(enums);
+    Some(template)
+}
+
+/// Fill the header and message fields missing in a partial message with the default values,
+/// returning the defaulted fields, E.g: message.param7
+pub fn fill(value: &mut Value) -> Vec<String> {
+    let defaults = match value["message"]["type"].as_str().and_then(default_message) {
+        Some(defaults) => defaults,
+        // Unknown messages are rejected by the parser
+        None => return vec![],
+    };
+
+    let mut defaulted = vec![];
+    for section in ["header", "message"] {
+        let default_fields = match defaults[section].as_object() {
+            Some(default_fields) => default_fields,
+            None => continue,
+        };
+        let fields = match value
+            .as_object_mut()
+            .map(|object| object.entry(section).or_insert_with(|| Map::new().into()))
+            .and_then(Value::as_object_mut)
+        {
+            Some(fields) => fields,
+            None => continue,
+        };
+        for (name, default) in default_fields {
+            if !fields.contains_key(name) {
+                fields.insert(name.clone(), default.clone());
+                defaulted.push(format!("{section}.{name}"));
+            }
+        }
+    }
+    defaulted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_message() {
+        let mut value = json!({
+            "header": {"system_id": 255, "component_id": 0, "sequence": 0},
+            "message": {
+                "type": "COMMAND_LONG",
+                "command": {"type": "MAV_CMD_COMPONENT_ARM_DISARM"},
+                "param1": 1.0,
+                "target_system": 1,
+                "target_component": 1,
+            },
+        });
+
+        let defaulted = fill(&mut value);
+        assert!(defaulted.contains(&"message.param2".to_string()));
+        assert!(defaulted.contains(&"message.confirmation".to_string()));
+        assert!(!defaulted.contains(&"message.param1".to_string()));
+        assert!(!defaulted.iter().any(|field| field.starts_with("header.")));
+        assert_eq!(value["message"]["param7"], 0.0);
+        assert_eq!(value["message"]["param1"], 1.0);
+
+        assert!(fill(&mut json!({"message": {"type": "UNKNOWN"}})).is_empty());
+    }
+
+    #[test]
+    fn message_template() {
+        let template = template("COMMAND_LONG").unwrap();
+        assert_eq!(template["message"]["type"], "COMMAND_LONG");
+        assert_eq!(template["enums"]["command"]["enum"], "MAV_CMD");
+        assert!(template["enums"]["command"]["options"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("MAV_CMD_COMPONENT_ARM_DISARM")));
+        assert!(template("UNKNOWN").is_none());
+    }
+}
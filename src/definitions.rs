@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::Serialize;
+
+// MAVLink XML definitions downloaded by the build script, used to describe messages and enums
+const DEFINITION_FILES: &[(&str, &str)] = &[
+    (
+        "ardupilotmega.xml",
+        include_str!(concat!(
+            env!("OUT_DIR"),
+            "/message_definitions/ardupilotmega.xml"
+        )),
+    ),
+    (
+        "common.xml",
+        include_str!(concat!(env!("OUT_DIR"), "/message_definitions/common.xml")),
+    ),
+    (
+        "standard.xml",
+        include_str!(concat!(
+            env!("OUT_DIR"),
+            "/message_definitions/standard.xml"
+        )),
+    ),
+    (
+        "minimal.xml",
+        include_str!(concat!(env!("OUT_DIR"), "/message_definitions/minimal.xml")),
+    ),
+    (
+        "uAvionix.xml",
+        include_str!(concat!(
+            env!("OUT_DIR"),
+            "/message_definitions/uAvionix.xml"
+        )),
+    ),
+    (
+        "icarous.xml",
+        include_str!(concat!(env!("OUT_DIR"), "/message_definitions/icarous.xml")),
+    ),
+];
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EnumEntry {
+    pub name: String,
+    pub value: u64,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EnumDefinition {
+    pub name: String,
+    pub bitmask: bool,
+    pub description: String,
+    pub entries: Vec<EnumEntry>,
+}
+
+impl EnumDefinition {
+    pub fn value_of(&self, entry_name: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == entry_name)
+            .map(|entry| entry.value)
+    }
+
+    pub fn name_of(&self, value: u64) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.value == value)
+            .map(|entry| entry.name.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FieldDefinition {
+    /// Name used in the JSON representation
+    pub name: String,
+    /// MAVLink type, E.g: uint8_t, float, char[16]
+    #[serde(rename = "type")]
+    pub mavlink_type: String,
+    #[serde(rename = "enum")]
+    pub enum_name: Option<String>,
+    pub units: Option<String>,
+    pub description: String,
+    pub extension: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MessageDefinition {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub fields: Vec<FieldDefinition>,
+}
+
+#[derive(Debug, Default)]
+struct Definitions {
+    messages: HashMap<String, MessageDefinition>,
+    enums: HashMap<String, EnumDefinition>,
+}
+
+lazy_static! {
+    static ref DEFINITIONS: Definitions = load_definitions();
+}
+
+fn text_of(node: &roxmltree::Node) -> String {
+    node.children()
+        .find(|child| child.has_tag_name("description"))
+        .and_then(|description| description.text())
+        .or_else(|| node.text())
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn parse_integer(text: &str) -> Option<u64> {
+    let text = text.trim();
+    match text.strip_prefix("0x") {
+        Some(hexadecimal) => u64::from_str_radix(hexadecimal, 16).ok(),
+        None => text.parse::<u64>().ok(),
+    }
+}
+
+fn parse_enum(node: &roxmltree::Node, definitions: &mut Definitions) {
+    let name = node.attribute("name").unwrap_or_default().to_string();
+    // Enums can be extended by other dialects
+    let definition = definitions
+        .enums
+        .entry(name.clone())
+        .or_insert_with(|| EnumDefinition {
+            name,
+            ..Default::default()
+        });
+    definition.bitmask |= node.attribute("bitmask") == Some("true");
+    if definition.description.is_empty() {
+        definition.description = text_of(node);
+    }
+
+    for entry in node.children().filter(|child| child.has_tag_name("entry")) {
+        let value = entry
+            .attribute("value")
+            .and_then(parse_integer)
+            .unwrap_or_else(|| definition.entries.last().map_or(0, |entry| entry.value + 1));
+        definition.entries.push(EnumEntry {
+            name: entry.attribute("name").unwrap_or_default().to_string(),
+            value,
+            description: text_of(&entry),
+        });
+    }
+}
+
+fn parse_message(node: &roxmltree::Node, definitions: &mut Definitions) {
+    let mut extension = false;
+    let mut fields = vec![];
+    for child in node.children().filter(|child| child.is_element()) {
+        match child.tag_name().name() {
+            "extensions" => extension = true,
+            "field" => {
+                let name = child.attribute("name").unwrap_or_default();
+                fields.push(FieldDefinition {
+                    // Same as the rust-mavlink generator, since type is a reserved keyword
+                    name: if name == "type" { "mavtype" } else { name }.to_string(),
+                    mavlink_type: child.attribute("type").unwrap_or_default().to_string(),
+                    enum_name: child.attribute("enum").map(String::from),
+                    units: child.attribute("units").map(String::from),
+                    description: text_of(&child),
+                    extension,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let name = node.attribute("name").unwrap_or_default().to_string();
+    definitions.messages.insert(
+        name.clone(),
+        MessageDefinition {
+            id: node
+                .attribute("id")
+                .and_then(parse_integer)
+                .unwrap_or_default() as u32,
+            name,
+            description: text_of(node),
+            fields,
+        },
+    );
+}
+
+fn load_definitions() -> Definitions {
+    let mut definitions = Definitions::default();
+    for (filename, content) in DEFINITION_FILES {
+        let document = match roxmltree::Document::parse(content) {
+            Ok(document) => document,
+            Err(error) => {
+                warn!("Failed to parse MAVLink definition {filename}: {error}");
+                continue;
+            }
+        };
+
+        for node in document.descendants() {
+            if node.has_tag_name("enum") {
+                parse_enum(&node, &mut definitions);
+            } else if node.has_tag_name("message") {
+                parse_message(&node, &mut definitions);
+            }
+        }
+    }
+
+    definitions
+}
+
+pub fn message(name: &str) -> Option<&'static MessageDefinition> {
+    DEFINITIONS.messages.get(name)
+}
+
+pub fn messages() -> Vec<&'static MessageDefinition> {
+    DEFINITIONS.messages.values().collect()
+}
+
+pub fn enumeration(name: &str) -> Option<&'static EnumDefinition> {
+    DEFINITIONS.enums.get(name)
+}
+
+/// Message definition with the description of the enums used by its fields
+pub fn message_info(name: &str) -> Option<serde_json::Value> {
+    let message = message(name)?;
+    let mut info = serde_json::to_value(message).ok()?;
+
+    let enums: HashMap<&str, &EnumDefinition> = message
+        .fields
+        .iter()
+        .filter_map(|field| field.enum_name.as_deref())
+        .filter_map(|name| enumeration(name).map(|definition| (name, definition)))
+        .collect();
+    info["enums"] = serde_json::to_value(enums).ok()?;
+
+    Some(info)
+}
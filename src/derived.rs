@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+
+// Longest window of the climb rate averages
+const CLIMB_WINDOW_S: i64 = 60;
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+#[derive(Default)]
+struct State {
+    armed_since: Option<DateTime<Utc>>,
+    // Climb rate in m/s from GLOBAL_POSITION_INT
+    climb_samples: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+lazy_static! {
+    static ref STATES: Mutex<BTreeMap<u8, State>> = Mutex::new(BTreeMap::new());
+}
+
+/// Track the arming state and climb rate of the vehicles
+pub fn update(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let now = Utc::now();
+    match message {
+        mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::HEARTBEAT(
+            heartbeat,
+        )) if heartbeat.autopilot != mavlink::common::MavAutopilot::MAV_AUTOPILOT_INVALID => {
+            let armed = heartbeat
+                .base_mode
+                .contains(mavlink::common::MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED);
+            let mut states = STATES.lock().unwrap();
+            let state = states.entry(header.system_id).or_default();
+            state.armed_since = match (armed, state.armed_since) {
+                (true, None) => Some(now),
+                (true, since) => since,
+                (false, _) => None,
+            };
+        }
+        mavlink::ardupilotmega::MavMessage::common(
+            mavlink::common::MavMessage::GLOBAL_POSITION_INT(position),
+        ) => {
+            let mut states = STATES.lock().unwrap();
+            let samples = &mut states.entry(header.system_id).or_default().climb_samples;
+            // Velocity is positive down in cm/s
+            samples.push_back((now, -(position.vz as f64) / 100.0));
+            while let Some((time, _)) = samples.front() {
+                if (now - *time).num_seconds() < CLIMB_WINDOW_S {
+                    break;
+                }
+                samples.pop_front();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Great circle distance in meters and initial bearing in degrees between two points in degrees
+pub fn distance_and_bearing(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let (latitude_from, longitude_from) = (from.0.to_radians(), from.1.to_radians());
+    let (latitude_to, longitude_to) = (to.0.to_radians(), to.1.to_radians());
+    let delta_latitude = latitude_to - latitude_from;
+    let delta_longitude = longitude_to - longitude_from;
+
+    let a = (delta_latitude / 2.0).sin().powi(2)
+        + latitude_from.cos() * latitude_to.cos() * (delta_longitude / 2.0).sin().powi(2);
+    let distance = 2.0 * EARTH_RADIUS_M * a.sqrt().atan2((1.0 - a).sqrt());
+
+    let bearing = (delta_longitude.sin() * latitude_to.cos()).atan2(
+        latitude_from.cos() * latitude_to.sin()
+            - latitude_from.sin() * latitude_to.cos() * delta_longitude.cos(),
+    );
+    (distance, (bearing.to_degrees() + 360.0) % 360.0)
+}
+
+fn position(message: &Value) -> Option<(f64, f64)> {
+    Some((
+        message["latitude"].as_f64()? / 1e7,
+        message["longitude"].as_f64()? / 1e7,
+    ))
+}
+
+fn climb_average(
+    samples: &VecDeque<(DateTime<Utc>, f64)>,
+    now: DateTime<Utc>,
+    window_s: i64,
+) -> Value {
+    let rates: Vec<f64> = samples
+        .iter()
+        .filter(|(time, _)| (now - *time).num_seconds() < window_s)
+        .map(|(_, rate)| *rate)
+        .collect();
+    if rates.is_empty() {
+        return Value::Null;
+    }
+    json!(rates.iter().sum::<f64>() / rates.len() as f64)
+}
+
+/// Values derived from the last GLOBAL_POSITION_INT and HOME_POSITION of a vehicle
+pub fn describe(
+    system_id: u8,
+    global_position: Option<&Value>,
+    home_position: Option<&Value>,
+) -> Value {
+    let now = Utc::now();
+
+    let mut to_home = Value::Null;
+    let current = global_position.and_then(|message| {
+        Some((
+            message["lat"].as_f64()? / 1e7,
+            message["lon"].as_f64()? / 1e7,
+        ))
+    });
+    if let (Some(current), Some(home)) = (current, home_position.and_then(position)) {
+        let (distance, bearing) = distance_and_bearing(current, home);
+        to_home = json!({
+            "distance": distance,
+            "bearing": bearing,
+        });
+    }
+
+    let ground_speed = global_position.and_then(|message| {
+        let velocities: Option<Vec<f64>> = ["vx", "vy", "vz"]
+            .iter()
+            .map(|field| message[field].as_f64().map(|velocity| velocity / 100.0))
+            .collect();
+        Some(
+            velocities?
+                .iter()
+                .map(|velocity| velocity.powi(2))
+                .sum::<f64>()
+                .sqrt(),
+        )
+    });
+
+    let states = STATES.lock().unwrap();
+    let state = states.get(&system_id);
+    let climb_rate = match state {
+        Some(state) => json!({
+            "1s": climb_average(&state.climb_samples, now, 1),
+            "10s": climb_average(&state.climb_samples, now, 10),
+            "60s": climb_average(&state.climb_samples, now, CLIMB_WINDOW_S),
+        }),
+        None => Value::Null,
+    };
+    let flight_time = state
+        .and_then(|state| state.armed_since)
+        .map(|since| (now - since).num_milliseconds() as f64 / 1e3);
+
+    json!({
+        "home": to_home,
+        "ground_speed_3d": ground_speed,
+        "climb_rate": climb_rate,
+        "armed": state.map(|state| state.armed_since.is_some()),
+        "flight_time": flight_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_distance_and_bearing() {
+        // One degree of latitude to the north
+        let (distance, bearing) = distance_and_bearing((0.0, 0.0), (1.0, 0.0));
+        assert!((distance - 111_195.0).abs() < 1.0);
+        assert!(bearing.abs() < 1e-9);
+
+        let (_, bearing) = distance_and_bearing((0.0, 0.0), (0.0, -1.0));
+        assert!((bearing - 270.0).abs() < 1e-9);
+
+        let derived = describe(
+            0,
+            Some(&json!({"lat": 0, "lon": 0, "vx": 300, "vy": 400, "vz": 0})),
+            Some(&json!({"latitude": 10000000, "longitude": 0})),
+        );
+        assert!((derived["home"]["distance"].as_f64().unwrap() - 111_195.0).abs() < 1.0);
+        assert_eq!(derived["ground_speed_3d"], 5.0);
+    }
+}
@@ -0,0 +1,196 @@
+use serde_json::{json, Map, Value};
+
+use super::definitions;
+
+// Thresholds used by ground stations, variances of ArduPilot and test ratios of PX4 (rejected above 1)
+const EKF_VARIANCE_WARNING: f64 = 0.5;
+const EKF_VARIANCE_ERROR: f64 = 0.8;
+const ESTIMATOR_RATIO_WARNING: f64 = 0.5;
+const ESTIMATOR_RATIO_ERROR: f64 = 1.0;
+
+const EKF_VARIANCES: &[&str] = &[
+    "velocity_variance",
+    "pos_horiz_variance",
+    "pos_vert_variance",
+    "compass_variance",
+    "terrain_alt_variance",
+    "airspeed_variance",
+];
+
+const ESTIMATOR_RATIOS: &[&str] = &[
+    "vel_ratio",
+    "pos_horiz_ratio",
+    "pos_vert_ratio",
+    "mag_ratio",
+    "hagl_ratio",
+    "tas_ratio",
+];
+
+// Flags set when the estimator has a problem
+const ESTIMATOR_ERROR_FLAGS: &[&str] = &[
+    "EKF_UNINITIALIZED",
+    "EKF_GPS_GLITCH",
+    "ESTIMATOR_GPS_GLITCH",
+    "ESTIMATOR_ACCEL_ERROR",
+];
+
+fn bits(value: &Value) -> u64 {
+    value["bits"]
+        .as_u64()
+        .or_else(|| value.as_u64())
+        .unwrap_or(0)
+}
+
+// Named booleans of the flags, E.g: pos_horiz_abs for EKF_POS_HORIZ_ABS
+fn flags(
+    enumeration: &str,
+    prefix: &str,
+    value: &Value,
+    errors: &mut Vec<String>,
+) -> Map<String, Value> {
+    let value = bits(value);
+    let mut flags = Map::new();
+    let entries = definitions::enumeration(enumeration)
+        .map(|enumeration| enumeration.entries.as_slice())
+        .unwrap_or_default();
+    for entry in entries {
+        let is_set = value & entry.value != 0;
+        if is_set && ESTIMATOR_ERROR_FLAGS.contains(&entry.name.as_str()) {
+            errors.push(entry.name.clone());
+        }
+        flags.insert(
+            entry.name.trim_start_matches(prefix).to_lowercase(),
+            is_set.into(),
+        );
+    }
+    flags
+}
+
+// Values of the fields, with the fields over the thresholds in the warnings and errors
+fn levels(
+    message: &Value,
+    fields: &[&str],
+    (warning, error): (f64, f64),
+    warnings: &mut Vec<String>,
+    errors: &mut Vec<String>,
+) -> Map<String, Value> {
+    let mut levels = Map::new();
+    for field in fields {
+        let value = match message[field].as_f64() {
+            Some(value) => value,
+            None => continue,
+        };
+        if value >= error {
+            errors.push(field.to_string());
+        } else if value >= warning {
+            warnings.push(field.to_string());
+        }
+        levels.insert(field.to_string(), json!(value));
+    }
+    levels
+}
+
+fn is_set(flags: &Map<String, Value>, name: &str) -> bool {
+    flags.get(name).and_then(Value::as_bool).unwrap_or(false)
+}
+
+// Attitude, horizontal velocity and position, relative or absolute, and vertical position are required
+fn is_navigating(flags: &Map<String, Value>) -> bool {
+    ["attitude", "velocity_horiz", "pos_vert_abs"]
+        .iter()
+        .all(|name| is_set(flags, name))
+        && (is_set(flags, "pos_horiz_rel") || is_set(flags, "pos_horiz_abs"))
+}
+
+/// Decode the flags of EKF_STATUS_REPORT (ArduPilot) and ESTIMATOR_STATUS (PX4), with variance warnings
+pub fn describe(
+    ekf_status_report: Option<&Value>,
+    estimator_status: Option<&Value>,
+) -> Option<Value> {
+    if ekf_status_report.is_none() && estimator_status.is_none() {
+        return None;
+    }
+
+    let mut warnings = vec![];
+    let mut errors = vec![];
+    let mut healthy = true;
+
+    let ekf_status_report = ekf_status_report.map(|report| {
+        let flags = flags("EKF_STATUS_FLAGS", "EKF_", &report["flags"], &mut errors);
+        healthy &= is_navigating(&flags);
+        let variances = levels(
+            report,
+            EKF_VARIANCES,
+            (EKF_VARIANCE_WARNING, EKF_VARIANCE_ERROR),
+            &mut warnings,
+            &mut errors,
+        );
+        json!({
+            "flags": flags,
+            "variances": variances,
+        })
+    });
+
+    let estimator_status = estimator_status.map(|status| {
+        let flags = flags(
+            "ESTIMATOR_STATUS_FLAGS",
+            "ESTIMATOR_",
+            &status["flags"],
+            &mut errors,
+        );
+        healthy &= is_navigating(&flags);
+        let ratios = levels(
+            status,
+            ESTIMATOR_RATIOS,
+            (ESTIMATOR_RATIO_WARNING, ESTIMATOR_RATIO_ERROR),
+            &mut warnings,
+            &mut errors,
+        );
+        json!({
+            "flags": flags,
+            "test_ratios": ratios,
+            "pos_horiz_accuracy": status["pos_horiz_accuracy"],
+            "pos_vert_accuracy": status["pos_vert_accuracy"],
+        })
+    });
+
+    Some(json!({
+        "healthy": healthy && errors.is_empty(),
+        "warnings": warnings,
+        "errors": errors,
+        "ekf_status_report": ekf_status_report,
+        "estimator_status": estimator_status,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ekf_status_report() {
+        // Attitude (1), horizontal (2) and vertical (4) velocity, relative (8) and absolute (16)
+        // horizontal position and absolute vertical position (32)
+        let status = describe(
+            Some(&json!({
+                "flags": {"bits": 63},
+                "velocity_variance": 0.1,
+                "pos_horiz_variance": 0.6,
+                "pos_vert_variance": 0.1,
+                "compass_variance": 0.9,
+                "terrain_alt_variance": 0.0,
+            })),
+            None,
+        )
+        .unwrap();
+
+        let report = &status["ekf_status_report"];
+        assert_eq!(report["flags"]["attitude"], true);
+        assert_eq!(report["flags"]["const_pos_mode"], false);
+        assert_eq!(status["warnings"], json!(["pos_horiz_variance"]));
+        assert_eq!(status["errors"], json!(["compass_variance"]));
+        assert_eq!(status["healthy"], false);
+        assert!(status["estimator_status"].is_null());
+        assert!(describe(None, None).is_none());
+    }
+}
@@ -1,6 +1,7 @@
 use std::path::Path;
 
 use actix_web::{
+    error::BlockingError,
     web::{self, Json},
     HttpRequest, HttpResponse,
 };
@@ -9,10 +10,56 @@ use include_dir::{include_dir, Dir};
 use paperclip::actix::{api_v2_operation, Apiv2Schema};
 use serde::{Deserialize, Serialize};
 
+use super::attitude;
+use super::autopilot;
+use super::batteries;
+use super::calibration;
+use super::cli;
+use super::command_tracker;
+use super::commands;
+use super::component_information::{self, MetadataError};
 use super::data;
-use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::defaults;
+use super::definitions;
+use super::derived;
+use super::ekf;
+use super::escs;
+use super::events;
+use super::export;
+use super::ftp::FtpError;
+use super::geojson;
+use super::gimbal;
+use super::grafana;
+use super::guided;
+use super::health;
+use super::history;
+use super::hud;
+use super::json_schema;
+use super::link;
+use super::mavlink_vehicle::{self, MAVLinkVehicleArcMutex};
+use super::metrics;
+use super::mission::{self, MissionItem};
+use super::modes;
+use super::output::{self, Encoding, OutputOptions, OutputQuery};
+use super::params;
+use super::plan;
+use super::protobuf;
+use super::raw_frames;
+use super::raw_websocket::RawWebsocketActor;
+use super::rosbridge::RosbridgeWebsocketActor;
+use super::rtcm;
+use super::setpoints;
+use super::shell::ShellWebsocketActor;
+use super::sse;
+use super::throttle::{self, Decimator};
+use super::timesync;
+use super::traffic;
+use super::tunnel::{self, TunnelWebsocketActor};
+use super::vehicles;
+use super::waiter;
 use super::websocket_manager::WebsocketActor;
 
+use futures::StreamExt;
 use log::*;
 use mavlink::Message;
 
@@ -43,6 +90,12 @@ pub struct Info {
 pub struct WebsocketQuery {
     /// Regex filter to selected the desired MAVLink messages by name
     filter: Option<String>,
+    /// Messages encoding: json, msgpack, cbor or protobuf, binary encodings are sent as binary frames
+    format: Option<String>,
+    /// Maximum rate in Hz of each message type of each component
+    max_rate_hz: Option<f32>,
+    /// Maximum rates of specific message types, E.g: ATTITUDE:10,GPS_RAW_INT:1
+    max_rates: Option<String>,
 }
 
 #[derive(Apiv2Schema, Deserialize)]
@@ -51,6 +104,80 @@ pub struct MAVLinkHelperQuery {
     name: String,
 }
 
+// Flattened query parameters are deserialized as strings, and JSON body fields as numbers
+fn optional_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number<T> {
+        Value(T),
+        Text(String),
+    }
+
+    match Option::<Number<T>>::deserialize(deserializer)? {
+        Some(Number::Value(value)) => Ok(Some(value)),
+        Some(Number::Text(text)) => text.parse().map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct Target {
+    /// Target vehicle, 1 by default
+    #[serde(default, deserialize_with = "optional_number")]
+    system_id: Option<u8>,
+    /// Target component, 1 (autopilot) by default, 100 for cameras, 154 for gimbals, 0 is all components of a tunnel
+    #[serde(default, deserialize_with = "optional_number")]
+    component_id: Option<u8>,
+}
+
+impl Target {
+    fn system_id(&self) -> u8 {
+        self.system_id.unwrap_or(1)
+    }
+
+    fn component_id(&self) -> u8 {
+        self.component_id_or(1)
+    }
+
+    // Component of the endpoints that are not addressed to the autopilot by default
+    fn component_id_or(&self, default: u8) -> u8 {
+        self.component_id.unwrap_or(default)
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct CommandOptions {
+    /// Time to wait for the COMMAND_ACK, 1000 milliseconds by default
+    #[serde(default, deserialize_with = "optional_number")]
+    timeout_ms: Option<u64>,
+    /// Times the command is sent again when no COMMAND_ACK arrives, --command-retries by default
+    #[serde(default, deserialize_with = "optional_number")]
+    retries: Option<u8>,
+}
+
+impl CommandOptions {
+    fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_ms.unwrap_or(1000))
+    }
+
+    fn retries(&self) -> u8 {
+        self.retries.unwrap_or_else(cli::command_retries)
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct MAVLinkRequestQuery {
+    #[serde(flatten)]
+    target: Target,
+    /// Time to wait for the message, 1000 milliseconds by default
+    timeout_ms: Option<u64>,
+}
+
 fn load_html_file(filename: &str) -> Option<String> {
     if let Some(file) = HTML_DIST.get_file(filename) {
         return Some(file.contents_utf8().unwrap().to_string());
@@ -80,6 +207,12 @@ pub fn root(req: HttpRequest) -> HttpResponse {
         .body("File does not exist");
 }
 
+pub fn redirect_to_root(req: HttpRequest) -> HttpResponse {
+    HttpResponse::PermanentRedirect()
+        .header("Location", format!("{}/", req.path()))
+        .finish()
+}
+
 #[api_v2_operation]
 /// Provides information about the API and this program
 pub async fn info() -> Json<Info> {
@@ -97,138 +230,2328 @@ pub async fn info() -> Json<Info> {
     Json(info)
 }
 
+#[api_v2_operation]
+/// Provides the state of the connection with the vehicle and if each vehicle is online
+pub async fn status() -> actix_web::Result<HttpResponse> {
+    let status = serde_json::json!({
+        "link": mavlink_vehicle::link_status(),
+        "vehicles": vehicles::vehicles(),
+    });
+    ok_response(parse_query(&status)).await
+}
+
+#[api_v2_operation]
+/// Requests and latency of each route, streaming clients and serialization time of the server
+pub async fn stats_server() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&metrics::stats())).await
+}
+
+#[api_v2_operation]
+/// Server metrics in the Prometheus text format
+pub async fn prometheus_metrics() -> actix_web::Result<HttpResponse> {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::prometheus())
+        .await
+}
+
+#[api_v2_operation]
+/// Provides all vehicles and components seen on the link, with their type and heartbeat rate
+pub async fn mavlink_vehicles() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&vehicles::components())).await
+}
+
+// Vehicle, component and message name of a path like vehicles/1/components/1/messages/ATTITUDE
+fn message_path(path: &str) -> (Option<u8>, Option<u8>, Option<String>) {
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let segment = |index: usize, parent: &str| match (segments.get(index - 1), segments.get(index))
+    {
+        (Some(&previous), Some(&segment)) if previous == parent => Some(segment),
+        _ => None,
+    };
+    (
+        segment(1, "vehicles").and_then(|id| id.parse().ok()),
+        segment(3, "components").and_then(|id| id.parse().ok()),
+        segment(5, "messages").map(String::from),
+    )
+}
+
+// Path with the vehicle name replaced by its system id, E.g: boat/ATTITUDE is
+// vehicles/1/components/1/messages/ATTITUDE, with the autopilot or the first component that has the message
+fn vehicle_name_path(path: &str) -> String {
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let (system_id, rest) = match segments.as_slice() {
+        ["vehicles", name, rest @ ..] | [name, rest @ ..] => match vehicles::system_id_of(name) {
+            Some(system_id) => (system_id, rest),
+            None => return path.to_string(),
+        },
+        [] => return path.to_string(),
+    };
+
+    let vehicle = format!("vehicles/{system_id}");
+    match rest {
+        [] => vehicle,
+        ["components", ..] => format!("{vehicle}/{}", rest.join("/")),
+        [name, fields @ ..] => {
+            let components = data::message_components(system_id, name);
+            let component_id = if components.contains(&1) {
+                1
+            } else {
+                components.first().copied().unwrap_or(1)
+            };
+            let message = format!("{vehicle}/components/{component_id}/messages/{name}");
+            [&[message.as_str()][..], fields].concat().join("/")
+        }
+    }
+}
+
 #[api_v2_operation]
 /// Provides an object containing all MAVLink messages received by the service
-pub async fn mavlink(req: HttpRequest) -> actix_web::Result<HttpResponse> {
-    let path = req.match_info().query("path");
-    let message = data::messages().pointer(path);
-    ok_response(message).await
+pub async fn mavlink(
+    req: HttpRequest,
+    query: web::Query<OutputQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let path = vehicle_name_path(req.match_info().query("path"));
+    let path = path.as_str();
+    if query.wait.unwrap_or(false) {
+        let timeout = std::time::Duration::from_millis(query.timeout_ms.unwrap_or(5000));
+        let (system_id, component_id, name) = message_path(path);
+        if !data::wait_update(system_id, component_id, name, timeout).await {
+            return HttpResponse::GatewayTimeout()
+                .content_type("application/json")
+                .body(parse_query(&format!(
+                    "No update of {path:?} in {}ms",
+                    timeout.as_millis()
+                )))
+                .await;
+        }
+    }
+
+    let options = OutputOptions::from_query(&query);
+    let encoding = Encoding::from_request(&req, query.format.as_deref());
+    let mut messages = data::messages();
+    // Messages without transformations are served from the JSON serialized when they were updated
+    if query.types.is_none() && encoding == Encoding::Json && options == OutputOptions::default() {
+        if let Some(content) = messages.message_json(path) {
+            return HttpResponse::Ok()
+                .content_type(encoding.content_type())
+                .body(content)
+                .await;
+        }
+    }
+    if let Some(types) = &query.types {
+        let types: Vec<&str> = types.split(',').map(str::trim).collect();
+        messages.retain_types(&types);
+    }
+
+    let mut value = match messages.pointer(path) {
+        Some(value) => value,
+        None => return ok_response("None".into()).await,
+    };
+
+    output::apply(&mut value, &options);
+
+    let content = match encoding {
+        Encoding::Protobuf => protobuf::encode_tree(&value, path),
+        _ => encoding.encode(&value),
+    };
+
+    HttpResponse::Ok()
+        .content_type(encoding.content_type())
+        .body(content)
+        .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GeoJsonQuery {
+    /// Add the recorded track of each vehicle, requires --history
+    track: Option<bool>,
+}
+
+#[api_v2_operation]
+/// Vehicle and home positions as a GeoJSON FeatureCollection
+pub async fn geojson(query: web::Query<GeoJsonQuery>) -> actix_web::Result<HttpResponse> {
+    let track = query.track.unwrap_or(false);
+    if track && !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+
+    let collection = geojson::feature_collection(track);
+    HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .body(collection.to_string())
+        .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct TrackQuery {
+    /// Export only the track of this vehicle
+    system_id: Option<u8>,
+}
+
+#[api_v2_operation]
+/// Recorded vehicle tracks as a GPX file, requires --history
+pub async fn export_gpx(query: web::Query<TrackQuery>) -> actix_web::Result<HttpResponse> {
+    if !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/gpx+xml")
+        .body(export::gpx(query.system_id))
+        .await
+}
+
+#[api_v2_operation]
+/// Recorded vehicle tracks as a KML file, requires --history
+pub async fn export_kml(query: web::Query<TrackQuery>) -> actix_web::Result<HttpResponse> {
+    if !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/vnd.google-earth.kml+xml")
+        .body(export::kml(query.system_id))
+        .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct CsvQuery {
+    /// Export only the messages of this vehicle
+    system_id: Option<u8>,
+    /// Export only the messages received after this RFC 3339 time, E.g: 2020-09-01T20:36:24.088Z
+    since: Option<String>,
+}
+
+#[api_v2_operation]
+/// Recorded messages of a type as a CSV file, one column per field, requires --history
+pub async fn export_csv(
+    req: HttpRequest,
+    query: web::Query<CsvQuery>,
+) -> actix_web::Result<HttpResponse> {
+    if !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+
+    let since = match query
+        .since
+        .as_deref()
+        .map(chrono::DateTime::parse_from_rfc3339)
+    {
+        Some(Ok(since)) => Some(since.with_timezone(&chrono::Utc)),
+        Some(Err(_)) => return bad_request("Invalid since time, it should be RFC 3339").await,
+        None => None,
+    };
+
+    let name = req.match_info().query("name").to_uppercase();
+    match export::csv(&name, query.system_id, since) {
+        Some(content) => {
+            HttpResponse::Ok()
+                .content_type("text/csv")
+                .header(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{name}.csv\""),
+                )
+                .body(content)
+                .await
+        }
+        None => not_found_response(format!("\"Unknown message: {name}\"")).await,
+    }
+}
+
+#[api_v2_operation]
+/// Connection test of the Grafana JSON datasource
+pub async fn grafana_root() -> actix_web::Result<HttpResponse> {
+    if !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+    ok_response("\"OK\"".into()).await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GrafanaSearch {
+    /// Text contained in the MESSAGE.FIELD targets
+    target: Option<String>,
+}
+
+#[api_v2_operation]
+/// Numeric fields of the recorded messages, as MESSAGE.FIELD targets of the Grafana JSON datasource
+pub async fn grafana_search(search: web::Json<GrafanaSearch>) -> actix_web::Result<HttpResponse> {
+    if !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+    let filter = search.into_inner().target.unwrap_or_default();
+    ok_response(parse_query(&grafana::search(&filter))).await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GrafanaRange {
+    /// RFC 3339 time, E.g: 2020-09-01T20:36:24.088Z
+    from: String,
+    to: String,
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GrafanaTarget {
+    /// MESSAGE.FIELD, E.g: ATTITUDE.roll
+    target: Option<String>,
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrafanaQuery {
+    range: GrafanaRange,
+    targets: Vec<GrafanaTarget>,
+    max_data_points: Option<usize>,
+}
+
+#[api_v2_operation]
+/// Time series of the recorded messages fields for the Grafana JSON datasource, requires --history
+pub async fn grafana_query(query: web::Json<GrafanaQuery>) -> actix_web::Result<HttpResponse> {
+    if !history::is_enabled() {
+        return history_disabled_response().await;
+    }
+
+    let parse = |time: &str| {
+        chrono::DateTime::parse_from_rfc3339(time).map(|time| time.with_timezone(&chrono::Utc))
+    };
+    let (from, to) = match (parse(&query.range.from), parse(&query.range.to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => return bad_request("Invalid time range, it should be RFC 3339").await,
+    };
+
+    let series: Vec<serde_json::Value> = query
+        .targets
+        .iter()
+        .filter_map(|target| target.target.as_deref())
+        .flat_map(|target| grafana::query(target, from, to, query.max_data_points))
+        .collect();
+    ok_response(parse_query(&series)).await
+}
+
+#[api_v2_operation]
+/// Hex dumps of the last messages of a type re-encoded as frames, requires --reencoded-frames
+pub async fn debug_reencoded(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    if !raw_frames::is_enabled() {
+        return HttpResponse::BadRequest()
+            .content_type("application/json")
+            .body("\"Re-encoded frames are disabled, it can be enabled with --reencoded-frames.\"")
+            .await;
+    }
+
+    let name = req.match_info().query("name");
+    match raw_frames::frames(name) {
+        Some(frames) => ok_response(parse_query(&frames)).await,
+        None => not_found_response(format!("\"No frames received for: {name}\"")).await,
+    }
+}
+
+#[api_v2_operation]
+/// Protobuf schema of the MAVLink messages, used by the protobuf encoding
+pub async fn protobuf_schema() -> actix_web::Result<HttpResponse> {
+    HttpResponse::Ok()
+        .content_type("text/plain")
+        .body(protobuf::schema())
+        .await
+}
+
+#[api_v2_operation]
+/// JSON Schema of a MAVLink message, generated from the dialect definitions
+pub async fn mavlink_schema(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let name = req.match_info().query("name");
+    match json_schema::message_schema(name) {
+        Some(schema) => ok_response(parse_query(&schema)).await,
+        None => not_found_response(format!("\"Unknown message: {name}\"")).await,
+    }
 }
 
 pub fn parse_query<T: serde::ser::Serialize>(message: &T) -> String {
     let error_message =
         "Not possible to parse mavlink message, please report this issue!".to_string();
-    serde_json::to_string_pretty(&message).unwrap_or(error_message)
+    let start = std::time::Instant::now();
+    let text = serde_json::to_string_pretty(&message).unwrap_or(error_message);
+    metrics::record_serialization(start.elapsed());
+    text
 }
 
 #[api_v2_operation]
-/// Returns a MAVLink message matching the given message name
+/// Returns a MAVLink message template with default values and the options of its enums
 pub async fn helper_mavlink(
     _req: HttpRequest,
     query: web::Query<MAVLinkHelperQuery>,
 ) -> actix_web::Result<HttpResponse> {
-    let message_name = query.into_inner().name;
+    let message_name = query.into_inner().name.to_uppercase();
 
-    let result = match mavlink::ardupilotmega::MavMessage::message_id_from_name(&message_name) {
-        Ok(id) => mavlink::Message::default_message_from_id(id),
-        Err(error) => Err(error),
-    };
+    match defaults::template(&message_name) {
+        Some(template) => ok_response(parse_query(&template)).await,
+        None => not_found_response(parse_query(&format!("Unknown message {message_name}"))).await,
+    }
+}
 
-    match result {
-        Ok(result) => {
-            let msg = match result {
-                mavlink::ardupilotmega::MavMessage::common(msg) => {
-                    parse_query(&data::MAVLinkMessage {
-                        header: mavlink::MavHeader::default(),
-                        message: msg,
-                    })
-                }
-                msg => parse_query(&data::MAVLinkMessage {
-                    header: mavlink::MavHeader::default(),
-                    message: msg,
-                }),
-            };
+#[derive(Apiv2Schema, Deserialize)]
+pub struct TelemetryQuery {
+    #[serde(flatten)]
+    target: Target,
+}
 
-            ok_response(msg).await
-        }
-        Err(content) => not_found_response(parse_query(&content)).await,
+impl TelemetryQuery {
+    // Last received message of the vehicle
+    fn message(&self, name: &str) -> Option<serde_json::Value> {
+        data::messages().pointer(&format!(
+            "vehicles/{}/components/{}/messages/{name}/message",
+            self.target.system_id(),
+            self.target.component_id()
+        ))
     }
 }
 
 #[api_v2_operation]
-#[allow(clippy::await_holding_lock)]
-/// Send a MAVLink message for the desired vehicle
-pub async fn mavlink_post(
-    data: web::Data<MAVLinkVehicleArcMutex>,
-    _req: HttpRequest,
-    bytes: web::Bytes,
+/// Sensors health, battery and load decoded from SYS_STATUS
+pub async fn helper_health(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    match query.message("SYS_STATUS") {
+        Some(sys_status) => ok_response(parse_query(&health::describe(&sys_status))).await,
+        None => not_found_response("\"No SYS_STATUS received\"".into()).await,
+    }
+}
+
+#[api_v2_operation]
+/// Batteries of the vehicle from BATTERY_STATUS of all components and SYS_STATUS
+pub async fn helper_batteries(
+    query: web::Query<TelemetryQuery>,
 ) -> actix_web::Result<HttpResponse> {
-    let json_string = match String::from_utf8(bytes.to_vec()) {
-        Ok(content) => content,
-        Err(err) => {
-            return not_found_response(format!("Failed to parse input as UTF-8 string: {err:?}"))
-                .await;
+    let system_id = query.target.system_id();
+    match data::messages().pointer(&format!("vehicles/{system_id}/components")) {
+        Some(components) => {
+            let summary = batteries::summary(system_id, &components, query.target.component_id());
+            ok_response(parse_query(&summary)).await
         }
-    };
+        None => not_found_response(format!("\"Vehicle {system_id} not found\"")).await,
+    }
+}
 
-    debug!("MAVLink post received: {json_string}");
+#[api_v2_operation]
+/// Commands sent by the server waiting for their COMMAND_ACK, in the order they were sent
+pub async fn commands_pending() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&command_tracker::pending())).await
+}
 
-    if let Ok(content) =
-        json5::from_str::<data::MAVLinkMessage<mavlink::ardupilotmega::MavMessage>>(&json_string)
-    {
-        match data.lock().unwrap().send(&content.header, &content.message) {
-            Ok(_result) => {
-                data::update((content.header, content.message));
-                return HttpResponse::Ok().await;
-            }
-            Err(err) => {
-                return not_found_response(format!("Failed to send message: {err:?}")).await
-            }
+#[api_v2_operation]
+/// State and COMMAND_ACK of a command, by the correlation id returned when it was sent
+pub async fn command_status(id: web::Path<u64>) -> actix_web::Result<HttpResponse> {
+    let id = id.into_inner();
+    match command_tracker::command(id) {
+        Some(command) => ok_response(parse_query(&command)).await,
+        None => not_found_response(format!("\"Command {id} not found\"")).await,
+    }
+}
+
+#[api_v2_operation]
+/// Estimator health with named flags and variances, from EKF_STATUS_REPORT and ESTIMATOR_STATUS
+pub async fn helper_ekf(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let description = ekf::describe(
+        query.message("EKF_STATUS_REPORT").as_ref(),
+        query.message("ESTIMATOR_STATUS").as_ref(),
+    );
+    match description {
+        Some(description) => ok_response(parse_query(&description)).await,
+        None => {
+            not_found_response("\"No EKF_STATUS_REPORT or ESTIMATOR_STATUS received\"".into()).await
         }
     }
+}
 
-    if let Ok(content) =
-        json5::from_str::<data::MAVLinkMessage<mavlink::common::MavMessage>>(&json_string)
-    {
-        let content_ardupilotmega = mavlink::ardupilotmega::MavMessage::common(content.message);
-        match data
-            .lock()
-            .unwrap()
-            .send(&content.header, &content_ardupilotmega)
-        {
-            Ok(_result) => {
-                data::update((content.header, content_ardupilotmega));
-                return HttpResponse::Ok().await;
-            }
-            Err(err) => {
-                return not_found_response(format!("Failed to send message: {err:?}")).await;
-            }
+#[api_v2_operation]
+/// Packet loss of each component, from the message sequence numbers, and telemetry radios state from RADIO_STATUS
+pub async fn helper_link() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&link::quality())).await
+}
+
+#[api_v2_operation]
+/// Table of the ESCs with RPM, temperature, voltage and current, from ESC_TELEMETRY_* and ESC_STATUS
+pub async fn helper_escs(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let system_id = query.target.system_id();
+    let component_id = query.target.component_id();
+    match data::messages().pointer(&format!(
+        "vehicles/{system_id}/components/{component_id}/messages"
+    )) {
+        Some(messages) => {
+            let esc_status = escs::esc_status(system_id, component_id);
+            ok_response(parse_query(&escs::summary(&messages, &esc_status))).await
+        }
+        None => {
+            not_found_response(format!(
+                "\"Component {component_id} of vehicle {system_id} not found\""
+            ))
+            .await
         }
     }
+}
 
-    not_found_response(String::from(
-        "Failed to parse message, not a valid MAVLinkMessage.",
-    ))
-    .await
+#[api_v2_operation]
+/// Distance and bearing to home, 3D ground speed, climb rate averages and flight time
+pub async fn helper_derived(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let derived = derived::describe(
+        query.system_id.unwrap_or(1),
+        query.message("GLOBAL_POSITION_INT").as_ref(),
+        query.message("HOME_POSITION").as_ref(),
+    );
+    ok_response(parse_query(&derived)).await
 }
 
 #[api_v2_operation]
-/// Websocket used to receive and send MAVLink messages asynchronously
-pub async fn websocket(
-    req: HttpRequest,
-    query: web::Query<WebsocketQuery>,
-    stream: web::Payload,
-) -> Result<HttpResponse, actix_web::Error> {
-    let filter = match query.into_inner().filter {
-        Some(filter) => filter,
-        _ => ".*".to_owned(),
-    };
+/// Attitude as Euler angles, quaternion and rotation matrix, from ATTITUDE_QUATERNION or ATTITUDE
+pub async fn helper_attitude(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let description = attitude::describe(
+        query.message("ATTITUDE").as_ref(),
+        query.message("ATTITUDE_QUATERNION").as_ref(),
+    );
+    match description {
+        Some(description) => ok_response(parse_query(&description)).await,
+        None => not_found_response("\"No ATTITUDE or ATTITUDE_QUATERNION received\"".into()).await,
+    }
+}
 
-    debug!("New websocket with filter {:#?}", &filter);
+#[api_v2_operation]
+/// Attitude, speeds, altitude, GPS fix and battery in a single object, for overlays
+pub async fn helper_hud(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let hud = hud::summary(
+        query.message("ATTITUDE").as_ref(),
+        query.message("VFR_HUD").as_ref(),
+        query.message("GPS_RAW_INT").as_ref(),
+        query.message("SYS_STATUS").as_ref(),
+    );
+    ok_response(parse_query(&hud)).await
+}
 
-    ws::start(WebsocketActor::new(filter), &req, stream)
+#[derive(Apiv2Schema, Deserialize)]
+pub struct EventsQuery {
+    /// Only the events of this vehicle
+    system_id: Option<u8>,
 }
 
-async fn not_found_response(message: String) -> actix_web::Result<HttpResponse> {
-    HttpResponse::NotFound()
-        .content_type("application/json")
-        .body(message)
-        .await
+#[api_v2_operation]
+/// Events received with the MAVLink events protocol in chronological order, lost events are requested again
+pub async fn helper_events(query: web::Query<EventsQuery>) -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&events::events(query.system_id))).await
 }
 
-async fn ok_response(message: String) -> actix_web::Result<HttpResponse> {
+#[api_v2_operation]
+/// Aircraft reported by ADSB_VEHICLE messages
+pub async fn helper_traffic() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&traffic::aircrafts())).await
+}
+
+#[api_v2_operation]
+/// Aircraft reported by ADSB_VEHICLE messages as a GeoJSON FeatureCollection
+pub async fn helper_traffic_geojson() -> actix_web::Result<HttpResponse> {
     HttpResponse::Ok()
-        .content_type("application/json")
-        .body(message)
+        .content_type("application/geo+json")
+        .body(parse_query(&traffic::feature_collection()))
         .await
 }
+
+#[api_v2_operation]
+/// Provides the clock offset of each vehicle, estimated with TIMESYNC
+pub async fn helper_timesync() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&timesync::states())).await
+}
+
+#[api_v2_operation]
+/// Returns the fields of a MAVLink message with their types, units, descriptions and enums
+pub async fn helper_message_info(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+    let name = req.match_info().query("name");
+    match definitions::message_info(name) {
+        Some(info) => ok_response(parse_query(&info)).await,
+        None => not_found_response(format!("\"Unknown message: {name}\"")).await,
+    }
+}
+
+#[api_v2_operation]
+/// Requests a MAVLink message from the vehicle with MAV_CMD_REQUEST_MESSAGE and waits for it
+pub async fn helper_request(
+    req: HttpRequest,
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MAVLinkRequestQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let name = req.match_info().query("name").to_string();
+    request_response(
+        &data,
+        name,
+        query.target.system_id(),
+        query.target.component_id(),
+        std::time::Duration::from_millis(query.timeout_ms.unwrap_or(1000)),
+    )
+    .await
+}
+
+// Request a message and answer with it when it arrives
+async fn request_response(
+    data: &MAVLinkVehicleArcMutex,
+    name: String,
+    system_id: u8,
+    component_id: u8,
+    timeout: std::time::Duration,
+) -> actix_web::Result<HttpResponse> {
+    match request_value(data, name, system_id, component_id, timeout).await {
+        Ok(mut value) => {
+            output::apply(&mut value, &OutputOptions::global());
+            ok_response(parse_query(&value)).await
+        }
+        Err(response) => Ok(response),
+    }
+}
+
+// Request a message and wait for it, failures are returned as their response
+async fn request_value(
+    data: &MAVLinkVehicleArcMutex,
+    name: String,
+    system_id: u8,
+    component_id: u8,
+    timeout: std::time::Duration,
+) -> Result<serde_json::Value, HttpResponse> {
+    let message_id = match mavlink::ardupilotmega::MavMessage::message_id_from_name(&name) {
+        Ok(id) => id,
+        Err(error) => {
+            return Err(HttpResponse::NotFound()
+                .content_type("application/json")
+                .body(parse_query(&error)))
+        }
+    };
+
+    let subscription = {
+        let name = name.clone();
+        waiter::subscribe(move |header, message| {
+            header.system_id == system_id && message.message_name() == name
+        })
+    };
+
+    let request = commands::request_message(system_id, component_id, message_id);
+    let result = data.lock().unwrap().send_default(&request);
+    if let Err(error) = result {
+        return Err(HttpResponse::InternalServerError()
+            .content_type("application/json")
+            .body(parse_query(&format!("Failed to send request: {error}"))));
+    }
+
+    let received = web::block(move || subscription.wait(timeout).ok_or(())).await;
+    match received {
+        Ok((header, message)) => Ok(output::to_value(&data::MAVLinkMessage { header, message })),
+        Err(_) => Err(HttpResponse::GatewayTimeout()
+            .content_type("application/json")
+            .body(parse_query(&format!(
+                "{name} was not received in {}ms",
+                timeout.as_millis()
+            )))),
+    }
+}
+
+#[api_v2_operation]
+/// Requests AUTOPILOT_VERSION and describes the capabilities and versions of the autopilot
+pub async fn helper_autopilot(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MAVLinkRequestQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let value = request_value(
+        &data,
+        "AUTOPILOT_VERSION".into(),
+        query.target.system_id(),
+        query.target.component_id(),
+        std::time::Duration::from_millis(query.timeout_ms.unwrap_or(1000)),
+    )
+    .await;
+
+    match value {
+        Ok(value) => ok_response(parse_query(&autopilot::describe(&value["message"]))).await,
+        Err(response) => Ok(response),
+    }
+}
+
+#[api_v2_operation]
+/// Requests COMPONENT_INFORMATION and downloads its metadata files with MAVLink FTP, E.g: parameters and actuators
+pub async fn helper_component_information(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MAVLinkRequestQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let system_id = query.target.system_id();
+    let component_id = query.target.component_id();
+    let timeout = std::time::Duration::from_millis(query.timeout_ms.unwrap_or(1000));
+    let value = match request_value(
+        &data,
+        "COMPONENT_INFORMATION".into(),
+        system_id,
+        component_id,
+        timeout,
+    )
+    .await
+    {
+        Ok(value) => value,
+        Err(response) => return Ok(response),
+    };
+
+    let vehicle = data.get_ref().clone();
+    let result = web::block(move || {
+        component_information::fetch(
+            &vehicle,
+            system_id,
+            component_id,
+            &value["message"],
+            timeout,
+        )
+    })
+    .await;
+    match result {
+        Ok(information) => ok_response(parse_query(&information)).await,
+        Err(error) => {
+            match &error {
+                BlockingError::Error(MetadataError::Ftp(FtpError::Timeout(_))) => {
+                    HttpResponse::GatewayTimeout()
+                }
+                BlockingError::Error(MetadataError::Unavailable(_)) => HttpResponse::NotFound(),
+                _ => HttpResponse::InternalServerError(),
+            }
+            .content_type("application/json")
+            .body(parse_query(&error.to_string()))
+            .await
+        }
+    }
+}
+
+// Send a command and answer with its COMMAND_ACK
+async fn command_response(
+    data: &MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    command: mavlink::common::MavCmd,
+    params: [f32; 7],
+    options: &CommandOptions,
+) -> actix_web::Result<HttpResponse> {
+    let vehicle = data.clone();
+    let (timeout, retries) = (options.timeout(), options.retries());
+    let result = web::block(move || {
+        commands::send_command(
+            &vehicle,
+            system_id,
+            component_id,
+            command,
+            params,
+            timeout,
+            retries,
+        )
+    })
+    .await;
+    ack_response(result, command, timeout).await
+}
+
+// Answer with the COMMAND_ACK of a command, or why it was not received
+async fn ack_response(
+    result: Result<mavlink::common::COMMAND_ACK_DATA, BlockingError<commands::CommandError>>,
+    command: mavlink::common::MavCmd,
+    timeout: std::time::Duration,
+) -> actix_web::Result<HttpResponse> {
+    match result {
+        Ok(ack) => {
+            let mut value = output::to_value(&ack);
+            output::apply(&mut value, &OutputOptions::global());
+            ok_response(parse_query(&value)).await
+        }
+        Err(BlockingError::Error(commands::CommandError::Timeout(attempts))) => {
+            HttpResponse::GatewayTimeout()
+                .content_type("application/json")
+                .body(parse_query(&format!(
+                    "No COMMAND_ACK for {command:?} received in {}ms, after {attempts} attempts",
+                    timeout.as_millis()
+                )))
+                .await
+        }
+        Err(BlockingError::Error(commands::CommandError::InProgress)) => {
+            HttpResponse::GatewayTimeout()
+                .content_type("application/json")
+                .body(parse_query(&format!(
+                    "{command:?} is in progress, no final COMMAND_ACK received in {}ms",
+                    timeout.as_millis()
+                )))
+                .await
+        }
+        Err(error) => {
+            HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&error.to_string()))
+                .await
+        }
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct CameraQuery {
+    // The camera, MAV_COMP_ID_CAMERA (100), is the target by default
+    #[serde(flatten)]
+    target: Target,
+    #[serde(flatten)]
+    options: CommandOptions,
+    /// Seconds between images, for image capture
+    interval: Option<f32>,
+    /// Number of images, 1 by default and 0 to capture until stopped
+    count: Option<u32>,
+}
+
+impl CameraQuery {
+    fn target(&self) -> (u8, u8, std::time::Duration) {
+        (
+            self.target.system_id(),
+            self.target.component_id_or(100),
+            self.options.timeout(),
+        )
+    }
+}
+
+#[api_v2_operation]
+/// Requests CAMERA_INFORMATION from the camera
+pub async fn camera_information(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<CameraQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (system_id, component_id, timeout) = query.target();
+    request_response(
+        &data,
+        "CAMERA_INFORMATION".into(),
+        system_id,
+        component_id,
+        timeout,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Requests CAMERA_SETTINGS from the camera
+pub async fn camera_settings(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<CameraQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (system_id, component_id, timeout) = query.target();
+    request_response(
+        &data,
+        "CAMERA_SETTINGS".into(),
+        system_id,
+        component_id,
+        timeout,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Captures still images with MAV_CMD_IMAGE_START_CAPTURE
+pub async fn camera_capture(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<CameraQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (system_id, component_id, _) = query.target();
+    let interval = query.interval.unwrap_or(0.0);
+    let count = query.count.unwrap_or(1) as f32;
+    command_response(
+        &data,
+        system_id,
+        component_id,
+        mavlink::common::MavCmd::MAV_CMD_IMAGE_START_CAPTURE,
+        [0.0, interval, count, 0.0, 0.0, 0.0, 0.0],
+        &query.options,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Starts video recording with MAV_CMD_VIDEO_START_CAPTURE
+pub async fn camera_video_start(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<CameraQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (system_id, component_id, _) = query.target();
+    command_response(
+        &data,
+        system_id,
+        component_id,
+        mavlink::common::MavCmd::MAV_CMD_VIDEO_START_CAPTURE,
+        [0.0; 7],
+        &query.options,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Stops video recording with MAV_CMD_VIDEO_STOP_CAPTURE
+pub async fn camera_video_stop(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<CameraQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let (system_id, component_id, _) = query.target();
+    command_response(
+        &data,
+        system_id,
+        component_id,
+        mavlink::common::MavCmd::MAV_CMD_VIDEO_STOP_CAPTURE,
+        [0.0; 7],
+        &query.options,
+    )
+    .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct MissionQuery {
+    #[serde(flatten)]
+    target: Target,
+    /// Time to wait for each answer of the vehicle, 1000 milliseconds by default
+    timeout_ms: Option<u64>,
+}
+
+impl MissionQuery {
+    fn target(&self, mission_type: mavlink::common::MavMissionType) -> mission::Target {
+        mission::Target {
+            system_id: self.target.system_id(),
+            component_id: self.target.component_id(),
+            mission_type,
+        }
+    }
+
+    fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_ms.unwrap_or(1000))
+    }
+}
+
+fn mission_error_response(error: BlockingError<mission::MissionError>) -> HttpResponse {
+    match error {
+        BlockingError::Error(mission::MissionError::Timeout(_)) => HttpResponse::GatewayTimeout(),
+        BlockingError::Error(mission::MissionError::Rejected(_)) => HttpResponse::Conflict(),
+        _ => HttpResponse::InternalServerError(),
+    }
+    .content_type("application/json")
+    .body(parse_query(&error.to_string()))
+}
+
+async fn mission_download_response(
+    data: &MAVLinkVehicleArcMutex,
+    query: &MissionQuery,
+    mission_type: mavlink::common::MavMissionType,
+) -> actix_web::Result<HttpResponse> {
+    let vehicle = data.clone();
+    let target = query.target(mission_type);
+    let timeout = query.timeout();
+    match web::block(move || mission::download(&vehicle, target, timeout)).await {
+        Ok(items) => {
+            let mut value = output::to_value(&items);
+            output::apply(&mut value, &OutputOptions::global());
+            ok_response(parse_query(&value)).await
+        }
+        Err(error) => Ok(mission_error_response(error)),
+    }
+}
+
+async fn mission_upload_response(
+    data: &MAVLinkVehicleArcMutex,
+    query: &MissionQuery,
+    mission_type: mavlink::common::MavMissionType,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let items: Vec<MissionItem> = match serde_json::from_slice(&bytes) {
+        Ok(items) => items,
+        Err(error) => {
+            return bad_request(&format!("Failed to parse items: {error}")).await;
+        }
+    };
+
+    let vehicle = data.clone();
+    let target = query.target(mission_type);
+    let timeout = query.timeout();
+    match web::block(move || mission::upload(&vehicle, target, &items, timeout)).await {
+        Ok(()) => HttpResponse::Ok().await,
+        Err(error) => Ok(mission_error_response(error)),
+    }
+}
+
+#[api_v2_operation]
+/// Downloads the geofence items from the vehicle
+pub async fn fence_download(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MissionQuery>,
+) -> actix_web::Result<HttpResponse> {
+    mission_download_response(
+        &data,
+        &query,
+        mavlink::common::MavMissionType::MAV_MISSION_TYPE_FENCE,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Uploads the geofence items to the vehicle, replacing the current fence
+pub async fn fence_upload(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MissionQuery>,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    mission_upload_response(
+        &data,
+        &query,
+        mavlink::common::MavMissionType::MAV_MISSION_TYPE_FENCE,
+        bytes,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Downloads the rally points from the vehicle
+pub async fn rally_download(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MissionQuery>,
+) -> actix_web::Result<HttpResponse> {
+    mission_download_response(
+        &data,
+        &query,
+        mavlink::common::MavMissionType::MAV_MISSION_TYPE_RALLY,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Uploads the rally points to the vehicle, replacing the current ones
+pub async fn rally_upload(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MissionQuery>,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    mission_upload_response(
+        &data,
+        &query,
+        mavlink::common::MavMissionType::MAV_MISSION_TYPE_RALLY,
+        bytes,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Downloads the mission, geofence and rally points as a QGroundControl .plan file
+pub async fn plan_download(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MissionQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let vehicle = data.get_ref().clone();
+    let system_id = query.target.system_id();
+    let component_id = query.target.component_id();
+    let timeout = query.timeout();
+    match web::block(move || plan::download(&vehicle, system_id, component_id, timeout)).await {
+        Ok(items) => {
+            let is_ardupilot = plan::is_ardupilot(system_id, component_id);
+            ok_response(parse_query(&plan::to_plan(&items, is_ardupilot))).await
+        }
+        Err(error) => Ok(mission_error_response(error)),
+    }
+}
+
+#[api_v2_operation]
+/// Uploads a QGroundControl .plan file, replacing the mission, geofence and rally points
+pub async fn plan_upload(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MissionQuery>,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let system_id = query.target.system_id();
+    let component_id = query.target.component_id();
+    let items = serde_json::from_slice(&bytes)
+        .map_err(|error| error.to_string())
+        .and_then(|file| plan::from_plan(&file, plan::is_ardupilot(system_id, component_id)));
+    let items = match items {
+        Ok(items) => items,
+        Err(error) => {
+            return bad_request(&format!("Failed to parse plan: {error}")).await;
+        }
+    };
+
+    let vehicle = data.get_ref().clone();
+    let timeout = query.timeout();
+    match web::block(move || plan::upload(&vehicle, system_id, component_id, &items, timeout)).await
+    {
+        Ok(()) => HttpResponse::Ok().await,
+        Err(error) => Ok(mission_error_response(error)),
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct ParametersQuery {
+    #[serde(flatten)]
+    target: Target,
+    /// Time to wait for each answer of the vehicle, 1000 milliseconds by default
+    timeout_ms: Option<u64>,
+}
+
+impl ParametersQuery {
+    fn target(&self) -> params::Target {
+        params::Target {
+            system_id: self.target.system_id(),
+            component_id: self.target.component_id(),
+        }
+    }
+
+    fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_ms.unwrap_or(1000))
+    }
+}
+
+fn params_error_response(error: BlockingError<params::ParamError>) -> HttpResponse {
+    match error {
+        BlockingError::Error(params::ParamError::Timeout(_)) => HttpResponse::GatewayTimeout(),
+        _ => HttpResponse::InternalServerError(),
+    }
+    .content_type("application/json")
+    .body(parse_query(&error.to_string()))
+}
+
+#[api_v2_operation]
+/// Downloads the parameters of the vehicle as a QGroundControl .params file
+pub async fn parameters_export(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<ParametersQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let vehicle = data.get_ref().clone();
+    let target = query.target();
+    let timeout = query.timeout();
+    match web::block(move || params::fetch_all(&vehicle, target, timeout)).await {
+        Ok(parameters) => {
+            HttpResponse::Ok()
+                .content_type("text/plain")
+                .body(params::to_params_file(target, &parameters))
+                .await
+        }
+        Err(error) => Ok(params_error_response(error)),
+    }
+}
+
+#[api_v2_operation]
+/// Applies the parameters of a QGroundControl .params file that differ from the vehicle ones
+pub async fn parameters_import(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<ParametersQuery>,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let file_parameters = match params::from_params_file(&String::from_utf8_lossy(&bytes)) {
+        Ok(file_parameters) => file_parameters,
+        Err(error) => {
+            return bad_request(&format!("Failed to parse parameters file: {error}")).await;
+        }
+    };
+
+    let vehicle = data.get_ref().clone();
+    let target = query.target();
+    let timeout = query.timeout();
+    match web::block(move || params::import(&vehicle, target, &file_parameters, timeout)).await {
+        Ok(reports) => ok_response(parse_query(&reports)).await,
+        Err(error) => Ok(params_error_response(error)),
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GimbalQuery {
+    // The gimbal device, MAV_COMP_ID_GIMBAL (154), is the target by default
+    #[serde(flatten)]
+    target: Target,
+    /// Time to wait for the message, 1000 milliseconds by default
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GimbalControl {
+    #[serde(flatten)]
+    target: Target,
+    /// Pitch angle in degrees, negative points down
+    pitch: f32,
+    /// Yaw angle in degrees, relative to the vehicle heading
+    yaw: f32,
+    /// Protocol used to control the gimbal, 'v2' (gimbal manager) by default or 'mount'
+    protocol: Option<String>,
+}
+
+#[api_v2_operation]
+/// Last HOME_POSITION of the vehicle, requested when it was not received
+pub async fn home_position(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<MAVLinkRequestQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let system_id = query.target.system_id();
+    let component_id = query.target.component_id();
+    let stored = data::messages().pointer(&format!(
+        "vehicles/{system_id}/components/{component_id}/messages/HOME_POSITION/message"
+    ));
+    let mut value = match stored {
+        Some(value) => value,
+        None => match request_value(
+            &data,
+            "HOME_POSITION".into(),
+            system_id,
+            component_id,
+            std::time::Duration::from_millis(query.timeout_ms.unwrap_or(1000)),
+        )
+        .await
+        {
+            Ok(value) => value["message"].clone(),
+            Err(response) => return Ok(response),
+        },
+    };
+    output::apply(&mut value, &OutputOptions::global());
+    ok_response(parse_query(&value)).await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct HomeControl {
+    #[serde(flatten)]
+    target: Target,
+    /// Latitude in degrees, the current position is used without coordinates
+    latitude: Option<f64>,
+    /// Longitude in degrees
+    longitude: Option<f64>,
+    /// Altitude in meters (MSL)
+    altitude: Option<f32>,
+    #[serde(flatten)]
+    options: CommandOptions,
+}
+
+#[api_v2_operation]
+/// Sets the home position to the coordinates or to the current position with MAV_CMD_DO_SET_HOME as COMMAND_INT
+pub async fn home_position_set(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    control: web::Json<HomeControl>,
+) -> actix_web::Result<HttpResponse> {
+    // COMMAND_INT keeps the coordinates in degE7, a f32 param of COMMAND_LONG loses meters
+    let (use_current, x, y, z) = match (control.latitude, control.longitude, control.altitude) {
+        (None, None, None) => (1.0, 0, 0, 0.0),
+        (Some(latitude), Some(longitude), Some(altitude)) => (
+            0.0,
+            (latitude * 1e7).round() as i32,
+            (longitude * 1e7).round() as i32,
+            altitude,
+        ),
+        _ => {
+            return bad_request("latitude, longitude and altitude should be provided together")
+                .await;
+        }
+    };
+
+    let command = mavlink::common::MavCmd::MAV_CMD_DO_SET_HOME;
+    let message = commands::command_int(
+        control.target.system_id(),
+        control.target.component_id(),
+        mavlink::common::MavFrame::MAV_FRAME_GLOBAL,
+        command,
+        [use_current, 0.0, 0.0, 0.0],
+        (x, y, z),
+    );
+    let (timeout, retries) = (control.options.timeout(), control.options.retries());
+    let vehicle = data.get_ref().clone();
+    let result =
+        web::block(move || commands::send_message(&vehicle, None, &message, timeout, retries))
+            .await;
+    ack_response(result, command, timeout).await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct GotoControl {
+    #[serde(flatten)]
+    target: Target,
+    /// Latitude in degrees
+    latitude: f64,
+    /// Longitude in degrees
+    longitude: f64,
+    /// Altitude in meters, relative to home by default
+    altitude: f32,
+    /// Reference of the altitude, 'relative' (to home) by default or 'amsl'
+    altitude_reference: Option<String>,
+    /// Ground speed in m/s
+    speed: Option<f32>,
+    /// Heading in degrees
+    yaw: Option<f32>,
+    #[serde(flatten)]
+    options: CommandOptions,
+}
+
+#[api_v2_operation]
+/// Flies to the position, with SET_POSITION_TARGET_GLOBAL_INT for ArduPilot or MAV_CMD_DO_REPOSITION
+pub async fn helper_goto(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    control: web::Json<GotoControl>,
+) -> actix_web::Result<HttpResponse> {
+    let relative_altitude =
+        match guided::is_relative_altitude(control.altitude_reference.as_deref()) {
+            Ok(relative_altitude) => relative_altitude,
+            Err(error) => {
+                return bad_request(&error).await;
+            }
+        };
+    let position = guided::Position {
+        latitude: control.latitude,
+        longitude: control.longitude,
+        altitude: control.altitude,
+        relative_altitude,
+    };
+    let system_id = control.target.system_id();
+    let component_id = control.target.component_id();
+
+    // ArduPilot does not acknowledge position targets, they are only sent
+    if plan::is_ardupilot(system_id, component_id) {
+        let mut messages = vec![];
+        if let Some(speed) = control.speed {
+            messages.push(guided::change_speed(system_id, component_id, speed));
+        }
+        messages.push(guided::set_position_target(
+            system_id,
+            component_id,
+            &position,
+            control.yaw,
+        ));
+
+        let result = {
+            let vehicle = data.lock().unwrap();
+            messages
+                .iter()
+                .try_for_each(|message| vehicle.send_default(message).map(|_| ()))
+        };
+        if let Err(error) = result {
+            return HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&format!("Failed to send goto: {error}")))
+                .await;
+        }
+        return HttpResponse::Ok().await;
+    }
+
+    let command = mavlink::common::MavCmd::MAV_CMD_DO_REPOSITION;
+    let message = guided::reposition(
+        system_id,
+        component_id,
+        &position,
+        control.speed,
+        control.yaw,
+    );
+    let (timeout, retries) = (control.options.timeout(), control.options.retries());
+    let vehicle = data.get_ref().clone();
+    let result =
+        web::block(move || commands::send_message(&vehicle, None, &message, timeout, retries))
+            .await;
+    ack_response(result, command, timeout).await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct RebootQuery {
+    #[serde(flatten)]
+    target: Target,
+    #[serde(flatten)]
+    options: CommandOptions,
+    /// Reboot and keep the autopilot in the bootloader, false by default
+    bootloader: Option<bool>,
+}
+
+#[api_v2_operation]
+/// Reboots the autopilot with MAV_CMD_PREFLIGHT_REBOOT_SHUTDOWN, the link is recovered when the vehicle comes back
+pub async fn helper_reboot(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<RebootQuery>,
+) -> actix_web::Result<HttpResponse> {
+    // 1 reboots the autopilot and 3 reboots it to the bootloader
+    let action = if query.bootloader.unwrap_or(false) {
+        3.0
+    } else {
+        1.0
+    };
+    command_response(
+        &data,
+        query.target.system_id(),
+        query.target.component_id(),
+        mavlink::common::MavCmd::MAV_CMD_PREFLIGHT_REBOOT_SHUTDOWN,
+        [action, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        &query.options,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Shuts down the autopilot with MAV_CMD_PREFLIGHT_REBOOT_SHUTDOWN
+pub async fn helper_shutdown(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<RebootQuery>,
+) -> actix_web::Result<HttpResponse> {
+    command_response(
+        &data,
+        query.target.system_id(),
+        query.target.component_id(),
+        mavlink::common::MavCmd::MAV_CMD_PREFLIGHT_REBOOT_SHUTDOWN,
+        [2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        &query.options,
+    )
+    .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct MotorTestControl {
+    /// Motor number, starting at 1
+    motor: u8,
+    /// Throttle value, in the throttle_type
+    throttle: f32,
+    /// percent (0 to 100) by default, pwm or pilot (throttle of the RC input)
+    throttle_type: Option<String>,
+    /// Time spinning each motor, in seconds
+    duration: f32,
+    /// Number of motors tested in sequence, 1 by default
+    count: Option<u8>,
+    #[serde(flatten)]
+    target: Target,
+    #[serde(flatten)]
+    options: CommandOptions,
+}
+
+#[api_v2_operation]
+/// Spins a motor with MAV_CMD_DO_MOTOR_TEST, requires --allow-motor-test
+pub async fn helper_motor_test(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    control: web::Json<MotorTestControl>,
+) -> actix_web::Result<HttpResponse> {
+    if !cli::is_motor_test_allowed() {
+        return HttpResponse::Forbidden()
+            .content_type("application/json")
+            .body(parse_query(
+                &"Motor test is disabled, mavlink2rest should run with --allow-motor-test",
+            ))
+            .await;
+    }
+
+    // MOTOR_TEST_THROTTLE_TYPE
+    let throttle_type = match control.throttle_type.as_deref() {
+        None | Some("percent") if (0.0..=100.0).contains(&control.throttle) => 0.0,
+        Some("pwm") => 1.0,
+        Some("pilot") => 2.0,
+        None | Some("percent") => {
+            return bad_request("Throttle percent should be between 0 and 100").await
+        }
+        Some(throttle_type) => {
+            return bad_request(&format!(
+                "Unknown throttle type {throttle_type}, it should be percent, pwm or pilot"
+            ))
+            .await
+        }
+    };
+    if control.motor == 0 || control.duration <= 0.0 {
+        return bad_request("Motor should start at 1 and the duration should be positive").await;
+    }
+
+    command_response(
+        &data,
+        control.target.system_id(),
+        control.target.component_id(),
+        mavlink::common::MavCmd::MAV_CMD_DO_MOTOR_TEST,
+        [
+            control.motor as f32,
+            throttle_type,
+            control.throttle,
+            control.duration,
+            control.count.unwrap_or(1) as f32,
+            0.0,
+            0.0,
+        ],
+        &control.options,
+    )
+    .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct CalibrationQuery {
+    /// Sensor to calibrate: gyro, accelerometer, level, compass or barometer
+    sensor: String,
+    #[serde(flatten)]
+    target: Target,
+    #[serde(flatten)]
+    options: CommandOptions,
+}
+
+#[api_v2_operation]
+/// Starts the calibration of a sensor with MAV_CMD_PREFLIGHT_CALIBRATION
+pub async fn helper_calibration(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<CalibrationQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let params = match calibration::params(&query.sensor) {
+        Some(params) => params,
+        None => {
+            return bad_request(&format!(
+                "Unknown sensor {}, available: {}",
+                query.sensor,
+                calibration::SENSORS.join(", ")
+            ))
+            .await
+        }
+    };
+    command_response(
+        &data,
+        query.target.system_id(),
+        query.target.component_id(),
+        mavlink::common::MavCmd::MAV_CMD_PREFLIGHT_CALIBRATION,
+        params,
+        &query.options,
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Server-Sent Events stream of the calibration progress: COMMAND_ACK, STATUSTEXT, MAG_CAL_PROGRESS and MAG_CAL_REPORT
+pub async fn calibration_sse() -> Result<HttpResponse, actix_web::Error> {
+    let stream = sse::subscribe(
+        Some(calibration::progress_filter()),
+        None,
+        Default::default(),
+    )
+    .map(Ok::<_, actix_web::Error>);
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(stream)
+        .await
+}
+
+// Flight stack of the vehicle, detected from its HEARTBEAT
+fn vehicle_firmware(system_id: u8, component_id: u8) -> Result<modes::Firmware, String> {
+    let autopilot = vehicles::autopilot(system_id, component_id);
+    let mav_type = vehicles::mav_type(system_id, component_id);
+    match (autopilot, mav_type) {
+        (Some(autopilot), Some(mav_type)) => modes::firmware(&autopilot, &mav_type)
+            .ok_or_else(|| format!("Flight modes of {autopilot} are not known")),
+        _ => Err(format!(
+            "No HEARTBEAT received from {system_id}/{component_id}"
+        )),
+    }
+}
+
+#[api_v2_operation]
+/// Current flight mode name, from the last HEARTBEAT, and the available modes
+pub async fn mode_status(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let firmware = match vehicle_firmware(query.target.system_id(), query.target.component_id()) {
+        Ok(firmware) => firmware,
+        Err(error) => return not_found_response(parse_query(&error)).await,
+    };
+
+    let custom_mode = query
+        .message("HEARTBEAT")
+        .and_then(|heartbeat| heartbeat["custom_mode"].as_u64())
+        .map(|custom_mode| custom_mode as u32);
+    let status = serde_json::json!({
+        "mode": custom_mode.and_then(|custom_mode| modes::name(firmware, custom_mode)),
+        "custom_mode": custom_mode,
+        "modes": modes::modes(firmware).iter().map(|(name, _)| name).collect::<Vec<_>>(),
+    });
+    ok_response(parse_query(&status)).await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct ModeControl {
+    #[serde(flatten)]
+    target: Target,
+    /// Name of the flight mode, E.g: GUIDED, AUTO or POSCTL
+    mode: String,
+    #[serde(flatten)]
+    options: CommandOptions,
+}
+
+#[api_v2_operation]
+/// Changes the flight mode by name with MAV_CMD_DO_SET_MODE, using the modes of the detected autopilot and vehicle type
+pub async fn mode_set(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    control: web::Json<ModeControl>,
+) -> actix_web::Result<HttpResponse> {
+    let system_id = control.target.system_id();
+    let component_id = control.target.component_id();
+    let firmware = match vehicle_firmware(system_id, component_id) {
+        Ok(firmware) => firmware,
+        Err(error) => {
+            return HttpResponse::Conflict()
+                .content_type("application/json")
+                .body(parse_query(&error))
+                .await;
+        }
+    };
+
+    let custom_mode = match modes::custom_mode(firmware, &control.mode) {
+        Some(custom_mode) => custom_mode,
+        None => {
+            let available: Vec<&str> = modes::modes(firmware)
+                .iter()
+                .map(|(name, _)| *name)
+                .collect();
+            return bad_request(&format!(
+                "Unknown mode {}, available modes: {}",
+                control.mode,
+                available.join(", ")
+            ))
+            .await;
+        }
+    };
+
+    command_response(
+        &data,
+        system_id,
+        component_id,
+        mavlink::common::MavCmd::MAV_CMD_DO_SET_MODE,
+        modes::set_mode_params(firmware, custom_mode),
+        &control.options,
+    )
+    .await
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct SetpointControl {
+    #[serde(flatten)]
+    target: Target,
+    /// Latitude in degrees, for position targets
+    latitude: Option<f64>,
+    /// Longitude in degrees, for position targets
+    longitude: Option<f64>,
+    /// Altitude in meters, relative to home by default, for position targets
+    altitude: Option<f32>,
+    /// Reference of the altitude, 'relative' (to home) by default or 'amsl'
+    altitude_reference: Option<String>,
+    /// North velocity in m/s, for velocity targets
+    vx: Option<f32>,
+    /// East velocity in m/s, for velocity targets
+    vy: Option<f32>,
+    /// Down velocity in m/s, for velocity targets
+    vz: Option<f32>,
+    /// Heading in degrees
+    yaw: Option<f32>,
+    /// Rate of the setpoints, 10 Hz by default, from 2 to 50 Hz
+    rate_hz: Option<f32>,
+}
+
+impl SetpointControl {
+    fn setpoint(&self) -> Result<setpoints::Target, String> {
+        let relative_altitude = guided::is_relative_altitude(self.altitude_reference.as_deref())?;
+        match (
+            (self.latitude, self.longitude, self.altitude),
+            (self.vx, self.vy, self.vz),
+        ) {
+            ((Some(latitude), Some(longitude), Some(altitude)), (None, None, None)) => {
+                Ok(setpoints::Target::Position(guided::Position {
+                    latitude,
+                    longitude,
+                    altitude,
+                    relative_altitude,
+                }))
+            }
+            ((None, None, None), (Some(vx), Some(vy), Some(vz))) => {
+                Ok(setpoints::Target::Velocity { vx, vy, vz })
+            }
+            _ => Err("The target should be a position (latitude, longitude and altitude) or a velocity (vx, vy and vz)".into()),
+        }
+    }
+}
+
+#[api_v2_operation]
+/// Streams a position or velocity target to the vehicle until it is updated or stopped, for offboard and guided control
+pub async fn setpoint_start(
+    control: web::Json<SetpointControl>,
+) -> actix_web::Result<HttpResponse> {
+    let target = match control.setpoint() {
+        Ok(target) => target,
+        Err(error) => {
+            return bad_request(&error).await;
+        }
+    };
+
+    setpoints::start(
+        control.target.system_id(),
+        control.target.component_id(),
+        target,
+        control.yaw,
+        control.rate_hz.unwrap_or(setpoints::DEFAULT_RATE_HZ),
+    );
+    HttpResponse::Ok().await
+}
+
+#[api_v2_operation]
+/// Stops streaming setpoints to the vehicle
+pub async fn setpoint_stop(query: web::Query<TelemetryQuery>) -> actix_web::Result<HttpResponse> {
+    let system_id = query.target.system_id();
+    let component_id = query.target.component_id();
+    if setpoints::stop(system_id, component_id) {
+        return HttpResponse::Ok().await;
+    }
+    not_found_response(format!(
+        "\"No setpoints are streamed to {system_id}/{component_id}\""
+    ))
+    .await
+}
+
+#[api_v2_operation]
+/// Setpoints being streamed to the vehicles
+pub async fn setpoint_list() -> actix_web::Result<HttpResponse> {
+    ok_response(parse_query(&setpoints::setpoints())).await
+}
+
+#[api_v2_operation]
+/// Requests GIMBAL_DEVICE_ATTITUDE_STATUS from the gimbal
+pub async fn gimbal_status(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<GimbalQuery>,
+) -> actix_web::Result<HttpResponse> {
+    request_response(
+        &data,
+        "GIMBAL_DEVICE_ATTITUDE_STATUS".into(),
+        query.target.system_id(),
+        query.target.component_id_or(154),
+        std::time::Duration::from_millis(query.timeout_ms.unwrap_or(1000)),
+    )
+    .await
+}
+
+#[api_v2_operation]
+/// Points the gimbal with MOUNT_CONTROL or GIMBAL_MANAGER_SET_PITCHYAW
+pub async fn gimbal_control(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    control: web::Json<GimbalControl>,
+) -> actix_web::Result<HttpResponse> {
+    let protocol = match control
+        .protocol
+        .as_deref()
+        .unwrap_or("v2")
+        .parse::<gimbal::Protocol>()
+    {
+        Ok(protocol) => protocol,
+        Err(error) => {
+            return bad_request(&error).await;
+        }
+    };
+
+    let message = gimbal::set_pitch_yaw(
+        protocol,
+        control.target.system_id(),
+        control.target.component_id(),
+        control.pitch,
+        control.yaw,
+    );
+    let result = data.lock().unwrap().send_default(&message);
+    match result {
+        Ok(_) => HttpResponse::Ok().await,
+        Err(error) => {
+            HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&format!(
+                    "Failed to send gimbal control: {error}"
+                )))
+                .await
+        }
+    }
+}
+
+#[api_v2_operation]
+/// Injects RTCM3 corrections, as raw bytes or as {"data": BASE64} JSON, with GPS_RTCM_DATA messages
+pub async fn helper_rtcm(
+    req: HttpRequest,
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let is_json = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, |content_type| content_type.contains("json"));
+
+    let rtcm_data = if is_json {
+        match rtcm::decode_base64(&String::from_utf8_lossy(&bytes)) {
+            Ok(rtcm_data) => rtcm_data,
+            Err(error) => {
+                return bad_request(&format!("Failed to decode RTCM data: {error}")).await;
+            }
+        }
+    } else {
+        bytes.to_vec()
+    };
+
+    let messages = rtcm::messages(&rtcm_data);
+    let result = {
+        let vehicle = data.lock().unwrap();
+        messages
+            .iter()
+            .try_for_each(|message| vehicle.send_default(message).map(|_| ()))
+    };
+
+    match result {
+        Ok(()) => {
+            ok_response(parse_query(
+                &serde_json::json!({ "messages": messages.len() }),
+            ))
+            .await
+        }
+        Err(error) => {
+            HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&format!("Failed to send RTCM data: {error}")))
+                .await
+        }
+    }
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct TunnelControl {
+    #[serde(flatten)]
+    target: Target,
+    /// MAV_TUNNEL_PAYLOAD_TYPE name, with or without the prefix, or value
+    payload_type: String,
+    /// Payload encoded as base64, split in TUNNEL messages of 128 bytes
+    payload: String,
+}
+
+#[api_v2_operation]
+/// Sends a payload to a component with TUNNEL messages
+pub async fn helper_tunnel(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    control: web::Json<TunnelControl>,
+) -> actix_web::Result<HttpResponse> {
+    let control = control.into_inner();
+    let target = tunnel::Target {
+        system_id: control.target.system_id(),
+        component_id: control.target.component_id(),
+    };
+    let payload = tunnel::Payload {
+        payload_type: control.payload_type,
+        payload: control.payload,
+    };
+    let messages = match tunnel::decode(target, &payload) {
+        Ok(messages) => messages,
+        Err(error) => {
+            return bad_request(&format!("Invalid tunnel payload: {error}")).await;
+        }
+    };
+
+    let result = {
+        let vehicle = data.lock().unwrap();
+        messages
+            .iter()
+            .try_for_each(|message| vehicle.send_default(message).map(|_| ()))
+    };
+    match result {
+        Ok(()) => {
+            ok_response(parse_query(
+                &serde_json::json!({ "messages": messages.len() }),
+            ))
+            .await
+        }
+        Err(error) => {
+            HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&format!(
+                    "Failed to send tunnel payload: {error}"
+                )))
+                .await
+        }
+    }
+}
+
+// Message in the ardupilotmega dialect, or in the common one
+pub fn parse_mavlink_message(
+    json_string: &str,
+) -> Option<data::MAVLinkMessage<mavlink::ardupilotmega::MavMessage>> {
+    if let Ok(content) =
+        json5::from_str::<data::MAVLinkMessage<mavlink::ardupilotmega::MavMessage>>(json_string)
+    {
+        return Some(content);
+    }
+
+    json5::from_str::<data::MAVLinkMessage<mavlink::common::MavMessage>>(json_string)
+        .ok()
+        .map(|content| data::MAVLinkMessage {
+            header: content.header,
+            message: mavlink::ardupilotmega::MavMessage::common(content.message),
+        })
+}
+
+// Each message of a batch may have a delay before being sent
+type BatchItem = (
+    std::time::Duration,
+    data::MAVLinkMessage<mavlink::ardupilotmega::MavMessage>,
+);
+
+// Fields missing in a partial message are filled with default values, unless --strict-messages
+fn fill_defaults(value: &mut serde_json::Value) -> Result<Vec<String>, String> {
+    let defaulted = defaults::fill(value);
+    if cli::is_strict_messages() && !defaulted.is_empty() {
+        return Err(format!("Missing fields: {}", defaulted.join(", ")));
+    }
+    Ok(defaulted)
+}
+
+// Delays of a batch are limited to keep its request from waiting indefinitely
+const MAX_BATCH_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+// Messages of the batch with the fields defaulted in each one
+fn parse_batch(
+    items: Vec<serde_json::Value>,
+) -> Result<(Vec<BatchItem>, Vec<Vec<String>>), String> {
+    let mut total_delay = std::time::Duration::default();
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut item)| {
+            let delay = match item
+                .as_object_mut()
+                .and_then(|item| item.remove("delay_ms"))
+            {
+                None => std::time::Duration::default(),
+                Some(delay) => delay
+                    .as_u64()
+                    .map(std::time::Duration::from_millis)
+                    .ok_or_else(|| {
+                        format!("Message {index}: delay_ms is not a positive integer")
+                    })?,
+            };
+            total_delay += delay;
+            if total_delay > MAX_BATCH_DELAY {
+                return Err(format!(
+                    "Message {index}: the delays add up to more than {}ms",
+                    MAX_BATCH_DELAY.as_millis()
+                ));
+            }
+            let defaulted =
+                fill_defaults(&mut item).map_err(|error| format!("Message {index}: {error}"))?;
+            let content = parse_mavlink_message(&item.to_string()).ok_or_else(|| {
+                format!("Failed to parse message {index}, not a valid MAVLinkMessage.")
+            })?;
+            Ok(((delay, content), defaulted))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map(|items| items.into_iter().unzip())
+}
+
+async fn mavlink_post_batch(
+    data: &MAVLinkVehicleArcMutex,
+    items: Vec<serde_json::Value>,
+) -> actix_web::Result<HttpResponse> {
+    // Invalid batches are rejected before sending any message
+    let (batch, defaulted) = match parse_batch(items) {
+        Ok(batch) => batch,
+        Err(error) => return bad_request(&error).await,
+    };
+
+    let vehicle = data.clone();
+    let result = web::block(move || {
+        let mut correlation_ids = vec![];
+        for (index, (delay, content)) in batch.into_iter().enumerate() {
+            std::thread::sleep(delay);
+            let result = vehicle
+                .lock()
+                .unwrap()
+                .send(&content.header, &content.message);
+            if let Err(error) = result {
+                let error = format!("Failed to send message {index}: {error:?}");
+                return Err((error, correlation_ids));
+            }
+            correlation_ids.push(command_tracker::track(&content.message));
+            data::update((content.header, content.message));
+        }
+        Ok(correlation_ids)
+    })
+    .await;
+
+    match result {
+        Ok(correlation_ids) => {
+            let answer = serde_json::json!({
+                "defaulted": defaulted,
+                "correlation_ids": correlation_ids,
+            });
+            ok_response(parse_query(&answer)).await
+        }
+        // The messages before the failure were already sent
+        Err(BlockingError::Error((error, correlation_ids))) => {
+            let answer = serde_json::json!({
+                "error": error,
+                "defaulted": defaulted,
+                "correlation_ids": correlation_ids,
+            });
+            HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&answer))
+                .await
+        }
+        Err(error) => {
+            HttpResponse::InternalServerError()
+                .content_type("application/json")
+                .body(parse_query(&error.to_string()))
+                .await
+        }
+    }
+}
+
+#[api_v2_operation]
+#[allow(clippy::await_holding_lock)]
+/// Send a MAVLink message for the desired vehicle, or an array of messages in order
+pub async fn mavlink_post(
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    _req: HttpRequest,
+    query: web::Query<CommandOptions>,
+    bytes: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let json_string = match String::from_utf8(bytes.to_vec()) {
+        Ok(content) => content,
+        Err(err) => {
+            return not_found_response(format!("Failed to parse input as UTF-8 string: {err:?}"))
+                .await;
+        }
+    };
+
+    debug!("MAVLink post received: {json_string}");
+
+    // Messages may use other key casings
+    let (json_string, defaulted) = match json5::from_str::<serde_json::Value>(&json_string) {
+        Ok(serde_json::Value::Array(mut items)) => {
+            items.iter_mut().for_each(output::normalize_keys);
+            return mavlink_post_batch(&data, items).await;
+        }
+        Ok(mut value) => {
+            output::normalize_keys(&mut value);
+            match fill_defaults(&mut value) {
+                Ok(defaulted) => (value.to_string(), defaulted),
+                Err(error) => return bad_request(&error).await,
+            }
+        }
+        Err(_) => (json_string, vec![]),
+    };
+
+    let content = match parse_mavlink_message(&json_string) {
+        Some(content) => content,
+        None => {
+            return not_found_response(String::from(
+                "Failed to parse message, not a valid MAVLinkMessage.",
+            ))
+            .await
+        }
+    };
+
+    // Commands are sent again until their COMMAND_ACK when the query has timeout_ms or retries,
+    // otherwise they are sent once without waiting for it
+    let is_acknowledged = query.timeout_ms.is_some() || query.retries.is_some();
+    let command = command_tracker::command_of(&content.message).filter(|_| is_acknowledged);
+    if let Some((command, _, _)) = command {
+        let (timeout, retries) = (query.timeout(), query.retries());
+        let vehicle = data.get_ref().clone();
+        let (header, message) = (content.header, content.message.clone());
+        let result = web::block(move || {
+            commands::send_message(&vehicle, Some(&header), &message, timeout, retries)
+        })
+        .await;
+        if !matches!(
+            result,
+            Err(BlockingError::Error(commands::CommandError::Send(_)))
+        ) {
+            data::update((content.header, content.message));
+        }
+        return ack_response(result, command, timeout).await;
+    }
+
+    match data.lock().unwrap().send(&content.header, &content.message) {
+        Ok(_result) => {
+            // Commands can be checked with their correlation id, until their COMMAND_ACK
+            let correlation_id = command_tracker::track(&content.message);
+            data::update((content.header, content.message));
+            let answer = serde_json::json!({
+                "defaulted": defaulted,
+                "correlation_id": correlation_id,
+            });
+            ok_response(parse_query(&answer)).await
+        }
+        Err(err) => not_found_response(format!("Failed to send message: {err:?}")).await,
+    }
+}
+
+#[api_v2_operation]
+/// Reload the configuration file, applying the settings that do not require a restart
+pub async fn admin_reload() -> actix_web::Result<HttpResponse> {
+    match cli::reload() {
+        Ok(()) => HttpResponse::Ok().await,
+        Err(error) => {
+            warn!("Failed to reload configuration: {error}");
+            HttpResponse::InternalServerError()
+                .content_type("text/plain")
+                .body(error)
+                .await
+        }
+    }
+}
+
+#[api_v2_operation]
+/// Websocket used to receive and send MAVLink messages asynchronously
+pub async fn websocket(
+    req: HttpRequest,
+    query: web::Query<WebsocketQuery>,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = query.into_inner();
+    let encoding = Encoding::from_request(&req, query.format.as_deref());
+    let filter = match query.filter {
+        Some(filter) => filter,
+        _ => ".*".to_owned(),
+    };
+
+    debug!("New websocket with filter {:#?}", &filter);
+
+    let max_rates = parse_max_rates(query.max_rates.as_deref());
+    ws::start(
+        WebsocketActor::new(filter, encoding, query.max_rate_hz, max_rates),
+        &req,
+        stream,
+    )
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct SseQuery {
+    /// Regex of the message names, all messages by default
+    filter: Option<String>,
+    /// Maximum rate in Hz of each message type of each component
+    max_rate_hz: Option<f32>,
+    /// Maximum rates of specific message types, E.g: ATTITUDE:10,GPS_RAW_INT:1
+    max_rates: Option<String>,
+}
+
+// Comma separated NAME:FREQUENCY rates of the streaming endpoints
+fn parse_max_rates(max_rates: Option<&str>) -> std::collections::HashMap<String, f32> {
+    let rates: Vec<&str> = max_rates
+        .map(|rates| rates.split(',').collect())
+        .unwrap_or_default();
+    throttle::parse_rates(&rates)
+}
+
+#[api_v2_operation]
+/// Server-Sent Events stream of the MAVLink messages, resumed from the history with Last-Event-ID
+pub async fn mavlink_sse(
+    req: HttpRequest,
+    query: web::Query<SseQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = match &query.filter {
+        Some(filter) => match regex::Regex::new(filter) {
+            Ok(filter) => Some(filter),
+            Err(error) => return bad_request(&format!("Invalid filter: {error}")).await,
+        },
+        None => None,
+    };
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok());
+
+    debug!("New SSE stream with filter {filter:?} after event {last_event_id:?}");
+
+    let decimator = Decimator::new(
+        query.max_rate_hz,
+        &parse_max_rates(query.max_rates.as_deref()),
+    );
+    let stream = sse::subscribe(filter, last_event_id, decimator).map(Ok::<_, actix_web::Error>);
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(stream)
+        .await
+}
+
+#[api_v2_operation]
+/// Websocket streaming the MAVLink bytes received, binary frames sent by the client are forwarded to the vehicle
+pub async fn websocket_raw(
+    req: HttpRequest,
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    debug!("New raw websocket");
+    ws::start(RawWebsocketActor::new(data.get_ref().clone()), &req, stream)
+}
+
+#[api_v2_operation]
+/// Websocket with the rosbridge v2 protocol, each message type is a topic, E.g: /mavlink/HEARTBEAT
+pub async fn websocket_rosbridge(
+    req: HttpRequest,
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    debug!("New rosbridge websocket");
+    ws::start(
+        RosbridgeWebsocketActor::new(data.get_ref().clone()),
+        &req,
+        stream,
+    )
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct ShellQuery {
+    // The shell is provided by the autopilot, only the vehicle is used
+    #[serde(flatten)]
+    target: Target,
+}
+
+#[api_v2_operation]
+/// Websocket with the MAVLink shell of the vehicle, using SERIAL_CONTROL messages
+pub async fn websocket_shell(
+    req: HttpRequest,
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<ShellQuery>,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let system_id = query.target.system_id();
+    debug!("New shell websocket for vehicle {system_id}");
+    ws::start(
+        ShellWebsocketActor::new(data.get_ref().clone(), system_id),
+        &req,
+        stream,
+    )
+}
+
+#[derive(Apiv2Schema, Deserialize)]
+pub struct TunnelQuery {
+    // A component_id of 0 receives the payloads of all components
+    #[serde(flatten)]
+    target: Target,
+    /// Only receive payloads of this MAV_TUNNEL_PAYLOAD_TYPE, also used for binary frames
+    payload_type: Option<String>,
+}
+
+#[api_v2_operation]
+/// Websocket with the TUNNEL payloads of the vehicle, sent and received as JSON with base64 payloads
+pub async fn websocket_tunnel(
+    req: HttpRequest,
+    data: web::Data<MAVLinkVehicleArcMutex>,
+    query: web::Query<TunnelQuery>,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let payload_type = match query
+        .payload_type
+        .as_deref()
+        .map(tunnel::payload_type)
+        .transpose()
+    {
+        Ok(payload_type) => payload_type,
+        Err(error) => return bad_request(&error).await,
+    };
+    let target = tunnel::Target {
+        system_id: query.target.system_id(),
+        component_id: query.target.component_id(),
+    };
+    debug!("New tunnel websocket for {target:?}");
+    ws::start(
+        TunnelWebsocketActor::new(data.get_ref().clone(), target, payload_type),
+        &req,
+        stream,
+    )
+}
+
+async fn not_found_response(message: String) -> actix_web::Result<HttpResponse> {
+    HttpResponse::NotFound()
+        .content_type("application/json")
+        .body(message)
+        .await
+}
+
+async fn bad_request(message: &str) -> actix_web::Result<HttpResponse> {
+    HttpResponse::BadRequest()
+        .content_type("application/json")
+        .body(parse_query(&message))
+        .await
+}
+
+async fn history_disabled_response() -> actix_web::Result<HttpResponse> {
+    HttpResponse::BadRequest()
+        .content_type("application/json")
+        .body("\"History is disabled, it can be enabled with --history.\"")
+        .await
+}
+
+async fn ok_response(message: String) -> actix_web::Result<HttpResponse> {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(message)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_rates_query() {
+        let query = web::Query::<SseQuery>::from_query(
+            "filter=ATTITUDE|VFR_HUD&max_rate_hz=5&max_rates=ATTITUDE:20,VFR_HUD:0.5",
+        )
+        .unwrap();
+        assert_eq!(query.max_rate_hz, Some(5.0));
+        let max_rates = parse_max_rates(query.max_rates.as_deref());
+        assert_eq!(max_rates.len(), 2);
+        assert_eq!(max_rates["ATTITUDE"], 20.0);
+        assert_eq!(max_rates["VFR_HUD"], 0.5);
+
+        let query =
+            web::Query::<WebsocketQuery>::from_query("max_rates=ATTITUDE:20,GPS_RAW_INT").unwrap();
+        assert!(query.max_rate_hz.is_none());
+        // Invalid rates are ignored
+        assert_eq!(parse_max_rates(query.max_rates.as_deref()).len(), 1);
+        assert!(parse_max_rates(None).is_empty());
+        assert!(web::Query::<WebsocketQuery>::from_query("max_rate_hz=fast").is_err());
+    }
+
+    #[test]
+    fn flattened_targets() {
+        let query =
+            web::Query::<RebootQuery>::from_query("system_id=2&retries=3&bootloader=true").unwrap();
+        assert_eq!(query.target.system_id(), 2);
+        assert_eq!(query.target.component_id(), 1);
+        assert_eq!(query.options.retries(), 3);
+        assert_eq!(
+            query.options.timeout(),
+            std::time::Duration::from_millis(1000)
+        );
+        assert_eq!(query.bootloader, Some(true));
+        assert!(web::Query::<RebootQuery>::from_query("system_id=256").is_err());
+
+        // Cameras and gimbals are not the autopilot
+        let query = web::Query::<CameraQuery>::from_query("system_id=2&count=3").unwrap();
+        assert_eq!(
+            query.target(),
+            (2, 100, std::time::Duration::from_millis(1000))
+        );
+        assert_eq!(query.count, Some(3));
+        let query = web::Query::<GimbalQuery>::from_query("component_id=155").unwrap();
+        assert_eq!(query.target.component_id_or(154), 155);
+        let query = web::Query::<TunnelQuery>::from_query("component_id=0").unwrap();
+        assert_eq!(query.target.component_id(), 0);
+        let query = web::Query::<TelemetryQuery>::from_query("").unwrap();
+        assert_eq!(
+            (query.target.system_id(), query.target.component_id()),
+            (1, 1)
+        );
+
+        let control: ModeControl = serde_json::from_str(
+            r#"{"system_id": 3, "component_id": 191, "mode": "GUIDED", "timeout_ms": 200}"#,
+        )
+        .unwrap();
+        assert_eq!(control.target.system_id(), 3);
+        assert_eq!(control.target.component_id(), 191);
+        assert_eq!(
+            control.options.timeout(),
+            std::time::Duration::from_millis(200)
+        );
+        assert!(control.options.retries.is_none());
+    }
+
+    #[test]
+    fn batch_delays() {
+        let batch = |items: serde_json::Value| match items {
+            serde_json::Value::Array(items) => parse_batch(items),
+            _ => unreachable!(),
+        };
+        let error = batch(serde_json::json!([{"delay_ms": 6000}, {"delay_ms": 6000}])).unwrap_err();
+        assert!(error.starts_with("Message 1: the delays add up"));
+        let error = batch(serde_json::json!([{"delay_ms": -1}])).unwrap_err();
+        assert!(error.contains("delay_ms"));
+        let error = batch(serde_json::json!([{"delay_ms": 100}])).unwrap_err();
+        assert!(error.starts_with("Failed to parse message 0"));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+
+use super::output;
+
+// ArduPilot reports the ESCs in groups of four, the first ESC of each message
+const ESC_TELEMETRY: &[(&str, usize)] = &[
+    ("ESC_TELEMETRY_1_TO_4", 0),
+    ("ESC_TELEMETRY_5_TO_8", 4),
+    ("ESC_TELEMETRY_9_TO_12", 8),
+];
+
+lazy_static! {
+    // Last ESC_STATUS of each group of four ESCs by system, component and index of the first ESC,
+    // the message store only keeps the last message of each component
+    static ref ESC_STATUS: Mutex<BTreeMap<(u8, u8, u8), Value>> = Mutex::new(BTreeMap::new());
+}
+
+/// Keep the ESC_STATUS of each group of ESCs
+pub fn update(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    if let mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::ESC_STATUS(
+        data,
+    )) = message
+    {
+        ESC_STATUS.lock().unwrap().insert(
+            (header.system_id, header.component_id, data.index),
+            output::to_value(message),
+        );
+    }
+}
+
+/// Last ESC_STATUS of each group of ESCs of the component
+pub fn esc_status(system_id: u8, component_id: u8) -> Vec<Value> {
+    ESC_STATUS
+        .lock()
+        .unwrap()
+        .range((system_id, component_id, 0)..=(system_id, component_id, u8::MAX))
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
+fn field(message: &Value, name: &str, index: usize) -> Option<f64> {
+    message[name][index].as_f64()
+}
+
+fn insert(esc: &mut Map<String, Value>, key: &str, value: Option<f64>) {
+    if let Some(value) = value {
+        esc.entry(key).or_insert_with(|| json!(value));
+    }
+}
+
+/// ESCs of the component by number, starting at 1, from ESC_TELEMETRY_* and ESC_STATUS
+pub fn summary(messages: &Value, esc_status: &[Value]) -> Vec<Value> {
+    let mut escs: BTreeMap<usize, Map<String, Value>> = BTreeMap::new();
+
+    for (name, first) in ESC_TELEMETRY {
+        let message = &messages[name]["message"];
+        if !message.is_object() {
+            continue;
+        }
+        for index in 0..4 {
+            // ESCs not connected are reported with zeros
+            let count = field(message, "count", index).unwrap_or(0.0);
+            let rpm = field(message, "rpm", index).unwrap_or(0.0);
+            let voltage = field(message, "voltage", index).unwrap_or(0.0);
+            if count == 0.0 && rpm == 0.0 && voltage == 0.0 {
+                continue;
+            }
+            let esc = escs.entry(first + index + 1).or_default();
+            insert(esc, "rpm", Some(rpm));
+            insert(esc, "temperature", field(message, "temperature", index));
+            insert(esc, "voltage", Some(voltage / 100.0));
+            insert(
+                esc,
+                "current",
+                field(message, "current", index).map(|current| current / 100.0),
+            );
+            insert(
+                esc,
+                "current_consumed",
+                field(message, "totalcurrent", index),
+            );
+        }
+    }
+
+    // Each ESC_STATUS has four ESCs starting at its index
+    for message in esc_status {
+        let first = message["index"].as_u64().unwrap_or(0) as usize;
+        for index in 0..4 {
+            let rpm = field(message, "rpm", index).unwrap_or(0.0);
+            let voltage = field(message, "voltage", index).unwrap_or(0.0);
+            let current = field(message, "current", index).unwrap_or(0.0);
+            if rpm == 0.0 && voltage == 0.0 && current == 0.0 {
+                continue;
+            }
+            let esc = escs.entry(first + index + 1).or_default();
+            insert(esc, "rpm", Some(rpm));
+            insert(esc, "voltage", Some(voltage));
+            insert(esc, "current", Some(current));
+        }
+    }
+
+    escs.into_iter()
+        .map(|(number, mut esc)| {
+            esc.insert("esc".into(), json!(number));
+            for key in [
+                "rpm",
+                "temperature",
+                "voltage",
+                "current",
+                "current_consumed",
+            ] {
+                esc.entry(key).or_insert(Value::Null);
+            }
+            Value::Object(esc)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn esc_table() {
+        let messages = json!({
+            "ESC_TELEMETRY_1_TO_4": {"message": {
+                "temperature": [30, 31, 0, 0],
+                "voltage": [1600, 1590, 0, 0],
+                "current": [250, 240, 0, 0],
+                "totalcurrent": [10, 9, 0, 0],
+                "rpm": [5000, 5100, 0, 0],
+                "count": [100, 100, 0, 0],
+            }},
+        });
+        let esc_status = json!({
+            "index": 4,
+            "rpm": [4000, 0, 0, 0],
+            "voltage": [15.5, 0.0, 0.0, 0.0],
+            "current": [2.0, 0.0, 0.0, 0.0],
+        });
+
+        let escs = summary(&messages, &[esc_status]);
+        assert_eq!(escs.len(), 3);
+        assert_eq!(escs[0]["esc"], 1);
+        assert_eq!(escs[0]["voltage"], 16.0);
+        assert_eq!(escs[1]["current"], 2.4);
+        assert_eq!(escs[2]["esc"], 5);
+        assert_eq!(escs[2]["rpm"], 4000.0);
+        assert!(escs[2]["temperature"].is_null());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common;
+use serde::Serialize;
+
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+
+// Number of events kept for the feed
+const MAX_EVENTS: usize = 1000;
+// Larger gaps are not recovered, since the vehicle only buffers the last events
+const MAX_RECOVERED_EVENTS: u16 = 100;
+// Log levels of the events protocol, the same values of MAV_SEVERITY
+const LOG_LEVELS: &[&str] = &[
+    "emergency",
+    "alert",
+    "critical",
+    "error",
+    "warning",
+    "notice",
+    "info",
+    "debug",
+    "protocol",
+    "disabled",
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub system_id: u8,
+    pub component_id: u8,
+    pub sequence: u16,
+    /// Event identifier, described by the events metadata of the component
+    pub id: u32,
+    pub event_time_boot_ms: u32,
+    /// Estimated UTC time of the event
+    pub time: DateTime<Utc>,
+    pub log_level: String,
+    pub internal_log_level: String,
+    pub arguments: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Sequence {
+    last: Option<u16>,
+    last_boot_ms: u32,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    sequences: BTreeMap<(u8, u8), Sequence>,
+    events: VecDeque<Event>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+fn log_level(level: u8) -> String {
+    LOG_LEVELS
+        .get(level as usize)
+        .map_or_else(|| level.to_string(), |level| level.to_string())
+}
+
+// Range of sequences missing before the received one, sequences wrap around
+fn missing(last: Option<u16>, sequence: u16) -> Option<(u16, u16)> {
+    let last = last?;
+    let distance = sequence.wrapping_sub(last);
+    if distance <= 1 || distance >= 0x8000 {
+        return None;
+    }
+    let count = (distance - 1).min(MAX_RECOVERED_EVENTS);
+    Some((sequence.wrapping_sub(count), sequence.wrapping_sub(1)))
+}
+
+// Sequences after the last one received move it forward
+fn is_newer(last: Option<u16>, sequence: u16) -> bool {
+    last.map_or(true, |last| {
+        let distance = sequence.wrapping_sub(last);
+        distance != 0 && distance < 0x8000
+    })
+}
+
+fn request_events(
+    vehicle: &MAVLinkVehicleArcMutex,
+    header: &mavlink::MavHeader,
+    (first_sequence, last_sequence): (u16, u16),
+) {
+    debug!(
+        "Requesting events {first_sequence} to {last_sequence} of {}/{}",
+        header.system_id, header.component_id
+    );
+    let request = MavMessage::common(common::MavMessage::REQUEST_EVENT(
+        common::REQUEST_EVENT_DATA {
+            first_sequence,
+            last_sequence,
+            target_system: header.system_id,
+            target_component: header.component_id,
+        },
+    ));
+    if let Err(error) = vehicle.lock().unwrap().send_default(&request) {
+        warn!("Failed to request events: {error:?}");
+    }
+}
+
+fn push(state: &mut State, header: &mavlink::MavHeader, event: &common::EVENT_DATA) {
+    let key = (header.system_id, header.component_id);
+    let is_duplicated = state.events.iter().any(|stored| {
+        (stored.system_id, stored.component_id) == key
+            && stored.sequence == event.sequence
+            && stored.id == event.id
+    });
+    if is_duplicated {
+        return;
+    }
+
+    let sequence = state.sequences.entry(key).or_default();
+    if is_newer(sequence.last, event.sequence) {
+        sequence.last = Some(event.sequence);
+        sequence.last_boot_ms = sequence.last_boot_ms.max(event.event_time_boot_ms);
+    }
+    // Older events happened before the newest one received
+    let age_ms = sequence
+        .last_boot_ms
+        .saturating_sub(event.event_time_boot_ms);
+
+    if state.events.len() >= MAX_EVENTS {
+        state.events.pop_front();
+    }
+    state.events.push_back(Event {
+        system_id: header.system_id,
+        component_id: header.component_id,
+        sequence: event.sequence,
+        id: event.id,
+        event_time_boot_ms: event.event_time_boot_ms,
+        time: Utc::now() - Duration::milliseconds(age_ms as i64),
+        log_level: log_level(event.log_levels & 0x0f),
+        internal_log_level: log_level(event.log_levels >> 4),
+        arguments: event.arguments.to_vec(),
+    });
+}
+
+/// Track the events of each component and request the ones lost
+pub fn on_message(
+    vehicle: &MAVLinkVehicleArcMutex,
+    header: &mavlink::MavHeader,
+    message: &MavMessage,
+) {
+    let message = match message {
+        MavMessage::common(message) => message,
+        _ => return,
+    };
+
+    match message {
+        common::MavMessage::EVENT(event) => {
+            let gap = {
+                let mut state = STATE.lock().unwrap();
+                let last = state
+                    .sequences
+                    .get(&(header.system_id, header.component_id))
+                    .and_then(|sequence| sequence.last);
+                push(&mut state, header, event);
+                missing(last, event.sequence)
+            };
+            if let Some(gap) = gap {
+                request_events(vehicle, header, gap);
+            }
+        }
+        common::MavMessage::CURRENT_EVENT_SEQUENCE(current) => {
+            let gap = {
+                let mut state = STATE.lock().unwrap();
+                let sequence = state
+                    .sequences
+                    .entry((header.system_id, header.component_id))
+                    .or_default();
+                let reset =
+                    common::MavEventCurrentSequenceFlags::MAV_EVENT_CURRENT_SEQUENCE_FLAGS_RESET;
+                if current.flags == reset {
+                    // The vehicle rebooted, the sequence starts again
+                    sequence.last = Some(current.sequence);
+                    sequence.last_boot_ms = 0;
+                    None
+                } else {
+                    // The current sequence is the last event sent, it is missing when not received
+                    let gap = missing(sequence.last, current.sequence.wrapping_add(1));
+                    if sequence.last.is_none() {
+                        sequence.last = Some(current.sequence);
+                    }
+                    gap
+                }
+            };
+            if let Some(gap) = gap {
+                request_events(vehicle, header, gap);
+            }
+        }
+        common::MavMessage::RESPONSE_EVENT_ERROR(error) => {
+            warn!(
+                "Event {} of {}/{} is not available: {:?}",
+                error.sequence, header.system_id, header.component_id, error.reason
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Received events in chronological order
+pub fn events(system_id: Option<u8>) -> Vec<Event> {
+    let mut events: Vec<Event> = STATE
+        .lock()
+        .unwrap()
+        .events
+        .iter()
+        .filter(|event| system_id.map_or(true, |id| id == event.system_id))
+        .cloned()
+        .collect();
+    events.sort_by_key(|event| event.time);
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_gaps() {
+        assert_eq!(missing(None, 10), None);
+        assert_eq!(missing(Some(9), 10), None);
+        assert_eq!(missing(Some(10), 10), None);
+        assert_eq!(missing(Some(5), 10), Some((6, 9)));
+        assert_eq!(missing(Some(65534), 2), Some((65535, 1)));
+        assert_eq!(missing(Some(10), 5), None);
+        assert_eq!(missing(Some(0), 1000), Some((900, 999)));
+
+        assert!(is_newer(None, 3));
+        assert!(is_newer(Some(65535), 0));
+        assert!(!is_newer(Some(10), 9));
+    }
+}
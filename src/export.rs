@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::Value;
+
+use super::definitions;
+use super::history;
+use super::output;
+
+struct TrackPoint {
+    time: DateTime<Utc>,
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+}
+
+// Recorded GLOBAL_POSITION_INT positions grouped by vehicle and component
+fn tracks(system_id: Option<u8>) -> BTreeMap<(u8, u8), Vec<TrackPoint>> {
+    let mut tracks: BTreeMap<(u8, u8), Vec<TrackPoint>> = BTreeMap::new();
+    for entry in history::entries(system_id, None, "GLOBAL_POSITION_INT") {
+        let position = output::to_value(&entry.message);
+        let point = (|| {
+            Some(TrackPoint {
+                time: entry.time,
+                latitude: position["lat"].as_f64()? / 1e7,
+                longitude: position["lon"].as_f64()? / 1e7,
+                altitude: position["alt"].as_f64()? / 1e3,
+            })
+        })();
+
+        if let Some(point) = point {
+            tracks
+                .entry((entry.header.system_id, entry.header.component_id))
+                .or_default()
+                .push(point);
+        }
+    }
+    tracks
+}
+
+fn track_name(system_id: u8, component_id: u8) -> String {
+    format!("Vehicle {system_id} component {component_id}")
+}
+
+pub fn gpx(system_id: Option<u8>) -> String {
+    let mut content = String::new();
+    content.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    content.push('\n');
+    content.push_str(
+        r#"<gpx version="1.1" creator="mavlink2rest" xmlns="http://www.topografix.com/GPX/1/1">"#,
+    );
+    content.push('\n');
+
+    for ((system_id, component_id), points) in tracks(system_id) {
+        let _ = writeln!(
+            content,
+            "  <trk>\n    <name>{}</name>\n    <trkseg>",
+            track_name(system_id, component_id)
+        );
+        for point in points {
+            let _ = writeln!(
+                content,
+                r#"      <trkpt lat="{:.7}" lon="{:.7}"><ele>{:.3}</ele><time>{}</time></trkpt>"#,
+                point.latitude,
+                point.longitude,
+                point.altitude,
+                point.time.to_rfc3339_opts(SecondsFormat::Millis, true)
+            );
+        }
+        content.push_str("    </trkseg>\n  </trk>\n");
+    }
+
+    content.push_str("</gpx>\n");
+    content
+}
+
+pub fn kml(system_id: Option<u8>) -> String {
+    let mut content = String::new();
+    content.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    content.push('\n');
+    content.push_str(r#"<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">"#);
+    content.push_str("\n<Document>\n  <name>mavlink2rest</name>\n");
+
+    for ((system_id, component_id), points) in tracks(system_id) {
+        let _ = writeln!(
+            content,
+            "  <Placemark>\n    <name>{}</name>\n    <gx:Track>\n      <altitudeMode>absolute</altitudeMode>",
+            track_name(system_id, component_id)
+        );
+        for point in &points {
+            let _ = writeln!(
+                content,
+                "      <when>{}</when>",
+                point.time.to_rfc3339_opts(SecondsFormat::Millis, true)
+            );
+        }
+        for point in &points {
+            let _ = writeln!(
+                content,
+                "      <gx:coord>{:.7} {:.7} {:.3}</gx:coord>",
+                point.longitude, point.latitude, point.altitude
+            );
+        }
+        content.push_str("    </gx:Track>\n  </Placemark>\n");
+    }
+
+    content.push_str("</Document>\n</kml>\n");
+    content
+}
+
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        // Enums are written with their entry name and bitflags with their value
+        Value::Object(object) => match (object.get("type"), object.get("bits")) {
+            (Some(entry), _) => csv_value(entry),
+            (_, Some(bits)) => csv_value(bits),
+            _ => value.to_string(),
+        },
+        Value::Array(values) => values.iter().map(csv_value).collect::<Vec<_>>().join(" "),
+        value => value.to_string(),
+    }
+}
+
+fn csv_cell(value: &str) -> String {
+    if value.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    value.to_string()
+}
+
+/// Recorded messages of a type as CSV, one column per field, None if the message is unknown
+pub fn csv(name: &str, system_id: Option<u8>, since: Option<DateTime<Utc>>) -> Option<String> {
+    let definition = definitions::message(name)?;
+
+    let mut content = String::from("timestamp,system_id,component_id");
+    for field in &definition.fields {
+        content.push(',');
+        content.push_str(&csv_cell(&field.name));
+    }
+    content.push('\n');
+
+    for entry in history::entries(system_id, None, name) {
+        if since.map_or(false, |since| entry.time < since) {
+            continue;
+        }
+
+        let message = output::to_value(&entry.message);
+        let _ = write!(
+            content,
+            "{},{},{}",
+            entry.time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            entry.header.system_id,
+            entry.header.component_id
+        );
+        for field in &definition.fields {
+            content.push(',');
+            content.push_str(&csv_cell(&csv_value(&message[&field.name])));
+        }
+        content.push('\n');
+    }
+    Some(content)
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::io::Cursor;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use mavlink::common::{self, MavMessage as CommonMessage};
+use mavlink::error::{MessageReadError, MessageWriteError};
+
+// The vehicle flies in circles around this point
+const CENTER_LAT_DEG: f64 = -27.5935;
+const CENTER_LON_DEG: f64 = -48.5439;
+const RADIUS_M: f64 = 50.0;
+const PERIOD_S: f64 = 60.0;
+const ALTITUDE_M: f64 = 30.0;
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// Synthetic vehicle sending ATTITUDE, GLOBAL_POSITION_INT and GPS_RAW_INT at the rate and
+// HEARTBEAT at 1Hz, to exercise the service without hardware or SITL, E.g: fake:50
+pub struct FakeConnection {
+    rate: f64,
+    start: Instant,
+    state: Mutex<State>,
+    version: mavlink::MavlinkVersion,
+}
+
+#[derive(Default)]
+struct State {
+    tick: u64,
+    sequence: u8,
+    // Messages of the current tick, encoded as they would be received from a vehicle
+    pending: VecDeque<Vec<u8>>,
+}
+
+pub fn connect(rate: &str) -> std::io::Result<FakeConnection> {
+    let rate = rate
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid fake message rate: {rate}, expected fake:<RATE>"),
+            )
+        })?;
+
+    Ok(FakeConnection {
+        rate,
+        start: Instant::now(),
+        state: Mutex::new(State::default()),
+        version: mavlink::MavlinkVersion::V2,
+    })
+}
+
+/// Messages of the vehicle at the time
+pub fn messages(time: Duration, with_heartbeat: bool) -> Vec<CommonMessage> {
+    let seconds = time.as_secs_f64();
+    let time_boot_ms = time.as_millis() as u32;
+    let angle = 2.0 * PI * seconds / PERIOD_S;
+    let speed = 2.0 * PI * RADIUS_M / PERIOD_S;
+    // Clockwise circle starting north of the center, the heading is perpendicular to the radius
+    let (north, east) = (RADIUS_M * angle.cos(), RADIUS_M * angle.sin());
+    let (vn, ve) = (-speed * angle.sin(), speed * angle.cos());
+    let heading = ve.atan2(vn).rem_euclid(2.0 * PI);
+    let lat = CENTER_LAT_DEG + (north / EARTH_RADIUS_M).to_degrees();
+    let lon =
+        CENTER_LON_DEG + (east / (EARTH_RADIUS_M * CENTER_LAT_DEG.to_radians().cos())).to_degrees();
+    // Small oscillation, like a real vehicle in the wind
+    let altitude = ALTITUDE_M + (seconds / 5.0).sin();
+
+    let mut messages = vec![];
+    if with_heartbeat {
+        messages.push(CommonMessage::HEARTBEAT(common::HEARTBEAT_DATA {
+            custom_mode: 4,
+            mavtype: common::MavType::MAV_TYPE_QUADROTOR,
+            autopilot: common::MavAutopilot::MAV_AUTOPILOT_ARDUPILOTMEGA,
+            base_mode: common::MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED
+                | common::MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED,
+            system_status: common::MavState::MAV_STATE_ACTIVE,
+            mavlink_version: 3,
+        }));
+    }
+    messages.push(CommonMessage::ATTITUDE(common::ATTITUDE_DATA {
+        time_boot_ms,
+        roll: (speed * 2.0 * PI / PERIOD_S / 9.81).atan() as f32,
+        pitch: (0.05 * (seconds / 3.0).sin()) as f32,
+        yaw: (heading - if heading > PI { 2.0 * PI } else { 0.0 }) as f32,
+        rollspeed: 0.0,
+        pitchspeed: (0.05 / 3.0 * (seconds / 3.0).cos()) as f32,
+        yawspeed: (2.0 * PI / PERIOD_S) as f32,
+    }));
+    messages.push(CommonMessage::GLOBAL_POSITION_INT(
+        common::GLOBAL_POSITION_INT_DATA {
+            time_boot_ms,
+            lat: (lat * 1e7) as i32,
+            lon: (lon * 1e7) as i32,
+            alt: (altitude * 1000.0) as i32,
+            relative_alt: (altitude * 1000.0) as i32,
+            vx: (vn * 100.0) as i16,
+            vy: (ve * 100.0) as i16,
+            vz: (-(seconds / 5.0).cos() / 5.0 * 100.0) as i16,
+            hdg: (heading.to_degrees() * 100.0) as u16,
+        },
+    ));
+    messages.push(CommonMessage::GPS_RAW_INT(common::GPS_RAW_INT_DATA {
+        time_usec: time.as_micros() as u64,
+        lat: (lat * 1e7) as i32,
+        lon: (lon * 1e7) as i32,
+        alt: (altitude * 1000.0) as i32,
+        eph: 80,
+        epv: 120,
+        vel: (speed * 100.0) as u16,
+        cog: (heading.to_degrees() * 100.0) as u16,
+        fix_type: common::GpsFixType::GPS_FIX_TYPE_3D_FIX,
+        satellites_visible: 14,
+        ..Default::default()
+    }));
+    messages
+}
+
+impl FakeConnection {
+    fn tick_time(&self, tick: u64) -> Duration {
+        Duration::from_secs_f64(tick as f64 / self.rate)
+    }
+
+    // Wait for the next tick and encode its messages
+    fn next_tick(&self, state: &mut State) -> Result<(), MessageWriteError> {
+        let time = self.tick_time(state.tick);
+        if let Some(wait) = time.checked_sub(self.start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        // The first tick of each second has the HEARTBEAT
+        let with_heartbeat =
+            state.tick == 0 || self.tick_time(state.tick - 1).as_secs() < time.as_secs();
+        state.tick += 1;
+
+        for message in messages(time, with_heartbeat) {
+            let header = mavlink::MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: state.sequence,
+            };
+            state.sequence = state.sequence.wrapping_add(1);
+            let mut bytes = vec![];
+            mavlink::write_versioned_msg(&mut bytes, self.version, header, &message)?;
+            state.pending.push_back(bytes);
+        }
+        Ok(())
+    }
+}
+
+impl<M: mavlink::Message> mavlink::MavConnection<M> for FakeConnection {
+    fn recv(&self) -> Result<(mavlink::MavHeader, M), MessageReadError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(bytes) = state.pending.pop_front() {
+                // Parsed as the messages of a real vehicle, to have the same cost
+                return mavlink::read_versioned_msg(&mut Cursor::new(bytes), self.version);
+            }
+            if let Err(MessageWriteError::Io(error)) = self.next_tick(&mut state) {
+                return Err(MessageReadError::Io(error));
+            }
+        }
+    }
+
+    // Messages sent to the fake vehicle are discarded
+    fn send(&self, header: &mavlink::MavHeader, data: &M) -> Result<usize, MessageWriteError> {
+        let mut bytes = vec![];
+        mavlink::write_versioned_msg(&mut bytes, self.version, *header, data)
+    }
+
+    fn set_protocol_version(&mut self, version: mavlink::MavlinkVersion) {
+        self.version = version;
+    }
+
+    fn get_protocol_version(&self) -> mavlink::MavlinkVersion {
+        self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavlink::MavConnection;
+
+    #[test]
+    fn synthetic_streams() {
+        assert!(connect("0").is_err());
+        assert!(connect("fast").is_err());
+
+        let connection = connect("1000").unwrap();
+        let names: Vec<&str> = (0..8)
+            .map(|_| {
+                let (header, message): (_, mavlink::ardupilotmega::MavMessage) =
+                    connection.recv().unwrap();
+                assert_eq!(header.system_id, 1);
+                mavlink::Message::message_name(&message)
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "HEARTBEAT",
+                "ATTITUDE",
+                "GLOBAL_POSITION_INT",
+                "GPS_RAW_INT",
+                "ATTITUDE",
+                "GLOBAL_POSITION_INT",
+                "GPS_RAW_INT",
+                "ATTITUDE"
+            ]
+        );
+
+        // A quarter of the period later, the vehicle is east of the center going south
+        match &messages(Duration::from_secs_f64(PERIOD_S / 4.0), false)[1] {
+            CommonMessage::GLOBAL_POSITION_INT(position) => {
+                assert!(position.lon > (CENTER_LON_DEG * 1e7) as i32);
+                assert!(position.vx < 0);
+                assert!((17990..=18000).contains(&position.hdg));
+            }
+            _ => panic!("GLOBAL_POSITION_INT expected"),
+        }
+    }
+}
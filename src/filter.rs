@@ -0,0 +1,90 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use log::*;
+use regex::Regex;
+
+use super::cli;
+
+// Messages are dropped before any processing if they are blocked or not allowed
+#[derive(Debug, Default)]
+struct MessageFilter {
+    allow: Option<Regex>,
+    block: Option<Regex>,
+}
+
+impl MessageFilter {
+    fn from_cli() -> Self {
+        Self {
+            allow: build_regex(&cli::allowed_messages()),
+            block: build_regex(&cli::blocked_messages()),
+        }
+    }
+
+    fn is_allowed(&self, name: &str) -> bool {
+        if let Some(block) = &self.block {
+            if block.is_match(name) {
+                return false;
+            }
+        }
+
+        self.allow
+            .as_ref()
+            .map_or(true, |allow| allow.is_match(name))
+    }
+}
+
+lazy_static! {
+    static ref FILTER: RwLock<MessageFilter> = RwLock::new(MessageFilter::from_cli());
+}
+
+// Each pattern is a regex or a list of them separated by commas, and should match the entire message name
+fn build_regex(patterns: &[String]) -> Option<Regex> {
+    let patterns: Vec<&str> = patterns
+        .iter()
+        .flat_map(|pattern| pattern.split(','))
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+
+    if patterns.is_empty() {
+        return None;
+    }
+
+    let expression = format!("^(?:{})$", patterns.join("|"));
+    match Regex::new(&expression) {
+        Ok(regex) => Some(regex),
+        Err(error) => {
+            error!("Invalid message filter {expression}: {error}");
+            None
+        }
+    }
+}
+
+pub fn init() {
+    lazy_static::initialize(&FILTER);
+    cli::on_reload(|| *FILTER.write().unwrap() = MessageFilter::from_cli());
+}
+
+pub fn is_allowed(name: &str) -> bool {
+    FILTER.read().unwrap().is_allowed(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_and_block() {
+        let filter = MessageFilter {
+            allow: build_regex(&["HEARTBEAT, ATTITUDE.*".to_string()]),
+            block: build_regex(&["ATTITUDE_QUATERNION".to_string()]),
+        };
+
+        assert!(filter.is_allowed("HEARTBEAT"));
+        assert!(filter.is_allowed("ATTITUDE"));
+        assert!(!filter.is_allowed("ATTITUDE_QUATERNION"));
+        assert!(!filter.is_allowed("RAW_IMU"));
+        assert!(MessageFilter::default().is_allowed("RAW_IMU"));
+    }
+}
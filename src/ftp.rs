@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use log::*;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common;
+
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::waiter;
+
+// Attempts of each request before giving up
+const RETRIES: usize = 3;
+const PAYLOAD_SIZE: usize = 251;
+const HEADER_SIZE: usize = 12;
+const DATA_SIZE: usize = PAYLOAD_SIZE - HEADER_SIZE;
+
+// Operations of the MAVLink FTP protocol, https://mavlink.io/en/services/ftp.html
+const OPCODE_TERMINATE_SESSION: u8 = 1;
+const OPCODE_OPEN_FILE_RO: u8 = 4;
+const OPCODE_READ_FILE: u8 = 5;
+const OPCODE_ACK: u8 = 128;
+const OPCODE_NAK: u8 = 129;
+
+const NAK_FAIL_ERRNO: u8 = 2;
+const NAK_EOF: u8 = 6;
+const NAK_FILE_NOT_FOUND: u8 = 10;
+
+#[derive(Debug)]
+pub enum FtpError {
+    Send(std::io::Error),
+    Timeout(String),
+    Nak(String),
+}
+
+impl std::fmt::Display for FtpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FtpError::Send(error) => write!(f, "Failed to send message: {error}"),
+            FtpError::Timeout(step) => write!(f, "Vehicle did not answer {step}"),
+            FtpError::Nak(error) => write!(f, "Vehicle refused the transfer: {error}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Payload {
+    seq_number: u16,
+    session: u8,
+    opcode: u8,
+    req_opcode: u8,
+    offset: u32,
+    data: Vec<u8>,
+}
+
+impl Payload {
+    fn encode(&self) -> [u8; PAYLOAD_SIZE] {
+        let size = self.data.len().min(DATA_SIZE);
+        let mut payload = [0; PAYLOAD_SIZE];
+        payload[0..2].copy_from_slice(&self.seq_number.to_le_bytes());
+        payload[2] = self.session;
+        payload[3] = self.opcode;
+        payload[4] = size as u8;
+        payload[5] = self.req_opcode;
+        payload[8..12].copy_from_slice(&self.offset.to_le_bytes());
+        payload[HEADER_SIZE..HEADER_SIZE + size].copy_from_slice(&self.data[..size]);
+        payload
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() < HEADER_SIZE {
+            return None;
+        }
+        let size = (payload[4] as usize).min(payload.len() - HEADER_SIZE);
+        Some(Self {
+            seq_number: u16::from_le_bytes([payload[0], payload[1]]),
+            session: payload[2],
+            opcode: payload[3],
+            req_opcode: payload[5],
+            offset: u32::from_le_bytes([payload[8], payload[9], payload[10], payload[11]]),
+            data: payload[HEADER_SIZE..HEADER_SIZE + size].to_vec(),
+        })
+    }
+}
+
+fn nak_error(payload: &Payload) -> FtpError {
+    let error = match payload.data.first() {
+        Some(&NAK_EOF) => "end of file".to_string(),
+        Some(&NAK_FILE_NOT_FOUND) => "file not found".to_string(),
+        Some(&NAK_FAIL_ERRNO) => format!("failed with errno {:?}", payload.data.get(1)),
+        Some(error) => format!("error {error}"),
+        None => "unknown error".to_string(),
+    };
+    FtpError::Nak(error)
+}
+
+/// Client of the FTP server of a vehicle component
+pub struct Client<'a> {
+    vehicle: &'a MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    timeout: Duration,
+    seq_number: u16,
+}
+
+impl<'a> Client<'a> {
+    pub fn new(
+        vehicle: &'a MAVLinkVehicleArcMutex,
+        system_id: u8,
+        component_id: u8,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            vehicle,
+            system_id,
+            component_id,
+            timeout,
+            seq_number: 0,
+        }
+    }
+
+    // Send the request until its ACK or NAK arrives
+    fn exchange(&mut self, mut request: Payload) -> Result<Payload, FtpError> {
+        self.seq_number = self.seq_number.wrapping_add(1);
+        request.seq_number = self.seq_number;
+        let message = MavMessage::common(common::MavMessage::FILE_TRANSFER_PROTOCOL(
+            common::FILE_TRANSFER_PROTOCOL_DATA {
+                target_network: 0,
+                target_system: self.system_id,
+                target_component: self.component_id,
+                payload: request.encode(),
+            },
+        ));
+
+        let (system_id, component_id) = (self.system_id, self.component_id);
+        let (seq_number, opcode) = (request.seq_number, request.opcode);
+        for attempt in 1..=RETRIES {
+            let subscription = waiter::subscribe(move |header, message| match message {
+                MavMessage::common(common::MavMessage::FILE_TRANSFER_PROTOCOL(answer)) => {
+                    header.system_id == system_id
+                        && header.component_id == component_id
+                        && Payload::decode(&answer.payload).map_or(false, |answer| {
+                            answer.req_opcode == opcode
+                                && answer.seq_number == seq_number.wrapping_add(1)
+                        })
+                }
+                _ => false,
+            });
+            self.vehicle
+                .lock()
+                .unwrap()
+                .send_default(&message)
+                .map_err(FtpError::Send)?;
+
+            if let Some((
+                _,
+                MavMessage::common(common::MavMessage::FILE_TRANSFER_PROTOCOL(answer)),
+            )) = subscription.wait(self.timeout)
+            {
+                return Payload::decode(&answer.payload)
+                    .ok_or_else(|| FtpError::Nak("invalid answer".into()));
+            }
+            debug!("No answer to FTP opcode {opcode}, attempt {attempt} of {RETRIES}");
+        }
+        Err(FtpError::Timeout(format!("FTP opcode {opcode}")))
+    }
+
+    /// Download the content of a file
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FtpError> {
+        let open = self.exchange(Payload {
+            opcode: OPCODE_OPEN_FILE_RO,
+            data: path.as_bytes().to_vec(),
+            ..Default::default()
+        })?;
+        if open.opcode != OPCODE_ACK {
+            return Err(nak_error(&open));
+        }
+        let session = open.session;
+        let size = match open.data[..] {
+            [a, b, c, d, ..] => u32::from_le_bytes([a, b, c, d]) as usize,
+            _ => 0,
+        };
+
+        let mut content = Vec::with_capacity(size);
+        let result = loop {
+            let read = match self.exchange(Payload {
+                session,
+                opcode: OPCODE_READ_FILE,
+                offset: content.len() as u32,
+                data: vec![0; DATA_SIZE],
+                ..Default::default()
+            }) {
+                Ok(read) => read,
+                Err(error) => break Err(error),
+            };
+            match read.opcode {
+                OPCODE_ACK if !read.data.is_empty() => {
+                    content.extend_from_slice(&read.data);
+                    if size != 0 && content.len() >= size {
+                        break Ok(());
+                    }
+                }
+                OPCODE_NAK if read.data.first() == Some(&NAK_EOF) => break Ok(()),
+                OPCODE_NAK => break Err(nak_error(&read)),
+                _ => break Ok(()),
+            }
+        };
+
+        // The server has a limited number of sessions
+        let _ = self.exchange(Payload {
+            session,
+            opcode: OPCODE_TERMINATE_SESSION,
+            ..Default::default()
+        });
+
+        result.map(|_| content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload() {
+        let payload = Payload {
+            seq_number: 258,
+            session: 3,
+            opcode: OPCODE_READ_FILE,
+            req_opcode: 0,
+            offset: 239,
+            data: b"/etc/component.json".to_vec(),
+        };
+        let encoded = payload.encode();
+        assert_eq!(encoded[..6], [2, 1, 3, OPCODE_READ_FILE, 19, 0]);
+        assert_eq!(Payload::decode(&encoded), Some(payload));
+    }
+}
@@ -0,0 +1,120 @@
+use serde_json::{json, Value};
+
+use super::data;
+use super::history;
+use super::output;
+
+// MAVLink positions are in degE7 and altitudes in millimeters
+fn coordinates(latitude: &Value, longitude: &Value, altitude: &Value) -> Option<Value> {
+    let latitude = latitude.as_f64()? / 1e7;
+    let longitude = longitude.as_f64()? / 1e7;
+    let altitude = altitude.as_f64()? / 1e3;
+    Some(json!([longitude, latitude, altitude]))
+}
+
+fn vehicle_feature(system_id: &str, component_id: &str, position: &Value) -> Option<Value> {
+    let heading = position["hdg"]
+        .as_f64()
+        .filter(|heading| *heading != f64::from(u16::MAX))
+        .map(|heading| heading / 100.0);
+
+    Some(json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": coordinates(&position["lat"], &position["lon"], &position["alt"])?,
+        },
+        "properties": {
+            "kind": "vehicle",
+            "system_id": system_id.parse::<u8>().ok()?,
+            "component_id": component_id.parse::<u8>().ok()?,
+            "relative_altitude": position["relative_alt"].as_f64()? / 1e3,
+            "heading": heading,
+            "velocity": [
+                position["vx"].as_f64()? / 100.0,
+                position["vy"].as_f64()? / 100.0,
+                position["vz"].as_f64()? / 100.0,
+            ],
+        },
+    }))
+}
+
+fn home_feature(system_id: &str, component_id: &str, home: &Value) -> Option<Value> {
+    Some(json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": coordinates(&home["latitude"], &home["longitude"], &home["altitude"])?,
+        },
+        "properties": {
+            "kind": "home",
+            "system_id": system_id.parse::<u8>().ok()?,
+            "component_id": component_id.parse::<u8>().ok()?,
+        },
+    }))
+}
+
+fn track_feature(system_id: u8, component_id: u8, positions: &[Value]) -> Option<Value> {
+    let coordinates: Vec<Value> = positions
+        .iter()
+        .filter_map(|position| coordinates(&position["lat"], &position["lon"], &position["alt"]))
+        .collect();
+
+    if coordinates.len() < 2 {
+        return None;
+    }
+
+    Some(json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+        "properties": {
+            "kind": "track",
+            "system_id": system_id,
+            "component_id": component_id,
+        },
+    }))
+}
+
+/// Vehicle positions, home positions and optionally the recorded tracks as a GeoJSON FeatureCollection
+pub fn feature_collection(track: bool) -> Value {
+    let tree = data::messages().pointer("").unwrap_or_default();
+    let mut features = vec![];
+
+    let entries = |value: &Value| value.as_object().cloned().unwrap_or_default();
+    for (system_id, vehicle) in entries(&tree["vehicles"]) {
+        for (component_id, component) in entries(&vehicle["components"]) {
+            let messages = &component["messages"];
+            features.extend(vehicle_feature(
+                &system_id,
+                &component_id,
+                &messages["GLOBAL_POSITION_INT"]["message"],
+            ));
+            features.extend(home_feature(
+                &system_id,
+                &component_id,
+                &messages["HOME_POSITION"]["message"],
+            ));
+        }
+    }
+
+    if track {
+        let mut tracks: std::collections::BTreeMap<(u8, u8), Vec<Value>> = Default::default();
+        for entry in history::entries(None, None, "GLOBAL_POSITION_INT") {
+            tracks
+                .entry((entry.header.system_id, entry.header.component_id))
+                .or_default()
+                .push(output::to_value(&entry.message));
+        }
+        for ((system_id, component_id), positions) in tracks {
+            features.extend(track_feature(system_id, component_id, &positions));
+        }
+    }
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
@@ -0,0 +1,58 @@
+use mavlink::ardupilotmega::MavMessage;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// Legacy MOUNT_CONTROL, in centidegrees
+    Mount,
+    /// Gimbal manager v2 GIMBAL_MANAGER_SET_PITCHYAW, in radians
+    Manager,
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(protocol: &str) -> Result<Self, Self::Err> {
+        match protocol.to_lowercase().as_str() {
+            "mount" => Ok(Protocol::Mount),
+            "v2" | "manager" => Ok(Protocol::Manager),
+            _ => Err(format!(
+                "Unknown gimbal protocol: {protocol}, use 'mount' or 'v2'"
+            )),
+        }
+    }
+}
+
+/// Message pointing the gimbal to the pitch and yaw, in degrees
+pub fn set_pitch_yaw(
+    protocol: Protocol,
+    target_system: u8,
+    target_component: u8,
+    pitch: f32,
+    yaw: f32,
+) -> MavMessage {
+    match protocol {
+        Protocol::Mount => MavMessage::MOUNT_CONTROL(mavlink::ardupilotmega::MOUNT_CONTROL_DATA {
+            input_a: (pitch * 100.0) as i32,
+            input_b: 0,
+            input_c: (yaw * 100.0) as i32,
+            target_system,
+            target_component,
+            save_position: 0,
+        }),
+        Protocol::Manager => {
+            MavMessage::common(mavlink::common::MavMessage::GIMBAL_MANAGER_SET_PITCHYAW(
+                mavlink::common::GIMBAL_MANAGER_SET_PITCHYAW_DATA {
+                    flags: Default::default(),
+                    pitch: pitch.to_radians(),
+                    yaw: yaw.to_radians(),
+                    // Rates are not used
+                    pitch_rate: f32::NAN,
+                    yaw_rate: f32::NAN,
+                    target_system,
+                    target_component,
+                    gimbal_device_id: 0,
+                },
+            ))
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use super::definitions;
+use super::history;
+use super::output;
+
+// MAVLink types that are represented as a single number
+fn is_numeric(mavlink_type: &str) -> bool {
+    !mavlink_type.contains('[') && mavlink_type != "char"
+}
+
+/// Numeric fields of the recorded messages as MESSAGE.FIELD, containing the filter
+pub fn search(filter: &str) -> Vec<String> {
+    let filter = filter.to_uppercase();
+    history::names()
+        .iter()
+        .filter_map(|name| definitions::message(name))
+        .flat_map(|message| {
+            message
+                .fields
+                .iter()
+                .filter(|field| field.enum_name.is_none() && is_numeric(&field.mavlink_type))
+                .map(move |field| format!("{}.{}", message.name, field.name))
+        })
+        .filter(|target| target.to_uppercase().contains(&filter))
+        .collect()
+}
+
+/// Series of a MESSAGE.FIELD target between the times, one per vehicle and component
+pub fn query(
+    target: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    max_data_points: Option<usize>,
+) -> Vec<Value> {
+    let (name, field) = match target.split_once('.') {
+        Some(target) => target,
+        None => return vec![],
+    };
+
+    let mut series: BTreeMap<(u8, u8), Vec<Value>> = BTreeMap::new();
+    for entry in history::entries(None, None, name) {
+        if entry.time < from || entry.time > to {
+            continue;
+        }
+        if let Some(value) = output::to_value(&entry.message)[field].as_f64() {
+            series
+                .entry((entry.header.system_id, entry.header.component_id))
+                .or_default()
+                .push(json!([value, entry.time.timestamp_millis()]));
+        }
+    }
+
+    let is_single = series.len() == 1;
+    series
+        .into_iter()
+        .map(|((system_id, component_id), datapoints)| {
+            // Keep an evenly spaced subset when there are more points than the panel can show
+            let step = match max_data_points {
+                Some(max) if max > 0 && datapoints.len() > max => {
+                    (datapoints.len() + max - 1) / max
+                }
+                _ => 1,
+            };
+            let datapoints: Vec<Value> = datapoints.into_iter().step_by(step).collect();
+            let target = if is_single {
+                target.to_string()
+            } else {
+                format!("{target} {system_id}/{component_id}")
+            };
+            json!({
+                "target": target,
+                "datapoints": datapoints,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_graphql::{
+    http::{playground_source, GraphQLPlaygroundConfig},
+    EmptyMutation, Json, Object, Schema, SimpleObject, Subscription,
+};
+use async_graphql_actix_web::{Request, Response, WSSubscription};
+use futures::{channel::mpsc, future::ready, Stream, StreamExt};
+use lazy_static::lazy_static;
+use mavlink::Message as _;
+use serde_json::Value;
+
+use super::data::{self, MAVLinkMessage};
+use super::output;
+
+pub type MAVLinkSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct Message {
+    system_id: i32,
+    component_id: i32,
+    /// MAVLink message name
+    name: String,
+    /// Message content, with only the requested fields if any
+    message: Json<Value>,
+    /// Message status, available only for stored messages
+    status: Option<Json<Value>>,
+}
+
+// Arguments shared by queries and subscriptions
+struct MessageFilter {
+    system_id: Option<i32>,
+    component_id: Option<i32>,
+    types: Option<Vec<String>>,
+    fields: Option<Vec<String>>,
+}
+
+impl MessageFilter {
+    fn is_match(&self, system_id: i32, component_id: i32, name: &str) -> bool {
+        self.system_id.map_or(true, |id| id == system_id)
+            && self.component_id.map_or(true, |id| id == component_id)
+            && self.types.as_ref().map_or(true, |types| {
+                types.iter().any(|type_name| type_name == name)
+            })
+    }
+
+    fn select_fields(&self, message: Value) -> Value {
+        let fields = match &self.fields {
+            Some(fields) => fields,
+            None => return message,
+        };
+
+        match message {
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .filter(|(key, _)| key == "type" || fields.contains(key))
+                    .collect(),
+            ),
+            message => message,
+        }
+    }
+}
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<mpsc::UnboundedSender<Message>>> = Mutex::new(vec![]);
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Latest messages received, filtered by vehicle, component and message names
+    async fn messages(
+        &self,
+        system_id: Option<i32>,
+        component_id: Option<i32>,
+        types: Option<Vec<String>>,
+        fields: Option<Vec<String>>,
+    ) -> Vec<Message> {
+        let filter = MessageFilter {
+            system_id,
+            component_id,
+            types,
+            fields,
+        };
+
+        let tree = data::messages().pointer("").unwrap_or_default();
+        let mut messages = vec![];
+        for (system_id, vehicle) in object_entries(&tree["vehicles"]) {
+            for (component_id, component) in object_entries(&vehicle["components"]) {
+                for (name, entry) in object_entries(&component["messages"]) {
+                    let system_id = system_id.parse().unwrap_or_default();
+                    let component_id = component_id.parse().unwrap_or_default();
+                    if !filter.is_match(system_id, component_id, name) {
+                        continue;
+                    }
+
+                    messages.push(Message {
+                        system_id,
+                        component_id,
+                        name: name.clone(),
+                        message: Json(filter.select_fields(entry["message"].clone())),
+                        status: Some(Json(entry["status"].clone())),
+                    });
+                }
+            }
+        }
+
+        messages
+    }
+}
+
+fn object_entries(value: &Value) -> impl Iterator<Item = (&String, &Value)> {
+    value.as_object().into_iter().flatten()
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Messages as they are received, filtered by vehicle, component and message names
+    async fn messages(
+        &self,
+        system_id: Option<i32>,
+        component_id: Option<i32>,
+        types: Option<Vec<String>>,
+        fields: Option<Vec<String>>,
+    ) -> impl Stream<Item = Message> {
+        let filter = Arc::new(MessageFilter {
+            system_id,
+            component_id,
+            types,
+            fields,
+        });
+        let selection = filter.clone();
+
+        let (sender, receiver) = mpsc::unbounded();
+        SUBSCRIBERS.lock().unwrap().push(sender);
+
+        receiver
+            .filter(move |message| {
+                ready(filter.is_match(message.system_id, message.component_id, &message.name))
+            })
+            .map(move |mut message| {
+                message.message = Json(selection.select_fields(message.message.0));
+                message
+            })
+    }
+}
+
+pub fn schema() -> MAVLinkSchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot).finish()
+}
+
+// Forward a received message to all subscriptions
+pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let message = Message {
+        system_id: message.header.system_id as i32,
+        component_id: message.header.component_id as i32,
+        name: message.message.message_name().to_string(),
+        message: Json(output::to_value(&message.message)),
+        status: None,
+    };
+
+    subscribers.retain(|subscriber| subscriber.unbounded_send(message.clone()).is_ok());
+}
+
+pub async fn graphql(schema: web::Data<MAVLinkSchema>, request: Request) -> Response {
+    schema.execute(request.into_inner()).await.into()
+}
+
+pub async fn graphql_websocket(
+    schema: web::Data<MAVLinkSchema>,
+    request: HttpRequest,
+    payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    WSSubscription::start(Schema::clone(&*schema), &request, payload)
+}
+
+pub async fn playground() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(playground_source(
+            GraphQLPlaygroundConfig::new("graphql").subscription_endpoint("ws/graphql"),
+        ))
+}
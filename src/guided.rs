@@ -0,0 +1,165 @@
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{self, MavCmd, MavFrame, PositionTargetTypemask};
+use serde::Serialize;
+
+use super::commands;
+
+/// If the altitude reference, 'relative' (to home) by default or 'amsl', is relative
+pub fn is_relative_altitude(reference: Option<&str>) -> Result<bool, String> {
+    match reference {
+        None | Some("relative") => Ok(true),
+        Some("amsl") => Ok(false),
+        Some(reference) => Err(format!(
+            "Unknown altitude reference {reference}, it should be relative or amsl"
+        )),
+    }
+}
+
+/// Global position of a target, altitude relative to home or above mean sea level
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f32,
+    pub relative_altitude: bool,
+}
+
+impl Position {
+    fn frame(&self) -> MavFrame {
+        if self.relative_altitude {
+            MavFrame::MAV_FRAME_GLOBAL_RELATIVE_ALT
+        } else {
+            MavFrame::MAV_FRAME_GLOBAL
+        }
+    }
+
+    fn frame_int(&self) -> MavFrame {
+        if self.relative_altitude {
+            MavFrame::MAV_FRAME_GLOBAL_RELATIVE_ALT_INT
+        } else {
+            MavFrame::MAV_FRAME_GLOBAL_INT
+        }
+    }
+
+    fn coordinates(&self) -> (i32, i32) {
+        (
+            (self.latitude * 1e7).round() as i32,
+            (self.longitude * 1e7).round() as i32,
+        )
+    }
+}
+
+fn velocity_and_acceleration_ignored() -> PositionTargetTypemask {
+    PositionTargetTypemask::POSITION_TARGET_TYPEMASK_VX_IGNORE
+        | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_VY_IGNORE
+        | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_VZ_IGNORE
+        | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_AX_IGNORE
+        | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_AY_IGNORE
+        | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_AZ_IGNORE
+}
+
+fn yaw_mask(yaw: Option<f32>) -> PositionTargetTypemask {
+    match yaw {
+        Some(_) => PositionTargetTypemask::POSITION_TARGET_TYPEMASK_YAW_RATE_IGNORE,
+        None => {
+            PositionTargetTypemask::POSITION_TARGET_TYPEMASK_YAW_IGNORE
+                | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_YAW_RATE_IGNORE
+        }
+    }
+}
+
+/// SET_POSITION_TARGET_GLOBAL_INT to the position, used by ArduPilot in guided mode, yaw in degrees
+pub fn set_position_target(
+    system_id: u8,
+    component_id: u8,
+    position: &Position,
+    yaw: Option<f32>,
+) -> MavMessage {
+    let (lat_int, lon_int) = position.coordinates();
+    MavMessage::common(common::MavMessage::SET_POSITION_TARGET_GLOBAL_INT(
+        common::SET_POSITION_TARGET_GLOBAL_INT_DATA {
+            time_boot_ms: 0,
+            lat_int,
+            lon_int,
+            alt: position.altitude,
+            vx: 0.0,
+            vy: 0.0,
+            vz: 0.0,
+            afx: 0.0,
+            afy: 0.0,
+            afz: 0.0,
+            yaw: yaw.unwrap_or(0.0).to_radians(),
+            yaw_rate: 0.0,
+            type_mask: velocity_and_acceleration_ignored() | yaw_mask(yaw),
+            target_system: system_id,
+            target_component: component_id,
+            coordinate_frame: position.frame_int(),
+        },
+    ))
+}
+
+/// SET_POSITION_TARGET_LOCAL_NED with the velocity in m/s (north, east, down), yaw in degrees
+pub fn set_velocity_target(
+    system_id: u8,
+    component_id: u8,
+    (vx, vy, vz): (f32, f32, f32),
+    yaw: Option<f32>,
+) -> MavMessage {
+    let position_and_acceleration_ignored =
+        PositionTargetTypemask::POSITION_TARGET_TYPEMASK_X_IGNORE
+            | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_Y_IGNORE
+            | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_Z_IGNORE
+            | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_AX_IGNORE
+            | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_AY_IGNORE
+            | PositionTargetTypemask::POSITION_TARGET_TYPEMASK_AZ_IGNORE;
+    MavMessage::common(common::MavMessage::SET_POSITION_TARGET_LOCAL_NED(
+        common::SET_POSITION_TARGET_LOCAL_NED_DATA {
+            time_boot_ms: 0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            vx,
+            vy,
+            vz,
+            afx: 0.0,
+            afy: 0.0,
+            afz: 0.0,
+            yaw: yaw.unwrap_or(0.0).to_radians(),
+            yaw_rate: 0.0,
+            type_mask: position_and_acceleration_ignored | yaw_mask(yaw),
+            target_system: system_id,
+            target_component: component_id,
+            coordinate_frame: MavFrame::MAV_FRAME_LOCAL_NED,
+        },
+    ))
+}
+
+/// MAV_CMD_DO_CHANGE_SPEED of the ground speed in m/s
+pub fn change_speed(system_id: u8, component_id: u8, speed: f32) -> MavMessage {
+    commands::command_long(
+        system_id,
+        component_id,
+        MavCmd::MAV_CMD_DO_CHANGE_SPEED,
+        [1.0, speed, -1.0, 0.0, 0.0, 0.0, 0.0],
+    )
+}
+
+/// MAV_CMD_DO_REPOSITION to the position as COMMAND_INT, used by PX4, speed in m/s and yaw in degrees
+pub fn reposition(
+    system_id: u8,
+    component_id: u8,
+    position: &Position,
+    speed: Option<f32>,
+    yaw: Option<f32>,
+) -> MavMessage {
+    let (x, y) = position.coordinates();
+    // Switch to guided mode (MAV_DO_REPOSITION_FLAGS_CHANGE_MODE), NaN keeps the current values
+    commands::command_int(
+        system_id,
+        component_id,
+        position.frame(),
+        MavCmd::MAV_CMD_DO_REPOSITION,
+        [speed.unwrap_or(-1.0), 1.0, 0.0, yaw.unwrap_or(f32::NAN)],
+        (x, y, position.altitude),
+    )
+}
@@ -0,0 +1,91 @@
+use serde_json::{json, Map, Value};
+
+use super::definitions;
+
+fn bits(value: &Value) -> u64 {
+    value["bits"]
+        .as_u64()
+        .or_else(|| value.as_u64())
+        .unwrap_or(0)
+}
+
+// MAVLink uses -1 for unknown values
+fn known(value: &Value, scale: f64) -> Value {
+    match value.as_f64() {
+        Some(value) if value >= 0.0 => json!(value * scale),
+        _ => Value::Null,
+    }
+}
+
+/// Decode the sensors bitfields of a SYS_STATUS message
+pub fn describe(sys_status: &Value) -> Value {
+    let present = bits(&sys_status["onboard_control_sensors_present"]);
+    let enabled = bits(&sys_status["onboard_control_sensors_enabled"]);
+    let health = bits(&sys_status["onboard_control_sensors_health"]);
+
+    let mut sensors = Map::new();
+    let mut healthy = true;
+    let entries = definitions::enumeration("MAV_SYS_STATUS_SENSOR")
+        .map(|enumeration| enumeration.entries.as_slice())
+        .unwrap_or_default();
+    for entry in entries {
+        let name = entry
+            .name
+            .trim_start_matches("MAV_SYS_STATUS_")
+            .trim_start_matches("SENSOR_")
+            .to_lowercase();
+        let is_present = present & entry.value != 0;
+        let is_enabled = enabled & entry.value != 0;
+        let is_healthy = health & entry.value != 0;
+        if is_enabled && !is_healthy {
+            healthy = false;
+        }
+
+        sensors.insert(format!("{name}_present"), is_present.into());
+        sensors.insert(format!("{name}_enabled"), is_enabled.into());
+        sensors.insert(format!("{name}_healthy"), is_healthy.into());
+    }
+
+    json!({
+        "healthy": healthy,
+        "sensors": sensors,
+        "battery": {
+            "voltage": sys_status["voltage_battery"].as_f64().map(|voltage| voltage / 1e3),
+            "current": known(&sys_status["current_battery"], 0.01),
+            "remaining": known(&sys_status["battery_remaining"], 1.0),
+        },
+        "load": sys_status["load"].as_f64().map(|load| load / 10.0),
+        "communication": {
+            "drop_rate": sys_status["drop_rate_comm"].as_f64().map(|drop_rate| drop_rate / 100.0),
+            "errors": sys_status["errors_comm"],
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sys_status_decoding() {
+        // Gyro (1) and GPS (32) present and enabled, GPS unhealthy
+        let health = describe(&json!({
+            "onboard_control_sensors_present": {"bits": 33},
+            "onboard_control_sensors_enabled": {"bits": 33},
+            "onboard_control_sensors_health": {"bits": 1},
+            "voltage_battery": 12600,
+            "current_battery": -1,
+            "battery_remaining": 80,
+            "load": 250,
+        }));
+
+        assert_eq!(health["sensors"]["3d_gyro_healthy"], true);
+        assert_eq!(health["sensors"]["gps_enabled"], true);
+        assert_eq!(health["sensors"]["gps_healthy"], false);
+        assert_eq!(health["sensors"]["3d_mag_present"], false);
+        assert_eq!(health["healthy"], false);
+        assert_eq!(health["battery"]["voltage"], 12.6);
+        assert_eq!(health["battery"]["current"], Value::Null);
+        assert_eq!(health["load"], 25.0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{self, MavMessage as CommonMessage};
+use mavlink::Message;
+
+// Messages expanded from HIGH_LATENCY2, the normal telemetry paths of dashboards
+const EXPANDED: &[&str] = &["HEARTBEAT", "GLOBAL_POSITION_INT", "VFR_HUD", "SYS_STATUS"];
+// Messages received directly during this time are not replaced by the condensed ones
+const DIRECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    // Last time each expanded message type was received directly from a component
+    static ref LAST_DIRECT: Mutex<HashMap<(u8, u8, &'static str), Instant>> =
+        Mutex::new(HashMap::new());
+}
+
+fn expand(data: &common::HIGH_LATENCY2_DATA) -> Vec<CommonMessage> {
+    // Speeds are in 0.2 m/s, angles in 2 degrees and climb rate in dm/s
+    let heading_deg = data.heading as f32 * 2.0;
+    let groundspeed = data.groundspeed as f32 / 5.0;
+    let climb = data.climb_rate as f32 / 10.0;
+
+    vec![
+        CommonMessage::HEARTBEAT(common::HEARTBEAT_DATA {
+            custom_mode: data.custom_mode as u32,
+            mavtype: data.mavtype,
+            autopilot: data.autopilot,
+            base_mode: common::MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED,
+            system_status: common::MavState::MAV_STATE_ACTIVE,
+            mavlink_version: 3,
+        }),
+        CommonMessage::GLOBAL_POSITION_INT(common::GLOBAL_POSITION_INT_DATA {
+            time_boot_ms: data.timestamp,
+            lat: data.latitude,
+            lon: data.longitude,
+            alt: data.altitude as i32 * 1000,
+            vx: (groundspeed * heading_deg.to_radians().cos() * 100.0) as i16,
+            vy: (groundspeed * heading_deg.to_radians().sin() * 100.0) as i16,
+            vz: (-climb * 100.0) as i16,
+            hdg: (heading_deg * 100.0) as u16,
+            ..Default::default()
+        }),
+        CommonMessage::VFR_HUD(common::VFR_HUD_DATA {
+            airspeed: data.airspeed as f32 / 5.0,
+            groundspeed,
+            alt: data.altitude as f32,
+            climb,
+            heading: heading_deg as i16,
+            throttle: data.throttle as u16,
+        }),
+        CommonMessage::SYS_STATUS(common::SYS_STATUS_DATA {
+            // Unknown values, as specified by SYS_STATUS
+            voltage_battery: u16::MAX,
+            current_battery: -1,
+            battery_remaining: data.battery,
+            ..Default::default()
+        }),
+    ]
+}
+
+/// The message, followed by the standard messages condensed in it when it is a HIGH_LATENCY2,
+/// unless the component also sends them directly
+pub fn with_expanded(
+    header: mavlink::MavHeader,
+    message: MavMessage,
+) -> Vec<(mavlink::MavHeader, MavMessage)> {
+    let key = |name| (header.system_id, header.component_id, name);
+    let data = match &message {
+        MavMessage::common(CommonMessage::HIGH_LATENCY2(data)) => data.clone(),
+        _ => {
+            let name = message.message_name();
+            if let Some(name) = EXPANDED.iter().find(|expanded| **expanded == name) {
+                LAST_DIRECT
+                    .lock()
+                    .unwrap()
+                    .insert(key(*name), Instant::now());
+            }
+            return vec![(header, message)];
+        }
+    };
+
+    let last_direct = LAST_DIRECT.lock().unwrap();
+    let expanded = expand(&data).into_iter().filter(|expanded| {
+        last_direct
+            .get(&key(expanded.message_name()))
+            .map_or(true, |last| last.elapsed() > DIRECT_TIMEOUT)
+    });
+    std::iter::once((header, message))
+        .chain(expanded.map(|expanded| (header, MavMessage::common(expanded))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn condensed_telemetry() {
+        let header = mavlink::MavHeader {
+            system_id: 77,
+            component_id: 1,
+            sequence: 0,
+        };
+        let high_latency =
+            MavMessage::common(CommonMessage::HIGH_LATENCY2(common::HIGH_LATENCY2_DATA {
+                latitude: -275_000_000,
+                longitude: -485_000_000,
+                altitude: 120,
+                custom_mode: 4,
+                heading: 45,
+                groundspeed: 50,
+                climb_rate: -15,
+                battery: 80,
+                ..Default::default()
+            }));
+
+        let messages = with_expanded(header, high_latency.clone());
+        let names: Vec<&str> = messages.iter().map(|(_, m)| m.message_name()).collect();
+        assert_eq!(
+            names,
+            [
+                "HIGH_LATENCY2",
+                "HEARTBEAT",
+                "GLOBAL_POSITION_INT",
+                "VFR_HUD",
+                "SYS_STATUS"
+            ]
+        );
+        match &messages[2].1 {
+            MavMessage::common(CommonMessage::GLOBAL_POSITION_INT(position)) => {
+                assert_eq!(position.lat, -275_000_000);
+                assert_eq!(position.alt, 120_000);
+                assert_eq!(position.hdg, 9000);
+                assert_eq!(position.vx, 0);
+                assert_eq!(position.vy, 1000);
+                assert_eq!(position.vz, 150);
+            }
+            _ => panic!("GLOBAL_POSITION_INT expected"),
+        }
+
+        // Messages received directly are not replaced
+        let heartbeat = MavMessage::common(CommonMessage::HEARTBEAT(Default::default()));
+        assert_eq!(with_expanded(header, heartbeat).len(), 1);
+        let names: Vec<&str> = with_expanded(header, high_latency)
+            .iter()
+            .map(|(_, m)| m.message_name())
+            .collect();
+        assert!(!names.contains(&"HEARTBEAT"));
+        assert!(names.contains(&"SYS_STATUS"));
+    }
+}
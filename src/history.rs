@@ -0,0 +1,108 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use mavlink::Message;
+
+use super::cli;
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    /// Event identifier of the message, E.g: for SSE resumption
+    pub id: u64,
+    pub time: DateTime<Utc>,
+    /// Monotonic time of arrival, E.g: to limit the rates of replayed messages
+    pub instant: std::time::Instant,
+    pub header: mavlink::MavHeader,
+    pub message: mavlink::ardupilotmega::MavMessage,
+}
+
+// Last messages received per vehicle, component and message name, limited to a fixed size
+#[derive(Debug, Default)]
+struct History {
+    size: usize,
+    entries: HashMap<(u8, u8, String), VecDeque<HistoryEntry>>,
+}
+
+lazy_static! {
+    static ref HISTORY: Mutex<History> = Mutex::new(History {
+        size: cli::history_size(),
+        entries: HashMap::new(),
+    });
+}
+
+pub fn init() {
+    lazy_static::initialize(&HISTORY);
+}
+
+pub fn is_enabled() -> bool {
+    HISTORY.lock().unwrap().size > 0
+}
+
+pub fn push(id: u64, header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let mut history = HISTORY.lock().unwrap();
+    let size = history.size;
+    if size == 0 {
+        return;
+    }
+
+    let key = (
+        header.system_id,
+        header.component_id,
+        message.message_name().to_string(),
+    );
+    let entries = history.entries.entry(key).or_default();
+    if entries.len() >= size {
+        entries.pop_front();
+    }
+    entries.push_back(HistoryEntry {
+        id,
+        time: Utc::now(),
+        instant: std::time::Instant::now(),
+        header: *header,
+        message: message.clone(),
+    });
+}
+
+/// Recorded messages with the given name, from the oldest to the newest, for all matching vehicles and components
+pub fn entries(system_id: Option<u8>, component_id: Option<u8>, name: &str) -> Vec<HistoryEntry> {
+    let history = HISTORY.lock().unwrap();
+    let mut entries: Vec<HistoryEntry> = history
+        .entries
+        .iter()
+        .filter(|((entry_system_id, entry_component_id, entry_name), _)| {
+            system_id.map_or(true, |id| id == *entry_system_id)
+                && component_id.map_or(true, |id| id == *entry_component_id)
+                && entry_name == name
+        })
+        .flat_map(|(_, entries)| entries.iter().cloned())
+        .collect();
+    entries.sort_by_key(|entry| entry.time);
+    entries
+}
+
+/// Names of the recorded messages
+pub fn names() -> Vec<String> {
+    let history = HISTORY.lock().unwrap();
+    let mut names: Vec<String> = history
+        .entries
+        .keys()
+        .map(|(_, _, name)| name.clone())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Recorded messages of all names received after the given event identifier, in order
+pub fn since(id: u64) -> Vec<HistoryEntry> {
+    let history = HISTORY.lock().unwrap();
+    let mut entries: Vec<HistoryEntry> = history
+        .entries
+        .values()
+        .flat_map(|entries| entries.iter().filter(|entry| entry.id > id).cloned())
+        .collect();
+    entries.sort_by_key(|entry| entry.id);
+    entries
+}
@@ -0,0 +1,78 @@
+use serde_json::{json, Value};
+
+fn degrees(radians: &Value) -> Value {
+    radians
+        .as_f64()
+        .map_or(Value::Null, |radians| json!(radians.to_degrees()))
+}
+
+// MAVLink uses -1 or UINT16_MAX for unknown values
+fn known(value: &Value, scale: f64, unknown: f64) -> Value {
+    match value.as_f64() {
+        Some(value) if value >= 0.0 && value != unknown => json!(value * scale),
+        _ => Value::Null,
+    }
+}
+
+// Short name of a GPS_FIX_TYPE, E.g: 3D for GPS_FIX_TYPE_3D_FIX
+fn fix(gps_raw_int: &Value) -> Value {
+    gps_raw_int["fix_type"]["type"]
+        .as_str()
+        .map_or(Value::Null, |fix_type| {
+            json!(fix_type
+                .trim_start_matches("GPS_FIX_TYPE_")
+                .trim_end_matches("_FIX"))
+        })
+}
+
+/// Compact summary of ATTITUDE, VFR_HUD, GPS_RAW_INT and SYS_STATUS, for overlays
+pub fn summary(
+    attitude: Option<&Value>,
+    vfr_hud: Option<&Value>,
+    gps_raw_int: Option<&Value>,
+    sys_status: Option<&Value>,
+) -> Value {
+    let null = Value::Null;
+    let attitude = attitude.unwrap_or(&null);
+    let vfr_hud = vfr_hud.unwrap_or(&null);
+    let gps_raw_int = gps_raw_int.unwrap_or(&null);
+    let sys_status = sys_status.unwrap_or(&null);
+
+    json!({
+        "roll": degrees(&attitude["roll"]),
+        "pitch": degrees(&attitude["pitch"]),
+        "yaw": degrees(&attitude["yaw"]),
+        "heading": vfr_hud["heading"],
+        "airspeed": vfr_hud["airspeed"],
+        "groundspeed": vfr_hud["groundspeed"],
+        "alt": vfr_hud["alt"],
+        "climb": vfr_hud["climb"],
+        "throttle": vfr_hud["throttle"],
+        "fix": fix(gps_raw_int),
+        "sats": known(&gps_raw_int["satellites_visible"], 1.0, 255.0),
+        "voltage": known(&sys_status["voltage_battery"], 1e-3, 65535.0),
+        "current": known(&sys_status["current_battery"], 0.01, -1.0),
+        "remaining": known(&sys_status["battery_remaining"], 1.0, -1.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hud_summary() {
+        let hud = summary(
+            Some(&json!({"roll": 0.0, "pitch": std::f64::consts::FRAC_PI_2, "yaw": 0.0})),
+            Some(&json!({"heading": 90, "groundspeed": 5.0, "alt": 10.0})),
+            Some(&json!({"fix_type": {"type": "GPS_FIX_TYPE_3D_FIX"}, "satellites_visible": 12})),
+            None,
+        );
+
+        assert_eq!(hud["pitch"], 90.0);
+        assert_eq!(hud["heading"], 90);
+        assert_eq!(hud["fix"], "3D");
+        assert_eq!(hud["sats"], 12.0);
+        assert!(hud["voltage"].is_null());
+    }
+}
@@ -0,0 +1,282 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::Message;
+use serde_json::Value;
+
+use super::cli;
+use super::data::MAVLinkMessage;
+use super::network;
+use super::output;
+
+const MEASUREMENT: &str = "mavlink";
+// Lines are written in batches to reduce the number of HTTP requests
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+const UDP_MAX_PAYLOAD: usize = 1400;
+
+#[derive(Debug)]
+enum Destination {
+    // Host, path with the query and token of the v2 write API
+    Http {
+        host: String,
+        path: String,
+        token: Option<String>,
+    },
+    Udp(SocketAddr),
+}
+
+impl std::str::FromStr for Destination {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        if let Some(address) = url.strip_prefix("udp:") {
+            return network::socket_address(address.trim_start_matches("//"))
+                .map(Destination::Udp)
+                .map_err(|error| format!("Invalid InfluxDB address {url}: {error}"));
+        }
+
+        let url = url.strip_prefix("http://").ok_or_else(|| {
+            format!("Invalid InfluxDB url, it should be http://HOST:PORT/api/v2/write?org=ORG&bucket=BUCKET or udp:IP:PORT: {url}")
+        })?;
+        let (host, path) = match url.find('/') {
+            Some(index) => url.split_at(index),
+            None => (url, "/api/v2/write"),
+        };
+        let separator = if path.contains('?') { '&' } else { '?' };
+        Ok(Destination::Http {
+            host: host.to_string(),
+            path: format!("{path}{separator}precision=ns"),
+            token: cli::influx_token(),
+        })
+    }
+}
+
+// Fields selected as MESSAGE.FIELD, MESSAGE.* or *
+fn is_selected(selection: &[String], name: &str, field: &str) -> bool {
+    selection.is_empty()
+        || selection
+            .iter()
+            .any(|selected| match selected.split_once('.') {
+                Some((message, selected_field)) => {
+                    message == name && (selected_field == "*" || selected_field == field)
+                }
+                None => selected == "*" || selected == name,
+            })
+}
+
+fn escape_key(key: &str) -> String {
+    key.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn field_value(value: &Value) -> Option<String> {
+    match value {
+        // Numbers are always floats to avoid field type conflicts between messages
+        Value::Number(number) => number.as_f64().map(|number| format!("{number:?}")),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Object(object) => match (object.get("type"), object.get("bits")) {
+            (Some(Value::String(entry)), _) => Some(format!("\"{}\"", entry.replace('"', "\\\""))),
+            (_, Some(bits)) => field_value(bits),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Line protocol of the selected fields of the message, None if there is no field to write
+pub fn line(
+    header: &mavlink::MavHeader,
+    message: &Value,
+    selection: &[String],
+    timestamp_ns: u128,
+) -> Option<String> {
+    let name = message["type"].as_str()?;
+    let mut fields = vec![];
+    for (field, value) in message.as_object()? {
+        if field == "type" || !is_selected(selection, name, field) {
+            continue;
+        }
+
+        match value {
+            Value::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    if let Some(value) = value.as_f64().and_then(|value| field_value(&value.into()))
+                    {
+                        fields.push(format!("{}_{index}={value}", escape_key(field)));
+                    }
+                }
+            }
+            value => {
+                if let Some(value) = field_value(value) {
+                    fields.push(format!("{}={value}", escape_key(field)));
+                }
+            }
+        }
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{MEASUREMENT},system_id={},component_id={},message={} {} {timestamp_ns}",
+        header.system_id,
+        header.component_id,
+        escape_key(name),
+        fields.join(",")
+    ))
+}
+
+fn write_http(host: &str, path: &str, token: Option<&str>, body: &str) -> Result<(), String> {
+    let address = network::socket_address(host).map_err(|error| error.to_string())?;
+    let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(5))
+        .map_err(|error| error.to_string())?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|error| error.to_string())?;
+
+    let authorization = token
+        .map(|token| format!("Authorization: Token {token}\r\n"))
+        .unwrap_or_default();
+    let request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\n{authorization}Content-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|error| error.to_string())?;
+
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    let status = response.lines().next().unwrap_or_default();
+    // The write API answers 204 on success
+    if status.split_whitespace().nth(1) == Some("204") {
+        return Ok(());
+    }
+    Err(format!("Unexpected response: {status}"))
+}
+
+fn write_udp(address: SocketAddr, lines: &[String]) -> Result<(), String> {
+    let local_address = if address.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(local_address).map_err(|error| error.to_string())?;
+
+    let mut payload = String::new();
+    for line in lines {
+        if !payload.is_empty() && payload.len() + line.len() + 1 > UDP_MAX_PAYLOAD {
+            socket
+                .send_to(payload.as_bytes(), address)
+                .map_err(|error| error.to_string())?;
+            payload.clear();
+        }
+        payload.push_str(line);
+        payload.push('\n');
+    }
+    if !payload.is_empty() {
+        socket
+            .send_to(payload.as_bytes(), address)
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
+
+fn run(destination: Destination, receiver: mpsc::Receiver<String>) {
+    loop {
+        std::thread::sleep(BATCH_INTERVAL);
+        let lines: Vec<String> = receiver.try_iter().collect();
+        if lines.is_empty() {
+            continue;
+        }
+
+        let result = match &destination {
+            Destination::Http { host, path, token } => {
+                write_http(host, path, token.as_deref(), &lines.join("\n"))
+            }
+            Destination::Udp(address) => write_udp(*address, &lines),
+        };
+        if let Err(error) = result {
+            warn!("Failed to write {} lines to InfluxDB: {error}", lines.len());
+        }
+    }
+}
+
+lazy_static! {
+    // Lines are written by a dedicated thread, so InfluxDB latency does not hold the main loop
+    static ref SENDER: Option<Mutex<mpsc::SyncSender<String>>> = cli::influx_url().map(|url| {
+        let destination: Destination = url.parse().unwrap_or_else(|error| panic!("{error}"));
+        let (sender, receiver) = mpsc::sync_channel(10000);
+        std::thread::spawn(move || run(destination, receiver));
+        Mutex::new(sender)
+    });
+    static ref SELECTION: Vec<String> = cli::influx_fields();
+}
+
+pub fn init() {
+    lazy_static::initialize(&SENDER);
+}
+
+pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    let sender = match SENDER.as_ref() {
+        Some(sender) => sender,
+        None => return,
+    };
+    let name = message.message.message_name();
+    if !SELECTION.is_empty()
+        && !SELECTION
+            .iter()
+            .any(|selected| selected == "*" || selected.split('.').next() == Some(name))
+    {
+        return;
+    }
+
+    let timestamp_ns = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let value = output::to_value(&message.message);
+    if let Some(line) = line(&message.header, &value, &SELECTION, timestamp_ns) {
+        // Drop lines while InfluxDB is not keeping up
+        if sender.lock().unwrap().try_send(line).is_err() {
+            debug!("InfluxDB output queue is full, dropping message.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn line_protocol() {
+        let header = mavlink::MavHeader {
+            system_id: 1,
+            component_id: 1,
+            sequence: 0,
+        };
+        let message = json!({
+            "type": "HEARTBEAT",
+            "custom_mode": 4,
+            "mavtype": {"type": "MAV_TYPE_QUADROTOR"},
+            "base_mode": {"bits": 81},
+        });
+
+        assert_eq!(
+            line(&header, &message, &[], 10).unwrap(),
+            "mavlink,system_id=1,component_id=1,message=HEARTBEAT base_mode=81.0,custom_mode=4.0,mavtype=\"MAV_TYPE_QUADROTOR\" 10"
+        );
+        assert_eq!(
+            line(&header, &message, &["HEARTBEAT.custom_mode".into()], 10).unwrap(),
+            "mavlink,system_id=1,component_id=1,message=HEARTBEAT custom_mode=4.0 10"
+        );
+        assert!(line(&header, &message, &["ATTITUDE.*".into()], 10).is_none());
+    }
+}
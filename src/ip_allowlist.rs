@@ -0,0 +1,118 @@
+use std::net::IpAddr;
+
+use actix_web::dev::ServiceRequest;
+use lazy_static::lazy_static;
+
+use super::cli;
+
+// Network in CIDR notation, E.g: 192.168.2.0/24, a single address without prefix length
+#[derive(Debug, PartialEq)]
+pub struct Network {
+    address: IpAddr,
+    prefix_length: u8,
+}
+
+impl std::str::FromStr for Network {
+    type Err = String;
+
+    fn from_str(network: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_length) = match network.split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (network, None),
+        };
+        let address: IpAddr = address
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid IP address: {network}"))?;
+
+        let max_length = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_length| *prefix_length <= max_length)
+                .ok_or_else(|| format!("Invalid prefix length: {network}"))?,
+            None => max_length,
+        };
+
+        Ok(Network {
+            address,
+            prefix_length,
+        })
+    }
+}
+
+// IPv4 clients of dual-stack sockets have IPv4-mapped IPv6 addresses
+fn canonical(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(ipv6) => match ipv6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, ..] => IpAddr::V4(ipv6.to_ipv4().unwrap()),
+            _ => address,
+        },
+        address => address,
+    }
+}
+
+impl Network {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let mask = |bits: u32| match self.prefix_length as u32 {
+            0 => 0,
+            length => u128::MAX << (bits - length),
+        };
+        match (self.address, canonical(address)) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = mask(32) as u32;
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = mask(128);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+lazy_static! {
+    static ref ALLOWED_NETWORKS: Vec<Network> = cli::allowed_ips()
+        .iter()
+        .map(|network| network.parse().unwrap_or_else(|error| panic!("{error}")))
+        .collect();
+}
+
+// Check if the client address is in the allowed networks, any client is allowed if there are none
+pub fn is_allowed(request: &ServiceRequest) -> bool {
+    if ALLOWED_NETWORKS.is_empty() {
+        return true;
+    }
+
+    // Unix domain socket clients are local
+    match request.peer_addr() {
+        Some(address) => ALLOWED_NETWORKS
+            .iter()
+            .any(|network| network.contains(address.ip())),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_contains() {
+        let network: Network = "192.168.2.0/24".parse().unwrap();
+        assert!(network.contains("192.168.2.10".parse().unwrap()));
+        assert!(network.contains("::ffff:192.168.2.10".parse().unwrap()));
+        assert!(!network.contains("192.168.3.10".parse().unwrap()));
+
+        let address: Network = "10.0.0.1".parse().unwrap();
+        assert!(address.contains("10.0.0.1".parse().unwrap()));
+        assert!(!address.contains("10.0.0.2".parse().unwrap()));
+
+        let any: Network = "::/0".parse().unwrap();
+        assert!(any.contains("fe80::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use serde_json::{json, Map, Value};
+
+use super::definitions::{self, FieldDefinition};
+
+// Schema of a single value of the given MAVLink base type, E.g: uint16_t
+fn scalar_schema(base_type: &str) -> Value {
+    let integer = |minimum: i128, maximum: i128| json!({"type": "integer", "minimum": minimum, "maximum": maximum});
+
+    match base_type {
+        "float" | "double" => json!({"type": "number"}),
+        "char" => json!({"type": "string", "minLength": 1, "maxLength": 1}),
+        "int8_t" => integer(i8::MIN.into(), i8::MAX.into()),
+        "int16_t" => integer(i16::MIN.into(), i16::MAX.into()),
+        "int32_t" => integer(i32::MIN.into(), i32::MAX.into()),
+        "int64_t" => integer(i64::MIN.into(), i64::MAX.into()),
+        "uint16_t" => integer(0, u16::MAX.into()),
+        "uint32_t" => integer(0, u32::MAX.into()),
+        "uint64_t" => integer(0, u64::MAX.into()),
+        // uint8_t and uint8_t_mavlink_version
+        _ => integer(0, u8::MAX.into()),
+    }
+}
+
+fn enum_schema(enum_name: &str) -> Option<Value> {
+    let definition = definitions::enumeration(enum_name)?;
+
+    if definition.bitmask {
+        return Some(json!({
+            "type": "object",
+            "description": definition.description,
+            "properties": {"bits": {"type": "integer", "minimum": 0}},
+            "required": ["bits"],
+        }));
+    }
+
+    let names: Vec<&str> = definition
+        .entries
+        .iter()
+        .map(|entry| entry.name.as_str())
+        .collect();
+
+    Some(json!({
+        "type": "object",
+        "description": definition.description,
+        "properties": {"type": {"type": "string", "enum": names}},
+        "required": ["type"],
+    }))
+}
+
+fn field_schema(field: &FieldDefinition) -> Value {
+    let mut parts = field.mavlink_type.trim_end_matches(']').split('[');
+    let base_type = parts.next().unwrap_or_default();
+    let length = parts.next().and_then(|length| length.parse::<usize>().ok());
+
+    let item = field
+        .enum_name
+        .as_deref()
+        .and_then(enum_schema)
+        .unwrap_or_else(|| scalar_schema(base_type));
+
+    let mut schema = match length {
+        Some(length) => json!({
+            "type": "array",
+            "items": item,
+            "minItems": length,
+            "maxItems": length,
+        }),
+        None => item,
+    };
+
+    let mut description = field.description.clone();
+    if let Some(units) = &field.units {
+        description = format!("{description} [{units}]");
+    }
+    schema["description"] = json!(description.trim());
+    schema
+}
+
+/// JSON Schema of the given MAVLink message, as used in the `message` field of requests and responses
+pub fn message_schema(name: &str) -> Option<Value> {
+    let message = definitions::message(name)?;
+
+    let mut properties = Map::new();
+    properties.insert(
+        "type".to_string(),
+        json!({"type": "string", "const": message.name}),
+    );
+    for field in &message.fields {
+        properties.insert(field.name.clone(), field_schema(field));
+    }
+
+    let required: Vec<&String> = properties.keys().collect();
+
+    Some(json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "$id": format!("/schema/mavlink/{}", message.name),
+        "title": message.name,
+        "description": message.description,
+        "type": "object",
+        "required": required,
+        "properties": properties,
+    }))
+}
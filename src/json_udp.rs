@@ -0,0 +1,66 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use lazy_static::lazy_static;
+use log::*;
+
+use super::cli;
+use super::data::MAVLinkMessage;
+use super::network;
+use super::output;
+
+struct Output {
+    socket: UdpSocket,
+    address: SocketAddr,
+}
+
+// Addresses like udp:IP:PORT
+fn output(connection: &str) -> Result<Output, String> {
+    let address = connection
+        .strip_prefix("udp:")
+        .ok_or_else(|| format!("Invalid JSON output, it should be udp:IP:PORT: {connection}"))?;
+    let address = network::socket_address(address)
+        .map_err(|error| format!("Invalid JSON output address {connection}: {error}"))?;
+
+    let local_address = if address.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(local_address)
+        .map_err(|error| format!("Failed to create JSON output {connection}: {error}"))?;
+    // Collectors may be on the broadcast address of the ground station network
+    let _ = socket.set_broadcast(true);
+
+    Ok(Output { socket, address })
+}
+
+lazy_static! {
+    static ref OUTPUTS: Vec<Output> = cli::json_outputs()
+        .iter()
+        .map(|connection| output(connection).unwrap_or_else(|error| panic!("{error}")))
+        .collect();
+}
+
+pub fn init() {
+    lazy_static::initialize(&OUTPUTS);
+    for output in OUTPUTS.iter() {
+        info!("Sending JSON messages to udp:{}", output.address);
+    }
+}
+
+/// Send the message as a JSON datagram to all outputs
+pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    if OUTPUTS.is_empty() {
+        return;
+    }
+
+    let mut value = output::to_value(message);
+    output::apply(&mut value, &output::OutputOptions::global());
+    let payload = value.to_string();
+
+    for output in OUTPUTS.iter() {
+        if let Err(error) = output.socket.send_to(payload.as_bytes(), output.address) {
+            debug!("Failed to send JSON message to {}: {error}", output.address);
+        }
+    }
+}
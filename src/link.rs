@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+// Packets received and lost from a component, from the gaps in the sequence numbers of its messages
+#[derive(Clone, Debug, Serialize)]
+pub struct Sequence {
+    system_id: u8,
+    component_id: u8,
+    received: u64,
+    lost: u64,
+    /// Packets lost over the packets sent, in %
+    loss_percent: f64,
+    #[serde(skip)]
+    last_sequence: u8,
+}
+
+// Last RADIO_STATUS, or RADIO of ArduPilot, of a telemetry radio
+#[derive(Clone, Debug, Serialize)]
+pub struct Radio {
+    system_id: u8,
+    component_id: u8,
+    rssi: u8,
+    remrssi: u8,
+    noise: u8,
+    remnoise: u8,
+    /// Free space in the transmit buffer, in %
+    txbuf: u8,
+    rxerrors: u16,
+    fixed: u16,
+    last_update: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LinkQuality {
+    sequences: Vec<Sequence>,
+    radios: Vec<Radio>,
+}
+
+lazy_static! {
+    static ref SEQUENCES: Mutex<BTreeMap<(u8, u8), Sequence>> = Mutex::new(BTreeMap::new());
+    static ref RADIOS: Mutex<BTreeMap<(u8, u8), Radio>> = Mutex::new(BTreeMap::new());
+}
+
+impl Sequence {
+    fn update(&mut self, sequence: u8) {
+        // Sequence numbers wrap at 255, everything between the expected and the received one was lost
+        let lost = sequence.wrapping_sub(self.last_sequence.wrapping_add(1));
+        self.lost += lost as u64;
+        self.received += 1;
+        self.last_sequence = sequence;
+        self.loss_percent = 100.0 * self.lost as f64 / (self.received + self.lost) as f64;
+    }
+}
+
+pub fn update(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    use mavlink::ardupilotmega::MavMessage;
+    use mavlink::common::MavMessage as CommonMessage;
+
+    let key = (header.system_id, header.component_id);
+    SEQUENCES
+        .lock()
+        .unwrap()
+        .entry(key)
+        .and_modify(|sequence| sequence.update(header.sequence))
+        .or_insert_with(|| Sequence {
+            system_id: header.system_id,
+            component_id: header.component_id,
+            received: 1,
+            lost: 0,
+            loss_percent: 0.0,
+            last_sequence: header.sequence,
+        });
+
+    let radio = match message {
+        MavMessage::common(CommonMessage::RADIO_STATUS(data)) => (
+            data.rssi,
+            data.remrssi,
+            data.noise,
+            data.remnoise,
+            data.txbuf,
+            data.rxerrors,
+            data.fixed,
+        ),
+        MavMessage::RADIO(data) => (
+            data.rssi,
+            data.remrssi,
+            data.noise,
+            data.remnoise,
+            data.txbuf,
+            data.rxerrors,
+            data.fixed,
+        ),
+        _ => return,
+    };
+    let (rssi, remrssi, noise, remnoise, txbuf, rxerrors, fixed) = radio;
+    RADIOS.lock().unwrap().insert(
+        key,
+        Radio {
+            system_id: header.system_id,
+            component_id: header.component_id,
+            rssi,
+            remrssi,
+            noise,
+            remnoise,
+            txbuf,
+            rxerrors,
+            fixed,
+            last_update: Utc::now(),
+        },
+    );
+}
+
+/// Packet loss of each component and the state of the telemetry radios
+pub fn quality() -> LinkQuality {
+    LinkQuality {
+        sequences: SEQUENCES.lock().unwrap().values().cloned().collect(),
+        radios: RADIOS.lock().unwrap().values().cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_loss() {
+        let mut sequence = Sequence {
+            system_id: 1,
+            component_id: 1,
+            received: 1,
+            lost: 0,
+            loss_percent: 0.0,
+            last_sequence: 250,
+        };
+        sequence.update(251);
+        // 252 to 255 and 0 were lost
+        sequence.update(1);
+        assert_eq!(sequence.received, 3);
+        assert_eq!(sequence.lost, 5);
+        assert_eq!(sequence.loss_percent, 62.5);
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::Write;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use super::cli;
+
+/// Target of the access log lines, written by the server
+pub const ACCESS_LOG_TARGET: &str = "mavlink2rest::access";
+
+// env_logger filters can't change after creation, so it's replaced when the configuration is reloaded
+struct ReloadableLogger {
+    inner: RwLock<env_logger::Logger>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.read().unwrap().log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.read().unwrap().flush();
+    }
+}
+
+lazy_static! {
+    static ref LOGGER: ReloadableLogger = ReloadableLogger {
+        inner: RwLock::new(build_logger()),
+    };
+}
+
+// The filter is controlled by the verbosity unless RUST_LOG is defined
+fn filter() -> String {
+    if let Ok(filter) = std::env::var("RUST_LOG") {
+        return filter;
+    }
+
+    let level = match cli::verbosity() {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+
+    let access_log_level = if cli::is_access_log() { "info" } else { "off" };
+    format!("{level},{ACCESS_LOG_TARGET}={access_log_level}")
+}
+
+fn build_logger() -> env_logger::Logger {
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&filter());
+    builder.format_timestamp_millis();
+
+    if cli::is_log_json() {
+        builder.format(|buffer, record| {
+            writeln!(
+                buffer,
+                "{}",
+                serde_json::json!({
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                })
+            )
+        });
+    }
+
+    builder.build()
+}
+
+pub fn init() {
+    log::set_logger(&*LOGGER).expect("Failed to set logger.");
+    log::set_max_level(LOGGER.inner.read().unwrap().filter());
+    cli::on_reload(reload);
+}
+
+fn reload() {
+    let logger = build_logger();
+    log::set_max_level(logger.filter());
+    *LOGGER.inner.write().unwrap() = logger;
+}
@@ -1,36 +1,132 @@
+mod attitude;
+mod auth;
+mod autopilot;
+mod batteries;
+mod calibration;
+mod check;
 mod cli;
+mod command_tracker;
+mod commands;
+mod component_information;
 mod data;
+mod defaults;
+mod definitions;
+mod derived;
+mod ekf;
 mod endpoints;
+mod escs;
+mod events;
+mod export;
+mod fake;
+mod filter;
+mod ftp;
+mod geojson;
+mod gimbal;
+mod grafana;
+mod graphql;
+mod guided;
+mod health;
+mod high_latency;
+mod history;
+mod hud;
+mod influx;
+mod ip_allowlist;
+mod json_schema;
+mod json_udp;
+mod link;
+mod logger;
+mod mavlink_out;
 mod mavlink_vehicle;
+mod mdns;
+mod metrics;
+mod mission;
+mod modes;
+mod mqtt;
+mod network;
+mod output;
+mod params;
+mod plan;
+mod protobuf;
+mod rate_limit;
+mod raw_frames;
+mod raw_websocket;
+mod redis_output;
+mod rosbridge;
+mod rtcm;
+mod serial_detection;
 mod server;
+mod setpoints;
+mod shell;
+mod sse;
+mod stream_rates;
+mod systemd;
+mod throttle;
+mod timesync;
+mod traffic;
+mod tunnel;
+mod udp_multicast;
+mod vehicles;
+mod waiter;
 mod websocket_manager;
 
 use std::sync::{Arc, Mutex};
 
 use data::MAVLinkMessage;
 use log::*;
+use mavlink::Message;
 
 fn main() -> std::io::Result<()> {
-    let log_filter = if cli::is_verbose() { "debug" } else { "warn" };
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_filter)).init();
     cli::init();
+    logger::init();
+
+    if cli::is_list_ports() {
+        serial_detection::list_ports();
+        return Ok(());
+    }
+
+    if cli::is_check() {
+        std::process::exit(check::run());
+    }
+
+    data::init();
+    filter::init();
+    throttle::init();
+    history::init();
+    sse::init();
+    mqtt::init();
+    redis_output::init();
+    json_udp::init();
+    influx::init();
+    mdns::init();
+    systemd::init();
+    vehicles::init();
+
+    #[cfg(unix)]
+    reload_on_sighup();
+    #[cfg(unix)]
+    cleanup_on_exit();
 
     let mavlink_version = match cli::mavlink_version() {
         1 => mavlink::MavlinkVersion::V1,
         2 => mavlink::MavlinkVersion::V2,
         _ => panic!("Invalid mavlink version."),
     };
+    raw_frames::init(mavlink_version);
 
     let (system_id, component_id) = cli::mavlink_system_and_component_id();
     let vehicle = mavlink_vehicle::MAVLinkVehicleHandle::<mavlink::ardupilotmega::MavMessage>::new(
-        cli::mavlink_connection_string(),
+        &cli::mavlink_connection_string(),
         mavlink_version,
         system_id,
         component_id,
     );
 
     let inner_vehicle = vehicle.mavlink_vehicle.clone();
-    server::run(cli::server_address(), &inner_vehicle);
+    mavlink_out::init(&inner_vehicle);
+    timesync::init(&inner_vehicle);
+    setpoints::init(&inner_vehicle);
+    server::run(&cli::server_addresses(), &inner_vehicle);
+    systemd::notify("READY=1");
 
     //TODO: Do inside endpoint and use web::Data ?
     websocket_manager::manager()
@@ -44,20 +140,99 @@ fn main() -> std::io::Result<()> {
         std::thread::sleep(std::time::Duration::from_secs(1));
 
         while let Ok((header, message)) = vehicle.thread_rx_channel.recv() {
-            debug!("Received: {:#?} {:#?}", header, message);
-            websocket_manager::send(&MAVLinkMessage {
-                header,
-                message: message.clone(),
-            });
-            data::update((header, message));
+            // Outputs forward all messages, like a router
+            mavlink_out::send(&header, &message);
+            // Filtered messages can also be inspected
+            raw_frames::push(&header, &message);
+            link::update(&header, &message);
+            stream_rates::on_message(&vehicle.mavlink_vehicle, &header, &message);
+            timesync::on_message(&vehicle.mavlink_vehicle, &header, &message);
+            events::on_message(&vehicle.mavlink_vehicle, &header, &message);
+            command_tracker::on_message(&header, &message);
+            shell::send(&header, &message);
+            tunnel::send(&header, &message);
+
+            // Condensed HIGH_LATENCY2 telemetry is also available as the standard messages
+            for (header, message) in high_latency::with_expanded(header, message) {
+                if !filter::is_allowed(message.message_name()) {
+                    continue;
+                }
+
+                debug!("Received: {:#?} {:#?}", header, message);
+                vehicles::update(&header, &message);
+                traffic::update(&header, &message);
+                batteries::update(&header, &message);
+                escs::update(&header, &message);
+                derived::update(&header, &message);
+                waiter::notify(&header, &message);
+                raw_websocket::send(&header, &message);
+                let mavlink_message = MAVLinkMessage {
+                    header,
+                    message: message.clone(),
+                };
+                let event_id = sse::next_event_id();
+                websocket_manager::send(&mavlink_message);
+                sse::send(event_id, &mavlink_message);
+                rosbridge::send(&mavlink_message);
+                graphql::send(&mavlink_message);
+                mqtt::send(&mavlink_message);
+                redis_output::send(&mavlink_message);
+                json_udp::send(&mavlink_message);
+                influx::send(&mavlink_message);
+                // Only the store is limited, the history has all messages for the SSE resumption
+                history::push(event_id, &header, &message);
+                if throttle::should_store(&header, &message) {
+                    data::update((header, message));
+                }
+            }
         }
     }
 }
 
+#[cfg(unix)]
+fn reload_on_sighup() {
+    let mut signals = signal_hook::iterator::Signals::new(&[signal_hook::consts::SIGHUP])
+        .expect("Failed to register SIGHUP handler.");
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("SIGHUP received, reloading configuration.");
+            if let Err(error) = cli::reload() {
+                error!("Failed to reload configuration: {error}");
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn cleanup_on_exit() {
+    let mut signals = signal_hook::iterator::Signals::new(&[
+        signal_hook::consts::SIGINT,
+        signal_hook::consts::SIGTERM,
+    ])
+    .expect("Failed to register exit signal handlers.");
+
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("Signal {signal} received, exiting.");
+            server::cleanup();
+            std::process::exit(0);
+        }
+    });
+}
+
 fn ws_callback(
     inner_vehicle: Arc<Mutex<mavlink_vehicle::MAVLinkVehicle<mavlink::ardupilotmega::MavMessage>>>,
     value: &str,
 ) -> String {
+    let value = &match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(mut value) => {
+            output::normalize_keys(&mut value);
+            value.to_string()
+        }
+        Err(_) => value.to_string(),
+    };
+
     if let Ok(content @ MAVLinkMessage::<mavlink::ardupilotmega::MavMessage> { .. }) =
         serde_json::from_str(value)
     {
@@ -0,0 +1,96 @@
+use std::io::{BufReader, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use super::cli;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::network;
+use super::raw_frames;
+
+lazy_static! {
+    static ref CLIENTS: Mutex<Vec<TcpStream>> = Mutex::new(vec![]);
+}
+
+pub fn init(vehicle: &MAVLinkVehicleArcMutex) {
+    for output in cli::mavlink_outputs() {
+        match output.split_once(':') {
+            Some(("tcpin", address)) => start_tcp_server(address, vehicle.clone()),
+            _ => panic!("Invalid output: {output}, expected tcpin:IP:PORT"),
+        }
+    }
+}
+
+fn start_tcp_server(address: &str, vehicle: MAVLinkVehicleArcMutex) {
+    let listener = network::tcp_listener(address)
+        .unwrap_or_else(|error| panic!("Failed to bind MAVLink TCP server {address}: {error}"));
+    info!("MAVLink TCP server running: tcpin:{address}");
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!("Failed to accept MAVLink TCP client: {error}");
+                    continue;
+                }
+            };
+
+            info!("New MAVLink TCP client: {:?}", stream.peer_addr());
+            let _ = stream.set_nodelay(true);
+            // A client that does not read should not hold the main loop
+            let _ = stream.set_write_timeout(Some(std::time::Duration::from_secs(1)));
+
+            match stream.try_clone() {
+                Ok(writer) => CLIENTS.lock().unwrap().push(writer),
+                Err(error) => {
+                    warn!("Failed to register MAVLink TCP client: {error}");
+                    continue;
+                }
+            }
+
+            let vehicle = vehicle.clone();
+            std::thread::spawn(move || receive_loop(stream, vehicle));
+        }
+    });
+}
+
+// Forward the messages sent by the client to the vehicle
+fn receive_loop(stream: TcpStream, vehicle: MAVLinkVehicleArcMutex) {
+    let peer = stream.peer_addr();
+    let mut reader = BufReader::new(stream);
+    loop {
+        match mavlink::read_versioned_msg::<mavlink::ardupilotmega::MavMessage, _>(
+            &mut reader,
+            raw_frames::version(),
+        ) {
+            Ok((header, message)) => {
+                if let Err(error) = vehicle.lock().unwrap().send(&header, &message) {
+                    warn!("Failed to forward message from MAVLink TCP client: {error:?}");
+                }
+            }
+            Err(mavlink::error::MessageReadError::Io(error)) => {
+                info!("MAVLink TCP client {peer:?} disconnected: {error}");
+                return;
+            }
+            Err(error) => debug!("Invalid message from MAVLink TCP client: {error:?}"),
+        }
+    }
+}
+
+// Forward a message received from the vehicle to all clients
+pub fn send(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let mut clients = CLIENTS.lock().unwrap();
+    if clients.is_empty() {
+        return;
+    }
+
+    let bytes = match raw_frames::encode(header, message) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+
+    clients.retain(|mut client| client.write_all(&bytes).is_ok());
+}
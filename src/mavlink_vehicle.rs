@@ -1,17 +1,75 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 
+use lazy_static::lazy_static;
 use log::*;
+use serde::Serialize;
+
+use super::cli;
+use super::fake;
+use super::serial_detection;
+use super::udp_multicast;
 
 pub type MAVLinkVehicleArcMutex = Arc<Mutex<MAVLinkVehicle<mavlink::ardupilotmega::MavMessage>>>;
 
+#[derive(Clone, Debug, Serialize)]
+pub struct LinkStatus {
+    /// If the connection with the vehicle is available
+    pub connected: bool,
+    /// Time of the last change of connection state, in RFC 3339
+    pub since: String,
+    /// Number of times the connection was recovered
+    pub reconnections: u64,
+    pub last_error: Option<String>,
+}
+
+lazy_static! {
+    static ref LINK_STATUS: RwLock<LinkStatus> = RwLock::new(LinkStatus {
+        connected: true,
+        since: chrono::Utc::now().to_rfc3339(),
+        reconnections: 0,
+        last_error: None,
+    });
+}
+
+pub fn link_status() -> LinkStatus {
+    LINK_STATUS.read().unwrap().clone()
+}
+
+fn set_link_down(error: String) {
+    let mut status = LINK_STATUS.write().unwrap();
+    if status.connected {
+        status.connected = false;
+        status.since = chrono::Utc::now().to_rfc3339();
+    }
+    status.last_error = Some(error);
+}
+
+fn set_link_up() {
+    let mut status = LINK_STATUS.write().unwrap();
+    status.connected = true;
+    status.since = chrono::Utc::now().to_rfc3339();
+    status.reconnections += 1;
+}
+
 #[derive(Clone)]
 pub struct MAVLinkVehicle<M: mavlink::Message> {
     //TODO: Check if Arc<Box can be only Arc or Box
     vehicle: Arc<Box<dyn mavlink::MavConnection<M> + Sync + Send>>,
     header: Arc<Mutex<mavlink::MavHeader>>,
+    // Used to connect again when the device disappears
+    connection_string: String,
+    version: mavlink::MavlinkVersion,
 }
 
 impl<M: mavlink::Message> MAVLinkVehicle<M> {
+    /// Send a message with the header of this service
+    pub fn send_default(&self, message: &M) -> std::io::Result<usize> {
+        let mut header = self.header.lock().unwrap();
+        let result = self.send(&header, message);
+        header.sequence = header.sequence.wrapping_add(1);
+        result
+    }
+
     pub fn send(&self, header: &mavlink::MavHeader, message: &M) -> std::io::Result<usize> {
         let result = self.vehicle.send(header, message);
 
@@ -40,8 +98,11 @@ impl<M: mavlink::Message> MAVLinkVehicle<M> {
         system_id: u8,
         component_id: u8,
     ) -> Self {
-        let mut vehicle = mavlink::connect(mavlink_connection_string).unwrap();
-        vehicle.set_protocol_version(version);
+        let vehicle = connect(
+            &serial_detection::resolve(mavlink_connection_string),
+            version,
+        )
+        .unwrap();
         let header = mavlink::MavHeader {
             system_id,
             component_id,
@@ -51,6 +112,63 @@ impl<M: mavlink::Message> MAVLinkVehicle<M> {
         Self {
             vehicle: Arc::new(vehicle),
             header: Arc::new(Mutex::new(header)),
+            connection_string: mavlink_connection_string.to_string(),
+            version,
+        }
+    }
+
+    fn is_reconnectable(&self) -> bool {
+        is_reconnectable(&self.connection_string)
+    }
+}
+
+// Serial devices disappear and TCP connections are closed when the vehicle reboots, so they are
+// opened again. UDP sockets are connectionless, they are bound to the local address and keep
+// receiving once the vehicle sends again, with nothing to reconnect. Files and fake vehicles end.
+fn is_reconnectable(connection_string: &str) -> bool {
+    connection_string == "auto"
+        || ["serial:", "tcpout:", "tcpin:"]
+            .iter()
+            .any(|prefix| connection_string.starts_with(prefix))
+}
+
+pub fn connect<M: mavlink::Message>(
+    connection_string: &str,
+    version: mavlink::MavlinkVersion,
+) -> std::io::Result<Box<dyn mavlink::MavConnection<M> + Sync + Send>> {
+    let mut vehicle: Box<dyn mavlink::MavConnection<M> + Sync + Send> =
+        if let Some(address) = connection_string.strip_prefix("udpmcast:") {
+            let (interface, ttl) = cli::multicast_interface_and_ttl();
+            Box::new(udp_multicast::connect(address, interface, ttl)?)
+        } else if let Some(rate) = connection_string.strip_prefix("fake:") {
+            Box::new(fake::connect(rate)?)
+        } else {
+            mavlink::connect(connection_string)?
+        };
+    vehicle.set_protocol_version(version);
+    Ok(vehicle)
+}
+
+// Wait for the device to be available again, running the auto-detection if necessary
+fn reconnect<M: mavlink::Message>(mavlink_vehicle: &Arc<Mutex<MAVLinkVehicle<M>>>) {
+    let (connection_string, version) = {
+        let mavlink_vehicle = mavlink_vehicle.lock().unwrap();
+        (
+            mavlink_vehicle.connection_string.clone(),
+            mavlink_vehicle.version,
+        )
+    };
+
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        match connect(&serial_detection::resolve(&connection_string), version) {
+            Ok(vehicle) => {
+                info!("Connection with the vehicle recovered.");
+                mavlink_vehicle.lock().unwrap().vehicle = Arc::new(vehicle);
+                set_link_up();
+                return;
+            }
+            Err(error) => debug!("Failed to reconnect: {error}"),
         }
     }
 }
@@ -91,11 +209,11 @@ fn receive_message_loop<
     mavlink_vehicle: Arc<Mutex<MAVLinkVehicle<M>>>,
     channel: std::sync::mpsc::Sender<(mavlink::MavHeader, M)>,
 ) {
-    let mavlink_vehicle = mavlink_vehicle.as_ref().lock().unwrap();
+    let inner_vehicle = mavlink_vehicle.as_ref().lock().unwrap();
 
-    let vehicle = mavlink_vehicle.vehicle.clone();
-    drop(mavlink_vehicle);
-    let vehicle = vehicle.as_ref();
+    let mut vehicle = inner_vehicle.vehicle.clone();
+    let is_reconnectable = inner_vehicle.is_reconnectable();
+    drop(inner_vehicle);
     loop {
         match vehicle.recv() {
             Ok((header, msg)) => {
@@ -106,6 +224,16 @@ fn receive_message_loop<
             Err(error) => {
                 error!("Recv error: {:?}", error);
                 if let mavlink::error::MessageReadError::Io(error) = error {
+                    // Serial devices fail with IO errors when unplugged, and TCP connections when
+                    // closed, like when the vehicle reboots, wait for them to come back
+                    if is_reconnectable {
+                        warn!("Connection with the vehicle lost: {error}");
+                        set_link_down(error.to_string());
+                        reconnect(&mavlink_vehicle);
+                        vehicle = mavlink_vehicle.as_ref().lock().unwrap().vehicle.clone();
+                        continue;
+                    }
+
                     if error.kind() == std::io::ErrorKind::UnexpectedEof {
                         // We're probably running a file, time to exit!
                         std::process::exit(0);
@@ -121,6 +249,10 @@ fn heartbeat_loop<M: mavlink::Message + From<mavlink::common::MavMessage>>(
 ) {
     loop {
         std::thread::sleep(std::time::Duration::from_secs(1));
+        if !LINK_STATUS.read().unwrap().connected {
+            continue;
+        }
+
         let mavlink_vehicle = mavlink_vehicle.as_ref().lock().unwrap();
         let vehicle = mavlink_vehicle.vehicle.clone();
         let mut header = mavlink_vehicle.header.lock().unwrap();
@@ -141,3 +273,30 @@ pub fn heartbeat_message() -> mavlink::common::MavMessage {
         mavlink_version: 0x3,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconnectable_connections() {
+        for connection_string in [
+            "auto",
+            "serial:/dev/ttyACM0:115200",
+            "tcpout:127.0.0.1:5760",
+            "tcpin:0.0.0.0:5760",
+        ] {
+            assert!(is_reconnectable(connection_string), "{connection_string}");
+        }
+        for connection_string in [
+            "udpin:0.0.0.0:14550",
+            "udpout:192.168.2.2:14550",
+            "udpbcast:192.168.2.255:14550",
+            "udpmcast:239.255.145.50:14550",
+            "file:flight.tlog",
+            "fake:50",
+        ] {
+            assert!(!is_reconnectable(connection_string), "{connection_string}");
+        }
+    }
+}
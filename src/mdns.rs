@@ -0,0 +1,69 @@
+use log::*;
+
+use super::cli;
+use super::data;
+
+const SERVICE_TYPE: &str = "_mavlink2rest._tcp";
+
+fn txt_records(vehicles: &[u8]) -> Vec<String> {
+    let vehicles = vehicles
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+
+    vec![
+        format!("version={}", env!("VERGEN_GIT_SEMVER")),
+        format!("path={}/mavlink", cli::url_prefix()),
+        format!("vehicles={vehicles}"),
+    ]
+}
+
+// Keep the service registered, updating the TXT records when vehicles appear or disappear
+fn run(responder: libmdns::Responder, port: u16) {
+    let name = format!("mavlink2rest-{}", std::process::id());
+    let mut vehicles = None;
+    let mut _service = None;
+
+    loop {
+        let current_vehicles = data::vehicle_ids();
+        if vehicles.as_ref() != Some(&current_vehicles) {
+            let records = txt_records(&current_vehicles);
+            info!("Advertising {SERVICE_TYPE} on port {port}: {records:?}");
+            // Drop the previous service before registering its replacement
+            _service = None;
+            _service = Some(responder.register(
+                SERVICE_TYPE.to_string(),
+                name.clone(),
+                port,
+                &records.iter().map(String::as_str).collect::<Vec<&str>>(),
+            ));
+            vehicles = Some(current_vehicles);
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(5));
+    }
+}
+
+pub fn init() {
+    if !cli::is_mdns() {
+        return;
+    }
+
+    // Advertise the first TCP server
+    let port = cli::server_addresses()
+        .iter()
+        .filter(|address| !address.starts_with("unix:"))
+        .find_map(|address| address.rsplit(':').next()?.parse::<u16>().ok());
+
+    let port = match port {
+        Some(port) => port,
+        None => {
+            warn!("mDNS is not available without a TCP server address.");
+            return;
+        }
+    };
+
+    let responder = libmdns::Responder::new().expect("Failed to start mDNS responder.");
+    std::thread::spawn(move || run(responder, port));
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+
+use super::raw_websocket;
+use super::rosbridge;
+use super::sse;
+use super::websocket_manager;
+
+// Upper bounds of the latency histogram buckets, in milliseconds
+const LATENCY_BUCKETS_MS: &[f64] = &[
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 5000.0,
+];
+
+#[derive(Clone, Debug)]
+struct Latency {
+    count: u64,
+    sum_ms: f64,
+    max_ms: f64,
+    // Cumulative count of each bucket, the last one is above all bounds
+    buckets: Vec<u64>,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum_ms: 0.0,
+            max_ms: 0.0,
+            buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+}
+
+impl Latency {
+    fn record(&mut self, duration: Duration) {
+        let milliseconds = duration.as_secs_f64() * 1e3;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| milliseconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        for count in &mut self.buckets[bucket..] {
+            *count += 1;
+        }
+        self.count += 1;
+        self.sum_ms += milliseconds;
+        self.max_ms = self.max_ms.max(milliseconds);
+    }
+
+    fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum_ms / self.count as f64
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Route {
+    latency: Latency,
+    // Responses with 5xx status
+    errors: u64,
+}
+
+lazy_static! {
+    // Requests by method and route pattern, unknown paths are grouped to keep the number of routes bounded
+    static ref ROUTES: Mutex<BTreeMap<(String, String), Route>> = Mutex::new(BTreeMap::new());
+    static ref SERIALIZATION: Mutex<Latency> = Mutex::new(Latency::default());
+}
+
+// Responses served from the serialized messages, and the ones that had to serialize them
+static SERIALIZATION_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static SERIALIZATION_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+pub fn record_request(request: &actix_web::HttpRequest, status: u16, duration: Duration) {
+    let route = request
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let mut routes = ROUTES.lock().unwrap();
+    let entry = routes
+        .entry((request.method().to_string(), route))
+        .or_default();
+    entry.latency.record(duration);
+    if status >= 500 {
+        entry.errors += 1;
+    }
+}
+
+/// Time spent converting messages to the output formats
+pub fn record_serialization(duration: Duration) {
+    SERIALIZATION.lock().unwrap().record(duration);
+}
+
+/// Use of the serialized messages, a miss is also recorded as a serialization
+pub fn record_serialization_cache(is_hit: bool) {
+    let counter = if is_hit {
+        &SERIALIZATION_CACHE_HITS
+    } else {
+        &SERIALIZATION_CACHE_MISSES
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+fn clients() -> Value {
+    json!({
+        "websocket": websocket_manager::manager().lock().unwrap().clients.len(),
+        "sse": sse::client_count(),
+        "rosbridge": rosbridge::client_count(),
+        "raw": raw_websocket::client_count(),
+    })
+}
+
+/// Requests and latency of each route, streaming clients and serialization time
+pub fn stats() -> Value {
+    let routes: Vec<Value> = ROUTES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((method, route), stats)| {
+            json!({
+                "method": method,
+                "route": route,
+                "count": stats.latency.count,
+                "errors": stats.errors,
+                "mean_ms": stats.latency.mean_ms(),
+                "max_ms": stats.latency.max_ms,
+            })
+        })
+        .collect();
+    let requests: u64 = routes
+        .iter()
+        .filter_map(|route| route["count"].as_u64())
+        .sum();
+    let serialization = SERIALIZATION.lock().unwrap().clone();
+
+    json!({
+        "requests": requests,
+        "routes": routes,
+        "clients": clients(),
+        "serialization": {
+            "count": serialization.count,
+            "mean_ms": serialization.mean_ms(),
+            "max_ms": serialization.max_ms,
+            "cache_hits": SERIALIZATION_CACHE_HITS.load(Ordering::Relaxed),
+            "cache_misses": SERIALIZATION_CACHE_MISSES.load(Ordering::Relaxed),
+        },
+    })
+}
+
+fn histogram(text: &mut String, name: &str, labels: &str, latency: &Latency) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    for (bound, count) in LATENCY_BUCKETS_MS.iter().zip(&latency.buckets) {
+        let _ = writeln!(
+            text,
+            "{name}_bucket{{{labels}{separator}le=\"{}\"}} {count}",
+            bound / 1e3
+        );
+    }
+    let _ = writeln!(
+        text,
+        "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {}",
+        latency.count
+    );
+    let _ = writeln!(text, "{name}_sum{{{labels}}} {}", latency.sum_ms / 1e3);
+    let _ = writeln!(text, "{name}_count{{{labels}}} {}", latency.count);
+}
+
+/// Metrics in the Prometheus text format
+pub fn prometheus() -> String {
+    let mut text = String::new();
+
+    text += "# HELP mavlink2rest_http_requests_total HTTP requests by route.\n";
+    text += "# TYPE mavlink2rest_http_requests_total counter\n";
+    let routes = ROUTES.lock().unwrap().clone();
+    for ((method, route), stats) in &routes {
+        let _ = writeln!(
+            text,
+            "mavlink2rest_http_requests_total{{method=\"{method}\",route=\"{route}\"}} {}",
+            stats.latency.count
+        );
+    }
+
+    text += "# HELP mavlink2rest_http_errors_total HTTP responses with 5xx status by route.\n";
+    text += "# TYPE mavlink2rest_http_errors_total counter\n";
+    for ((method, route), stats) in &routes {
+        let _ = writeln!(
+            text,
+            "mavlink2rest_http_errors_total{{method=\"{method}\",route=\"{route}\"}} {}",
+            stats.errors
+        );
+    }
+
+    text += "# HELP mavlink2rest_http_request_duration_seconds HTTP request latency by route.\n";
+    text += "# TYPE mavlink2rest_http_request_duration_seconds histogram\n";
+    for ((method, route), stats) in &routes {
+        histogram(
+            &mut text,
+            "mavlink2rest_http_request_duration_seconds",
+            &format!("method=\"{method}\",route=\"{route}\""),
+            &stats.latency,
+        );
+    }
+
+    text += "# HELP mavlink2rest_clients Connected streaming clients by type.\n";
+    text += "# TYPE mavlink2rest_clients gauge\n";
+    for (client_type, count) in clients().as_object().cloned().unwrap_or_default() {
+        let _ = writeln!(
+            text,
+            "mavlink2rest_clients{{type=\"{client_type}\"}} {count}"
+        );
+    }
+
+    text += "# HELP mavlink2rest_serialization_duration_seconds Time converting messages to the output formats.\n";
+    text += "# TYPE mavlink2rest_serialization_duration_seconds histogram\n";
+    let serialization = SERIALIZATION.lock().unwrap().clone();
+    histogram(
+        &mut text,
+        "mavlink2rest_serialization_duration_seconds",
+        "",
+        &serialization,
+    );
+
+    text += "# HELP mavlink2rest_serialization_cache_total Messages served from their serialization cache by result.\n";
+    text += "# TYPE mavlink2rest_serialization_cache_total counter\n";
+    for (result, counter) in [
+        ("hit", &SERIALIZATION_CACHE_HITS),
+        ("miss", &SERIALIZATION_CACHE_MISSES),
+    ] {
+        let _ = writeln!(
+            text,
+            "mavlink2rest_serialization_cache_total{{result=\"{result}\"}} {}",
+            counter.load(Ordering::Relaxed)
+        );
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_histogram() {
+        let mut latency = Latency::default();
+        latency.record(Duration::from_micros(500));
+        latency.record(Duration::from_millis(20));
+        latency.record(Duration::from_secs(10));
+
+        assert_eq!(latency.count, 3);
+        assert_eq!(latency.buckets[0], 1);
+        // 25 ms bucket
+        assert_eq!(latency.buckets[3], 2);
+        assert_eq!(latency.buckets[LATENCY_BUCKETS_MS.len() - 1], 2);
+        assert_eq!(latency.buckets[LATENCY_BUCKETS_MS.len()], 3);
+        assert_eq!(latency.max_ms, 10000.0);
+    }
+}
@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use log::*;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{self, MavCmd, MavFrame, MavMissionResult, MavMissionType};
+use serde::{Deserialize, Serialize};
+
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::waiter;
+
+// Attempts of each step of the transfer before giving up
+const RETRIES: usize = 3;
+
+/// Item of a mission, fence or rally list, as in MISSION_ITEM_INT
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MissionItem {
+    pub frame: MavFrame,
+    pub command: MavCmd,
+    #[serde(default)]
+    pub current: u8,
+    #[serde(default = "default_autocontinue")]
+    pub autocontinue: u8,
+    #[serde(default)]
+    pub param1: f32,
+    #[serde(default)]
+    pub param2: f32,
+    #[serde(default)]
+    pub param3: f32,
+    #[serde(default)]
+    pub param4: f32,
+    /// Latitude in degE7 or local x position
+    pub x: i32,
+    /// Longitude in degE7 or local y position
+    pub y: i32,
+    pub z: f32,
+}
+
+fn default_autocontinue() -> u8 {
+    1
+}
+
+impl MissionItem {
+    fn from_mavlink(item: &common::MISSION_ITEM_INT_DATA) -> Self {
+        Self {
+            frame: item.frame,
+            command: item.command,
+            current: item.current,
+            autocontinue: item.autocontinue,
+            param1: item.param1,
+            param2: item.param2,
+            param3: item.param3,
+            param4: item.param4,
+            x: item.x,
+            y: item.y,
+            z: item.z,
+        }
+    }
+
+    fn to_mavlink(&self, target: Target, seq: u16) -> MavMessage {
+        MavMessage::common(common::MavMessage::MISSION_ITEM_INT(
+            common::MISSION_ITEM_INT_DATA {
+                param1: self.param1,
+                param2: self.param2,
+                param3: self.param3,
+                param4: self.param4,
+                x: self.x,
+                y: self.y,
+                z: self.z,
+                seq,
+                command: self.command,
+                target_system: target.system_id,
+                target_component: target.component_id,
+                frame: self.frame,
+                current: self.current,
+                autocontinue: self.autocontinue,
+                mission_type: target.mission_type,
+            },
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum MissionError {
+    Send(std::io::Error),
+    Timeout(String),
+    Rejected(MavMissionResult),
+}
+
+impl std::fmt::Display for MissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MissionError::Send(error) => write!(f, "Failed to send message: {error}"),
+            MissionError::Timeout(step) => write!(f, "Vehicle did not answer {step}"),
+            MissionError::Rejected(result) => {
+                write!(f, "Vehicle rejected the transfer: {result:?}")
+            }
+        }
+    }
+}
+
+/// Vehicle and list used in a transfer
+#[derive(Clone, Copy, Debug)]
+pub struct Target {
+    pub system_id: u8,
+    pub component_id: u8,
+    pub mission_type: MavMissionType,
+}
+
+fn send(vehicle: &MAVLinkVehicleArcMutex, message: &MavMessage) -> Result<(), MissionError> {
+    vehicle
+        .lock()
+        .unwrap()
+        .send_default(message)
+        .map(|_| ())
+        .map_err(MissionError::Send)
+}
+
+// Send the message until a matching answer arrives
+fn exchange(
+    vehicle: &MAVLinkVehicleArcMutex,
+    message: &MavMessage,
+    predicate: impl Fn(&mavlink::MavHeader, &MavMessage) -> bool + Clone + Send + 'static,
+    timeout: Duration,
+    step: &str,
+) -> Result<MavMessage, MissionError> {
+    for attempt in 1..=RETRIES {
+        let subscription = waiter::subscribe(predicate.clone());
+        send(vehicle, message)?;
+        if let Some((_, answer)) = subscription.wait(timeout) {
+            return Ok(answer);
+        }
+        debug!("No answer to {step}, attempt {attempt} of {RETRIES}");
+    }
+    Err(MissionError::Timeout(step.to_string()))
+}
+
+fn mission_ack(target: Target, result: MavMissionResult) -> MavMessage {
+    MavMessage::common(common::MavMessage::MISSION_ACK(common::MISSION_ACK_DATA {
+        target_system: target.system_id,
+        target_component: target.component_id,
+        mavtype: result,
+        mission_type: target.mission_type,
+    }))
+}
+
+/// Download the items of the list from the vehicle
+pub fn download(
+    vehicle: &MAVLinkVehicleArcMutex,
+    target: Target,
+    timeout: Duration,
+) -> Result<Vec<MissionItem>, MissionError> {
+    let request_list = MavMessage::common(common::MavMessage::MISSION_REQUEST_LIST(
+        common::MISSION_REQUEST_LIST_DATA {
+            target_system: target.system_id,
+            target_component: target.component_id,
+            mission_type: target.mission_type,
+        },
+    ));
+    let count = exchange(
+        vehicle,
+        &request_list,
+        move |header, message| match message {
+            MavMessage::common(common::MavMessage::MISSION_COUNT(count)) => {
+                header.system_id == target.system_id && count.mission_type == target.mission_type
+            }
+            _ => false,
+        },
+        timeout,
+        "MISSION_REQUEST_LIST",
+    )?;
+    let count = match count {
+        MavMessage::common(common::MavMessage::MISSION_COUNT(count)) => count.count,
+        _ => unreachable!(),
+    };
+
+    let mut items = Vec::with_capacity(count as usize);
+    for seq in 0..count {
+        let request = MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(
+            common::MISSION_REQUEST_INT_DATA {
+                seq,
+                target_system: target.system_id,
+                target_component: target.component_id,
+                mission_type: target.mission_type,
+            },
+        ));
+        let item = exchange(
+            vehicle,
+            &request,
+            move |header, message| match message {
+                MavMessage::common(common::MavMessage::MISSION_ITEM_INT(item)) => {
+                    header.system_id == target.system_id
+                        && item.mission_type == target.mission_type
+                        && item.seq == seq
+                }
+                _ => false,
+            },
+            timeout,
+            "MISSION_REQUEST_INT",
+        )?;
+        if let MavMessage::common(common::MavMessage::MISSION_ITEM_INT(item)) = item {
+            items.push(MissionItem::from_mavlink(&item));
+        }
+    }
+
+    send(
+        vehicle,
+        &mission_ack(target, MavMissionResult::MAV_MISSION_ACCEPTED),
+    )?;
+    Ok(items)
+}
+
+/// Upload the items to the vehicle, replacing the list
+pub fn upload(
+    vehicle: &MAVLinkVehicleArcMutex,
+    target: Target,
+    items: &[MissionItem],
+    timeout: Duration,
+) -> Result<(), MissionError> {
+    // The vehicle requests each item and finishes with an ack
+    let is_answer = move |header: &mavlink::MavHeader, message: &MavMessage| {
+        header.system_id == target.system_id
+            && match message {
+                MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(request)) => {
+                    request.mission_type == target.mission_type
+                }
+                MavMessage::common(common::MavMessage::MISSION_REQUEST(request)) => {
+                    request.mission_type == target.mission_type
+                }
+                MavMessage::common(common::MavMessage::MISSION_ACK(ack)) => {
+                    ack.mission_type == target.mission_type
+                }
+                _ => false,
+            }
+    };
+
+    let mut message = MavMessage::common(common::MavMessage::MISSION_COUNT(
+        common::MISSION_COUNT_DATA {
+            count: items.len() as u16,
+            target_system: target.system_id,
+            target_component: target.component_id,
+            mission_type: target.mission_type,
+        },
+    ));
+    let mut step = "MISSION_COUNT".to_string();
+
+    loop {
+        let seq = match exchange(vehicle, &message, is_answer, timeout, &step)? {
+            MavMessage::common(common::MavMessage::MISSION_REQUEST_INT(request)) => request.seq,
+            MavMessage::common(common::MavMessage::MISSION_REQUEST(request)) => request.seq,
+            MavMessage::common(common::MavMessage::MISSION_ACK(ack)) => {
+                return match ack.mavtype {
+                    MavMissionResult::MAV_MISSION_ACCEPTED => Ok(()),
+                    result => Err(MissionError::Rejected(result)),
+                };
+            }
+            _ => unreachable!(),
+        };
+
+        let item = match items.get(seq as usize) {
+            Some(item) => item,
+            None => {
+                send(
+                    vehicle,
+                    &mission_ack(target, MavMissionResult::MAV_MISSION_INVALID_SEQUENCE),
+                )?;
+                return Err(MissionError::Rejected(
+                    MavMissionResult::MAV_MISSION_INVALID_SEQUENCE,
+                ));
+            }
+        };
+        message = item.to_mavlink(target, seq);
+        step = format!("MISSION_ITEM_INT {seq}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_conversion() {
+        let item: MissionItem = serde_json::from_str(
+            r#"{
+                "frame": {"type": "MAV_FRAME_GLOBAL_RELATIVE_ALT_INT"},
+                "command": {"type": "MAV_CMD_NAV_RALLY_POINT"},
+                "x": -275000000,
+                "y": -485000000,
+                "z": 30.0
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(item.autocontinue, 1);
+
+        let target = Target {
+            system_id: 1,
+            component_id: 1,
+            mission_type: MavMissionType::MAV_MISSION_TYPE_RALLY,
+        };
+        match item.to_mavlink(target, 3) {
+            MavMessage::common(common::MavMessage::MISSION_ITEM_INT(data)) => {
+                assert_eq!(data.seq, 3);
+                assert_eq!(data.mission_type, MavMissionType::MAV_MISSION_TYPE_RALLY);
+                assert_eq!(MissionItem::from_mavlink(&data).x, -275000000);
+            }
+            _ => panic!("Not a MISSION_ITEM_INT message"),
+        }
+    }
+}
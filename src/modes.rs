@@ -0,0 +1,217 @@
+use mavlink::common::MavModeFlag;
+
+// Flight stacks with their own custom_mode numbering
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Firmware {
+    ArduCopter,
+    ArduPlane,
+    ArduRover,
+    ArduSub,
+    Px4,
+}
+
+const ARDUCOPTER_MODES: &[(&str, u32)] = &[
+    ("STABILIZE", 0),
+    ("ACRO", 1),
+    ("ALT_HOLD", 2),
+    ("AUTO", 3),
+    ("GUIDED", 4),
+    ("LOITER", 5),
+    ("RTL", 6),
+    ("CIRCLE", 7),
+    ("LAND", 9),
+    ("DRIFT", 11),
+    ("SPORT", 13),
+    ("FLIP", 14),
+    ("AUTOTUNE", 15),
+    ("POSHOLD", 16),
+    ("BRAKE", 17),
+    ("THROW", 18),
+    ("AVOID_ADSB", 19),
+    ("GUIDED_NOGPS", 20),
+    ("SMART_RTL", 21),
+    ("FLOWHOLD", 22),
+    ("FOLLOW", 23),
+    ("ZIGZAG", 24),
+    ("SYSTEMID", 25),
+    ("AUTOROTATE", 26),
+    ("AUTO_RTL", 27),
+];
+
+const ARDUPLANE_MODES: &[(&str, u32)] = &[
+    ("MANUAL", 0),
+    ("CIRCLE", 1),
+    ("STABILIZE", 2),
+    ("TRAINING", 3),
+    ("ACRO", 4),
+    ("FBWA", 5),
+    ("FBWB", 6),
+    ("CRUISE", 7),
+    ("AUTOTUNE", 8),
+    ("AUTO", 10),
+    ("RTL", 11),
+    ("LOITER", 12),
+    ("TAKEOFF", 13),
+    ("AVOID_ADSB", 14),
+    ("GUIDED", 15),
+    ("QSTABILIZE", 17),
+    ("QHOVER", 18),
+    ("QLOITER", 19),
+    ("QLAND", 20),
+    ("QRTL", 21),
+    ("QAUTOTUNE", 22),
+    ("QACRO", 23),
+    ("THERMAL", 24),
+    ("LOITER_ALT_QLAND", 25),
+];
+
+const ARDUROVER_MODES: &[(&str, u32)] = &[
+    ("MANUAL", 0),
+    ("ACRO", 1),
+    ("STEERING", 3),
+    ("HOLD", 4),
+    ("LOITER", 5),
+    ("FOLLOW", 6),
+    ("SIMPLE", 7),
+    ("DOCK", 8),
+    ("AUTO", 10),
+    ("RTL", 11),
+    ("SMART_RTL", 12),
+    ("GUIDED", 15),
+];
+
+const ARDUSUB_MODES: &[(&str, u32)] = &[
+    ("STABILIZE", 0),
+    ("ACRO", 1),
+    ("ALT_HOLD", 2),
+    ("AUTO", 3),
+    ("GUIDED", 4),
+    ("CIRCLE", 7),
+    ("SURFACE", 9),
+    ("POSHOLD", 16),
+    ("MANUAL", 19),
+    ("MOTOR_DETECT", 20),
+    ("SURFTRAK", 21),
+];
+
+// PX4 custom_mode has the main mode in the third byte and the sub mode in the fourth byte
+const fn px4_mode(main_mode: u32, sub_mode: u32) -> u32 {
+    (main_mode << 16) | (sub_mode << 24)
+}
+
+const PX4_MODES: &[(&str, u32)] = &[
+    ("MANUAL", px4_mode(1, 0)),
+    ("ALTCTL", px4_mode(2, 0)),
+    ("POSCTL", px4_mode(3, 0)),
+    ("AUTO.READY", px4_mode(4, 1)),
+    ("AUTO.TAKEOFF", px4_mode(4, 2)),
+    ("AUTO.LOITER", px4_mode(4, 3)),
+    ("AUTO.MISSION", px4_mode(4, 4)),
+    ("AUTO.RTL", px4_mode(4, 5)),
+    ("AUTO.LAND", px4_mode(4, 6)),
+    ("AUTO.FOLLOW_TARGET", px4_mode(4, 8)),
+    ("AUTO.PRECLAND", px4_mode(4, 9)),
+    ("ACRO", px4_mode(5, 0)),
+    ("OFFBOARD", px4_mode(6, 0)),
+    ("STABILIZED", px4_mode(7, 0)),
+    ("RATTITUDE", px4_mode(8, 0)),
+];
+
+/// Flight stack of the vehicle, from the MAV_AUTOPILOT and MAV_TYPE of its HEARTBEAT
+pub fn firmware(autopilot: &str, mav_type: &str) -> Option<Firmware> {
+    match autopilot {
+        "MAV_AUTOPILOT_PX4" => Some(Firmware::Px4),
+        "MAV_AUTOPILOT_ARDUPILOTMEGA" => Some(match mav_type {
+            "MAV_TYPE_FIXED_WING"
+            | "MAV_TYPE_VTOL_DUOROTOR"
+            | "MAV_TYPE_VTOL_QUADROTOR"
+            | "MAV_TYPE_VTOL_TILTROTOR"
+            | "MAV_TYPE_VTOL_RESERVED2"
+            | "MAV_TYPE_VTOL_RESERVED3"
+            | "MAV_TYPE_VTOL_RESERVED4"
+            | "MAV_TYPE_VTOL_RESERVED5" => Firmware::ArduPlane,
+            "MAV_TYPE_GROUND_ROVER" | "MAV_TYPE_SURFACE_BOAT" => Firmware::ArduRover,
+            "MAV_TYPE_SUBMARINE" => Firmware::ArduSub,
+            _ => Firmware::ArduCopter,
+        }),
+        _ => None,
+    }
+}
+
+/// Names and custom_mode values of the flight modes
+pub fn modes(firmware: Firmware) -> &'static [(&'static str, u32)] {
+    match firmware {
+        Firmware::ArduCopter => ARDUCOPTER_MODES,
+        Firmware::ArduPlane => ARDUPLANE_MODES,
+        Firmware::ArduRover => ARDUROVER_MODES,
+        Firmware::ArduSub => ARDUSUB_MODES,
+        Firmware::Px4 => PX4_MODES,
+    }
+}
+
+/// Name of the custom_mode
+pub fn name(firmware: Firmware, custom_mode: u32) -> Option<&'static str> {
+    modes(firmware)
+        .iter()
+        .find(|(_, mode)| *mode == custom_mode)
+        .map(|(name, _)| *name)
+}
+
+/// custom_mode of the mode name, case insensitive, PX4 auto modes can be used without the AUTO. prefix
+pub fn custom_mode(firmware: Firmware, name: &str) -> Option<u32> {
+    let name = name.to_uppercase();
+    let modes = modes(firmware);
+    modes
+        .iter()
+        .find(|(mode, _)| *mode == name)
+        .or_else(|| {
+            modes
+                .iter()
+                .find(|(mode, _)| mode.strip_prefix("AUTO.") == Some(name.as_str()))
+        })
+        .map(|(_, custom_mode)| *custom_mode)
+}
+
+/// MAV_CMD_DO_SET_MODE parameters to switch to the custom_mode
+pub fn set_mode_params(firmware: Firmware, custom_mode: u32) -> [f32; 7] {
+    let base_mode = MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED.bits() as f32;
+    match firmware {
+        Firmware::Px4 => [
+            base_mode,
+            ((custom_mode >> 16) & 0xff) as f32,
+            (custom_mode >> 24) as f32,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+        ],
+        _ => [base_mode, custom_mode as f32, 0.0, 0.0, 0.0, 0.0, 0.0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_names() {
+        let copter = firmware("MAV_AUTOPILOT_ARDUPILOTMEGA", "MAV_TYPE_QUADROTOR").unwrap();
+        assert_eq!(copter, Firmware::ArduCopter);
+        assert_eq!(custom_mode(copter, "guided"), Some(4));
+        assert_eq!(name(copter, 6), Some("RTL"));
+
+        let plane = firmware("MAV_AUTOPILOT_ARDUPILOTMEGA", "MAV_TYPE_FIXED_WING").unwrap();
+        assert_eq!(custom_mode(plane, "GUIDED"), Some(15));
+
+        let px4 = firmware("MAV_AUTOPILOT_PX4", "MAV_TYPE_QUADROTOR").unwrap();
+        let mission = custom_mode(px4, "MISSION").unwrap();
+        assert_eq!(name(px4, mission), Some("AUTO.MISSION"));
+        assert_eq!(set_mode_params(px4, mission)[..3], [1.0, 4.0, 4.0]);
+        assert_eq!(custom_mode(px4, "GUIDED"), None);
+
+        assert_eq!(
+            firmware("MAV_AUTOPILOT_GENERIC", "MAV_TYPE_QUADROTOR"),
+            None
+        );
+    }
+}
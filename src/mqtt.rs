@@ -0,0 +1,120 @@
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::Message;
+use rumqttc::{Client, Connection, MqttOptions, QoS};
+
+use super::cli;
+use super::data::MAVLinkMessage;
+use super::output;
+
+struct Publisher {
+    client: Client,
+    prefix: String,
+    qos: QoS,
+}
+
+lazy_static! {
+    static ref PUBLISHER: Option<Mutex<Publisher>> = cli::mqtt_url().map(|url| {
+        let (host, port, prefix) = parse_url(&url).unwrap_or_else(|| {
+            panic!("Invalid MQTT url: {url}, expected mqtt://BROKER:PORT/PREFIX")
+        });
+
+        let options = MqttOptions::new(format!("mavlink2rest-{}", std::process::id()), host, port);
+        let (client, connection) = Client::new(options, 100);
+        std::thread::spawn(move || run_connection(connection));
+
+        Mutex::new(Publisher {
+            client,
+            prefix,
+            qos: match cli::mqtt_qos() {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                _ => QoS::ExactlyOnce,
+            },
+        })
+    });
+}
+
+// Parse mqtt://BROKER[:PORT][/PREFIX], the port defaults to 1883
+fn parse_url(url: &str) -> Option<(String, u16, String)> {
+    let address = url.strip_prefix("mqtt://")?;
+    let (address, prefix) = address.split_once('/').unwrap_or((address, ""));
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (address, 1883),
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    Some((
+        host.to_string(),
+        port,
+        prefix.trim_end_matches('/').to_string(),
+    ))
+}
+
+// The event loop must be driven for messages to be sent, it also takes care of reconnecting
+fn run_connection(mut connection: Connection) {
+    for notification in connection.iter() {
+        if let Err(error) = notification {
+            warn!("MQTT connection error: {error}");
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        }
+    }
+}
+
+pub fn init() {
+    lazy_static::initialize(&PUBLISHER);
+}
+
+pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    let publisher = match PUBLISHER.as_ref() {
+        Some(publisher) => publisher,
+        None => return,
+    };
+
+    let mut publisher = publisher.lock().unwrap();
+    let topic = [
+        publisher.prefix.clone(),
+        message.header.system_id.to_string(),
+        message.header.component_id.to_string(),
+        message.message.message_name().to_string(),
+    ]
+    .iter()
+    .filter(|level| !level.is_empty())
+    .cloned()
+    .collect::<Vec<String>>()
+    .join("/");
+
+    let mut value = output::to_value(&message.message);
+    output::apply(&mut value, &output::OutputOptions::global());
+    let payload = value.to_string();
+
+    // Avoid blocking the main loop when the broker is not keeping up
+    let qos = publisher.qos;
+    if let Err(error) = publisher.client.try_publish(topic, qos, true, payload) {
+        debug!("Failed to publish MQTT message: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_parsing() {
+        assert_eq!(
+            parse_url("mqtt://broker:1884/vehicles/"),
+            Some(("broker".into(), 1884, "vehicles".into()))
+        );
+        assert_eq!(
+            parse_url("mqtt://broker"),
+            Some(("broker".into(), 1883, "".into()))
+        );
+        assert_eq!(parse_url("tcp://broker:1883"), None);
+    }
+}
@@ -0,0 +1,42 @@
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+use socket2::{Domain, Socket, Type};
+
+/// Resolve an address like 0.0.0.0:8088, [::]:8088 or localhost:8088
+pub fn socket_address(address: &str) -> std::io::Result<SocketAddr> {
+    address.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid address: {address}"),
+        )
+    })
+}
+
+/// TCP listener for the address, the unspecified IPv6 address ([::]) also accepts IPv4 clients when possible
+pub fn tcp_listener(address: &str) -> std::io::Result<TcpListener> {
+    let address = socket_address(address)?;
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+
+    if address.is_ipv6() && address.ip().is_unspecified() {
+        // Not all systems support dual-stack sockets, IPv6 keeps working without it
+        let _ = socket.set_only_v6(false);
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_parsing() {
+        assert!(socket_address("0.0.0.0:8088").unwrap().is_ipv4());
+        assert!(socket_address("[::]:8088").unwrap().is_ipv6());
+        assert!(socket_address("[::1]:14550").unwrap().ip().is_loopback());
+        assert!(socket_address("::1").is_err());
+    }
+}
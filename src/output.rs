@@ -0,0 +1,623 @@
+use actix_web::{http::header, HttpRequest};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::cli;
+use super::definitions;
+use super::protobuf;
+
+#[derive(Apiv2Schema, Deserialize, Debug, Default)]
+pub struct OutputQuery {
+    /// Add the numeric value to enums, E.g: {"type": "MAV_TYPE_QUADROTOR", "value": 2}
+    enum_values: Option<bool>,
+    /// Output encoding: json, msgpack, cbor or protobuf, it has priority over the Accept header
+    pub format: Option<String>,
+    /// Comma separated message names to include, E.g: HEARTBEAT,ATTITUDE,GPS_RAW_INT
+    pub types: Option<String>,
+    /// Wait for the next update of the path before answering
+    pub wait: Option<bool>,
+    /// Maximum time to wait for an update, 5000 by default
+    pub timeout_ms: Option<u64>,
+    /// Output units: raw or si, to convert scaled integer fields to natural units, E.g: degE7 to degrees
+    units: Option<String>,
+    /// Single-level map with dot separated keys, E.g: {"HEARTBEAT.system_status": "MAV_STATE_ACTIVE"}
+    flat: Option<bool>,
+    /// Casing of message and field names: mavlink, camel or lower
+    key_case: Option<String>,
+}
+
+// Binary encodings are negotiated via Accept header or format query parameter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+    Cbor,
+    Protobuf,
+}
+
+impl Encoding {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "msgpack" | "messagepack" => Encoding::MessagePack,
+            "cbor" => Encoding::Cbor,
+            "protobuf" | "proto" => Encoding::Protobuf,
+            _ => Encoding::Json,
+        }
+    }
+
+    fn from_accept(accept: &str) -> Self {
+        if accept.contains("msgpack") {
+            Encoding::MessagePack
+        } else if accept.contains("application/cbor") {
+            Encoding::Cbor
+        } else if accept.contains("protobuf") {
+            Encoding::Protobuf
+        } else {
+            Encoding::Json
+        }
+    }
+
+    pub fn from_request(request: &HttpRequest, format: Option<&str>) -> Self {
+        if let Some(format) = format {
+            return Self::from_name(format);
+        }
+
+        request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map_or(Encoding::Json, Self::from_accept)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::MessagePack => "application/msgpack",
+            Encoding::Cbor => "application/cbor",
+            Encoding::Protobuf => "application/x-protobuf",
+        }
+    }
+
+    pub fn encode(&self, value: &Value) -> Vec<u8> {
+        match self {
+            Encoding::Json => serde_json::to_vec_pretty(value).unwrap(),
+            Encoding::MessagePack => rmp_serde::to_vec_named(value).unwrap(),
+            Encoding::Cbor => serde_cbor::to_vec(value).unwrap(),
+            // A single message with header, as the ones sent by the websocket
+            Encoding::Protobuf => protobuf::encode(value),
+        }
+    }
+}
+
+// How NaN and infinity float values are represented, since JSON does not support them
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NonFinitePolicy {
+    #[default]
+    Null,
+    String,
+    Omit,
+}
+
+impl std::str::FromStr for NonFinitePolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "null" => Ok(NonFinitePolicy::Null),
+            "string" => Ok(NonFinitePolicy::String),
+            "omit" => Ok(NonFinitePolicy::Omit),
+            _ => Err(format!("Invalid non-finite policy: {policy}")),
+        }
+    }
+}
+
+// Convert to a JSON value following the global non-finite policy
+pub fn to_value<T: Serialize>(content: &T) -> Value {
+    let policy = cli::non_finite_policy();
+    if policy == NonFinitePolicy::Null {
+        // This is already the serde_json behavior
+        return serde_json::to_value(content).unwrap();
+    }
+
+    // serde_json replaces non-finite floats with null, so go through an intermediate representation
+    match serde_value::to_value(content) {
+        Ok(value) => from_serde_value(value, policy).unwrap_or(Value::Null),
+        Err(_) => serde_json::to_value(content).unwrap(),
+    }
+}
+
+fn from_float(value: f64, policy: NonFinitePolicy) -> Option<Value> {
+    if let Some(number) = serde_json::Number::from_f64(value) {
+        return Some(Value::Number(number));
+    }
+
+    match policy {
+        NonFinitePolicy::Null => Some(Value::Null),
+        NonFinitePolicy::Omit => None,
+        NonFinitePolicy::String => Some(Value::String(
+            if value.is_nan() {
+                "NaN"
+            } else if value.is_sign_positive() {
+                "Infinity"
+            } else {
+                "-Infinity"
+            }
+            .to_string(),
+        )),
+    }
+}
+
+// Returns None when the value should be omitted
+fn from_serde_value(value: serde_value::Value, policy: NonFinitePolicy) -> Option<Value> {
+    use serde_value::Value as SerdeValue;
+
+    Some(match value {
+        SerdeValue::Bool(value) => Value::Bool(value),
+        SerdeValue::U8(value) => value.into(),
+        SerdeValue::U16(value) => value.into(),
+        SerdeValue::U32(value) => value.into(),
+        SerdeValue::U64(value) => value.into(),
+        SerdeValue::I8(value) => value.into(),
+        SerdeValue::I16(value) => value.into(),
+        SerdeValue::I32(value) => value.into(),
+        SerdeValue::I64(value) => value.into(),
+        SerdeValue::F32(value) => return from_float(value.into(), policy),
+        SerdeValue::F64(value) => return from_float(value, policy),
+        SerdeValue::Char(value) => Value::String(value.to_string()),
+        SerdeValue::String(value) => Value::String(value),
+        SerdeValue::Unit | SerdeValue::Option(None) => Value::Null,
+        SerdeValue::Option(Some(value)) | SerdeValue::Newtype(value) => {
+            return from_serde_value(*value, policy)
+        }
+        // Array positions are meaningful, so omitted values are replaced with null
+        SerdeValue::Seq(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| from_serde_value(value, policy).unwrap_or(Value::Null))
+                .collect(),
+        ),
+        SerdeValue::Map(map) => Value::Object(
+            map.into_iter()
+                .filter_map(|(key, value)| {
+                    let key = match from_serde_value(key, policy)? {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    Some((key, from_serde_value(value, policy)?))
+                })
+                .collect(),
+        ),
+        SerdeValue::Bytes(bytes) => Value::Array(bytes.into_iter().map(Value::from).collect()),
+    })
+}
+
+// Casing of message and field names, MAVLink uses upper snake case for messages and snake case for fields
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyCase {
+    #[default]
+    Mavlink,
+    Camel,
+    Lower,
+}
+
+impl std::str::FromStr for KeyCase {
+    type Err = String;
+
+    fn from_str(case: &str) -> Result<Self, Self::Err> {
+        match case {
+            "mavlink" => Ok(KeyCase::Mavlink),
+            "camel" => Ok(KeyCase::Camel),
+            "lower" => Ok(KeyCase::Lower),
+            _ => Err(format!("Invalid key case: {case}")),
+        }
+    }
+}
+
+impl KeyCase {
+    pub fn convert(&self, name: &str) -> String {
+        match self {
+            KeyCase::Mavlink => name.to_string(),
+            KeyCase::Lower => name.to_lowercase(),
+            KeyCase::Camel => name
+                .split('_')
+                .filter(|word| !word.is_empty())
+                .enumerate()
+                .map(|(index, word)| {
+                    let word = word.to_lowercase();
+                    let mut characters = word.chars();
+                    match characters.next() {
+                        Some(first) if index > 0 => {
+                            first.to_uppercase().chain(characters).collect()
+                        }
+                        _ => word,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+// Transformations applied to the JSON output, query parameters have priority over global options
+#[derive(Debug, Default, PartialEq)]
+pub struct OutputOptions {
+    pub enum_values: bool,
+    pub si_units: bool,
+    pub flat: bool,
+    pub key_case: KeyCase,
+}
+
+impl OutputOptions {
+    pub fn global() -> Self {
+        Self {
+            enum_values: cli::is_enum_values(),
+            si_units: cli::is_si_units(),
+            flat: false,
+            key_case: cli::key_case(),
+        }
+    }
+
+    pub fn from_query(query: &OutputQuery) -> Self {
+        let global = Self::global();
+        Self {
+            enum_values: query.enum_values.unwrap_or(global.enum_values),
+            si_units: query
+                .units
+                .as_deref()
+                .map_or(global.si_units, |units| units == "si"),
+            flat: query.flat.unwrap_or(global.flat),
+            key_case: query
+                .key_case
+                .as_deref()
+                .and_then(|case| case.parse().ok())
+                .unwrap_or(global.key_case),
+        }
+    }
+}
+
+pub fn apply(value: &mut Value, options: &OutputOptions) {
+    if options.enum_values {
+        add_enum_values(value);
+    }
+    if options.si_units {
+        convert_units(value);
+    }
+    if options.flat {
+        let mut flat = Map::new();
+        flatten("", value, &mut flat);
+        *value = Value::Object(flat);
+    }
+    if options.key_case != KeyCase::Mavlink {
+        convert_keys(value, options.key_case);
+    }
+}
+
+// Rename all keys and message types, flat keys are converted by segment
+fn convert_keys(value: &mut Value, case: KeyCase) {
+    match value {
+        Value::Object(object) => {
+            let entries = std::mem::take(object);
+            for (key, mut child) in entries {
+                let is_message_type = key == "type"
+                    && child
+                        .as_str()
+                        .map_or(false, |name| definitions::message(name).is_some());
+                if is_message_type {
+                    child = Value::String(case.convert(child.as_str().unwrap()));
+                } else {
+                    convert_keys(&mut child, case);
+                }
+
+                let key = key
+                    .split('.')
+                    .map(|segment| case.convert(segment))
+                    .collect::<Vec<String>>()
+                    .join(".");
+                object.insert(key, child);
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| convert_keys(value, case)),
+        _ => {}
+    }
+}
+
+fn find_message(name: &str) -> Option<&'static definitions::MessageDefinition> {
+    definitions::message(name).or_else(|| {
+        definitions::messages().into_iter().find(|message| {
+            [KeyCase::Camel, KeyCase::Lower]
+                .iter()
+                .any(|case| case.convert(&message.name) == name)
+        })
+    })
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for character in name.chars() {
+        if character.is_uppercase() && !snake.is_empty() {
+            snake.push('_');
+        }
+        snake.extend(character.to_lowercase());
+    }
+    snake
+}
+
+/// Rename message types and field names received in any supported casing to the MAVLink ones
+pub fn normalize_keys(value: &mut Value) {
+    let object = match value {
+        Value::Object(object) => object,
+        Value::Array(values) => return values.iter_mut().for_each(normalize_keys),
+        _ => return,
+    };
+
+    let message = object
+        .get("type")
+        .and_then(Value::as_str)
+        .and_then(find_message);
+    let entries = std::mem::take(object);
+    for (key, mut child) in entries {
+        normalize_keys(&mut child);
+        let key = match message {
+            Some(message) if key == "type" => {
+                child = Value::String(message.name.clone());
+                key
+            }
+            Some(message) => message
+                .fields
+                .iter()
+                .find(|field| {
+                    [KeyCase::Camel, KeyCase::Lower]
+                        .iter()
+                        .any(|case| case.convert(&field.name) == key)
+                })
+                .map_or(key, |field| field.name.clone()),
+            None => to_snake_case(&key),
+        };
+        object.insert(key, child);
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    match (prefix.is_empty(), key.is_empty()) {
+        (true, _) => key.to_string(),
+        (_, true) => prefix.to_string(),
+        _ => format!("{prefix}.{key}"),
+    }
+}
+
+// Collapse the tree into dot separated keys, messages are named after their type
+fn flatten(prefix: &str, value: &Value, flat: &mut Map<String, Value>) {
+    let object = match value {
+        Value::Object(object) => object,
+        Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                flatten(&join(prefix, &index.to_string()), value, flat);
+            }
+            return;
+        }
+        value => {
+            flat.insert(prefix.to_string(), value.clone());
+            return;
+        }
+    };
+
+    // Enums and bitflags are represented by their content
+    if object.len() == 1 {
+        if let Some(content @ (Value::String(_) | Value::Number(_))) =
+            object.get("type").or_else(|| object.get("bits"))
+        {
+            flat.insert(prefix.to_string(), content.clone());
+            return;
+        }
+    }
+
+    let message_name = object
+        .get("type")
+        .and_then(Value::as_str)
+        .filter(|name| definitions::message(name).is_some());
+    for (key, child) in object {
+        if key == "type" && message_name.is_some() {
+            continue;
+        }
+
+        let child_name = child
+            .get("type")
+            .and_then(Value::as_str)
+            .filter(|name| definitions::message(name).is_some());
+        let key = match child_name {
+            // Avoid repeating the name when the path already has it, E.g: messages/HEARTBEAT/message
+            Some(name) if key == "message" && prefix.rsplit('.').next() == Some(name) => "",
+            Some(name) if key == "message" => name,
+            _ => key.as_str(),
+        };
+        flatten(&join(prefix, key), child, flat);
+    }
+}
+
+// Scale from the dialect units to natural units
+fn si_scale(units: &str) -> Option<f64> {
+    Some(match units {
+        "degE7" => 1e-7,
+        "degE5" => 1e-5,
+        "cdeg" | "cdeg/s" | "cdegC" => 1e-2,
+        "mdeg" | "mdegC" => 1e-3,
+        "cm" | "cm/s" | "cm/s/s" => 1e-2,
+        "mm" | "mm/s" | "mm/s/s" => 1e-3,
+        "mrad" | "mrad/s" => 1e-3,
+        "cV" | "cA" | "cPa" => 1e-2,
+        "mV" | "mA" | "mAh" | "mW" => 1e-3,
+        "hJ" => 1e2,
+        _ => return None,
+    })
+}
+
+// Search for MAVLink messages in the tree and convert their scaled fields to natural units
+fn convert_units(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let message = object
+                .get("type")
+                .and_then(Value::as_str)
+                .and_then(definitions::message);
+
+            if let Some(message) = message {
+                for field in &message.fields {
+                    let scale = match field.units.as_deref().and_then(si_scale) {
+                        Some(scale) => scale,
+                        None => continue,
+                    };
+
+                    let scale_value = |value: &mut Value| {
+                        if let Some(number) = value.as_f64() {
+                            *value = (number * scale).into();
+                        }
+                    };
+                    match object.get_mut(&field.name) {
+                        Some(Value::Array(values)) => values.iter_mut().for_each(scale_value),
+                        Some(value) => scale_value(value),
+                        None => {}
+                    }
+                }
+            }
+
+            for child in object.values_mut() {
+                convert_units(child);
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(convert_units),
+        _ => {}
+    }
+}
+
+// Search for MAVLink messages in the tree and add the numeric value to their enum fields
+fn add_enum_values(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let message = object
+                .get("type")
+                .and_then(Value::as_str)
+                .and_then(definitions::message);
+
+            if let Some(message) = message {
+                for field in &message.fields {
+                    let enumeration = match field
+                        .enum_name
+                        .as_deref()
+                        .and_then(definitions::enumeration)
+                    {
+                        Some(enumeration) => enumeration,
+                        None => continue,
+                    };
+
+                    if let Some(Value::Object(field_value)) = object.get_mut(&field.name) {
+                        let entry_value = field_value
+                            .get("type")
+                            .and_then(Value::as_str)
+                            .and_then(|entry| enumeration.value_of(entry));
+                        if let Some(entry_value) = entry_value {
+                            field_value.insert("value".into(), entry_value.into());
+                        }
+                    }
+                }
+            }
+
+            for child in object.values_mut() {
+                add_enum_values(child);
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(add_enum_values),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn si_units() {
+        let mut value = json!({
+            "header": {"system_id": 1},
+            "message": {
+                "type": "GLOBAL_POSITION_INT",
+                "lat": -275000000,
+                "relative_alt": 1500,
+                "vx": 250,
+                "hdg": 9000,
+                "time_boot_ms": 1000,
+            }
+        });
+        apply(
+            &mut value,
+            &OutputOptions {
+                si_units: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(value["message"]["lat"], -27.5);
+        assert_eq!(value["message"]["relative_alt"], 1.5);
+        assert_eq!(value["message"]["vx"], 2.5);
+        assert_eq!(value["message"]["hdg"], 90.0);
+        assert_eq!(value["message"]["time_boot_ms"], 1000);
+    }
+
+    #[test]
+    fn key_case() {
+        let mut value = json!({
+            "header": {"system_id": 1},
+            "message": {
+                "type": "GLOBAL_POSITION_INT",
+                "time_boot_ms": 1000,
+                "relative_alt": 1500,
+            }
+        });
+        let original = value.clone();
+        apply(
+            &mut value,
+            &OutputOptions {
+                key_case: KeyCase::Camel,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(value["header"]["systemId"], 1);
+        assert_eq!(value["message"]["type"], "globalPositionInt");
+        assert_eq!(value["message"]["relativeAlt"], 1500);
+
+        normalize_keys(&mut value);
+        assert_eq!(value, original);
+        assert_eq!(KeyCase::Lower.convert("ICAO_address"), "icao_address");
+    }
+
+    #[test]
+    fn flat() {
+        let mut value = json!({
+            "message": {
+                "type": "HEARTBEAT",
+                "system_status": {"type": "MAV_STATE_ACTIVE"},
+                "base_mode": {"bits": 81},
+            },
+            "status": {"time": {"frequency": 1.0}},
+        });
+        apply(
+            &mut value,
+            &OutputOptions {
+                flat: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            value,
+            json!({
+                "HEARTBEAT.system_status": "MAV_STATE_ACTIVE",
+                "HEARTBEAT.base_mode": 81,
+                "status.time.frequency": 1.0,
+            })
+        );
+    }
+}
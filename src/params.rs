@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use log::*;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{self, MavParamType};
+use serde::Serialize;
+
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::vehicles;
+use super::waiter;
+
+// Attempts of each request before giving up
+const RETRIES: usize = 3;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameter {
+    pub name: String,
+    pub value: f64,
+    #[serde(skip)]
+    pub param_type: MavParamType,
+    pub index: u16,
+}
+
+#[derive(Debug)]
+pub enum ParamError {
+    Send(std::io::Error),
+    Timeout(String),
+}
+
+impl std::fmt::Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamError::Send(error) => write!(f, "Failed to send message: {error}"),
+            ParamError::Timeout(step) => write!(f, "Vehicle did not answer {step}"),
+        }
+    }
+}
+
+/// Autopilot and component that own the parameters
+#[derive(Clone, Copy, Debug)]
+pub struct Target {
+    pub system_id: u8,
+    pub component_id: u8,
+}
+
+impl Target {
+    // PX4 sends integer parameters with their bytes in the float, ArduPilot casts them
+    fn is_bytewise(&self) -> bool {
+        vehicles::autopilot(self.system_id, self.component_id).as_deref()
+            == Some("MAV_AUTOPILOT_PX4")
+    }
+}
+
+pub fn param_type_id(param_type: MavParamType) -> u8 {
+    param_type as u8
+}
+
+pub fn param_type_from_id(id: u8) -> Option<MavParamType> {
+    Some(match id {
+        1 => MavParamType::MAV_PARAM_TYPE_UINT8,
+        2 => MavParamType::MAV_PARAM_TYPE_INT8,
+        3 => MavParamType::MAV_PARAM_TYPE_UINT16,
+        4 => MavParamType::MAV_PARAM_TYPE_INT16,
+        5 => MavParamType::MAV_PARAM_TYPE_UINT32,
+        6 => MavParamType::MAV_PARAM_TYPE_INT32,
+        7 => MavParamType::MAV_PARAM_TYPE_UINT64,
+        8 => MavParamType::MAV_PARAM_TYPE_INT64,
+        9 => MavParamType::MAV_PARAM_TYPE_REAL32,
+        10 => MavParamType::MAV_PARAM_TYPE_REAL64,
+        _ => return None,
+    })
+}
+
+pub fn is_float(param_type: MavParamType) -> bool {
+    matches!(
+        param_type,
+        MavParamType::MAV_PARAM_TYPE_REAL32 | MavParamType::MAV_PARAM_TYPE_REAL64
+    )
+}
+
+fn decode_value(value: f32, param_type: MavParamType, bytewise: bool) -> f64 {
+    if !bytewise || is_float(param_type) {
+        return value as f64;
+    }
+
+    let bits = value.to_bits();
+    match param_type {
+        MavParamType::MAV_PARAM_TYPE_UINT8 => (bits as u8) as f64,
+        MavParamType::MAV_PARAM_TYPE_INT8 => (bits as i8) as f64,
+        MavParamType::MAV_PARAM_TYPE_UINT16 => (bits as u16) as f64,
+        MavParamType::MAV_PARAM_TYPE_INT16 => (bits as i16) as f64,
+        MavParamType::MAV_PARAM_TYPE_UINT32 => bits as f64,
+        _ => (bits as i32) as f64,
+    }
+}
+
+fn encode_value(value: f64, param_type: MavParamType, bytewise: bool) -> f32 {
+    if !bytewise || is_float(param_type) {
+        return value as f32;
+    }
+
+    let bits = match param_type {
+        MavParamType::MAV_PARAM_TYPE_UINT8 => value as u8 as u32,
+        MavParamType::MAV_PARAM_TYPE_INT8 => value as i8 as u8 as u32,
+        MavParamType::MAV_PARAM_TYPE_UINT16 => value as u16 as u32,
+        MavParamType::MAV_PARAM_TYPE_INT16 => value as i16 as u16 as u32,
+        MavParamType::MAV_PARAM_TYPE_UINT32 => value as u32,
+        _ => value as i32 as u32,
+    };
+    f32::from_bits(bits)
+}
+
+fn param_name(param_id: &[u8]) -> String {
+    String::from_utf8_lossy(param_id)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn param_id(name: &str) -> [u8; 16] {
+    let mut id = [0; 16];
+    let bytes = name.as_bytes();
+    let length = bytes.len().min(id.len());
+    id[..length].copy_from_slice(&bytes[..length]);
+    id
+}
+
+fn send(vehicle: &MAVLinkVehicleArcMutex, message: &MavMessage) -> Result<(), ParamError> {
+    vehicle
+        .lock()
+        .unwrap()
+        .send_default(message)
+        .map(|_| ())
+        .map_err(ParamError::Send)
+}
+
+fn param_value(
+    target: Target,
+    message: &MavMessage,
+) -> Option<(common::PARAM_VALUE_DATA, Parameter)> {
+    let value = match message {
+        MavMessage::common(common::MavMessage::PARAM_VALUE(value)) => value.clone(),
+        _ => return None,
+    };
+    let parameter = Parameter {
+        name: param_name(&value.param_id),
+        value: decode_value(value.param_value, value.param_type, target.is_bytewise()),
+        param_type: value.param_type,
+        index: value.param_index,
+    };
+    Some((value, parameter))
+}
+
+fn is_param_value(
+    target: Target,
+) -> impl Fn(&mavlink::MavHeader, &MavMessage) -> bool + Clone + Send + 'static {
+    move |header, message| {
+        header.system_id == target.system_id
+            && header.component_id == target.component_id
+            && matches!(
+                message,
+                MavMessage::common(common::MavMessage::PARAM_VALUE(_))
+            )
+    }
+}
+
+/// Download all parameters, requesting again the ones that were lost
+pub fn fetch_all(
+    vehicle: &MAVLinkVehicleArcMutex,
+    target: Target,
+    timeout: Duration,
+) -> Result<Vec<Parameter>, ParamError> {
+    let subscription = waiter::subscribe_all(is_param_value(target));
+    let request_list = MavMessage::common(common::MavMessage::PARAM_REQUEST_LIST(
+        common::PARAM_REQUEST_LIST_DATA {
+            target_system: target.system_id,
+            target_component: target.component_id,
+        },
+    ));
+    send(vehicle, &request_list)?;
+
+    let mut parameters: BTreeMap<u16, Parameter> = BTreeMap::new();
+    let mut count = None;
+    let mut attempts = 0;
+    loop {
+        if let Some((_, message)) = subscription.next(timeout) {
+            if let Some((value, parameter)) = param_value(target, &message) {
+                count = Some(value.param_count);
+                if value.param_index < value.param_count {
+                    parameters.insert(value.param_index, parameter);
+                }
+            }
+            if count == Some(parameters.len() as u16) {
+                break;
+            }
+            continue;
+        }
+
+        attempts += 1;
+        if attempts > RETRIES {
+            return Err(ParamError::Timeout("PARAM_REQUEST_LIST".into()));
+        }
+
+        let count = match count {
+            Some(count) => count,
+            None => {
+                send(vehicle, &request_list)?;
+                continue;
+            }
+        };
+
+        let missing: Vec<u16> = (0..count)
+            .filter(|index| !parameters.contains_key(index))
+            .collect();
+        debug!("Requesting {} missing parameters.", missing.len());
+        for index in missing {
+            send(
+                vehicle,
+                &MavMessage::common(common::MavMessage::PARAM_REQUEST_READ(
+                    common::PARAM_REQUEST_READ_DATA {
+                        param_index: index as i16,
+                        target_system: target.system_id,
+                        target_component: target.component_id,
+                        param_id: [0; 16],
+                    },
+                )),
+            )?;
+        }
+    }
+
+    Ok(parameters.into_values().collect())
+}
+
+/// Set a parameter and wait for the vehicle to confirm the new value
+pub fn set(
+    vehicle: &MAVLinkVehicleArcMutex,
+    target: Target,
+    name: &str,
+    value: f64,
+    param_type: MavParamType,
+    timeout: Duration,
+) -> Result<Parameter, ParamError> {
+    let message = MavMessage::common(common::MavMessage::PARAM_SET(common::PARAM_SET_DATA {
+        param_value: encode_value(value, param_type, target.is_bytewise()),
+        target_system: target.system_id,
+        target_component: target.component_id,
+        param_id: param_id(name),
+        param_type,
+    }));
+
+    for _ in 0..RETRIES {
+        let name = name.to_string();
+        let is_param_value = is_param_value(target);
+        let subscription = waiter::subscribe(move |header, message| {
+            is_param_value(header, message)
+                && match message {
+                    MavMessage::common(common::MavMessage::PARAM_VALUE(value)) => {
+                        param_name(&value.param_id) == name
+                    }
+                    _ => false,
+                }
+        });
+        send(vehicle, &message)?;
+        if let Some((_, message)) = subscription.wait(timeout) {
+            if let Some((_, parameter)) = param_value(target, &message) {
+                return Ok(parameter);
+            }
+        }
+    }
+    Err(ParamError::Timeout(format!("PARAM_SET {name}")))
+}
+
+/// Parameter of a QGroundControl .params file
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileParameter {
+    pub name: String,
+    pub value: f64,
+    pub param_type: MavParamType,
+}
+
+/// Write the parameters in the QGroundControl .params format
+pub fn to_params_file(target: Target, parameters: &[Parameter]) -> String {
+    let mut file = format!(
+        "# Onboard parameters for Vehicle {}\n#\n# Vehicle-Id Component-Id Name Value Type\n",
+        target.system_id
+    );
+    for parameter in parameters {
+        let value = if is_float(parameter.param_type) {
+            format!("{:.18}", parameter.value)
+        } else {
+            format!("{}", parameter.value as i64)
+        };
+        file.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            target.system_id,
+            target.component_id,
+            parameter.name,
+            value,
+            param_type_id(parameter.param_type)
+        ));
+    }
+    file
+}
+
+/// Parse a QGroundControl .params file
+pub fn from_params_file(content: &str) -> Result<Vec<FileParameter>, String> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 5 {
+                return Err(format!(
+                    "Line {number}: expected 5 fields, got {}",
+                    fields.len()
+                ));
+            }
+            let value = fields[3]
+                .parse::<f64>()
+                .map_err(|error| format!("Line {number}: invalid value: {error}"))?;
+            let param_type = fields[4]
+                .parse::<u8>()
+                .ok()
+                .and_then(param_type_from_id)
+                .ok_or_else(|| format!("Line {number}: invalid type: {}", fields[4]))?;
+            Ok(FileParameter {
+                name: fields[2].to_string(),
+                value,
+                param_type,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportResult {
+    Unchanged,
+    Changed,
+    Unknown,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportReport {
+    name: String,
+    previous: Option<f64>,
+    value: f64,
+    result: ImportResult,
+    error: Option<String>,
+}
+
+/// Apply the parameters that differ from the vehicle ones
+pub fn import(
+    vehicle: &MAVLinkVehicleArcMutex,
+    target: Target,
+    file_parameters: &[FileParameter],
+    timeout: Duration,
+) -> Result<Vec<ImportReport>, ParamError> {
+    let current: BTreeMap<String, Parameter> = fetch_all(vehicle, target, timeout)?
+        .into_iter()
+        .map(|parameter| (parameter.name.clone(), parameter))
+        .collect();
+
+    Ok(file_parameters
+        .iter()
+        .map(|file_parameter| {
+            let mut report = ImportReport {
+                name: file_parameter.name.clone(),
+                previous: None,
+                value: file_parameter.value,
+                result: ImportResult::Unknown,
+                error: None,
+            };
+
+            let parameter = match current.get(&file_parameter.name) {
+                Some(parameter) => parameter,
+                None => return report,
+            };
+            report.previous = Some(parameter.value);
+
+            // Values are transferred as f32
+            if parameter.value as f32 == file_parameter.value as f32 {
+                report.result = ImportResult::Unchanged;
+                return report;
+            }
+
+            match set(
+                vehicle,
+                target,
+                &file_parameter.name,
+                file_parameter.value,
+                parameter.param_type,
+                timeout,
+            ) {
+                Ok(_) => report.result = ImportResult::Changed,
+                Err(error) => {
+                    report.result = ImportResult::Failed;
+                    report.error = Some(error.to_string());
+                }
+            }
+            report
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_file() {
+        let target = Target {
+            system_id: 1,
+            component_id: 1,
+        };
+        let parameters = vec![
+            Parameter {
+                name: "ATC_RAT_PIT_P".into(),
+                value: 0.125,
+                param_type: MavParamType::MAV_PARAM_TYPE_REAL32,
+                index: 0,
+            },
+            Parameter {
+                name: "FRAME_CLASS".into(),
+                value: 2.0,
+                param_type: MavParamType::MAV_PARAM_TYPE_INT8,
+                index: 1,
+            },
+        ];
+
+        let file = to_params_file(target, &parameters);
+        assert!(file.contains("1\t1\tATC_RAT_PIT_P\t0.125000000000000000\t9\n"));
+        assert!(file.contains("1\t1\tFRAME_CLASS\t2\t2\n"));
+
+        let parsed = from_params_file(&file).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].name, "FRAME_CLASS");
+        assert_eq!(parsed[1].param_type, MavParamType::MAV_PARAM_TYPE_INT8);
+        assert!(from_params_file("1 1 FRAME_CLASS").is_err());
+    }
+
+    #[test]
+    fn bytewise_values() {
+        let encoded = encode_value(-3.0, MavParamType::MAV_PARAM_TYPE_INT16, true);
+        assert_eq!(encoded.to_bits(), 0xfffd);
+        assert_eq!(
+            decode_value(encoded, MavParamType::MAV_PARAM_TYPE_INT16, true),
+            -3.0
+        );
+        assert_eq!(
+            encode_value(-3.0, MavParamType::MAV_PARAM_TYPE_INT16, false),
+            -3.0
+        );
+    }
+}
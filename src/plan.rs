@@ -0,0 +1,434 @@
+use std::time::Duration;
+
+use mavlink::common::{MavCmd, MavFrame, MavMissionType};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use super::definitions;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::mission::{self, MissionError, MissionItem};
+use super::vehicles;
+
+// MAV_AUTOPILOT_ARDUPILOTMEGA, ArduPilot keeps the home position as the first mission item
+const FIRMWARE_ARDUPILOT: u64 = 3;
+
+/// Mission, geofence and rally point items of a plan
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    pub mission: Vec<MissionItem>,
+    pub fence: Vec<MissionItem>,
+    pub rally: Vec<MissionItem>,
+}
+
+// .plan files use the numeric values of the enums
+fn enum_from_value<T: DeserializeOwned>(enum_name: &str, value: u64) -> Option<T> {
+    let entry = definitions::enumeration(enum_name)?.name_of(value)?;
+    serde_json::from_value(json!({ "type": entry })).ok()
+}
+
+fn is_global(frame: MavFrame) -> bool {
+    format!("{frame:?}").contains("GLOBAL")
+}
+
+fn item(frame: MavFrame, command: MavCmd, params: [f64; 7]) -> MissionItem {
+    let (x, y) = if is_global(frame) {
+        ((params[4] * 1e7) as i32, (params[5] * 1e7) as i32)
+    } else {
+        (params[4] as i32, params[5] as i32)
+    };
+
+    MissionItem {
+        frame,
+        command,
+        current: 0,
+        autocontinue: 1,
+        param1: params[0] as f32,
+        param2: params[1] as f32,
+        param3: params[2] as f32,
+        param4: params[3] as f32,
+        x,
+        y,
+        z: params[6] as f32,
+    }
+}
+
+fn item_params(item: &MissionItem) -> Vec<Value> {
+    let (x, y) = if is_global(item.frame) {
+        (item.x as f64 / 1e7, item.y as f64 / 1e7)
+    } else {
+        (item.x as f64, item.y as f64)
+    };
+
+    // NaN is not valid JSON, QGroundControl uses null
+    [
+        item.param1 as f64,
+        item.param2 as f64,
+        item.param3 as f64,
+        item.param4 as f64,
+        x,
+        y,
+        item.z as f64,
+    ]
+    .iter()
+    .map(|param| {
+        if param.is_finite() {
+            json!(param)
+        } else {
+            Value::Null
+        }
+    })
+    .collect()
+}
+
+fn coordinate(value: &Value, index: usize) -> Result<f64, String> {
+    value[index]
+        .as_f64()
+        .ok_or_else(|| format!("Invalid coordinate: {value}"))
+}
+
+fn simple_item(value: &Value) -> Result<MissionItem, String> {
+    let command = value["command"]
+        .as_u64()
+        .and_then(|command| enum_from_value::<MavCmd>("MAV_CMD", command))
+        .ok_or_else(|| format!("Invalid command: {}", value["command"]))?;
+    let frame = value["frame"]
+        .as_u64()
+        .and_then(|frame| enum_from_value::<MavFrame>("MAV_FRAME", frame))
+        .ok_or_else(|| format!("Invalid frame: {}", value["frame"]))?;
+
+    let mut params = [0.0; 7];
+    for (index, param) in params.iter_mut().enumerate() {
+        *param = value["params"][index].as_f64().unwrap_or(f64::NAN);
+    }
+
+    let mut item = item(frame, command, params);
+    item.autocontinue = value["autoContinue"].as_bool().unwrap_or(true) as u8;
+    Ok(item)
+}
+
+fn mission_items(mission: &Value) -> Result<Vec<MissionItem>, String> {
+    let items = mission["items"].as_array().cloned().unwrap_or_default();
+    items
+        .iter()
+        .map(|value| match value["type"].as_str() {
+            Some("SimpleItem") => simple_item(value),
+            Some(kind) => Err(format!("{kind} items are not supported, only SimpleItem")),
+            None => Err("Mission item without type".into()),
+        })
+        .collect()
+}
+
+fn fence_items(fence: &Value) -> Result<Vec<MissionItem>, String> {
+    let mut items = vec![];
+
+    for polygon in fence["polygons"].as_array().cloned().unwrap_or_default() {
+        let command = if polygon["inclusion"].as_bool().unwrap_or(true) {
+            MavCmd::MAV_CMD_NAV_FENCE_POLYGON_VERTEX_INCLUSION
+        } else {
+            MavCmd::MAV_CMD_NAV_FENCE_POLYGON_VERTEX_EXCLUSION
+        };
+        let vertices = polygon["polygon"].as_array().cloned().unwrap_or_default();
+        for vertex in &vertices {
+            let params = [
+                vertices.len() as f64,
+                0.0,
+                0.0,
+                0.0,
+                coordinate(vertex, 0)?,
+                coordinate(vertex, 1)?,
+                0.0,
+            ];
+            items.push(item(MavFrame::MAV_FRAME_GLOBAL, command, params));
+        }
+    }
+
+    for circle in fence["circles"].as_array().cloned().unwrap_or_default() {
+        let command = if circle["inclusion"].as_bool().unwrap_or(true) {
+            MavCmd::MAV_CMD_NAV_FENCE_CIRCLE_INCLUSION
+        } else {
+            MavCmd::MAV_CMD_NAV_FENCE_CIRCLE_EXCLUSION
+        };
+        let center = &circle["circle"]["center"];
+        let radius = circle["circle"]["radius"]
+            .as_f64()
+            .ok_or("Fence circle without radius")?;
+        let params = [
+            radius,
+            0.0,
+            0.0,
+            0.0,
+            coordinate(center, 0)?,
+            coordinate(center, 1)?,
+            0.0,
+        ];
+        items.push(item(MavFrame::MAV_FRAME_GLOBAL, command, params));
+    }
+
+    Ok(items)
+}
+
+fn rally_items(rally: &Value) -> Result<Vec<MissionItem>, String> {
+    rally["points"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|point| {
+            let params = [
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                coordinate(point, 0)?,
+                coordinate(point, 1)?,
+                coordinate(point, 2)?,
+            ];
+            Ok(item(
+                MavFrame::MAV_FRAME_GLOBAL_RELATIVE_ALT,
+                MavCmd::MAV_CMD_NAV_RALLY_POINT,
+                params,
+            ))
+        })
+        .collect()
+}
+
+/// Read the items of a QGroundControl .plan file
+pub fn from_plan(plan: &Value, is_ardupilot: bool) -> Result<Plan, String> {
+    if plan["fileType"] != "Plan" {
+        return Err("Not a QGroundControl plan file".into());
+    }
+
+    let mut mission = mission_items(&plan["mission"])?;
+    if is_ardupilot {
+        let home = &plan["mission"]["plannedHomePosition"];
+        let params = [
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            coordinate(home, 0).unwrap_or(0.0),
+            coordinate(home, 1).unwrap_or(0.0),
+            coordinate(home, 2).unwrap_or(0.0),
+        ];
+        let home = item(
+            MavFrame::MAV_FRAME_GLOBAL,
+            MavCmd::MAV_CMD_NAV_WAYPOINT,
+            params,
+        );
+        mission.insert(0, home);
+    }
+
+    Ok(Plan {
+        mission,
+        fence: fence_items(&plan["geoFence"])?,
+        rally: rally_items(&plan["rallyPoints"])?,
+    })
+}
+
+fn geofence(items: &[MissionItem]) -> Value {
+    let mut polygons = vec![];
+    let mut circles = vec![];
+
+    let position = |item: &MissionItem| json!([item.x as f64 / 1e7, item.y as f64 / 1e7]);
+    let mut index = 0;
+    while index < items.len() {
+        let item = &items[index];
+        match item.command {
+            MavCmd::MAV_CMD_NAV_FENCE_POLYGON_VERTEX_INCLUSION
+            | MavCmd::MAV_CMD_NAV_FENCE_POLYGON_VERTEX_EXCLUSION => {
+                let count = (item.param1 as usize).max(1);
+                let vertices: Vec<Value> =
+                    items[index..].iter().take(count).map(position).collect();
+                polygons.push(json!({
+                    "inclusion": item.command == MavCmd::MAV_CMD_NAV_FENCE_POLYGON_VERTEX_INCLUSION,
+                    "polygon": vertices,
+                    "version": 1,
+                }));
+                index += count;
+                continue;
+            }
+            MavCmd::MAV_CMD_NAV_FENCE_CIRCLE_INCLUSION
+            | MavCmd::MAV_CMD_NAV_FENCE_CIRCLE_EXCLUSION => {
+                circles.push(json!({
+                    "circle": {"center": position(item), "radius": item.param1},
+                    "inclusion": item.command == MavCmd::MAV_CMD_NAV_FENCE_CIRCLE_INCLUSION,
+                    "version": 1,
+                }));
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    json!({
+        "circles": circles,
+        "polygons": polygons,
+        "version": 2,
+    })
+}
+
+/// Write the items as a QGroundControl .plan file
+pub fn to_plan(plan: &Plan, is_ardupilot: bool) -> Value {
+    let mut mission = plan.mission.as_slice();
+    let mut home = json!([0.0, 0.0, 0.0]);
+    if is_ardupilot && !mission.is_empty() {
+        let params = item_params(&mission[0]);
+        home = json!([params[4], params[5], params[6]]);
+        mission = &mission[1..];
+    }
+
+    let items: Vec<Value> = mission
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            json!({
+                "autoContinue": item.autocontinue != 0,
+                "command": item.command as u32,
+                "doJumpId": index + 1,
+                "frame": item.frame as u32,
+                "params": item_params(item),
+                "type": "SimpleItem",
+            })
+        })
+        .collect();
+
+    let points: Vec<Value> = plan
+        .rally
+        .iter()
+        .map(|item| {
+            let params = item_params(item);
+            json!([params[4], params[5], params[6]])
+        })
+        .collect();
+
+    json!({
+        "fileType": "Plan",
+        "geoFence": geofence(&plan.fence),
+        "groundStation": "mavlink2rest",
+        "mission": {
+            "firmwareType": if is_ardupilot { FIRMWARE_ARDUPILOT } else { 0 },
+            "items": items,
+            "plannedHomePosition": home,
+            "version": 2,
+        },
+        "rallyPoints": {
+            "points": points,
+            "version": 2,
+        },
+        "version": 1,
+    })
+}
+
+fn target(system_id: u8, component_id: u8, mission_type: MavMissionType) -> mission::Target {
+    mission::Target {
+        system_id,
+        component_id,
+        mission_type,
+    }
+}
+
+pub fn is_ardupilot(system_id: u8, component_id: u8) -> bool {
+    vehicles::autopilot(system_id, component_id).as_deref() == Some("MAV_AUTOPILOT_ARDUPILOTMEGA")
+}
+
+/// Download the mission, geofence and rally points of the vehicle
+pub fn download(
+    vehicle: &MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    timeout: Duration,
+) -> Result<Plan, MissionError> {
+    let download = |mission_type| {
+        mission::download(
+            vehicle,
+            target(system_id, component_id, mission_type),
+            timeout,
+        )
+    };
+
+    Ok(Plan {
+        mission: download(MavMissionType::MAV_MISSION_TYPE_MISSION)?,
+        fence: download(MavMissionType::MAV_MISSION_TYPE_FENCE)?,
+        rally: download(MavMissionType::MAV_MISSION_TYPE_RALLY)?,
+    })
+}
+
+/// Upload the mission, geofence and rally points, replacing the ones in the vehicle
+pub fn upload(
+    vehicle: &MAVLinkVehicleArcMutex,
+    system_id: u8,
+    component_id: u8,
+    plan: &Plan,
+    timeout: Duration,
+) -> Result<(), MissionError> {
+    for (mission_type, items) in [
+        (MavMissionType::MAV_MISSION_TYPE_MISSION, &plan.mission),
+        (MavMissionType::MAV_MISSION_TYPE_FENCE, &plan.fence),
+        (MavMissionType::MAV_MISSION_TYPE_RALLY, &plan.rally),
+    ] {
+        mission::upload(
+            vehicle,
+            target(system_id, component_id, mission_type),
+            items,
+            timeout,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_conversion() {
+        let file = json!({
+            "fileType": "Plan",
+            "geoFence": {
+                "circles": [],
+                "polygons": [{
+                    "inclusion": true,
+                    "polygon": [[-27.5, -48.5], [-27.5, -48.6], [-27.6, -48.6]],
+                    "version": 1
+                }],
+                "version": 2
+            },
+            "groundStation": "QGroundControl",
+            "mission": {
+                "firmwareType": 3,
+                "items": [{
+                    "autoContinue": true,
+                    "command": 22,
+                    "doJumpId": 1,
+                    "frame": 3,
+                    "params": [0, 0, 0, null, -27.5, -48.5, 50],
+                    "type": "SimpleItem"
+                }],
+                "plannedHomePosition": [-27.4, -48.4, 10],
+                "version": 2
+            },
+            "rallyPoints": {"points": [[-27.45, -48.45, 30]], "version": 2},
+            "version": 1
+        });
+
+        let plan = from_plan(&file, true).unwrap();
+        assert_eq!(plan.mission.len(), 2);
+        assert_eq!(plan.mission[1].command, MavCmd::MAV_CMD_NAV_TAKEOFF);
+        assert_eq!(plan.mission[1].x, -275000000);
+        assert!(plan.mission[1].param4.is_nan());
+        assert_eq!(plan.fence.len(), 3);
+        assert_eq!(plan.rally.len(), 1);
+
+        let exported = to_plan(&plan, true);
+        assert_eq!(exported["mission"]["items"][0]["command"], 22);
+        assert_eq!(exported["mission"]["items"][0]["params"][3], Value::Null);
+        assert_eq!(
+            exported["geoFence"]["polygons"][0]["polygon"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(exported["rallyPoints"]["points"][0][2], 30.0);
+    }
+}
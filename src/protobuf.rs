@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use super::definitions::{self, FieldDefinition, MessageDefinition};
+
+// The envelope uses the message id plus this offset as oneof field number, to skip the header fields
+const MESSAGE_FIELD_OFFSET: u32 = 10;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldKind {
+    Unsigned,
+    Signed,
+    Float,
+    Double,
+    String,
+    Bytes,
+}
+
+// Protobuf representation of a MAVLink field, returns the kind and if it's a repeated field
+fn field_kind(field: &FieldDefinition) -> (FieldKind, bool) {
+    let base_type = field.mavlink_type.split('[').next().unwrap_or_default();
+    let is_array = field.mavlink_type.contains('[');
+
+    let kind = match base_type {
+        "char" if is_array => return (FieldKind::String, false),
+        "uint8_t" if is_array => return (FieldKind::Bytes, false),
+        "float" => FieldKind::Float,
+        "double" => FieldKind::Double,
+        base_type if base_type.starts_with("int") => FieldKind::Signed,
+        _ => FieldKind::Unsigned,
+    };
+
+    (kind, is_array)
+}
+
+fn protobuf_type(field: &FieldDefinition) -> String {
+    let is_64_bits = field.mavlink_type.contains("64");
+    let (kind, is_repeated) = field_kind(field);
+    let name = match kind {
+        FieldKind::Unsigned if is_64_bits => "uint64",
+        FieldKind::Unsigned => "uint32",
+        FieldKind::Signed if is_64_bits => "sint64",
+        FieldKind::Signed => "sint32",
+        FieldKind::Float => "float",
+        FieldKind::Double => "double",
+        FieldKind::String => "string",
+        FieldKind::Bytes => "bytes",
+    };
+
+    if is_repeated {
+        return format!("repeated {name}");
+    }
+    name.to_string()
+}
+
+fn sorted_messages() -> Vec<&'static MessageDefinition> {
+    let mut messages = definitions::messages();
+    messages.sort_by_key(|message| message.id);
+    messages
+}
+
+fn generate_schema() -> String {
+    let mut schema = String::from(
+        "// Generated by mavlink2rest from the MAVLink definitions\nsyntax = \"proto3\";\n\npackage mavlink;\n",
+    );
+
+    let messages = sorted_messages();
+    for message in &messages {
+        schema += &format!(
+            "\n// {}\nmessage {} {{\n",
+            message.description, message.name
+        );
+        for (index, field) in message.fields.iter().enumerate() {
+            let mut comment = field.description.clone();
+            if let Some(units) = &field.units {
+                comment += &format!(" [{units}]");
+            }
+            if let Some(enum_name) = &field.enum_name {
+                comment += &format!(" Enum: {enum_name}");
+            }
+            schema += &format!(
+                "  // {comment}\n  {} {} = {};\n",
+                protobuf_type(field),
+                field.name,
+                index + 1
+            );
+        }
+        schema += "}\n";
+    }
+
+    schema += "\nmessage MAVLinkMessage {\n  uint32 system_id = 1;\n  uint32 component_id = 2;\n  uint32 sequence = 3;\n  oneof message {\n";
+    for message in &messages {
+        schema += &format!(
+            "    {} {} = {};\n",
+            message.name,
+            message.name.to_lowercase(),
+            message.id + MESSAGE_FIELD_OFFSET
+        );
+    }
+    schema += "  }\n}\n\nmessage MAVLinkMessages {\n  repeated MAVLinkMessage messages = 1;\n}\n";
+
+    schema
+}
+
+lazy_static! {
+    // Generated at runtime, on first use, like the message descriptions of the definitions module
+    static ref SCHEMA: String = generate_schema();
+}
+
+// Protobuf schema of the dialect definitions embedded by the build script, the same ones used to encode messages
+pub fn schema() -> &'static str {
+    &SCHEMA
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn write_key(buffer: &mut Vec<u8>, number: u32, wire_type: u8) {
+    write_varint(buffer, ((number as u64) << 3) | wire_type as u64);
+}
+
+fn write_length_delimited(buffer: &mut Vec<u8>, number: u32, content: &[u8]) {
+    write_key(buffer, number, WIRE_LENGTH_DELIMITED);
+    write_varint(buffer, content.len() as u64);
+    buffer.extend_from_slice(content);
+}
+
+// Integer value of a JSON number, enum or bitmask
+fn json_integer(field: &FieldDefinition, value: &Value) -> Option<i64> {
+    if let Some(number) = value.as_i64() {
+        return Some(number);
+    }
+    if let Some(number) = value.as_u64() {
+        return Some(number as i64);
+    }
+    if let Some(bits) = value.get("bits").and_then(Value::as_i64) {
+        return Some(bits);
+    }
+
+    let entry = value.get("type").and_then(Value::as_str)?;
+    definitions::enumeration(field.enum_name.as_deref()?)?
+        .value_of(entry)
+        .map(|value| value as i64)
+}
+
+// Encode a single value without key, as used in packed repeated fields
+fn write_scalar(buffer: &mut Vec<u8>, kind: FieldKind, field: &FieldDefinition, value: &Value) {
+    match kind {
+        FieldKind::Float => {
+            let number = value.as_f64().unwrap_or(f64::NAN) as f32;
+            buffer.extend_from_slice(&number.to_le_bytes());
+        }
+        FieldKind::Double => {
+            let number = value.as_f64().unwrap_or(f64::NAN);
+            buffer.extend_from_slice(&number.to_le_bytes());
+        }
+        FieldKind::Signed => write_varint(buffer, zigzag(json_integer(field, value).unwrap_or(0))),
+        _ => write_varint(buffer, json_integer(field, value).unwrap_or(0) as u64),
+    }
+}
+
+// Strings can be represented as string or as arrays of characters
+fn json_string(value: &Value) -> String {
+    let string: String = match value {
+        Value::String(string) => string.clone(),
+        Value::Array(characters) => characters
+            .iter()
+            .filter_map(|character| match character {
+                Value::String(character) => Some(character.clone()),
+                Value::Number(number) => number
+                    .as_u64()
+                    .map(|number| (number as u8 as char).to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => String::new(),
+    };
+    string.trim_end_matches('\0').to_string()
+}
+
+fn write_field(buffer: &mut Vec<u8>, number: u32, field: &FieldDefinition, value: &Value) {
+    if value.is_null() {
+        return;
+    }
+
+    match field_kind(field) {
+        (FieldKind::String, _) => {
+            write_length_delimited(buffer, number, json_string(value).as_bytes());
+        }
+        (FieldKind::Bytes, _) => {
+            let bytes: Vec<u8> = value
+                .as_array()
+                .map(|values| {
+                    values
+                        .iter()
+                        .map(|value| value.as_u64().unwrap_or(0) as u8)
+                        .collect()
+                })
+                .unwrap_or_default();
+            write_length_delimited(buffer, number, &bytes);
+        }
+        (kind, true) => {
+            let mut packed = vec![];
+            for value in value.as_array().into_iter().flatten() {
+                write_scalar(&mut packed, kind, field, value);
+            }
+            write_length_delimited(buffer, number, &packed);
+        }
+        (kind, false) => {
+            let wire_type = match kind {
+                FieldKind::Float => WIRE_FIXED32,
+                FieldKind::Double => WIRE_FIXED64,
+                _ => WIRE_VARINT,
+            };
+            write_key(buffer, number, wire_type);
+            write_scalar(buffer, kind, field, value);
+        }
+    }
+}
+
+fn encode_message(message: &Value) -> Option<(u32, Vec<u8>)> {
+    let definition = message
+        .get("type")
+        .and_then(Value::as_str)
+        .and_then(definitions::message)?;
+
+    let mut buffer = vec![];
+    for (index, field) in definition.fields.iter().enumerate() {
+        if let Some(value) = message.get(&field.name) {
+            write_field(&mut buffer, index as u32 + 1, field, value);
+        }
+    }
+
+    Some((definition.id, buffer))
+}
+
+// Encode a MAVLinkMessage envelope
+fn encode_envelope(header: &HashMap<&str, u64>, message: &Value) -> Option<Vec<u8>> {
+    let (id, content) = encode_message(message)?;
+
+    let mut buffer = vec![];
+    for (number, name) in [(1, "system_id"), (2, "component_id"), (3, "sequence")] {
+        write_key(&mut buffer, number, WIRE_VARINT);
+        write_varint(&mut buffer, header.get(name).copied().unwrap_or(0));
+    }
+    write_length_delimited(&mut buffer, id + MESSAGE_FIELD_OFFSET, &content);
+    Some(buffer)
+}
+
+// Encode a {"header": {..}, "message": {..}} value as a MAVLinkMessage
+pub fn encode(value: &Value) -> Vec<u8> {
+    let header = ["system_id", "component_id", "sequence"]
+        .iter()
+        .filter_map(|name| Some((*name, value.get("header")?.get(name)?.as_u64()?)))
+        .collect();
+
+    value
+        .get("message")
+        .and_then(|message| encode_envelope(&header, message))
+        .unwrap_or_default()
+}
+
+// Encode all messages found in a subtree of the message store as MAVLinkMessages,
+// the path of the subtree is used to identify the vehicle and component
+pub fn encode_tree(value: &Value, path: &str) -> Vec<u8> {
+    let mut path: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+        .collect();
+
+    let mut buffer = vec![];
+    collect_messages(value, &mut path, &mut buffer);
+    buffer
+}
+
+fn id_after(path: &[String], name: &str) -> u64 {
+    path.iter()
+        .rposition(|segment| segment == name)
+        .and_then(|position| path.get(position + 1))
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0)
+}
+
+fn collect_messages(value: &Value, path: &mut Vec<String>, buffer: &mut Vec<u8>) {
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return,
+    };
+
+    // Message entries of the store, the message itself is also accepted
+    let message = match object.get("message") {
+        Some(message) if message.get("type").is_some() => Some(message),
+        _ if object.get("type").is_some() && path.last().map(String::as_str) == Some("message") => {
+            Some(value)
+        }
+        _ => None,
+    };
+
+    if let Some(message) = message {
+        let header = [
+            ("system_id", id_after(path, "vehicles")),
+            ("component_id", id_after(path, "components")),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        if let Some(envelope) = encode_envelope(&header, message) {
+            write_length_delimited(buffer, 1, &envelope);
+        }
+        return;
+    }
+
+    for (key, child) in object {
+        path.push(key.clone());
+        collect_messages(child, path, buffer);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_encoding() {
+        let mut buffer = vec![];
+        write_varint(&mut buffer, 300);
+        assert_eq!(buffer, vec![0xac, 0x02]);
+
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+
+        let mut buffer = vec![];
+        write_key(&mut buffer, 1, WIRE_VARINT);
+        assert_eq!(buffer, vec![0x08]);
+    }
+}
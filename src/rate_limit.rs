@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use actix_web::dev::ServiceRequest;
+use lazy_static::lazy_static;
+
+use super::cli;
+
+// Number of clients tracked before idle buckets are discarded
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+// Token bucket, it's refilled with `rate` tokens per second up to `burst`
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_update: Instant,
+}
+
+#[derive(Debug)]
+struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    fn is_allowed(&mut self, address: IpAddr, now: Instant) -> bool {
+        let (rate, burst) = (self.rate, self.burst);
+
+        if self.buckets.len() > MAX_IDLE_BUCKETS {
+            self.buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.last_update).as_secs_f64() * rate < burst
+            });
+        }
+
+        let bucket = self.buckets.entry(address).or_insert(Bucket {
+            tokens: burst,
+            last_update: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_update).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.last_update = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+lazy_static! {
+    static ref RATE_LIMITER: Option<Mutex<RateLimiter>> = cli::rate_limit().map(|(rate, burst)| {
+        Mutex::new(RateLimiter {
+            rate,
+            burst,
+            buckets: HashMap::new(),
+        })
+    });
+}
+
+// Check if the client is still under the configured requests rate
+pub fn is_allowed(request: &ServiceRequest) -> bool {
+    let rate_limiter = match RATE_LIMITER.as_ref() {
+        Some(rate_limiter) => rate_limiter,
+        None => return true,
+    };
+
+    match request.peer_addr() {
+        Some(address) => rate_limiter
+            .lock()
+            .unwrap()
+            .is_allowed(address.ip(), Instant::now()),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn token_buckets() {
+        let mut rate_limiter = RateLimiter {
+            rate: 2.0,
+            burst: 3.0,
+            buckets: HashMap::new(),
+        };
+        let client: IpAddr = "192.168.2.2".parse().unwrap();
+        let other_client: IpAddr = "192.168.2.3".parse().unwrap();
+        let start = Instant::now();
+
+        // The burst is available at once, then the bucket is empty
+        for _ in 0..3 {
+            assert!(rate_limiter.is_allowed(client, start));
+        }
+        assert!(!rate_limiter.is_allowed(client, start));
+
+        // Each client has its own bucket
+        assert!(rate_limiter.is_allowed(other_client, start));
+
+        // 2 tokens per second, a single request is allowed after 500ms
+        let later = start + Duration::from_millis(500);
+        assert!(rate_limiter.is_allowed(client, later));
+        assert!(!rate_limiter.is_allowed(client, later));
+
+        // The refill is capped by the burst
+        let idle = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(rate_limiter.is_allowed(client, idle));
+        }
+        assert!(!rate_limiter.is_allowed(client, idle));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use mavlink::Message;
+use serde::Serialize;
+
+use super::cli;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RawFrame {
+    time: DateTime<Utc>,
+    system_id: u8,
+    component_id: u8,
+    sequence: u8,
+    length: usize,
+    /// Header bytes, from the start marker to the message id
+    header: String,
+    payload: String,
+    crc: String,
+    /// Hex dump of the full frame, 16 bytes per line
+    dump: Vec<String>,
+}
+
+// Last frames of each message type, re-encoded from the parsed messages with the configured version.
+// rust-mavlink only provides decoded messages, so the CRC, sequence, signature, flags and payload
+// truncation are the ones of the encoder, not the bytes received: corrupted frames are never here
+#[derive(Debug, Default)]
+struct RawFrames {
+    size: usize,
+    version: Option<mavlink::MavlinkVersion>,
+    frames: HashMap<String, VecDeque<RawFrame>>,
+}
+
+lazy_static! {
+    static ref RAW_FRAMES: Mutex<RawFrames> = Mutex::new(RawFrames {
+        size: cli::reencoded_frames_size(),
+        version: None,
+        frames: HashMap::new(),
+    });
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn encode_frame(
+    version: mavlink::MavlinkVersion,
+    header: &mavlink::MavHeader,
+    message: &mavlink::ardupilotmega::MavMessage,
+) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    mavlink::write_versioned_msg(&mut bytes, version, *header, message).ok()?;
+    Some(bytes)
+}
+
+fn raw_frame(
+    version: mavlink::MavlinkVersion,
+    header: &mavlink::MavHeader,
+    message: &mavlink::ardupilotmega::MavMessage,
+) -> Option<RawFrame> {
+    let bytes = encode_frame(version, header, message)?;
+
+    let header_length = match version {
+        mavlink::MavlinkVersion::V1 => 6,
+        mavlink::MavlinkVersion::V2 => 10,
+    };
+    if bytes.len() < header_length + 2 {
+        return None;
+    }
+    let crc_start = bytes.len() - 2;
+
+    Some(RawFrame {
+        time: Utc::now(),
+        system_id: header.system_id,
+        component_id: header.component_id,
+        sequence: header.sequence,
+        length: bytes.len(),
+        header: hex(&bytes[..header_length]),
+        payload: hex(&bytes[header_length..crc_start]),
+        crc: hex(&bytes[crc_start..]),
+        dump: bytes
+            .chunks(16)
+            .enumerate()
+            .map(|(line, chunk)| format!("{:04x}: {}", line * 16, hex(chunk)))
+            .collect(),
+    })
+}
+
+pub fn init(version: mavlink::MavlinkVersion) {
+    RAW_FRAMES.lock().unwrap().version = Some(version);
+}
+
+/// MAVLink version used to communicate with the vehicle
+pub fn version() -> mavlink::MavlinkVersion {
+    RAW_FRAMES
+        .lock()
+        .unwrap()
+        .version
+        .unwrap_or(mavlink::MavlinkVersion::V2)
+}
+
+/// Frame bytes of a message with the configured MAVLink version
+pub fn encode(
+    header: &mavlink::MavHeader,
+    message: &mavlink::ardupilotmega::MavMessage,
+) -> Option<Vec<u8>> {
+    encode_frame(version(), header, message)
+}
+
+/// All valid frames with the configured MAVLink version found in the bytes
+pub fn decode(bytes: &[u8]) -> Vec<(mavlink::MavHeader, mavlink::ardupilotmega::MavMessage)> {
+    let version = version();
+    let mut reader = std::io::Cursor::new(bytes);
+    let mut frames = vec![];
+    while (reader.position() as usize) < bytes.len() {
+        match mavlink::read_versioned_msg(&mut reader, version) {
+            Ok(frame) => frames.push(frame),
+            Err(_) => break,
+        }
+    }
+    frames
+}
+
+pub fn is_enabled() -> bool {
+    RAW_FRAMES.lock().unwrap().size > 0
+}
+
+pub fn push(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let mut raw_frames = RAW_FRAMES.lock().unwrap();
+    let (size, version) = match (raw_frames.size, raw_frames.version) {
+        (0, _) | (_, None) => return,
+        (size, Some(version)) => (size, version),
+    };
+
+    let frame = match raw_frame(version, header, message) {
+        Some(frame) => frame,
+        None => return,
+    };
+
+    let frames = raw_frames
+        .frames
+        .entry(message.message_name().to_string())
+        .or_default();
+    if frames.len() >= size {
+        frames.pop_front();
+    }
+    frames.push_back(frame);
+}
+
+/// Last frames of the given message type, from the oldest to the newest
+pub fn frames(name: &str) -> Option<Vec<RawFrame>> {
+    let raw_frames = RAW_FRAMES.lock().unwrap();
+    raw_frames
+        .frames
+        .get(name)
+        .map(|frames| frames.iter().cloned().collect())
+}
@@ -0,0 +1,94 @@
+use std::sync::Mutex;
+
+use actix::{Actor, Addr, AsyncContext, Handler, StreamHandler};
+use actix_web_actors::ws;
+use lazy_static::lazy_static;
+use log::*;
+
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::raw_frames;
+use super::websocket_manager::BinaryMessage;
+
+lazy_static! {
+    static ref CLIENTS: Mutex<Vec<Addr<RawWebsocketActor>>> = Mutex::new(vec![]);
+}
+
+// Forward a received message as MAVLink bytes to all raw websocket clients
+pub fn send(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let clients = CLIENTS.lock().unwrap();
+    if clients.is_empty() {
+        return;
+    }
+
+    let bytes = match raw_frames::encode(header, message) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+
+    for client in clients.iter() {
+        client.do_send(BinaryMessage(bytes.clone()));
+    }
+}
+
+pub struct RawWebsocketActor {
+    vehicle: MAVLinkVehicleArcMutex,
+}
+
+impl RawWebsocketActor {
+    pub fn new(vehicle: MAVLinkVehicleArcMutex) -> Self {
+        Self { vehicle }
+    }
+}
+
+impl Actor for RawWebsocketActor {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl Handler<BinaryMessage> for RawWebsocketActor {
+    type Result = ();
+
+    fn handle(&mut self, message: BinaryMessage, context: &mut Self::Context) {
+        context.binary(message.0);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RawWebsocketActor {
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!("Starting raw websocket.");
+        CLIENTS.lock().unwrap().push(ctx.address());
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        debug!("Finishing raw websocket.");
+        CLIENTS
+            .lock()
+            .unwrap()
+            .retain(|client| *client != ctx.address());
+    }
+
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Binary(bytes)) => {
+                let frames = raw_frames::decode(&bytes);
+                if frames.is_empty() {
+                    warn!("Raw websocket frame does not contain valid MAVLink messages.");
+                    return;
+                }
+
+                let vehicle = self.vehicle.lock().unwrap();
+                for (header, message) in frames {
+                    if let Err(error) = vehicle.send(&header, &message) {
+                        warn!("Failed to send raw websocket message: {error:?}");
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Number of connected clients
+pub fn client_count() -> usize {
+    CLIENTS.lock().unwrap().len()
+}
@@ -0,0 +1,84 @@
+use std::sync::{mpsc, Mutex};
+
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::Message;
+
+use super::cli;
+use super::data::MAVLinkMessage;
+use super::output;
+
+lazy_static! {
+    // Messages are written by a dedicated thread, so Redis latency does not hold the main loop
+    static ref SENDER: Option<Mutex<mpsc::SyncSender<(String, String)>>> =
+        cli::redis_url().map(|url| {
+            let client = redis::Client::open(url.as_str())
+                .unwrap_or_else(|error| panic!("Invalid Redis url: {url}, {error}"));
+            let (sender, receiver) = mpsc::sync_channel(1000);
+            std::thread::spawn(move || run(client, receiver));
+            Mutex::new(sender)
+        });
+}
+
+fn run(client: redis::Client, receiver: mpsc::Receiver<(String, String)>) {
+    let mut connection = None;
+    for (key, payload) in receiver {
+        if connection.is_none() {
+            connection = match client.get_connection() {
+                Ok(connection) => Some(connection),
+                Err(error) => {
+                    warn!("Failed to connect to Redis: {error}");
+                    continue;
+                }
+            };
+        }
+
+        let result = redis::pipe()
+            .cmd("SET")
+            .arg(&key)
+            .arg(&payload)
+            .ignore()
+            .cmd("PUBLISH")
+            .arg(&key)
+            .arg(&payload)
+            .ignore()
+            .query::<()>(connection.as_mut().unwrap());
+
+        if let Err(error) = result {
+            warn!("Failed to send message to Redis: {error}");
+            connection = None;
+        }
+    }
+}
+
+pub fn init() {
+    lazy_static::initialize(&SENDER);
+}
+
+pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    let sender = match SENDER.as_ref() {
+        Some(sender) => sender,
+        None => return,
+    };
+
+    let key = format!(
+        "{}/vehicles/{}/components/{}/messages/{}",
+        cli::redis_prefix(),
+        message.header.system_id,
+        message.header.component_id,
+        message.message.message_name()
+    );
+
+    let mut value = output::to_value(&message.message);
+    output::apply(&mut value, &output::OutputOptions::global());
+
+    // Drop messages while Redis is not keeping up
+    if sender
+        .lock()
+        .unwrap()
+        .try_send((key, value.to_string()))
+        .is_err()
+    {
+        debug!("Redis output queue is full, dropping message.");
+    }
+}
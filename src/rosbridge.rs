@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix::{Actor, Addr, AsyncContext, Handler, StreamHandler};
+use actix_web_actors::ws;
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::Message;
+use serde_json::{json, Value};
+
+use super::data::{self, MAVLinkMessage};
+use super::definitions;
+use super::endpoints;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::output::{self, OutputOptions};
+use super::websocket_manager::StringMessage;
+
+// Each MAVLink message type is a topic, E.g: /mavlink/HEARTBEAT
+const TOPIC_PREFIX: &str = "/mavlink/";
+
+fn topic(name: &str) -> String {
+    format!("{TOPIC_PREFIX}{name}")
+}
+
+fn topic_type(name: &str) -> String {
+    format!("mavlink/{name}")
+}
+
+fn message_name(topic: &str) -> Option<&str> {
+    topic
+        .strip_prefix(TOPIC_PREFIX)
+        .filter(|name| definitions::message(name).is_some())
+}
+
+#[derive(Debug)]
+struct Subscription {
+    // Minimum time between messages of the topic, from the throttle_rate of the subscription
+    throttle: Duration,
+    last_sent: Option<Instant>,
+}
+
+struct Client {
+    actor: Addr<RosbridgeWebsocketActor>,
+    subscriptions: HashMap<String, Subscription>,
+}
+
+lazy_static! {
+    static ref CLIENTS: Mutex<Vec<Client>> = Mutex::new(vec![]);
+}
+
+/// Publish the message to the clients subscribed to its topic
+pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    let mut clients = CLIENTS.lock().unwrap();
+    if clients.is_empty() {
+        return;
+    }
+
+    let topic = topic(message.message.message_name());
+    let now = Instant::now();
+    let mut payload = None;
+    for client in clients.iter_mut() {
+        let subscription = match client.subscriptions.get_mut(&topic) {
+            Some(subscription) => subscription,
+            None => continue,
+        };
+        if subscription.last_sent.map_or(false, |last_sent| {
+            now.duration_since(last_sent) < subscription.throttle
+        }) {
+            continue;
+        }
+        subscription.last_sent = Some(now);
+
+        let payload = payload.get_or_insert_with(|| {
+            let mut value = output::to_value(message);
+            output::apply(&mut value, &OutputOptions::global());
+            json!({
+                "op": "publish",
+                "topic": topic,
+                "msg": value,
+            })
+            .to_string()
+        });
+        client.actor.do_send(StringMessage(payload.clone()));
+    }
+}
+
+fn status(id: &Value, level: &str, message: &str) -> Value {
+    let mut status = json!({
+        "op": "status",
+        "level": level,
+        "msg": message,
+    });
+    if !id.is_null() {
+        status["id"] = id.clone();
+    }
+    status
+}
+
+pub struct RosbridgeWebsocketActor {
+    vehicle: MAVLinkVehicleArcMutex,
+}
+
+impl RosbridgeWebsocketActor {
+    pub fn new(vehicle: MAVLinkVehicleArcMutex) -> Self {
+        Self { vehicle }
+    }
+
+    fn subscriptions<T>(
+        &self,
+        address: &Addr<Self>,
+        f: impl FnOnce(&mut HashMap<String, Subscription>) -> T,
+    ) -> Option<T> {
+        CLIENTS
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|client| client.actor == *address)
+            .map(|client| f(&mut client.subscriptions))
+    }
+
+    fn publish(&self, topic: &str, message: &Value) -> Result<(), String> {
+        let mut message = message.clone();
+        output::normalize_keys(&mut message);
+        let content = endpoints::parse_mavlink_message(&message.to_string())
+            .ok_or_else(|| format!("Could not convert the message published in {topic}"))?;
+
+        let name = content.message.message_name();
+        if topic != TOPIC_PREFIX.trim_end_matches('/') && message_name(topic) != Some(name) {
+            return Err(format!("Message {name} can not be published in {topic}"));
+        }
+
+        self.vehicle
+            .lock()
+            .unwrap()
+            .send(&content.header, &content.message)
+            .map_err(|error| format!("Failed to send message: {error:?}"))?;
+        data::update((content.header, content.message));
+        Ok(())
+    }
+
+    // Answer of the rosbridge v2 operation, if any
+    fn operation(&self, address: &Addr<Self>, operation: &Value) -> Option<Value> {
+        let id = &operation["id"];
+        let operation_topic = operation["topic"].as_str().unwrap_or_default();
+        match operation["op"].as_str().unwrap_or_default() {
+            "subscribe" => {
+                if message_name(operation_topic).is_none() {
+                    let error = format!("Unknown topic: {operation_topic}");
+                    return Some(status(id, "error", &error));
+                }
+                let throttle =
+                    Duration::from_millis(operation["throttle_rate"].as_u64().unwrap_or(0));
+                self.subscriptions(address, |subscriptions| {
+                    subscriptions.insert(
+                        operation_topic.to_string(),
+                        Subscription {
+                            throttle,
+                            last_sent: None,
+                        },
+                    )
+                });
+                None
+            }
+            "unsubscribe" => {
+                self.subscriptions(address, |subscriptions| {
+                    subscriptions.remove(operation_topic)
+                });
+                None
+            }
+            // Messages can be published without advertising the topic
+            "advertise" | "unadvertise" => None,
+            "publish" => match self.publish(operation_topic, &operation["msg"]) {
+                Ok(()) => None,
+                Err(error) => Some(status(id, "error", &error)),
+            },
+            "call_service" => {
+                let service = operation["service"].as_str().unwrap_or_default();
+                let mut response = json!({
+                    "op": "service_response",
+                    "service": service,
+                });
+                if !id.is_null() {
+                    response["id"] = id.clone();
+                }
+                // Topic discovery of roslibjs, E.g: Ros.getTopics
+                if service == "/rosapi/topics" {
+                    let names: Vec<String> = definitions::messages()
+                        .iter()
+                        .map(|message| message.name.clone())
+                        .collect();
+                    response["values"] = json!({
+                        "topics": names.iter().map(|name| topic(name)).collect::<Vec<_>>(),
+                        "types": names.iter().map(|name| topic_type(name)).collect::<Vec<_>>(),
+                    });
+                    response["result"] = json!(true);
+                } else {
+                    response["values"] = json!(format!("Unknown service: {service}"));
+                    response["result"] = json!(false);
+                }
+                Some(response)
+            }
+            op => Some(status(id, "error", &format!("Unsupported operation: {op}"))),
+        }
+    }
+}
+
+impl Actor for RosbridgeWebsocketActor {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl Handler<StringMessage> for RosbridgeWebsocketActor {
+    type Result = ();
+
+    fn handle(&mut self, message: StringMessage, context: &mut Self::Context) {
+        context.text(message.0);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RosbridgeWebsocketActor {
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!("Starting rosbridge websocket.");
+        CLIENTS.lock().unwrap().push(Client {
+            actor: ctx.address(),
+            subscriptions: HashMap::new(),
+        });
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        debug!("Finishing rosbridge websocket.");
+        CLIENTS
+            .lock()
+            .unwrap()
+            .retain(|client| client.actor != ctx.address());
+    }
+
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => {
+                let answer = match serde_json::from_str::<Value>(&text) {
+                    Ok(operation) => self.operation(&ctx.address(), &operation),
+                    Err(error) => Some(status(
+                        &Value::Null,
+                        "error",
+                        &format!("Invalid operation: {error}"),
+                    )),
+                };
+                if let Some(answer) = answer {
+                    ctx.text(answer.to_string());
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Number of connected clients
+pub fn client_count() -> usize {
+    CLIENTS.lock().unwrap().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topics() {
+        assert_eq!(topic("HEARTBEAT"), "/mavlink/HEARTBEAT");
+        assert_eq!(message_name("/mavlink/HEARTBEAT"), Some("HEARTBEAT"));
+        assert_eq!(message_name("/mavlink/UNKNOWN"), None);
+        assert_eq!(message_name("/chatter"), None);
+    }
+}
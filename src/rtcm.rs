@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use lazy_static::lazy_static;
+use mavlink::ardupilotmega::MavMessage;
+use serde::Deserialize;
+
+// Payload size of a GPS_RTCM_DATA message
+const FRAGMENT_SIZE: usize = 180;
+// Fragment id has two bits
+const MAX_FRAGMENTS: usize = 4;
+
+lazy_static! {
+    // Sequence id of the fragmented messages, 5 bits
+    static ref SEQUENCE: AtomicU8 = AtomicU8::new(0);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RtcmJson {
+    /// RTCM3 data encoded as base64
+    pub data: String,
+}
+
+fn rtcm_message(flags: u8, fragment: &[u8]) -> MavMessage {
+    let mut data = [0; FRAGMENT_SIZE];
+    data[..fragment.len()].copy_from_slice(fragment);
+    MavMessage::common(mavlink::common::MavMessage::GPS_RTCM_DATA(
+        mavlink::common::GPS_RTCM_DATA_DATA {
+            flags,
+            len: fragment.len() as u8,
+            data,
+        },
+    ))
+}
+
+fn fragment(data: &[u8], sequence: u8) -> Vec<MavMessage> {
+    if data.len() <= FRAGMENT_SIZE {
+        return vec![rtcm_message((sequence & 0x1f) << 3, data)];
+    }
+
+    data.chunks(FRAGMENT_SIZE)
+        .enumerate()
+        .map(|(id, fragment)| {
+            let flags = 1 | ((id as u8 & 0x03) << 1) | ((sequence & 0x1f) << 3);
+            rtcm_message(flags, fragment)
+        })
+        .collect()
+}
+
+/// Split RTCM data in GPS_RTCM_DATA messages, data bigger than a fragmented message
+/// is sent as consecutive messages since the GPS receives it as a stream
+pub fn messages(data: &[u8]) -> Vec<MavMessage> {
+    data.chunks(FRAGMENT_SIZE * MAX_FRAGMENTS)
+        .flat_map(|chunk| fragment(chunk, SEQUENCE.fetch_add(1, Ordering::Relaxed)))
+        .collect()
+}
+
+pub fn decode_base64(content: &str) -> Result<Vec<u8>, String> {
+    let json: RtcmJson = serde_json::from_str(content).map_err(|error| error.to_string())?;
+    base64::decode(json.data.trim()).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags_and_len(message: &MavMessage) -> (u8, u8) {
+        match message {
+            MavMessage::common(mavlink::common::MavMessage::GPS_RTCM_DATA(data)) => {
+                (data.flags, data.len)
+            }
+            _ => panic!("Not a GPS_RTCM_DATA message"),
+        }
+    }
+
+    #[test]
+    fn fragmentation() {
+        let single = fragment(&[0xd3; 100], 2);
+        assert_eq!(
+            single.iter().map(flags_and_len).collect::<Vec<_>>(),
+            vec![(2 << 3, 100)]
+        );
+
+        let fragmented = fragment(&[0xd3; 400], 3);
+        assert_eq!(
+            fragmented.iter().map(flags_and_len).collect::<Vec<_>>(),
+            vec![
+                (1 | 3 << 3, 180),
+                (1 | 1 << 1 | 3 << 3, 180),
+                (1 | 2 << 1 | 3 << 3, 40)
+            ]
+        );
+
+        assert_eq!(messages(&[0xd3; 1000]).len(), 6);
+        assert_eq!(decode_base64(r#"{"data": "0wA="}"#), Ok(vec![0xd3, 0x00]));
+    }
+}
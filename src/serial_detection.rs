@@ -0,0 +1,120 @@
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use log::*;
+
+const BAUD_RATES: &[u32] = &[115200, 57600, 921600, 500000, 230400];
+const PROBE_DURATION: Duration = Duration::from_millis(1500);
+
+pub fn list_ports() {
+    match serialport::available_ports() {
+        Ok(ports) if ports.is_empty() => println!("No serial ports found."),
+        Ok(ports) => {
+            for port in ports {
+                let description = match port.port_type {
+                    serialport::SerialPortType::UsbPort(info) => format!(
+                        "USB {:04x}:{:04x} {} {}",
+                        info.vid,
+                        info.pid,
+                        info.manufacturer.unwrap_or_default(),
+                        info.product.unwrap_or_default()
+                    ),
+                    serialport::SerialPortType::PciPort => "PCI".to_string(),
+                    serialport::SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+                    serialport::SerialPortType::Unknown => "Unknown".to_string(),
+                };
+                println!("{}\t{}", port.port_name, description.trim());
+            }
+        }
+        Err(error) => println!("Failed to list serial ports: {error}"),
+    }
+}
+
+// Check if an autopilot HEARTBEAT is received in the bytes, with either MAVLink version
+fn has_autopilot_heartbeat(bytes: &[u8]) -> bool {
+    [mavlink::MavlinkVersion::V2, mavlink::MavlinkVersion::V1]
+        .iter()
+        .any(|version| {
+            let mut reader = std::io::Cursor::new(bytes);
+            loop {
+                match mavlink::read_versioned_msg::<mavlink::ardupilotmega::MavMessage, _>(
+                    &mut reader,
+                    *version,
+                ) {
+                    Ok((
+                        _,
+                        mavlink::ardupilotmega::MavMessage::common(
+                            mavlink::common::MavMessage::HEARTBEAT(heartbeat),
+                        ),
+                    )) if heartbeat.autopilot
+                        != mavlink::common::MavAutopilot::MAV_AUTOPILOT_INVALID =>
+                    {
+                        return true
+                    }
+                    Err(mavlink::error::MessageReadError::Io(_)) => return false,
+                    _ => continue,
+                }
+            }
+        })
+}
+
+fn probe(port_name: &str, baud_rate: u32) -> bool {
+    let mut port = match serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()
+    {
+        Ok(port) => port,
+        Err(error) => {
+            debug!("Failed to open {port_name}: {error}");
+            return false;
+        }
+    };
+
+    let start = Instant::now();
+    let mut bytes = vec![];
+    let mut buffer = [0u8; 1024];
+    while start.elapsed() < PROBE_DURATION {
+        match port.read(&mut buffer) {
+            Ok(size) => bytes.extend_from_slice(&buffer[..size]),
+            Err(error) if error.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(_) => return false,
+        }
+        if has_autopilot_heartbeat(&bytes) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Connection string of the first serial port with an autopilot sending HEARTBEATs
+pub fn detect() -> Option<String> {
+    let ports = serialport::available_ports().unwrap_or_default();
+    for port in ports {
+        for baud_rate in BAUD_RATES {
+            debug!("Probing {} at {baud_rate}", port.port_name);
+            if probe(&port.port_name, *baud_rate) {
+                info!("Autopilot found at {} with {baud_rate}", port.port_name);
+                return Some(format!("serial:{}:{baud_rate}", port.port_name));
+            }
+        }
+    }
+
+    None
+}
+
+/// Resolve the connection string, running the auto-detection when it is 'auto'
+pub fn resolve(connection_string: &str) -> String {
+    if connection_string != "auto" {
+        return connection_string.to_string();
+    }
+
+    info!("Looking for an autopilot in the serial ports.");
+    loop {
+        if let Some(connection_string) = detect() {
+            return connection_string;
+        }
+        warn!("No autopilot found, trying again.");
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
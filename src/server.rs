@@ -1,14 +1,32 @@
+use std::sync::Mutex;
+
+use super::auth;
+use super::cli;
 use super::endpoints;
+use super::graphql;
+use super::ip_allowlist;
+use super::logger;
 use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::metrics;
+use super::network;
+use super::rate_limit;
+use super::systemd;
 
 use paperclip::actix::{web, OpenApiExt};
 
 use actix_cors::Cors;
 use actix_web::{
-    error::{ErrorBadRequest, JsonPayloadError},
+    dev::Service,
+    error::{
+        ErrorBadRequest, ErrorForbidden, ErrorTooManyRequests, ErrorUnauthorized, JsonPayloadError,
+    },
     rt::System,
     App, HttpRequest, HttpServer,
 };
+use futures::future::{ready, Either, FutureExt};
+use lazy_static::lazy_static;
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{AllowAnyAuthenticatedClient, NoClientAuth, RootCertStore, ServerConfig};
 
 use log::*;
 
@@ -20,39 +38,453 @@ fn json_error_handler(error: JsonPayloadError, _: &HttpRequest) -> actix_web::Er
     }
 }
 
-// Start REST API server with the desired address
-pub fn run(server_address: &str, mavlink_vehicle: &MAVLinkVehicleArcMutex) {
-    let server_address = server_address.to_string();
+// Build the CORS middleware from the allowed origins, any origin is allowed by default
+fn cors() -> Cors {
+    let origins = cli::cors_origins();
+    if origins.is_empty() || origins.iter().any(|origin| origin == "*") {
+        return Cors::permissive();
+    }
+
+    origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allow_any_method()
+        .allow_any_header()
+        .max_age(3600)
+}
+
+// Load certificate chain and private key (PKCS8 or RSA) from PEM files
+fn tls_config(cert_path: &str, key_path: &str) -> ServerConfig {
+    let open = |path: &str| {
+        std::io::BufReader::new(
+            std::fs::File::open(path).unwrap_or_else(|_| panic!("Failed to open file: {path}")),
+        )
+    };
+
+    let cert_chain = certs(&mut open(cert_path))
+        .unwrap_or_else(|_| panic!("Failed to parse TLS certificate: {cert_path}"));
+    let mut keys = pkcs8_private_keys(&mut open(key_path)).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open(key_path)).unwrap_or_default();
+    }
+    if keys.is_empty() {
+        panic!("No valid TLS private key found: {key_path}");
+    }
+
+    // Connections without a certificate signed by the client CA are refused in the handshake
+    let client_verifier = match cli::tls_client_ca() {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            match roots.add_pem_file(&mut open(&ca_path)) {
+                Ok((valid, _)) if valid > 0 => {}
+                _ => panic!("No valid TLS client CA certificate found: {ca_path}"),
+            }
+            AllowAnyAuthenticatedClient::new(roots)
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let mut config = ServerConfig::new(client_verifier);
+    config
+        .set_single_cert(cert_chain, keys.remove(0))
+        .expect("Invalid TLS certificate or key.");
+    config
+}
+
+// Routes documented in the OpenAPI specification
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route("/", web::get().to(endpoints::root))
+        .route(
+            r"/{filename:.*(\.html|\.js|\.css)}",
+            web::get().to(endpoints::root),
+        )
+        .route("/admin/reload", web::post().to(endpoints::admin_reload))
+        .route(
+            "/debug/reencoded/{name}",
+            web::get().to(endpoints::debug_reencoded),
+        )
+        .route("/export/track.gpx", web::get().to(endpoints::export_gpx))
+        .route("/export/track.kml", web::get().to(endpoints::export_kml))
+        .route("/export/{name}.csv", web::get().to(endpoints::export_csv))
+        .route("/geojson", web::get().to(endpoints::geojson))
+        .route("/grafana", web::get().to(endpoints::grafana_root))
+        .route("/grafana/search", web::post().to(endpoints::grafana_search))
+        .route("/grafana/query", web::post().to(endpoints::grafana_query))
+        .route(
+            "/helper/attitude",
+            web::get().to(endpoints::helper_attitude),
+        )
+        .route(
+            "/helper/autopilot",
+            web::get().to(endpoints::helper_autopilot),
+        )
+        .route(
+            "/helper/batteries",
+            web::get().to(endpoints::helper_batteries),
+        )
+        .route(
+            "/helper/camera/information",
+            web::get().to(endpoints::camera_information),
+        )
+        .route(
+            "/helper/camera/settings",
+            web::get().to(endpoints::camera_settings),
+        )
+        .route(
+            "/helper/camera/capture",
+            web::post().to(endpoints::camera_capture),
+        )
+        .route(
+            "/helper/camera/video/start",
+            web::post().to(endpoints::camera_video_start),
+        )
+        .route(
+            "/helper/camera/video/stop",
+            web::post().to(endpoints::camera_video_stop),
+        )
+        .route(
+            "/helper/calibration",
+            web::post().to(endpoints::helper_calibration),
+        )
+        .route(
+            "/helper/commands/pending",
+            web::get().to(endpoints::commands_pending),
+        )
+        .route(
+            "/helper/commands/{id}",
+            web::get().to(endpoints::command_status),
+        )
+        .route(
+            "/helper/component_information",
+            web::get().to(endpoints::helper_component_information),
+        )
+        .route("/helper/derived", web::get().to(endpoints::helper_derived))
+        .route("/helper/ekf", web::get().to(endpoints::helper_ekf))
+        .route("/helper/escs", web::get().to(endpoints::helper_escs))
+        .route("/helper/events", web::get().to(endpoints::helper_events))
+        .route("/helper/fence", web::get().to(endpoints::fence_download))
+        .route("/helper/fence", web::post().to(endpoints::fence_upload))
+        .route("/helper/gimbal", web::get().to(endpoints::gimbal_status))
+        .route("/helper/gimbal", web::post().to(endpoints::gimbal_control))
+        .route("/helper/goto", web::post().to(endpoints::helper_goto))
+        .route("/helper/health", web::get().to(endpoints::helper_health))
+        .route("/helper/home", web::get().to(endpoints::home_position))
+        .route("/helper/home", web::post().to(endpoints::home_position_set))
+        .route("/helper/hud", web::get().to(endpoints::helper_hud))
+        .route("/helper/link", web::get().to(endpoints::helper_link))
+        .route("/helper/mavlink", web::get().to(endpoints::helper_mavlink))
+        .route("/helper/mode", web::get().to(endpoints::mode_status))
+        .route("/helper/mode", web::post().to(endpoints::mode_set))
+        .route(
+            "/helper/motor_test",
+            web::post().to(endpoints::helper_motor_test),
+        )
+        .route(
+            "/helper/parameters/export",
+            web::get().to(endpoints::parameters_export),
+        )
+        .route(
+            "/helper/parameters/import",
+            web::post().to(endpoints::parameters_import),
+        )
+        .route("/helper/plan", web::get().to(endpoints::plan_download))
+        .route("/helper/plan", web::post().to(endpoints::plan_upload))
+        .route("/helper/rally", web::get().to(endpoints::rally_download))
+        .route("/helper/rally", web::post().to(endpoints::rally_upload))
+        .route("/helper/reboot", web::post().to(endpoints::helper_reboot))
+        .route("/helper/rtcm", web::post().to(endpoints::helper_rtcm))
+        .route("/helper/setpoint", web::get().to(endpoints::setpoint_list))
+        .route(
+            "/helper/setpoint/start",
+            web::post().to(endpoints::setpoint_start),
+        )
+        .route(
+            "/helper/setpoint/stop",
+            web::post().to(endpoints::setpoint_stop),
+        )
+        .route(
+            "/helper/shutdown",
+            web::post().to(endpoints::helper_shutdown),
+        )
+        .route(
+            "/helper/timesync",
+            web::get().to(endpoints::helper_timesync),
+        )
+        .route("/helper/traffic", web::get().to(endpoints::helper_traffic))
+        .route("/helper/tunnel", web::post().to(endpoints::helper_tunnel))
+        .route(
+            "/helper/traffic.geojson",
+            web::get().to(endpoints::helper_traffic_geojson),
+        )
+        .route(
+            "/helper/request/{name}",
+            web::get().to(endpoints::helper_request),
+        )
+        .route(
+            "/helper/message_info/{name}",
+            web::get().to(endpoints::helper_message_info),
+        )
+        .route("/info", web::get().to(endpoints::info))
+        .route("/status", web::get().to(endpoints::status))
+        .route("/stats/server", web::get().to(endpoints::stats_server))
+        .route("/metrics", web::get().to(endpoints::prometheus_metrics))
+        .route(
+            "/protobuf/mavlink.proto",
+            web::get().to(endpoints::protobuf_schema),
+        )
+        .route(
+            "/schema/mavlink/{name}",
+            web::get().to(endpoints::mavlink_schema),
+        )
+        .route("/mavlink", web::get().to(endpoints::mavlink))
+        .route("/mavlink", web::post().to(endpoints::mavlink_post))
+        .route(
+            "/mavlink/vehicles",
+            web::get().to(endpoints::mavlink_vehicles),
+        )
+        .route(r"/mavlink/{path:.*}", web::get().to(endpoints::mavlink))
+        .service(web::resource("/ws/mavlink").route(web::get().to(endpoints::websocket)))
+        .route(
+            "/sse/calibration",
+            web::get().to(endpoints::calibration_sse),
+        )
+        .route("/sse/mavlink", web::get().to(endpoints::mavlink_sse))
+        .service(web::resource("/ws/raw").route(web::get().to(endpoints::websocket_raw)))
+        .service(
+            web::resource("/ws/rosbridge").route(web::get().to(endpoints::websocket_rosbridge)),
+        )
+        .service(web::resource("/ws/shell").route(web::get().to(endpoints::websocket_shell)))
+        .service(web::resource("/ws/tunnel").route(web::get().to(endpoints::websocket_tunnel)));
+}
+
+fn graphql_routes(config: &mut actix_web::web::ServiceConfig) {
+    config
+        .route("/graphql", actix_web::web::get().to(graphql::playground))
+        .route("/graphql", actix_web::web::post().to(graphql::graphql))
+        .route(
+            "/ws/graphql",
+            actix_web::web::get().to(graphql::graphql_websocket),
+        );
+}
+
+// Start REST API server with the desired addresses
+pub fn run(server_addresses: &[String], mavlink_vehicle: &MAVLinkVehicleArcMutex) {
     let mavlink_vehicle = mavlink_vehicle.clone();
-    println!("Server running: http://{server_address}");
+    let tls_files = cli::tls_files();
+    let graphql_schema = graphql::schema();
+    let url_prefix = cli::url_prefix();
 
     // Start HTTP server thread
     let _ = System::new("http-server");
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(Cors::permissive())
+            .wrap_fn(|request, service| {
+                if auth::is_authorized(&request) {
+                    Either::Left(service.call(request))
+                } else {
+                    warn!("Unauthorized request: {}", request.path());
+                    Either::Right(ready(Ok(
+                        request.error_response(ErrorUnauthorized("Missing or invalid API token."))
+                    )))
+                }
+            })
+            .wrap_fn(|request, service| {
+                if rate_limit::is_allowed(&request) {
+                    Either::Left(service.call(request))
+                } else {
+                    debug!("Rate limited request: {}", request.path());
+                    Either::Right(ready(Ok(
+                        request.error_response(ErrorTooManyRequests("Too many requests."))
+                    )))
+                }
+            })
+            .wrap_fn(|request, service| {
+                if ip_allowlist::is_allowed(&request) {
+                    Either::Left(service.call(request))
+                } else {
+                    warn!(
+                        "Request from a client not allowed: {:?}",
+                        request.peer_addr()
+                    );
+                    Either::Right(ready(Ok(
+                        request.error_response(ErrorForbidden("Client address not allowed."))
+                    )))
+                }
+            })
+            .wrap(cors())
+            .wrap_fn(|request, service| {
+                let start = std::time::Instant::now();
+                service.call(request).map(move |response| {
+                    if let Ok(response) = &response {
+                        let request = response.request();
+                        let status = response.status().as_u16();
+                        metrics::record_request(request, status, start.elapsed());
+                        // Access log output is controlled by the logger filter
+                        if log_enabled!(target: logger::ACCESS_LOG_TARGET, Level::Info) {
+                            info!(
+                                target: logger::ACCESS_LOG_TARGET,
+                                "{}",
+                                access_log_line(request, status, start.elapsed())
+                            );
+                        }
+                    }
+                    response
+                })
+            })
             // Record services and routes for paperclip OpenAPI plugin for Actix.
             .wrap_api()
-            //TODO Add middle man to print all http events
             .data(web::JsonConfig::default().error_handler(json_error_handler))
             .data(mavlink_vehicle.clone())
-            //TODO: Add cors
-            .route("/", web::get().to(endpoints::root))
-            .with_json_spec_at("/docs.json")
-            .with_swagger_ui_at("/docs")
-            .route(
-                r"/{filename:.*(\.html|\.js|\.css)}",
-                web::get().to(endpoints::root),
-            )
-            .route("/helper/mavlink", web::get().to(endpoints::helper_mavlink))
-            .route("/info", web::get().to(endpoints::info))
-            .route("/mavlink", web::get().to(endpoints::mavlink))
-            .route("/mavlink", web::post().to(endpoints::mavlink_post))
-            .route(r"/mavlink/{path:.*}", web::get().to(endpoints::mavlink))
-            .service(web::resource("/ws/mavlink").route(web::get().to(endpoints::websocket)))
+            .with_json_spec_at(&format!("{url_prefix}/docs.json"))
+            .with_swagger_ui_at(&format!("{url_prefix}/docs"))
+            .configure(|config| match url_prefix.as_str() {
+                "" => routes(config),
+                url_prefix => {
+                    // Relative links of the frontend require the trailing slash
+                    config.route(url_prefix, web::get().to(endpoints::redirect_to_root));
+                    config.service(web::scope(url_prefix).configure(routes));
+                }
+            })
             .build()
-    })
-    .bind(server_address)
-    .unwrap()
-    .run();
+            // GraphQL has its own introspection, keep it out of the OpenAPI specification
+            .data(graphql_schema.clone())
+            .configure(|config| match url_prefix.as_str() {
+                "" => graphql_routes(config),
+                url_prefix => {
+                    config.service(actix_web::web::scope(url_prefix).configure(graphql_routes));
+                }
+            })
+    });
+
+    let activated_listeners = systemd::listeners();
+    if !activated_listeners.is_empty() {
+        info!(
+            "Server running on {} sockets from systemd activation",
+            activated_listeners.len()
+        );
+        let server = activated_listeners
+            .into_iter()
+            .try_fold(server, |server, listener| match listener {
+                systemd::ActivatedListener::Tcp(listener) => match &tls_files {
+                    Some((cert_path, key_path)) => {
+                        server.listen_rustls(listener, tls_config(cert_path, key_path))
+                    }
+                    None => server.listen(listener),
+                },
+                #[cfg(unix)]
+                systemd::ActivatedListener::Unix(listener) => server.listen_uds(listener),
+            });
+        server.unwrap().run();
+        return;
+    }
+
+    // All listeners share the same application state
+    let server = server_addresses
+        .iter()
+        .try_fold(server, |server, server_address| {
+            match server_address.strip_prefix("unix:") {
+                #[cfg(unix)]
+                Some(path) => {
+                    if tls_files.is_some() {
+                        warn!("TLS is not used with unix domain sockets.");
+                    }
+                    remove_unix_socket(path);
+                    UNIX_SOCKETS.lock().unwrap().push(path.into());
+                    info!("Server running: {server_address}");
+                    server.bind_uds(path)
+                }
+                #[cfg(not(unix))]
+                Some(_) => panic!("Unix domain sockets are not supported in this platform."),
+                None => {
+                    let listener = network::tcp_listener(server_address).unwrap_or_else(|error| {
+                        panic!("Failed to bind server {server_address}: {error}")
+                    });
+                    match &tls_files {
+                        Some((cert_path, key_path)) => {
+                            info!("Server running: https://{server_address}");
+                            server.listen_rustls(listener, tls_config(cert_path, key_path))
+                        }
+                        None => {
+                            info!("Server running: http://{server_address}");
+                            server.listen(listener)
+                        }
+                    }
+                }
+            }
+        });
+    server.unwrap().run();
+}
+
+// Client, request and result, the token of query authenticated requests is not written
+fn access_log_line(request: &HttpRequest, status: u16, elapsed: std::time::Duration) -> String {
+    let query: Vec<&str> = request
+        .query_string()
+        .split('&')
+        .filter(|parameter| !parameter.is_empty() && !parameter.starts_with("token="))
+        .collect();
+    let target = if query.is_empty() {
+        request.path().to_string()
+    } else {
+        format!("{}?{}", request.path(), query.join("&"))
+    };
+    let client = request
+        .peer_addr()
+        .map_or("-".to_string(), |address| address.to_string());
+    format!(
+        r#"{client} "{} {target} {:?}" {status} {:.3}ms"#,
+        request.method(),
+        request.version(),
+        elapsed.as_secs_f64() * 1000.0
+    )
+}
+
+lazy_static! {
+    static ref UNIX_SOCKETS: Mutex<Vec<std::path::PathBuf>> = Mutex::new(vec![]);
+}
+
+// Remove the socket file, like the ones left by a previous run, but never other files
+#[cfg(unix)]
+fn remove_unix_socket(path: impl AsRef<std::path::Path>) {
+    use std::os::unix::fs::FileTypeExt;
+
+    let path = path.as_ref();
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            if let Err(error) = std::fs::remove_file(path) {
+                warn!("Failed to remove unix socket {path:?}: {error}");
+            }
+        }
+    }
+}
+
+/// Remove the unix domain sockets created by the server, should be called before exiting
+pub fn cleanup() {
+    #[cfg(unix)]
+    for path in UNIX_SOCKETS.lock().unwrap().drain(..) {
+        remove_unix_socket(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn access_log_without_token() {
+        let request = TestRequest::with_uri("/mavlink?token=secret&system_id=1")
+            .peer_addr("192.168.2.2:51000".parse().unwrap())
+            .to_http_request();
+        let line = access_log_line(&request, 200, std::time::Duration::from_millis(2));
+        assert!(!line.contains("secret"), "{line}");
+        assert_eq!(
+            line,
+            r#"192.168.2.2:51000 "GET /mavlink?system_id=1 HTTP/1.1" 200 2.000ms"#
+        );
+
+        let request = TestRequest::with_uri("/ws/mavlink?token=secret").to_http_request();
+        let line = access_log_line(&request, 101, std::time::Duration::from_millis(1));
+        assert_eq!(line, r#"- "GET /ws/mavlink HTTP/1.1" 101 1.000ms"#);
+    }
 }
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::*;
+use serde::Serialize;
+
+use super::guided;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+
+// Resolution of the stream rates
+const TICK: Duration = Duration::from_millis(10);
+pub const DEFAULT_RATE_HZ: f32 = 10.0;
+// PX4 leaves offboard mode when setpoints are slower than 2 Hz
+pub const MIN_RATE_HZ: f32 = 2.0;
+pub const MAX_RATE_HZ: f32 = 50.0;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Position(guided::Position),
+    /// North, east and down velocity in m/s
+    Velocity {
+        vx: f32,
+        vy: f32,
+        vz: f32,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Setpoint {
+    pub system_id: u8,
+    pub component_id: u8,
+    pub target: Target,
+    /// Heading in degrees
+    pub yaw: Option<f32>,
+    pub rate_hz: f32,
+    pub updated: DateTime<Utc>,
+    #[serde(skip)]
+    next_send: Instant,
+}
+
+lazy_static! {
+    static ref SETPOINTS: Mutex<BTreeMap<(u8, u8), Setpoint>> = Mutex::new(BTreeMap::new());
+}
+
+impl Setpoint {
+    fn message(&self) -> mavlink::ardupilotmega::MavMessage {
+        match self.target {
+            Target::Position(position) => {
+                guided::set_position_target(self.system_id, self.component_id, &position, self.yaw)
+            }
+            Target::Velocity { vx, vy, vz } => guided::set_velocity_target(
+                self.system_id,
+                self.component_id,
+                (vx, vy, vz),
+                self.yaw,
+            ),
+        }
+    }
+}
+
+fn stream(vehicle: MAVLinkVehicleArcMutex) {
+    loop {
+        std::thread::sleep(TICK);
+
+        let now = Instant::now();
+        let messages: Vec<_> = SETPOINTS
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|setpoint| setpoint.next_send <= now)
+            .map(|setpoint| {
+                setpoint.next_send = now + Duration::from_secs_f32(1.0 / setpoint.rate_hz);
+                setpoint.message()
+            })
+            .collect();
+
+        if messages.is_empty() {
+            continue;
+        }
+        let vehicle = vehicle.lock().unwrap();
+        for message in &messages {
+            if let Err(error) = vehicle.send_default(message) {
+                debug!("Failed to send setpoint: {error:?}");
+            }
+        }
+    }
+}
+
+pub fn init(vehicle: &MAVLinkVehicleArcMutex) {
+    let vehicle = vehicle.clone();
+    std::thread::spawn(move || stream(vehicle));
+}
+
+/// Start streaming the target to the vehicle, replacing its current setpoint
+pub fn start(system_id: u8, component_id: u8, target: Target, yaw: Option<f32>, rate_hz: f32) {
+    let rate_hz = rate_hz.clamp(MIN_RATE_HZ, MAX_RATE_HZ);
+    info!("Streaming setpoints to {system_id}/{component_id} at {rate_hz} Hz: {target:?}");
+    SETPOINTS.lock().unwrap().insert(
+        (system_id, component_id),
+        Setpoint {
+            system_id,
+            component_id,
+            target,
+            yaw,
+            rate_hz,
+            updated: Utc::now(),
+            next_send: Instant::now(),
+        },
+    );
+}
+
+/// Stop streaming to the vehicle, returns false if there was no stream
+pub fn stop(system_id: u8, component_id: u8) -> bool {
+    let stopped = SETPOINTS
+        .lock()
+        .unwrap()
+        .remove(&(system_id, component_id))
+        .is_some();
+    if stopped {
+        info!("Stopped streaming setpoints to {system_id}/{component_id}");
+    }
+    stopped
+}
+
+/// Setpoints being streamed
+pub fn setpoints() -> Vec<Setpoint> {
+    SETPOINTS.lock().unwrap().values().cloned().collect()
+}
@@ -0,0 +1,162 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix::{Actor, Addr, AsyncContext, Handler, StreamHandler};
+use actix_web_actors::ws;
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::common::{SerialControlDev, SerialControlFlag, SERIAL_CONTROL_DATA};
+
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::websocket_manager::BinaryMessage;
+
+// Payload size of a SERIAL_CONTROL message
+const CHUNK_SIZE: usize = 70;
+// Time the vehicle waits for more output before answering
+const REPLY_TIMEOUT_MS: u16 = 10;
+// The vehicle only sends the shell output as answers, so it is polled while the client is connected
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+lazy_static! {
+    static ref CLIENTS: Mutex<Vec<(u8, Addr<ShellWebsocketActor>)>> = Mutex::new(vec![]);
+}
+
+fn serial_control(flags: SerialControlFlag, data: &[u8]) -> mavlink::ardupilotmega::MavMessage {
+    let mut payload = [0; CHUNK_SIZE];
+    payload[..data.len()].copy_from_slice(data);
+    mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::SERIAL_CONTROL(
+        SERIAL_CONTROL_DATA {
+            baudrate: 0,
+            timeout: REPLY_TIMEOUT_MS,
+            device: SerialControlDev::SERIAL_CONTROL_DEV_SHELL,
+            flags,
+            count: data.len() as u8,
+            data: payload,
+        },
+    ))
+}
+
+fn shell_flags() -> SerialControlFlag {
+    SerialControlFlag::SERIAL_CONTROL_FLAG_RESPOND
+        | SerialControlFlag::SERIAL_CONTROL_FLAG_EXCLUSIVE
+}
+
+/// Messages to write the input in the shell, the input is split in payload sized chunks
+pub fn input_messages(input: &[u8]) -> Vec<mavlink::ardupilotmega::MavMessage> {
+    input
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| serial_control(shell_flags(), chunk))
+        .collect()
+}
+
+// Forward the shell output of a vehicle to its clients
+pub fn send(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let output = match message {
+        mavlink::ardupilotmega::MavMessage::common(
+            mavlink::common::MavMessage::SERIAL_CONTROL(output),
+        ) => output,
+        _ => return,
+    };
+
+    if output.device != SerialControlDev::SERIAL_CONTROL_DEV_SHELL
+        || !output
+            .flags
+            .contains(SerialControlFlag::SERIAL_CONTROL_FLAG_REPLY)
+        || output.count == 0
+    {
+        return;
+    }
+
+    let count = (output.count as usize).min(CHUNK_SIZE);
+    for (system_id, client) in CLIENTS.lock().unwrap().iter() {
+        if *system_id == header.system_id {
+            client.do_send(BinaryMessage(output.data[..count].to_vec()));
+        }
+    }
+}
+
+pub struct ShellWebsocketActor {
+    vehicle: MAVLinkVehicleArcMutex,
+    system_id: u8,
+}
+
+impl ShellWebsocketActor {
+    pub fn new(vehicle: MAVLinkVehicleArcMutex, system_id: u8) -> Self {
+        Self { vehicle, system_id }
+    }
+
+    fn write(&self, messages: &[mavlink::ardupilotmega::MavMessage]) {
+        let vehicle = self.vehicle.lock().unwrap();
+        for message in messages {
+            if let Err(error) = vehicle.send_default(message) {
+                warn!("Failed to send shell input: {error:?}");
+            }
+        }
+    }
+}
+
+impl Actor for ShellWebsocketActor {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl Handler<BinaryMessage> for ShellWebsocketActor {
+    type Result = ();
+
+    fn handle(&mut self, message: BinaryMessage, context: &mut Self::Context) {
+        context.binary(message.0);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ShellWebsocketActor {
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!("Starting shell websocket for vehicle {}.", self.system_id);
+        CLIENTS
+            .lock()
+            .unwrap()
+            .push((self.system_id, ctx.address()));
+
+        ctx.run_interval(POLL_INTERVAL, |actor, _ctx| {
+            actor.write(&[serial_control(shell_flags(), &[])]);
+        });
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        debug!("Finishing shell websocket for vehicle {}.", self.system_id);
+        CLIENTS
+            .lock()
+            .unwrap()
+            .retain(|(_, client)| *client != ctx.address());
+
+        // Release the exclusive access to the shell
+        self.write(&[serial_control(SerialControlFlag::empty(), &[])]);
+    }
+
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => self.write(&input_messages(text.as_bytes())),
+            Ok(ws::Message::Binary(bytes)) => self.write(&input_messages(&bytes)),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_chunking() {
+        let messages = input_messages(&[b'a'; 150]);
+        let counts: Vec<u8> = messages
+            .iter()
+            .map(|message| match message {
+                mavlink::ardupilotmega::MavMessage::common(
+                    mavlink::common::MavMessage::SERIAL_CONTROL(data),
+                ) => data.count,
+                _ => panic!("Not a SERIAL_CONTROL message"),
+            })
+            .collect();
+        assert_eq!(counts, vec![70, 70, 10]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures::channel::mpsc::{self, Sender};
+use futures::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::Message;
+use regex::Regex;
+
+use super::data::MAVLinkMessage;
+use super::history;
+use super::metrics;
+use super::output::{self, OutputOptions};
+use super::throttle::Decimator;
+
+// Proxies usually drop connections without traffic after 30 or 60 seconds
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// Events waiting to be sent to a client, slower clients are disconnected and resume with Last-Event-ID
+const CLIENT_QUEUE_SIZE: usize = 1000;
+
+struct Client {
+    sender: Sender<Bytes>,
+    filter: Option<Regex>,
+    // Slow clients can drop the updates faster than their rates, without affecting others
+    decimator: Decimator,
+}
+
+impl Client {
+    // Clients that are gone or not keeping up are removed
+    fn push(&mut self, bytes: Bytes) -> bool {
+        match self.sender.try_send(bytes) {
+            Ok(()) => true,
+            Err(error) => {
+                if error.is_full() {
+                    warn!("Disconnecting SSE client, {CLIENT_QUEUE_SIZE} events are waiting");
+                }
+                false
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref CLIENTS: Mutex<Vec<Client>> = Mutex::new(vec![]);
+    // Event identifiers restart with the process, the start time tells them apart
+    static ref EPOCH: i64 = chrono::Utc::now().timestamp_millis();
+}
+
+static EVENT_ID: AtomicU64 = AtomicU64::new(0);
+
+pub fn init() {
+    lazy_static::initialize(&EPOCH);
+    std::thread::spawn(|| loop {
+        std::thread::sleep(KEEP_ALIVE_INTERVAL);
+        let mut clients = CLIENTS.lock().unwrap();
+        clients.retain_mut(|client| client.push(Bytes::from_static(b": keep-alive\n\n")));
+    });
+}
+
+/// Identifier of the next message, shared by the SSE stream and the history
+pub fn next_event_id() -> u64 {
+    EVENT_ID.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Identifier of an event of this process, from the SSE `id` field, E.g: Last-Event-ID
+pub fn parse_event_id(text: &str) -> Option<u64> {
+    let (epoch, id) = text.trim().split_once('-')?;
+    if epoch.parse::<i64>().ok()? != *EPOCH {
+        return None;
+    }
+    id.parse().ok()
+}
+
+fn event(id: u64, message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) -> Bytes {
+    let start = std::time::Instant::now();
+    let mut value = output::to_value(message);
+    output::apply(&mut value, &OutputOptions::global());
+    let event = Bytes::from(format!("id: {}-{id}\ndata: {value}\n\n", *EPOCH));
+    metrics::record_serialization(start.elapsed());
+    event
+}
+
+fn is_match(filter: &Option<Regex>, name: &str) -> bool {
+    filter.as_ref().map_or(true, |filter| filter.is_match(name))
+}
+
+pub fn send(id: u64, message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
+    let mut clients = CLIENTS.lock().unwrap();
+    if clients.is_empty() {
+        return;
+    }
+
+    let name = message.message.message_name();
+    let header = &message.header;
+    let mut bytes = None;
+    clients.retain_mut(|client| {
+        if !is_match(&client.filter, name)
+            || !client
+                .decimator
+                .should_send(header.system_id, header.component_id, name)
+        {
+            return !client.sender.is_closed();
+        }
+        let bytes = bytes.get_or_insert_with(|| event(id, message));
+        client.push(bytes.clone())
+    });
+}
+
+// Messages in the history after last_event_id, with the filter and rates of the client
+fn replay(
+    last_event_id: Option<&str>,
+    filter: &Option<Regex>,
+    decimator: &mut Decimator,
+) -> Vec<Bytes> {
+    let last_event_id = match last_event_id {
+        Some(last_event_id) => last_event_id,
+        None => return vec![],
+    };
+    if !history::is_enabled() {
+        return vec![Bytes::from_static(
+            b": events are not replayed, resuming streams requires --history\n\n",
+        )];
+    }
+    let id = match parse_event_id(last_event_id) {
+        Some(id) => id,
+        None => {
+            return vec![Bytes::from(format!(
+                ": event {last_event_id:?} is not from this server run, it is not replayed\n\n"
+            ))]
+        }
+    };
+
+    let entries = history::since(id);
+    debug!("Replaying {} SSE events after {id}", entries.len());
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let name = entry.message.message_name();
+            is_match(filter, name)
+                && decimator.should_send_at(
+                    entry.header.system_id,
+                    entry.header.component_id,
+                    name,
+                    None,
+                    entry.instant,
+                )
+        })
+        .map(|entry| {
+            let message = MAVLinkMessage {
+                header: entry.header,
+                message: entry.message,
+            };
+            event(entry.id, &message)
+        })
+        .collect()
+}
+
+/// New stream, replaying the messages in the history after last_event_id
+pub fn subscribe(
+    filter: Option<Regex>,
+    last_event_id: Option<&str>,
+    mut decimator: Decimator,
+) -> impl Stream<Item = Bytes> {
+    let (sender, receiver) = mpsc::channel(CLIENT_QUEUE_SIZE);
+
+    // Holding the clients avoids sending new messages before the replayed ones
+    let mut clients = CLIENTS.lock().unwrap();
+    let replayed = replay(last_event_id, &filter, &mut decimator);
+    clients.push(Client {
+        sender,
+        filter,
+        decimator,
+    });
+    futures::stream::iter(replayed).chain(receiver)
+}
+
+/// Number of connected clients
+pub fn client_count() -> usize {
+    CLIENTS.lock().unwrap().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_ids_of_this_run() {
+        let id = next_event_id();
+        assert_eq!(parse_event_id(&format!("{}-{id}", *EPOCH)), Some(id));
+        assert_eq!(parse_event_id(&format!(" {}-{id}\n", *EPOCH)), Some(id));
+        // Identifiers of a previous run, or without the epoch, are not replayed
+        assert!(parse_event_id(&format!("{}-{id}", *EPOCH - 1)).is_none());
+        assert!(parse_event_id(&id.to_string()).is_none());
+    }
+
+    #[test]
+    fn stalled_client() {
+        // The stream is never read, like a client that stopped reading
+        let _stream = subscribe(
+            Some(Regex::new("^HEARTBEAT$").unwrap()),
+            None,
+            Decimator::default(),
+        );
+        let message = MAVLinkMessage {
+            header: mavlink::MavHeader::default(),
+            message: mavlink::ardupilotmega::MavMessage::common(
+                mavlink::common::MavMessage::HEARTBEAT(Default::default()),
+            ),
+        };
+        for _ in 0..CLIENT_QUEUE_SIZE {
+            send(next_event_id(), &message);
+        }
+        assert_eq!(client_count(), 1);
+        // Instead of queueing more events, the client is disconnected
+        for _ in 0..10 {
+            send(next_event_id(), &message);
+        }
+        assert_eq!(client_count(), 0);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use log::*;
+
+use super::cli;
+use super::definitions;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+
+// Without heartbeats during this time, the vehicle probably rebooted or the link was lost
+const HEARTBEAT_GAP: Duration = Duration::from_secs(5);
+
+// Sequences restarting below this value, from further than it, are considered a reboot instead of reordered messages
+const SEQUENCE_RESET_WINDOW: u8 = 16;
+
+// Last state of each autopilot, to notice when it reboots
+struct AutopilotState {
+    last_heartbeat: Instant,
+    system_status: mavlink::common::MavState,
+    sequence: u8,
+}
+
+lazy_static! {
+    static ref AUTOPILOTS: Mutex<HashMap<(u8, u8), AutopilotState>> = Mutex::new(HashMap::new());
+}
+
+// Lost messages only move the sequence forward, a reboot restarts it from 0.
+// Reboots after more than half of the sequence look like lost messages, the heartbeat gap covers them
+fn is_sequence_reset(previous: u8, sequence: u8) -> bool {
+    let backwards = previous.wrapping_sub(sequence);
+    sequence < SEQUENCE_RESET_WINDOW && backwards > SEQUENCE_RESET_WINDOW && backwards < 128
+}
+
+impl AutopilotState {
+    fn new(
+        header: &mavlink::MavHeader,
+        heartbeat: &mavlink::common::HEARTBEAT_DATA,
+        now: Instant,
+    ) -> Self {
+        Self {
+            last_heartbeat: now,
+            system_status: heartbeat.system_status,
+            sequence: header.sequence,
+        }
+    }
+
+    // Returns true when the autopilot probably rebooted or the link was recovered
+    fn update(
+        &mut self,
+        header: &mavlink::MavHeader,
+        heartbeat: Option<&mavlink::common::HEARTBEAT_DATA>,
+        now: Instant,
+    ) -> bool {
+        let is_sequence_reset = is_sequence_reset(self.sequence, header.sequence);
+        self.sequence = header.sequence;
+        let heartbeat = match heartbeat {
+            Some(heartbeat) => heartbeat,
+            None => return is_sequence_reset,
+        };
+
+        let is_heartbeat_gap = now.duration_since(self.last_heartbeat) > HEARTBEAT_GAP;
+        self.last_heartbeat = now;
+        let previous_status = std::mem::replace(&mut self.system_status, heartbeat.system_status);
+        let is_booting = previous_status != heartbeat.system_status
+            && matches!(
+                heartbeat.system_status,
+                mavlink::common::MavState::MAV_STATE_BOOT
+                    | mavlink::common::MavState::MAV_STATE_STANDBY
+            );
+        is_sequence_reset || is_heartbeat_gap || is_booting
+    }
+}
+
+// Parse STREAM:RATE, where the stream is a MAV_DATA_STREAM name, with or without prefix, or id
+fn parse_stream_rate(stream_rate: &str) -> Option<(u8, u16)> {
+    let (stream, rate) = stream_rate.split_once(':')?;
+    let rate = rate.trim().parse::<u16>().ok()?;
+    let stream = stream.trim();
+
+    if let Ok(id) = stream.parse::<u8>() {
+        return Some((id, rate));
+    }
+
+    let name = if stream.starts_with("MAV_DATA_STREAM_") {
+        stream.to_string()
+    } else {
+        format!("MAV_DATA_STREAM_{stream}")
+    };
+    let id = definitions::enumeration("MAV_DATA_STREAM")?.value_of(&name)?;
+    Some((id as u8, rate))
+}
+
+fn stream_rates() -> Vec<(u8, u16)> {
+    cli::stream_rates()
+        .iter()
+        .filter_map(|stream_rate| {
+            let parsed = parse_stream_rate(stream_rate);
+            if parsed.is_none() {
+                warn!("Invalid stream rate: {stream_rate}, expected STREAM:RATE, E.g: POSITION:10");
+            }
+            parsed
+        })
+        .collect()
+}
+
+fn request_streams(vehicle: &MAVLinkVehicleArcMutex, header: &mavlink::MavHeader) {
+    let vehicle = vehicle.lock().unwrap();
+    for (stream_id, rate) in stream_rates() {
+        info!(
+            "Requesting stream {stream_id} at {rate}Hz from vehicle {}",
+            header.system_id
+        );
+        let message = mavlink::ardupilotmega::MavMessage::common(
+            mavlink::common::MavMessage::REQUEST_DATA_STREAM(
+                mavlink::common::REQUEST_DATA_STREAM_DATA {
+                    target_system: header.system_id,
+                    target_component: header.component_id,
+                    req_stream_id: stream_id,
+                    req_message_rate: rate,
+                    start_stop: if rate > 0 { 1 } else { 0 },
+                },
+            ),
+        );
+        if let Err(error) = vehicle.send_default(&message) {
+            warn!("Failed to request stream {stream_id}: {error:?}");
+        }
+    }
+}
+
+/// Request the configured streams when an autopilot appears, reconnects or reboots
+pub fn on_message(
+    vehicle: &MAVLinkVehicleArcMutex,
+    header: &mavlink::MavHeader,
+    message: &mavlink::ardupilotmega::MavMessage,
+) {
+    if cli::is_no_stream_request() {
+        return;
+    }
+    let heartbeat = match message {
+        mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::HEARTBEAT(
+            heartbeat,
+        )) if heartbeat.autopilot != mavlink::common::MavAutopilot::MAV_AUTOPILOT_INVALID => {
+            Some(heartbeat)
+        }
+        _ => None,
+    };
+
+    let now = Instant::now();
+    let should_request = {
+        let mut autopilots = AUTOPILOTS.lock().unwrap();
+        let key = (header.system_id, header.component_id);
+        match (autopilots.get_mut(&key), heartbeat) {
+            (Some(autopilot), heartbeat) => autopilot.update(header, heartbeat, now),
+            (None, Some(heartbeat)) => {
+                autopilots.insert(key, AutopilotState::new(header, heartbeat, now));
+                true
+            }
+            // Messages of other components
+            (None, None) => false,
+        }
+    };
+
+    if should_request {
+        request_streams(vehicle, header);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_rate_parsing() {
+        assert_eq!(parse_stream_rate("ALL:10"), Some((0, 10)));
+        assert_eq!(
+            parse_stream_rate("MAV_DATA_STREAM_POSITION:5"),
+            Some((6, 5))
+        );
+        assert_eq!(parse_stream_rate("12:2"), Some((12, 2)));
+        assert_eq!(parse_stream_rate("UNKNOWN:2"), None);
+        assert_eq!(parse_stream_rate("ALL"), None);
+    }
+
+    #[test]
+    fn reboot_detection() {
+        let header = |sequence| mavlink::MavHeader {
+            system_id: 1,
+            component_id: 1,
+            sequence,
+        };
+        let heartbeat = |system_status| mavlink::common::HEARTBEAT_DATA {
+            system_status,
+            ..Default::default()
+        };
+        let active = heartbeat(mavlink::common::MavState::MAV_STATE_ACTIVE);
+        let start = Instant::now();
+        let after = |seconds| start + Duration::from_secs(seconds);
+        let mut autopilot = AutopilotState::new(&header(100), &active, start);
+
+        assert!(!autopilot.update(&header(101), Some(&active), after(1)));
+        // Lost and reordered messages
+        assert!(!autopilot.update(&header(150), None, after(1)));
+        assert!(!autopilot.update(&header(148), None, after(1)));
+        assert!(!autopilot.update(&header(3), None, after(1)));
+        // The sequence restarts after a quick reboot
+        assert!(!autopilot.update(&header(60), None, after(1)));
+        assert!(autopilot.update(&header(0), None, after(2)));
+
+        let booting = heartbeat(mavlink::common::MavState::MAV_STATE_BOOT);
+        let standby = heartbeat(mavlink::common::MavState::MAV_STATE_STANDBY);
+        assert!(autopilot.update(&header(1), Some(&booting), after(2)));
+        assert!(autopilot.update(&header(2), Some(&standby), after(3)));
+        assert!(!autopilot.update(&header(3), Some(&standby), after(4)));
+
+        // A link lost for longer than the heartbeat gap
+        assert!(autopilot.update(&header(4), Some(&standby), after(10)));
+    }
+}
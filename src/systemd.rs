@@ -0,0 +1,95 @@
+use log::*;
+
+// First file descriptor passed by systemd socket activation, after stdin, stdout and stderr
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+pub enum ActivatedListener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+fn is_for_this_process(variable: &str) -> bool {
+    std::env::var(variable)
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .map_or(false, |pid| pid == std::process::id())
+}
+
+/// Listeners passed by systemd socket activation, E.g: from a mavlink2rest.socket unit
+#[cfg(unix)]
+pub fn listeners() -> Vec<ActivatedListener> {
+    use std::os::unix::io::FromRawFd;
+
+    if !is_for_this_process("LISTEN_PID") {
+        return vec![];
+    }
+
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    // Avoid using the same descriptors twice
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .filter_map(|fd| {
+            // The descriptors are owned by this process after activation
+            let socket = unsafe { socket2::Socket::from_raw_fd(fd) };
+            match socket.local_addr() {
+                Ok(address) if address.as_socket().is_some() => {
+                    Some(ActivatedListener::Tcp(socket.into()))
+                }
+                Ok(_) => Some(ActivatedListener::Unix(socket.into())),
+                Err(error) => {
+                    warn!("Invalid socket from systemd activation ({fd}): {error}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn listeners() -> Vec<ActivatedListener> {
+    vec![]
+}
+
+/// Send a state to the service manager, E.g: READY=1
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(path) = std::env::var_os("NOTIFY_SOCKET") {
+        let result = std::os::unix::net::UnixDatagram::unbound()
+            .and_then(|socket| socket.send_to(state.as_bytes(), &path));
+        if let Err(error) = result {
+            debug!("Failed to notify systemd with {state}: {error}");
+        }
+    }
+}
+
+/// Start sending watchdog notifications when the service has WatchdogSec configured
+pub fn init() {
+    let watchdog_usec = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok());
+
+    let watchdog_usec = match watchdog_usec {
+        Some(usec)
+            if is_for_this_process("WATCHDOG_PID") || std::env::var("WATCHDOG_PID").is_err() =>
+        {
+            usec
+        }
+        _ => return,
+    };
+
+    // Notify at half of the timeout, as recommended by systemd
+    let period = std::time::Duration::from_micros(watchdog_usec / 2);
+    info!("Systemd watchdog enabled, notifying every {period:?}.");
+    std::thread::spawn(move || loop {
+        notify("WATCHDOG=1");
+        std::thread::sleep(period);
+    });
+}
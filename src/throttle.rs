@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use log::*;
+
+use super::cli;
+use super::data;
+
+// Limit how often each message type of each component passes, messages arriving faster than that are skipped
+#[derive(Debug, Default)]
+pub struct Decimator {
+    default_period: Option<Duration>,
+    periods: HashMap<String, Duration>,
+    last_sent: HashMap<(u8, u8, String), Instant>,
+}
+
+fn period(frequency: f32) -> Option<Duration> {
+    if frequency > 0.0 && frequency.is_finite() {
+        return Some(Duration::from_secs_f32(1.0 / frequency));
+    }
+    None
+}
+
+impl Decimator {
+    /// Maximum rate in Hz of all message types, and of specific message types by name
+    pub fn new(max_rate_hz: Option<f32>, max_rates: &HashMap<String, f32>) -> Self {
+        Self {
+            default_period: max_rate_hz.and_then(period),
+            periods: max_rates
+                .iter()
+                .filter_map(|(name, frequency)| Some((name.clone(), period(*frequency)?)))
+                .collect(),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    pub fn should_send(&mut self, system_id: u8, component_id: u8, name: &str) -> bool {
+        self.should_send_at(system_id, component_id, name, None, Instant::now())
+    }
+
+    /// Messages stored by their name or index inside the type are limited by each of them
+    pub fn should_send_at(
+        &mut self,
+        system_id: u8,
+        component_id: u8,
+        name: &str,
+        entry_key: Option<&str>,
+        now: Instant,
+    ) -> bool {
+        let period = match self.periods.get(name).or(self.default_period.as_ref()) {
+            Some(period) => *period,
+            None => return true,
+        };
+
+        let entry = match entry_key {
+            Some(entry_key) => format!("{name}/{entry_key}"),
+            None => name.to_string(),
+        };
+        let key = (system_id, component_id, entry);
+        if let Some(last_sent) = self.last_sent.get(&key) {
+            if now.duration_since(*last_sent) < period {
+                return false;
+            }
+        }
+
+        self.last_sent.insert(key, now);
+        true
+    }
+}
+
+// Parse NAME:FREQUENCY rates, invalid ones are ignored
+pub fn parse_rates<S: AsRef<str>>(rates: &[S]) -> HashMap<String, f32> {
+    rates
+        .iter()
+        .filter_map(|rate| {
+            let rate = rate.as_ref();
+            let parsed = rate.split_once(':').and_then(|(name, frequency)| {
+                let frequency = frequency.trim().parse::<f32>().ok()?;
+                period(frequency)?;
+                Some((name.trim().to_string(), frequency))
+            });
+
+            if parsed.is_none() {
+                error!("Invalid maximum rate: {rate}, it should be in the format NAME:FREQUENCY");
+            }
+
+            parsed
+        })
+        .collect()
+}
+
+fn from_cli() -> Decimator {
+    Decimator::new(None, &parse_rates(&cli::max_store_rates()))
+}
+
+lazy_static! {
+    static ref THROTTLE: Mutex<Decimator> = Mutex::new(from_cli());
+}
+
+pub fn init() {
+    lazy_static::initialize(&THROTTLE);
+    cli::on_reload(|| *THROTTLE.lock().unwrap() = from_cli());
+}
+
+pub fn should_store(
+    header: &mavlink::MavHeader,
+    message: &mavlink::ardupilotmega::MavMessage,
+) -> bool {
+    THROTTLE.lock().unwrap().should_send_at(
+        header.system_id,
+        header.component_id,
+        mavlink::Message::message_name(message),
+        data::message_key(message).as_deref(),
+        Instant::now(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_caps() {
+        let start = Instant::now();
+        let after = |milliseconds| start + Duration::from_millis(milliseconds);
+        let mut decimator = Decimator::new(None, &parse_rates(&["ATTITUDE:10", "GPS_RAW_INT:0"]));
+
+        // 10Hz, messages less than 100ms after the last one sent are skipped
+        assert!(decimator.should_send_at(1, 1, "ATTITUDE", None, after(0)));
+        assert!(!decimator.should_send_at(1, 1, "ATTITUDE", None, after(50)));
+        assert!(decimator.should_send_at(1, 1, "ATTITUDE", None, after(120)));
+        assert!(!decimator.should_send_at(1, 1, "ATTITUDE", None, after(150)));
+        // Each component has its own limit
+        assert!(decimator.should_send_at(1, 2, "ATTITUDE", None, after(150)));
+
+        // Unlisted and invalid rates are untouched
+        for milliseconds in 0..5 {
+            assert!(decimator.should_send_at(1, 1, "HEARTBEAT", None, after(milliseconds)));
+            assert!(decimator.should_send_at(1, 1, "GPS_RAW_INT", None, after(milliseconds)));
+        }
+
+        // The reload replaces the caps and their history
+        decimator = Decimator::new(None, &parse_rates(&["HEARTBEAT:1"]));
+        assert!(decimator.should_send_at(1, 1, "ATTITUDE", None, after(160)));
+        assert!(decimator.should_send_at(1, 1, "ATTITUDE", None, after(170)));
+        assert!(decimator.should_send_at(1, 1, "HEARTBEAT", None, after(170)));
+        assert!(!decimator.should_send_at(1, 1, "HEARTBEAT", None, after(180)));
+
+        // Each name of a keyed message has its own limit
+        let mut decimator = Decimator::new(None, &parse_rates(&["NAMED_VALUE_FLOAT:1"]));
+        for name in ["depth", "temperature", "voltage"] {
+            assert!(decimator.should_send_at(1, 1, "NAMED_VALUE_FLOAT", Some(name), after(0)));
+            assert!(!decimator.should_send_at(1, 1, "NAMED_VALUE_FLOAT", Some(name), after(10)));
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use log::*;
+use serde::Serialize;
+
+use super::cli;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+
+// Weight of the newest offset in the moving average
+const OFFSET_SMOOTHING: f64 = 0.2;
+// Number of pending requests used to match the responses
+const PENDING_REQUESTS: usize = 10;
+// Bigger time_usec values are already UNIX time (after 2001), not time since boot
+const UNIX_TIME_THRESHOLD_US: u64 = 1_000_000_000_000_000;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Timesync {
+    system_id: u8,
+    /// Vehicle clock minus host UTC clock, in nanoseconds
+    offset_ns: i64,
+    /// Round trip time of the last exchange
+    round_trip_ms: f64,
+    /// UTC time when the vehicle clock started, usually the boot time
+    boot_time: DateTime<Utc>,
+    last_update: DateTime<Utc>,
+    samples: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pending_requests: VecDeque<i64>,
+    vehicles: BTreeMap<u8, Timesync>,
+    // Offsets from SYSTEM_TIME, used while there is no TIMESYNC estimate
+    system_time_offsets: BTreeMap<u8, i64>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+fn now_ns() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as i64)
+}
+
+fn timesync_message(tc1: i64, ts1: i64) -> mavlink::ardupilotmega::MavMessage {
+    mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::TIMESYNC(
+        mavlink::common::TIMESYNC_DATA { tc1, ts1 },
+    ))
+}
+
+// Periodically start exchanges with all vehicles
+fn request_loop(vehicle: MAVLinkVehicleArcMutex, interval: std::time::Duration) {
+    loop {
+        std::thread::sleep(interval);
+
+        let ts1 = now_ns();
+        {
+            let mut state = STATE.lock().unwrap();
+            if state.pending_requests.len() >= PENDING_REQUESTS {
+                state.pending_requests.pop_front();
+            }
+            state.pending_requests.push_back(ts1);
+        }
+
+        if let Err(error) = vehicle
+            .lock()
+            .unwrap()
+            .send_default(&timesync_message(0, ts1))
+        {
+            debug!("Failed to send TIMESYNC: {error:?}");
+        }
+    }
+}
+
+pub fn init(vehicle: &MAVLinkVehicleArcMutex) {
+    if let Some(interval) = cli::timesync_interval() {
+        let vehicle = vehicle.clone();
+        std::thread::spawn(move || request_loop(vehicle, interval));
+    }
+}
+
+/// Answer TIMESYNC requests and update the offsets with the responses and SYSTEM_TIME
+pub fn on_message(
+    vehicle: &MAVLinkVehicleArcMutex,
+    header: &mavlink::MavHeader,
+    message: &mavlink::ardupilotmega::MavMessage,
+) {
+    match message {
+        mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::TIMESYNC(
+            timesync,
+        )) => on_timesync(vehicle, header, timesync),
+        mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::SYSTEM_TIME(
+            system_time,
+        )) => on_system_time(header, system_time),
+        _ => {}
+    }
+}
+
+fn on_system_time(header: &mavlink::MavHeader, system_time: &mavlink::common::SYSTEM_TIME_DATA) {
+    // The vehicle has no UNIX time source yet
+    if system_time.time_unix_usec == 0 {
+        return;
+    }
+
+    let offset_ns =
+        (system_time.time_boot_ms as i64 - (system_time.time_unix_usec / 1000) as i64) * 1_000_000;
+    STATE
+        .lock()
+        .unwrap()
+        .system_time_offsets
+        .insert(header.system_id, offset_ns);
+}
+
+fn on_timesync(
+    vehicle: &MAVLinkVehicleArcMutex,
+    header: &mavlink::MavHeader,
+    timesync: &mavlink::common::TIMESYNC_DATA,
+) {
+    let now = now_ns();
+    if timesync.tc1 == 0 {
+        if let Err(error) = vehicle
+            .lock()
+            .unwrap()
+            .send_default(&timesync_message(now, timesync.ts1))
+        {
+            debug!("Failed to answer TIMESYNC: {error:?}");
+        }
+        return;
+    }
+
+    let mut state = STATE.lock().unwrap();
+    if !state.pending_requests.contains(&timesync.ts1) {
+        return;
+    }
+
+    // The vehicle answered in the middle of the round trip
+    let round_trip_ns = now - timesync.ts1;
+    let offset_ns = timesync.tc1 - (timesync.ts1 + round_trip_ns / 2);
+
+    let entry = state
+        .vehicles
+        .entry(header.system_id)
+        .or_insert_with(|| Timesync {
+            system_id: header.system_id,
+            offset_ns,
+            round_trip_ms: 0.0,
+            boot_time: Utc::now(),
+            last_update: Utc::now(),
+            samples: 0,
+        });
+
+    // A big jump means that the vehicle rebooted
+    let is_jump = (offset_ns - entry.offset_ns).abs() > 1_000_000_000;
+    if entry.samples == 0 || is_jump {
+        entry.offset_ns = offset_ns;
+    } else {
+        entry.offset_ns += (OFFSET_SMOOTHING * (offset_ns - entry.offset_ns) as f64) as i64;
+    }
+    entry.round_trip_ms = round_trip_ns as f64 / 1e6;
+    entry.boot_time = Utc.timestamp_nanos(-entry.offset_ns);
+    entry.last_update = Utc::now();
+    entry.samples += 1;
+}
+
+pub fn states() -> Vec<Timesync> {
+    STATE.lock().unwrap().vehicles.values().cloned().collect()
+}
+
+/// Convert a time since the vehicle clock started to UTC
+pub fn vehicle_time_to_utc(system_id: u8, time_us: u64) -> Option<DateTime<Utc>> {
+    let state = STATE.lock().unwrap();
+    let offset_ns = state
+        .vehicles
+        .get(&system_id)
+        .map(|timesync| timesync.offset_ns)
+        .or_else(|| state.system_time_offsets.get(&system_id).cloned())?;
+    Some(Utc.timestamp_nanos((time_us as i64) * 1000 - offset_ns))
+}
+
+/// UTC time of messages with time_boot_ms or time_usec fields
+pub fn message_utc_time(
+    system_id: u8,
+    message: &mavlink::ardupilotmega::MavMessage,
+) -> Option<DateTime<Utc>> {
+    let value = serde_json::to_value(message).ok()?;
+    if let Some(time_boot_ms) = value.get("time_boot_ms").and_then(|value| value.as_u64()) {
+        return vehicle_time_to_utc(system_id, time_boot_ms * 1000);
+    }
+
+    let time_usec = value.get("time_usec").and_then(|value| value.as_u64())?;
+    if time_usec >= UNIX_TIME_THRESHOLD_US {
+        return Some(Utc.timestamp_nanos((time_usec * 1000) as i64));
+    }
+    vehicle_time_to_utc(system_id, time_usec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_time_offset() {
+        let header = mavlink::MavHeader {
+            system_id: 42,
+            component_id: 1,
+            sequence: 0,
+        };
+        on_system_time(
+            &header,
+            &mavlink::common::SYSTEM_TIME_DATA {
+                time_unix_usec: 1_614_600_000_000_000,
+                time_boot_ms: 10_000,
+            },
+        );
+
+        let attitude = mavlink::ardupilotmega::MavMessage::common(
+            mavlink::common::MavMessage::ATTITUDE(mavlink::common::ATTITUDE_DATA {
+                time_boot_ms: 11_000,
+                ..Default::default()
+            }),
+        );
+        assert_eq!(
+            message_utc_time(42, &attitude),
+            Some(Utc.timestamp_nanos(1_614_600_001_000_000_000))
+        );
+        assert_eq!(message_utc_time(43, &attitude), None);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::output;
+
+// Aircraft without reports for longer than this are removed from the table
+const TRAFFIC_TIMEOUT_S: i64 = 60;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Aircraft {
+    icao_address: u32,
+    callsign: String,
+    /// Position in degrees and altitude in meters
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    altitude_type: Value,
+    /// Course over ground in degrees
+    heading: f64,
+    /// Velocities in m/s, vertical is positive up
+    horizontal_velocity: f64,
+    vertical_velocity: f64,
+    squawk: u64,
+    emitter_type: Value,
+    /// Vehicle that reported the aircraft
+    system_id: u8,
+    last_update: DateTime<Utc>,
+    /// Time since the last report, filled when requested
+    age_ms: i64,
+}
+
+lazy_static! {
+    static ref TRAFFIC: Mutex<BTreeMap<u32, Aircraft>> = Mutex::new(BTreeMap::new());
+}
+
+fn callsign(value: &Value) -> String {
+    let bytes: Vec<u8> = value
+        .as_array()
+        .map(|characters| {
+            characters
+                .iter()
+                .filter_map(|character| character.as_u64())
+                .map(|character| character as u8)
+                .collect()
+        })
+        .unwrap_or_default();
+    String::from_utf8_lossy(&bytes)
+        .trim_end_matches('\0')
+        .trim()
+        .to_string()
+}
+
+fn aircraft(system_id: u8, report: &Value) -> Option<Aircraft> {
+    Some(Aircraft {
+        icao_address: report["ICAO_address"].as_u64()? as u32,
+        callsign: callsign(&report["callsign"]),
+        latitude: report["lat"].as_f64()? / 1e7,
+        longitude: report["lon"].as_f64()? / 1e7,
+        altitude: report["altitude"].as_f64()? / 1e3,
+        altitude_type: report["altitude_type"].clone(),
+        heading: report["heading"].as_f64()? / 100.0,
+        horizontal_velocity: report["hor_velocity"].as_f64()? / 100.0,
+        vertical_velocity: report["ver_velocity"].as_f64()? / 100.0,
+        squawk: report["squawk"].as_u64()?,
+        emitter_type: report["emitter_type"].clone(),
+        system_id,
+        last_update: Utc::now(),
+        age_ms: 0,
+    })
+}
+
+fn prune(traffic: &mut BTreeMap<u32, Aircraft>, now: DateTime<Utc>) {
+    traffic.retain(|_, aircraft| (now - aircraft.last_update).num_seconds() < TRAFFIC_TIMEOUT_S);
+}
+
+/// Add ADSB_VEHICLE reports to the traffic table
+pub fn update(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    if !matches!(
+        message,
+        mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::ADSB_VEHICLE(_))
+    ) {
+        return;
+    }
+
+    if let Some(aircraft) = aircraft(header.system_id, &output::to_value(message)) {
+        let mut traffic = TRAFFIC.lock().unwrap();
+        prune(&mut traffic, aircraft.last_update);
+        traffic.insert(aircraft.icao_address, aircraft);
+    }
+}
+
+/// Aircraft reported recently
+pub fn aircrafts() -> Vec<Aircraft> {
+    let now = Utc::now();
+    let mut traffic = TRAFFIC.lock().unwrap();
+    prune(&mut traffic, now);
+    traffic
+        .values()
+        .cloned()
+        .map(|mut aircraft| {
+            aircraft.age_ms = (now - aircraft.last_update).num_milliseconds();
+            aircraft
+        })
+        .collect()
+}
+
+/// Aircraft as a GeoJSON FeatureCollection
+pub fn feature_collection() -> Value {
+    let features: Vec<Value> = aircrafts()
+        .into_iter()
+        .map(|aircraft| {
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [aircraft.longitude, aircraft.latitude, aircraft.altitude],
+                },
+                "properties": aircraft,
+            })
+        })
+        .collect();
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_parsing() {
+        let report = json!({
+            "ICAO_address": 11259375,
+            "lat": -275000000,
+            "lon": -485000000,
+            "altitude": 1500000,
+            "altitude_type": {"type": "ADSB_ALTITUDE_TYPE_GEOMETRIC"},
+            "heading": 9000,
+            "hor_velocity": 12000,
+            "ver_velocity": -250,
+            "squawk": 7000,
+            "emitter_type": {"type": "ADSB_EMITTER_TYPE_LIGHT"},
+            "callsign": [80, 80, 45, 77, 65, 86, 32, 0, 0],
+        });
+
+        let aircraft = aircraft(1, &report).unwrap();
+        assert_eq!(aircraft.callsign, "PP-MAV");
+        assert!((aircraft.latitude + 27.5).abs() < 1e-9);
+        assert!((aircraft.altitude - 1500.0).abs() < 1e-9);
+        assert!((aircraft.heading - 90.0).abs() < 1e-9);
+        assert!((aircraft.vertical_velocity + 2.5).abs() < 1e-9);
+    }
+}
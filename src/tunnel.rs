@@ -0,0 +1,240 @@
+use std::sync::Mutex;
+
+use actix::{Actor, Addr, AsyncContext, Handler, StreamHandler};
+use actix_web_actors::ws;
+use lazy_static::lazy_static;
+use log::*;
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::common::{MavTunnelPayloadType, TUNNEL_DATA};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::definitions;
+use super::mavlink_vehicle::MAVLinkVehicleArcMutex;
+use super::websocket_manager::StringMessage;
+
+// Payload size of a TUNNEL message
+const PAYLOAD_SIZE: usize = 128;
+const PAYLOAD_TYPE_PREFIX: &str = "MAV_TUNNEL_PAYLOAD_TYPE_";
+
+lazy_static! {
+    static ref CLIENTS: Mutex<
+        Vec<(
+            Target,
+            Option<MavTunnelPayloadType>,
+            Addr<TunnelWebsocketActor>
+        )>,
+    > = Mutex::new(vec![]);
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Target {
+    pub system_id: u8,
+    pub component_id: u8,
+}
+
+/// Payload in the format of POST /helper/tunnel and of the websocket frames
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Payload {
+    /// MAV_TUNNEL_PAYLOAD_TYPE name, with or without the prefix, or value
+    pub payload_type: String,
+    /// Payload encoded as base64
+    pub payload: String,
+}
+
+/// MAV_TUNNEL_PAYLOAD_TYPE by name, E.g: STORM32_RESERVED0, or by value
+pub fn payload_type(name: &str) -> Result<MavTunnelPayloadType, String> {
+    let name = name.trim();
+    let entry = match name.parse::<u64>() {
+        Ok(value) => definitions::enumeration("MAV_TUNNEL_PAYLOAD_TYPE")
+            .and_then(|enumeration| enumeration.name_of(value))
+            .map(str::to_string),
+        Err(_) if name.starts_with(PAYLOAD_TYPE_PREFIX) => Some(name.to_string()),
+        Err(_) => Some(format!("{PAYLOAD_TYPE_PREFIX}{name}")),
+    };
+    entry
+        .and_then(|entry| serde_json::from_value(json!({ "type": entry })).ok())
+        .ok_or_else(|| format!("Unknown tunnel payload type: {name}"))
+}
+
+/// TUNNEL messages with the payload, split in message sized chunks
+pub fn messages(
+    target: Target,
+    payload_type: MavTunnelPayloadType,
+    payload: &[u8],
+) -> Vec<MavMessage> {
+    payload
+        .chunks(PAYLOAD_SIZE)
+        .map(|chunk| {
+            let mut data = [0; PAYLOAD_SIZE];
+            data[..chunk.len()].copy_from_slice(chunk);
+            MavMessage::common(mavlink::common::MavMessage::TUNNEL(TUNNEL_DATA {
+                payload_type,
+                target_system: target.system_id,
+                target_component: target.component_id,
+                payload_length: chunk.len() as u8,
+                payload: data,
+            }))
+        })
+        .collect()
+}
+
+/// Messages of a payload in the JSON format
+pub fn decode(target: Target, payload: &Payload) -> Result<Vec<MavMessage>, String> {
+    let payload_type = payload_type(&payload.payload_type)?;
+    let bytes = base64::decode(payload.payload.trim()).map_err(|error| error.to_string())?;
+    Ok(messages(target, payload_type, &bytes))
+}
+
+// Forward the tunnel payloads of a vehicle to the clients of its payload type
+pub fn send(header: &mavlink::MavHeader, message: &MavMessage) {
+    let tunnel = match message {
+        MavMessage::common(mavlink::common::MavMessage::TUNNEL(tunnel)) => tunnel,
+        _ => return,
+    };
+
+    let length = (tunnel.payload_length as usize).min(PAYLOAD_SIZE);
+    let frame = json!({
+        "system_id": header.system_id,
+        "component_id": header.component_id,
+        "payload_type": format!("{:?}", tunnel.payload_type),
+        "payload": base64::encode(&tunnel.payload[..length]),
+    })
+    .to_string();
+
+    for (target, payload_type, client) in CLIENTS.lock().unwrap().iter() {
+        let is_source = target.system_id == header.system_id
+            && (target.component_id == 0 || target.component_id == header.component_id);
+        if is_source
+            && payload_type.map_or(true, |payload_type| payload_type == tunnel.payload_type)
+        {
+            client.do_send(StringMessage(frame.clone()));
+        }
+    }
+}
+
+pub struct TunnelWebsocketActor {
+    vehicle: MAVLinkVehicleArcMutex,
+    target: Target,
+    payload_type: Option<MavTunnelPayloadType>,
+}
+
+impl TunnelWebsocketActor {
+    pub fn new(
+        vehicle: MAVLinkVehicleArcMutex,
+        target: Target,
+        payload_type: Option<MavTunnelPayloadType>,
+    ) -> Self {
+        Self {
+            vehicle,
+            target,
+            payload_type,
+        }
+    }
+
+    fn write(
+        &self,
+        messages: Result<Vec<MavMessage>, String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let messages = match messages {
+            Ok(messages) => messages,
+            Err(error) => {
+                ctx.text(json!({ "error": error }).to_string());
+                return;
+            }
+        };
+        let vehicle = self.vehicle.lock().unwrap();
+        for message in &messages {
+            if let Err(error) = vehicle.send_default(message) {
+                warn!("Failed to send tunnel payload: {error:?}");
+            }
+        }
+    }
+}
+
+impl Actor for TunnelWebsocketActor {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl Handler<StringMessage> for TunnelWebsocketActor {
+    type Result = ();
+
+    fn handle(&mut self, message: StringMessage, context: &mut Self::Context) {
+        context.text(message.0);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for TunnelWebsocketActor {
+    fn started(&mut self, ctx: &mut Self::Context) {
+        debug!("Starting tunnel websocket for {:?}.", self.target);
+        CLIENTS
+            .lock()
+            .unwrap()
+            .push((self.target, self.payload_type, ctx.address()));
+    }
+
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        debug!("Finishing tunnel websocket for {:?}.", self.target);
+        CLIENTS
+            .lock()
+            .unwrap()
+            .retain(|(_, _, client)| *client != ctx.address());
+    }
+
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => {
+                let result = serde_json::from_str::<Payload>(&text)
+                    .map_err(|error| error.to_string())
+                    .and_then(|payload| decode(self.target, &payload));
+                self.write(result, ctx);
+            }
+            // Binary frames are raw payloads of the payload type of the websocket
+            Ok(ws::Message::Binary(bytes)) => {
+                let result = self
+                    .payload_type
+                    .map(|payload_type| messages(self.target, payload_type, &bytes))
+                    .ok_or_else(|| {
+                        "Binary payloads require the payload_type of the websocket".to_string()
+                    });
+                self.write(result, ctx);
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_chunking() {
+        let target = Target {
+            system_id: 1,
+            component_id: 1,
+        };
+        let storm32 = MavTunnelPayloadType::MAV_TUNNEL_PAYLOAD_TYPE_STORM32_RESERVED0;
+        let lengths: Vec<u8> = messages(target, storm32, &[7; 300])
+            .iter()
+            .map(|message| match message {
+                MavMessage::common(mavlink::common::MavMessage::TUNNEL(data)) => {
+                    assert_eq!(data.payload_type, storm32);
+                    data.payload_length
+                }
+                _ => panic!("Not a TUNNEL message"),
+            })
+            .collect();
+        assert_eq!(lengths, vec![128, 128, 44]);
+
+        assert_eq!(payload_type("STORM32_RESERVED0"), Ok(storm32));
+        assert_eq!(
+            payload_type("MAV_TUNNEL_PAYLOAD_TYPE_STORM32_RESERVED0"),
+            Ok(storm32)
+        );
+        assert_eq!(payload_type("200"), Ok(storm32));
+        assert!(payload_type("NOT_A_TYPE").is_err());
+    }
+}
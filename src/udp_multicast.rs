@@ -0,0 +1,98 @@
+use std::io::Cursor;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+
+use mavlink::error::{MessageReadError, MessageWriteError};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::network;
+
+// MAVLink over a UDP multicast group, messages are received from and sent to the group
+pub struct UdpMulticastConnection {
+    socket: UdpSocket,
+    group: SocketAddr,
+    // Datagrams can contain multiple messages
+    reader: Mutex<Cursor<Vec<u8>>>,
+    version: mavlink::MavlinkVersion,
+}
+
+pub fn connect(
+    address: &str,
+    interface: Ipv4Addr,
+    ttl: u32,
+) -> std::io::Result<UdpMulticastConnection> {
+    let invalid_input =
+        |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+
+    let group = network::socket_address(address).map_err(|_| {
+        invalid_input(format!(
+            "Invalid multicast address: {address}, expected GROUP:PORT"
+        ))
+    })?;
+    if !group.ip().is_multicast() {
+        return Err(invalid_input(format!(
+            "{} is not a multicast address",
+            group.ip()
+        )));
+    }
+
+    let socket = Socket::new(Domain::for_address(group), Type::DGRAM, Some(Protocol::UDP))?;
+    // Allow other applications in the same host to join the group
+    socket.set_reuse_address(true)?;
+    match group.ip() {
+        IpAddr::V4(ip) => {
+            socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, group.port())).into())?;
+            socket.join_multicast_v4(&ip, &interface)?;
+            socket.set_multicast_if_v4(&interface)?;
+            socket.set_multicast_ttl_v4(ttl)?;
+        }
+        // IPv6 groups use the default interface
+        IpAddr::V6(ip) => {
+            socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, group.port())).into())?;
+            socket.join_multicast_v6(&ip, 0)?;
+            socket.set_multicast_hops_v6(ttl)?;
+        }
+    }
+
+    Ok(UdpMulticastConnection {
+        socket: socket.into(),
+        group,
+        reader: Mutex::new(Cursor::new(vec![])),
+        version: mavlink::MavlinkVersion::V2,
+    })
+}
+
+impl<M: mavlink::Message> mavlink::MavConnection<M> for UdpMulticastConnection {
+    fn recv(&self) -> Result<(mavlink::MavHeader, M), MessageReadError> {
+        let mut reader = self.reader.lock().unwrap();
+        loop {
+            if reader.position() < reader.get_ref().len() as u64 {
+                match mavlink::read_versioned_msg(&mut *reader, self.version) {
+                    Ok(message) => return Ok(message),
+                    // Incomplete frame at the end of the datagram
+                    Err(MessageReadError::Io(_)) => (),
+                    Err(error) => return Err(error),
+                }
+            }
+
+            let mut buffer = vec![0u8; 65536];
+            let (size, _) = self.socket.recv_from(&mut buffer)?;
+            buffer.truncate(size);
+            *reader = Cursor::new(buffer);
+        }
+    }
+
+    fn send(&self, header: &mavlink::MavHeader, data: &M) -> Result<usize, MessageWriteError> {
+        let mut bytes = vec![];
+        mavlink::write_versioned_msg(&mut bytes, self.version, *header, data)?;
+        Ok(self.socket.send_to(&bytes, self.group)?)
+    }
+
+    fn set_protocol_version(&mut self, version: mavlink::MavlinkVersion) {
+        self.version = version;
+    }
+
+    fn get_protocol_version(&self) -> mavlink::MavlinkVersion {
+        self.version
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::*;
+use serde::Serialize;
+
+use super::cli;
+use super::data::FrequencyEstimator;
+use super::websocket_manager;
+
+// Every vehicle and component seen on the link
+#[derive(Clone, Debug, Serialize)]
+pub struct Component {
+    system_id: u8,
+    component_id: u8,
+    /// MAV_TYPE and MAV_AUTOPILOT from the last HEARTBEAT, if any
+    mav_type: Option<String>,
+    autopilot: Option<String>,
+    /// Name of the vehicle, from --vehicle-name
+    vehicle_name: Option<String>,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    last_heartbeat: Option<DateTime<Utc>>,
+    heartbeat: FrequencyEstimator,
+    /// If a HEARTBEAT was received within the heartbeat timeout, filled when requested
+    online: bool,
+}
+
+impl Component {
+    fn is_online(&self, now: DateTime<Utc>, timeout: std::time::Duration) -> bool {
+        self.last_heartbeat.map_or(false, |last_heartbeat| {
+            (now - last_heartbeat)
+                .to_std()
+                .map_or(true, |age| age <= timeout)
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct VehicleState {
+    system_id: u8,
+    /// Name of the vehicle, from --vehicle-name
+    name: Option<String>,
+    /// If any component of the vehicle is online
+    online: bool,
+    last_heartbeat: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    static ref COMPONENTS: Mutex<BTreeMap<(u8, u8), Component>> = Mutex::new(BTreeMap::new());
+    static ref NAMES: Mutex<BTreeMap<u8, String>> = Mutex::new(parse_names(&cli::vehicle_names()));
+}
+
+// Parse ID:NAME vehicle names, names that could be confused with paths are ignored
+fn parse_names<S: AsRef<str>>(names: &[S]) -> BTreeMap<u8, String> {
+    names
+        .iter()
+        .filter_map(|entry| {
+            let entry = entry.as_ref();
+            let parsed = entry.split_once(':').and_then(|(system_id, name)| {
+                let system_id = system_id.trim().parse::<u8>().ok()?;
+                let name = name.trim();
+                let is_valid = !name.is_empty()
+                    && name != "vehicles"
+                    && name.parse::<u8>().is_err()
+                    && !name.contains('/');
+                is_valid.then(|| (system_id, name.to_string()))
+            });
+
+            if parsed.is_none() {
+                error!("Invalid vehicle name: {entry}, it should be in the format ID:NAME");
+            }
+
+            parsed
+        })
+        .collect()
+}
+
+/// Name of the vehicle, from --vehicle-name
+pub fn name(system_id: u8) -> Option<String> {
+    NAMES.lock().unwrap().get(&system_id).cloned()
+}
+
+/// System id of the vehicle with the name
+pub fn system_id_of(name: &str) -> Option<u8> {
+    NAMES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_, vehicle_name)| vehicle_name.as_str() == name)
+        .map(|(system_id, _)| *system_id)
+}
+
+pub fn update(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let now = Utc::now();
+    let mut components = COMPONENTS.lock().unwrap();
+    let component = components
+        .entry((header.system_id, header.component_id))
+        .or_insert_with(|| Component {
+            system_id: header.system_id,
+            component_id: header.component_id,
+            mav_type: None,
+            autopilot: None,
+            vehicle_name: None,
+            first_seen: now,
+            last_seen: now,
+            last_heartbeat: None,
+            heartbeat: FrequencyEstimator::default(),
+            online: false,
+        });
+    component.last_seen = now;
+
+    if let mavlink::ardupilotmega::MavMessage::common(mavlink::common::MavMessage::HEARTBEAT(
+        heartbeat,
+    )) = message
+    {
+        component.mav_type = Some(format!("{:?}", heartbeat.mavtype));
+        component.autopilot = Some(format!("{:?}", heartbeat.autopilot));
+        if let Some(last_heartbeat) = component.last_heartbeat {
+            let interval = (now - last_heartbeat)
+                .num_microseconds()
+                .unwrap_or(i64::MAX);
+            component.heartbeat.update(interval as f64 / 1e6);
+        }
+        component.last_heartbeat = Some(now);
+    }
+}
+
+/// Components seen on the link, sorted by vehicle and component
+pub fn components() -> Vec<Component> {
+    let now = Utc::now();
+    let timeout = cli::heartbeat_timeout();
+    let names = NAMES.lock().unwrap().clone();
+    COMPONENTS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|component| Component {
+            online: component.is_online(now, timeout),
+            vehicle_name: names.get(&component.system_id).cloned(),
+            ..component.clone()
+        })
+        .collect()
+}
+
+/// MAV_AUTOPILOT of the component, from its last HEARTBEAT
+pub fn autopilot(system_id: u8, component_id: u8) -> Option<String> {
+    COMPONENTS
+        .lock()
+        .unwrap()
+        .get(&(system_id, component_id))
+        .and_then(|component| component.autopilot.clone())
+}
+
+/// MAV_TYPE of the component, from its last HEARTBEAT
+pub fn mav_type(system_id: u8, component_id: u8) -> Option<String> {
+    COMPONENTS
+        .lock()
+        .unwrap()
+        .get(&(system_id, component_id))
+        .and_then(|component| component.mav_type.clone())
+}
+
+/// Online state of each vehicle, sorted by system id
+pub fn vehicles() -> Vec<VehicleState> {
+    let mut vehicles: BTreeMap<u8, VehicleState> = BTreeMap::new();
+    for component in components() {
+        let vehicle = vehicles
+            .entry(component.system_id)
+            .or_insert_with(|| VehicleState {
+                system_id: component.system_id,
+                name: component.vehicle_name.clone(),
+                online: false,
+                last_heartbeat: None,
+            });
+        vehicle.online |= component.online;
+        vehicle.last_heartbeat = vehicle.last_heartbeat.max(component.last_heartbeat);
+    }
+    vehicles.into_values().collect()
+}
+
+// Report vehicles going online or offline, since the link is quiet when a vehicle drops
+fn monitor() {
+    let mut online = BTreeSet::new();
+    loop {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+
+        for vehicle in vehicles() {
+            let was_online = online.contains(&vehicle.system_id);
+            if vehicle.online == was_online {
+                continue;
+            }
+
+            let event = if vehicle.online {
+                info!("Vehicle {} is online.", vehicle.system_id);
+                online.insert(vehicle.system_id);
+                "vehicle_online"
+            } else {
+                warn!("Vehicle {} is offline, heartbeat lost.", vehicle.system_id);
+                online.remove(&vehicle.system_id);
+                "vehicle_offline"
+            };
+
+            if cli::is_vehicle_events() {
+                websocket_manager::broadcast(&serde_json::json!({
+                    "event": event,
+                    "vehicle": vehicle,
+                }));
+            }
+        }
+    }
+}
+
+pub fn init() {
+    lazy_static::initialize(&NAMES);
+    cli::on_reload(|| *NAMES.lock().unwrap() = parse_names(&cli::vehicle_names()));
+    std::thread::spawn(monitor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vehicle_names() {
+        let names = parse_names(&[
+            "1:boat",
+            " 2 : skiff ",
+            "3:vehicles",
+            "4:5",
+            "300:barge",
+            "tug",
+        ]);
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            vec![(1, "boat".to_string()), (2, "skiff".to_string())]
+        );
+    }
+}
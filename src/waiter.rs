@@ -0,0 +1,85 @@
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+type Predicate =
+    Box<dyn Fn(&mavlink::MavHeader, &mavlink::ardupilotmega::MavMessage) -> bool + Send>;
+type Received = (mavlink::MavHeader, mavlink::ardupilotmega::MavMessage);
+
+// Pending requests waiting for a message that matches the predicate
+struct Waiter {
+    predicate: Predicate,
+    sender: mpsc::Sender<Received>,
+    // Keep receiving matches until the subscription is dropped
+    persistent: bool,
+}
+
+lazy_static! {
+    static ref WAITERS: Mutex<Vec<Waiter>> = Mutex::new(vec![]);
+}
+
+pub struct Subscription {
+    receiver: mpsc::Receiver<Received>,
+}
+
+impl Subscription {
+    /// Block until the message arrives or the timeout expires
+    pub fn wait(self, timeout: Duration) -> Option<Received> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Block until the next message of a persistent subscription arrives or the timeout expires
+    pub fn next(&self, timeout: Duration) -> Option<Received> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+fn add(
+    predicate: impl Fn(&mavlink::MavHeader, &mavlink::ardupilotmega::MavMessage) -> bool
+        + Send
+        + 'static,
+    persistent: bool,
+) -> Subscription {
+    let (sender, receiver) = mpsc::channel();
+    WAITERS.lock().unwrap().push(Waiter {
+        predicate: Box::new(predicate),
+        sender,
+        persistent,
+    });
+    Subscription { receiver }
+}
+
+/// Wait for the next message matching the predicate, it should be called before requesting the message to avoid races
+pub fn subscribe(
+    predicate: impl Fn(&mavlink::MavHeader, &mavlink::ardupilotmega::MavMessage) -> bool
+        + Send
+        + 'static,
+) -> Subscription {
+    add(predicate, false)
+}
+
+/// Receive all messages matching the predicate while the subscription exists
+pub fn subscribe_all(
+    predicate: impl Fn(&mavlink::MavHeader, &mavlink::ardupilotmega::MavMessage) -> bool
+        + Send
+        + 'static,
+) -> Subscription {
+    add(predicate, true)
+}
+
+pub fn notify(header: &mavlink::MavHeader, message: &mavlink::ardupilotmega::MavMessage) {
+    let mut waiters = WAITERS.lock().unwrap();
+    if waiters.is_empty() {
+        return;
+    }
+
+    // Waiters are done after a match, or when the subscription was dropped after a timeout
+    waiters.retain(|waiter| {
+        if !(waiter.predicate)(header, message) {
+            return true;
+        }
+        let sent = waiter.sender.send((*header, message.clone())).is_ok();
+        waiter.persistent && sent
+    });
+}
@@ -1,29 +1,108 @@
-use actix::{Actor, Addr, AsyncContext, Handler, Message, StreamHandler}; //TODO: Check include orders
+use actix::{prelude::SendError, Actor, Addr, AsyncContext, Handler, Message, StreamHandler}; //TODO: Check include orders
 use actix_web_actors::ws;
 use derivative::Derivative;
 use lazy_static::lazy_static;
+use log::*;
 use mavlink::Message as MavMessage;
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+use crate::metrics;
+use crate::output::{self, Encoding, OutputOptions};
+use crate::throttle::Decimator;
 use crate::MAVLinkMessage;
 
-pub struct StringMessage(String);
+// Messages waiting for a client, newer ones are dropped while a slow client is not reading them
+const CLIENT_MAILBOX_CAPACITY: usize = 64;
+
+pub struct StringMessage(pub String);
 
 impl Message for StringMessage {
     type Result = ();
 }
 
+pub struct BinaryMessage(pub Vec<u8>);
+
+impl Message for BinaryMessage {
+    type Result = ();
+}
+
 #[derive(Serialize, Debug)]
 pub struct WebsocketError {
     pub error: String,
 }
 
+/// Messages requested by a client with a control frame, it replaces the filter query parameter
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Subscription {
+    pub types: Option<Vec<String>>,
+    pub system_id: Option<u8>,
+    pub component_id: Option<u8>,
+    /// Maximum rate of each message type of each component
+    pub max_rate_hz: Option<f32>,
+    /// Maximum rate of specific message types, by name, replacing max_rate_hz for them
+    pub max_rates: Option<HashMap<String, f32>>,
+}
+
+impl Subscription {
+    fn decimator(&self) -> Decimator {
+        Decimator::new(
+            self.max_rate_hz,
+            &self.max_rates.clone().unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct ControlFrame {
+    subscribe: Subscription,
+}
+
 #[derive(Debug)]
 pub struct WebsocketActorContent {
     pub actor: Addr<WebsocketActor>,
     pub re: Option<Regex>,
+    pub encoding: Encoding,
+    pub subscription: Option<Subscription>,
+    // Drops the updates faster than the rates requested by the client, only for it
+    decimator: Decimator,
+    // Messages dropped because the client was not reading them
+    dropped: u64,
+}
+
+impl WebsocketActorContent {
+    fn try_send<M>(&mut self, message: M)
+    where
+        M: Message<Result = ()> + Send + 'static,
+        WebsocketActor: Handler<M>,
+    {
+        if let Err(SendError::Full(_)) = self.actor.try_send(message) {
+            if self.dropped == 0 {
+                warn!("Websocket client is not keeping up, dropping its messages.");
+            }
+            self.dropped += 1;
+        }
+    }
+
+    fn should_send(&mut self, value: &serde_json::Value, name: &str) -> bool {
+        let id = |field: &str| value["header"][field].as_u64().unwrap_or(0) as u8;
+        let (system_id, component_id) = (id("system_id"), id("component_id"));
+        let is_match = match &self.subscription {
+            Some(subscription) => {
+                subscription.types.as_ref().map_or(true, |types| {
+                    types.iter().any(|type_name| type_name == name)
+                }) && subscription.system_id.map_or(true, |id| id == system_id)
+                    && subscription
+                        .component_id
+                        .map_or(true, |id| id == component_id)
+            }
+            None => self.re.as_ref().map_or(false, |regx| regx.is_match(name)),
+        };
+
+        is_match && self.decimator.should_send(system_id, component_id, name)
+    }
 }
 
 #[derive(Derivative, Default)]
@@ -36,16 +115,53 @@ pub struct WebsocketManager {
 }
 
 impl WebsocketManager {
-    pub fn send(&self, value: &serde_json::Value, name: &str) {
+    pub fn send(&mut self, value: &serde_json::Value, name: &str) {
         if self.clients.is_empty() {
             return;
         }
 
-        let string = serde_json::to_string_pretty(value).unwrap();
-        for client in &self.clients {
-            let is_match = client.re.as_ref().map_or(false, |regx| regx.is_match(name));
-            if is_match {
-                client.actor.do_send(StringMessage(string.clone()));
+        // Each encoding is done only once and if necessary
+        let mut encoded: Vec<(Encoding, Vec<u8>)> = vec![];
+        let mut string = None;
+        for client in &mut self.clients {
+            if !client.should_send(value, name) {
+                continue;
+            }
+
+            if client.encoding == Encoding::Json {
+                let string =
+                    string.get_or_insert_with(|| serde_json::to_string_pretty(value).unwrap());
+                client.try_send(StringMessage(string.clone()));
+                continue;
+            }
+
+            let bytes = match encoded
+                .iter()
+                .find(|(encoding, _)| *encoding == client.encoding)
+            {
+                Some((_, bytes)) => bytes.clone(),
+                None => {
+                    let bytes = client.encoding.encode(value);
+                    encoded.push((client.encoding, bytes.clone()));
+                    bytes
+                }
+            };
+            client.try_send(BinaryMessage(bytes));
+        }
+    }
+}
+
+impl WebsocketManager {
+    // Send to all clients, ignoring message filters
+    fn broadcast(&mut self, value: &serde_json::Value) {
+        for client in &mut self.clients {
+            match client.encoding {
+                Encoding::Json => {
+                    client.try_send(StringMessage(serde_json::to_string_pretty(value).unwrap()))
+                }
+                // The protobuf schema only describes MAVLink messages
+                Encoding::Protobuf => (),
+                encoding => client.try_send(BinaryMessage(encoding.encode(value))),
             }
         }
     }
@@ -62,21 +178,41 @@ pub fn manager() -> Arc<Mutex<WebsocketManager>> {
 
 pub fn send(message: &MAVLinkMessage<mavlink::ardupilotmega::MavMessage>) {
     let name = message.message.message_name();
-    let value = serde_json::to_value(message).unwrap();
+    let start = std::time::Instant::now();
+    let mut value = output::to_value(message);
+    output::apply(&mut value, &OutputOptions::global());
+    metrics::record_serialization(start.elapsed());
     MANAGER.lock().unwrap().send(&value, name);
 }
 
+/// Send an event that is not a MAVLink message to all clients
+pub fn broadcast(value: &serde_json::Value) {
+    MANAGER.lock().unwrap().broadcast(value);
+}
+
 #[derive(Debug)]
 pub struct WebsocketActor {
     server: Arc<Mutex<WebsocketManager>>,
     pub filter: String,
+    pub encoding: Encoding,
+    // Rates of the query parameters, until the client sends a control frame
+    pub max_rate_hz: Option<f32>,
+    pub max_rates: HashMap<String, f32>,
 }
 
 impl WebsocketActor {
-    pub fn new(message_filter: String) -> Self {
+    pub fn new(
+        message_filter: String,
+        encoding: Encoding,
+        max_rate_hz: Option<f32>,
+        max_rates: HashMap<String, f32>,
+    ) -> Self {
         Self {
             server: MANAGER.clone(),
             filter: message_filter,
+            encoding,
+            max_rate_hz,
+            max_rates,
         }
     }
 }
@@ -89,13 +225,22 @@ impl Handler<StringMessage> for WebsocketActor {
     }
 }
 
+impl Handler<BinaryMessage> for WebsocketActor {
+    type Result = ();
+
+    fn handle(&mut self, message: BinaryMessage, context: &mut Self::Context) {
+        context.binary(message.0);
+    }
+}
+
 impl Actor for WebsocketActor {
     type Context = ws::WebsocketContext<Self>;
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
     fn started(&mut self, ctx: &mut Self::Context) {
-        println!("Starting websocket, add itself in manager.");
+        debug!("Starting websocket, add itself in manager.");
+        ctx.set_mailbox_capacity(CLIENT_MAILBOX_CAPACITY);
         self.server
             .lock()
             .unwrap()
@@ -103,11 +248,15 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
             .push(WebsocketActorContent {
                 actor: ctx.address(),
                 re: Regex::new(&self.filter).ok(),
+                encoding: self.encoding,
+                subscription: None,
+                decimator: Decimator::new(self.max_rate_hz, &self.max_rates),
+                dropped: 0,
             });
     }
 
     fn finished(&mut self, ctx: &mut Self::Context) {
-        println!("Finishing websocket, remove itself from manager.");
+        debug!("Finishing websocket, remove itself from manager.");
         self.server
             .lock()
             .unwrap()
@@ -119,6 +268,22 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
             Ok(ws::Message::Text(text)) => {
+                if let Ok(control) = serde_json::from_str::<ControlFrame>(&text) {
+                    let mut server = self.server.lock().unwrap();
+                    let address = ctx.address();
+                    if let Some(client) = server
+                        .clients
+                        .iter_mut()
+                        .find(|client| client.actor == address)
+                    {
+                        client.subscription = Some(control.subscribe.clone());
+                        client.decimator = control.subscribe.decimator();
+                    }
+                    let answer = serde_json::json!({ "subscribed": control.subscribe });
+                    ctx.text(answer.to_string());
+                    return;
+                }
+
                 let text = match &self.server.lock().unwrap().new_message_callback {
                     Some(callback) => callback(&text),
                     None => serde_json::to_string(&WebsocketError {
@@ -133,3 +298,61 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebsocketActor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_rates() {
+        let control_frame: ControlFrame = serde_json::from_str(
+            r#"{"subscribe": {"types": ["ATTITUDE"], "max_rate_hz": 1, "max_rates": {"VFR_HUD": 0.5}}}"#,
+        )
+        .unwrap();
+        let subscription = control_frame.subscribe;
+        assert_eq!(subscription.types, Some(vec!["ATTITUDE".to_string()]));
+        assert_eq!(subscription.max_rate_hz, Some(1.0));
+        assert_eq!(subscription.max_rates.as_ref().unwrap()["VFR_HUD"], 0.5);
+
+        // The slow client drops the updates of the last second, the other one receives all of them
+        let mut slow_client = subscription.decimator();
+        let mut client = Subscription::default().decimator();
+        assert!(slow_client.should_send(1, 1, "ATTITUDE"));
+        for _ in 0..3 {
+            assert!(client.should_send(1, 1, "ATTITUDE"));
+            assert!(!slow_client.should_send(1, 1, "ATTITUDE"));
+        }
+        assert!(slow_client.should_send(1, 1, "VFR_HUD"));
+        assert!(!slow_client.should_send(1, 1, "VFR_HUD"));
+        assert!(client.should_send(1, 1, "VFR_HUD"));
+    }
+
+    #[test]
+    fn stalled_client() {
+        actix_web::rt::System::new("test").block_on(async {
+            // The response of the websocket is never read, like a client that stopped reading
+            let (actor, _response) = ws::WebsocketContext::create_with_addr(
+                WebsocketActor::new(".*".to_string(), Encoding::Json, None, HashMap::new()),
+                futures::stream::pending(),
+            );
+            let mut manager = WebsocketManager::default();
+            manager.clients.push(WebsocketActorContent {
+                actor,
+                re: Regex::new(".*").ok(),
+                encoding: Encoding::Json,
+                subscription: None,
+                decimator: Decimator::default(),
+                dropped: 0,
+            });
+
+            let value = serde_json::json!({"header": {"system_id": 1, "component_id": 1}});
+            let count = 10 * CLIENT_MAILBOX_CAPACITY;
+            for _ in 0..count {
+                manager.send(&value, "HEARTBEAT");
+            }
+            // Only the mailbox is filled, the remaining messages are dropped
+            let dropped = manager.clients[0].dropped as usize;
+            assert!(dropped >= count - CLIENT_MAILBOX_CAPACITY - 1);
+        });
+    }
+}